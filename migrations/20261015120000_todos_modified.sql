@@ -0,0 +1,23 @@
+CREATE TABLE todos_modified
+(
+    id BOOLEAN PRIMARY KEY DEFAULT true CHECK (id),
+    modified_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+INSERT INTO todos_modified DEFAULT VALUES;
+
+CREATE FUNCTION touch_todos_modified() RETURNS trigger AS
+$$
+BEGIN
+    UPDATE todos_modified SET modified_at = clock_timestamp();
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER todos_modified_on_todos
+    AFTER INSERT OR UPDATE OR DELETE OR TRUNCATE ON todos
+    FOR EACH STATEMENT EXECUTE FUNCTION touch_todos_modified();
+
+CREATE TRIGGER todos_modified_on_todo_labels
+    AFTER INSERT OR UPDATE OR DELETE OR TRUNCATE ON todo_labels
+    FOR EACH STATEMENT EXECUTE FUNCTION touch_todos_modified();
@@ -0,0 +1,5 @@
+ALTER TABLE todos ADD COLUMN created_at TIMESTAMPTZ NOT NULL DEFAULT now();
+ALTER TABLE todos ADD COLUMN updated_at TIMESTAMPTZ NOT NULL DEFAULT now();
+
+-- Serves the default `created_at DESC, id DESC` listing of an owner's todos.
+CREATE INDEX todos_owner_id_created_at_idx ON todos (owner_id, created_at DESC, id DESC);
@@ -0,0 +1,12 @@
+-- Field-level changes recorded by updates, `old` and `new` hold the JSON values.
+CREATE TABLE todo_history
+(
+    id         SERIAL PRIMARY KEY,
+    todo_id    INTEGER     NOT NULL,
+    changed_at TIMESTAMPTZ NOT NULL,
+    field      TEXT        NOT NULL,
+    old        JSONB       NOT NULL,
+    new        JSONB       NOT NULL
+);
+
+CREATE INDEX todo_history_todo_id_idx ON todo_history (todo_id, changed_at, id);
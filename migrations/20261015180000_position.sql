@@ -0,0 +1,5 @@
+ALTER TABLE todos ADD COLUMN position INTEGER;
+
+-- Serves the default `position ASC NULLS LAST, created_at DESC, id DESC` listing.
+CREATE INDEX todos_owner_id_position_idx
+    ON todos (owner_id, position ASC NULLS LAST, created_at DESC, id DESC);
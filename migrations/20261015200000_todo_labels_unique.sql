@@ -0,0 +1,5 @@
+-- Lets a label be attached with `ON CONFLICT DO NOTHING`, dropping the copies left behind.
+DELETE FROM todo_labels
+WHERE id NOT IN (SELECT MIN(id) FROM todo_labels GROUP BY todo_id, label_id);
+
+CREATE UNIQUE INDEX todo_labels_todo_id_label_id_idx ON todo_labels (todo_id, label_id);
@@ -0,0 +1,61 @@
+-- One row per owner in place of the single one, so that writes of different owners don't
+-- queue on it. Labels count too, as todos are listed with their names.
+DROP TRIGGER todos_modified_on_todos ON todos;
+DROP TRIGGER todos_modified_on_todo_labels ON todo_labels;
+DROP FUNCTION touch_todos_modified();
+DROP TABLE todos_modified;
+
+CREATE TABLE todos_modified
+(
+    owner_id    INTEGER PRIMARY KEY,
+    modified_at TIMESTAMPTZ NOT NULL
+);
+
+INSERT INTO todos_modified (owner_id, modified_at)
+SELECT owner_id, now() FROM todos
+UNION
+SELECT owner_id, now() FROM labels;
+
+-- Never moves back, should a transaction that read the clock first commit last.
+CREATE FUNCTION touch_todos_modified(owner INTEGER) RETURNS void AS
+$$
+INSERT INTO todos_modified (owner_id, modified_at) VALUES (owner, clock_timestamp())
+ON CONFLICT (owner_id) DO UPDATE
+    SET modified_at = greatest(todos_modified.modified_at, excluded.modified_at);
+$$ LANGUAGE sql;
+
+CREATE FUNCTION touch_todos_modified_of_owner() RETURNS trigger AS
+$$
+BEGIN
+    IF TG_OP = 'DELETE' THEN
+        PERFORM touch_todos_modified(OLD.owner_id);
+    ELSE
+        PERFORM touch_todos_modified(NEW.owner_id);
+    END IF;
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE FUNCTION touch_todos_modified_of_todo() RETURNS trigger AS
+$$
+BEGIN
+    IF TG_OP = 'DELETE' THEN
+        PERFORM touch_todos_modified(owner_id) FROM todos WHERE id = OLD.todo_id;
+    ELSE
+        PERFORM touch_todos_modified(owner_id) FROM todos WHERE id = NEW.todo_id;
+    END IF;
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER todos_modified_on_todos
+    AFTER INSERT OR UPDATE OR DELETE ON todos
+    FOR EACH ROW EXECUTE FUNCTION touch_todos_modified_of_owner();
+
+CREATE TRIGGER todos_modified_on_labels
+    AFTER INSERT OR UPDATE OR DELETE ON labels
+    FOR EACH ROW EXECUTE FUNCTION touch_todos_modified_of_owner();
+
+CREATE TRIGGER todos_modified_on_todo_labels
+    AFTER INSERT OR UPDATE OR DELETE ON todo_labels
+    FOR EACH ROW EXECUTE FUNCTION touch_todos_modified_of_todo();
@@ -0,0 +1,22 @@
+-- Stops concurrent creates from giving an owner two labels of one name, folding the copies
+-- already made into the first one. Names compare exactly like the checks of the repository
+-- do, and the check waits for the commit so that `PATCH /labels/bulk` can swap names.
+CREATE TEMPORARY TABLE label_copies AS
+SELECT labels.id, kept.id AS kept_id
+FROM labels
+JOIN (SELECT DISTINCT ON (owner_id, name) id, owner_id, name
+      FROM labels
+      ORDER BY owner_id, name, id) kept
+    ON kept.owner_id = labels.owner_id AND kept.name = labels.name AND kept.id <> labels.id;
+
+INSERT INTO todo_labels (todo_id, label_id)
+SELECT todo_labels.todo_id, label_copies.kept_id
+FROM todo_labels
+JOIN label_copies ON label_copies.id = todo_labels.label_id
+ON CONFLICT (todo_id, label_id) DO NOTHING;
+DELETE FROM todo_labels USING label_copies WHERE todo_labels.label_id = label_copies.id;
+DELETE FROM labels USING label_copies WHERE labels.id = label_copies.id;
+DROP TABLE label_copies;
+
+ALTER TABLE labels
+    ADD CONSTRAINT labels_owner_id_name_key UNIQUE (owner_id, name) DEFERRABLE INITIALLY DEFERRED;
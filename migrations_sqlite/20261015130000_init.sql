@@ -0,0 +1,59 @@
+CREATE TABLE todos
+(
+    id           INTEGER PRIMARY KEY AUTOINCREMENT,
+    text         TEXT     NOT NULL,
+    completed    BOOLEAN  NOT NULL DEFAULT false,
+    completed_at DATETIME,
+    archived     BOOLEAN  NOT NULL DEFAULT false
+);
+
+CREATE TABLE labels
+(
+    id   INTEGER PRIMARY KEY AUTOINCREMENT,
+    name TEXT NOT NULL
+);
+
+CREATE TABLE todo_labels
+(
+    id       INTEGER PRIMARY KEY AUTOINCREMENT,
+    todo_id  INTEGER NOT NULL REFERENCES todos (id),
+    label_id INTEGER NOT NULL REFERENCES labels (id)
+);
+
+CREATE TABLE todos_modified
+(
+    id          BOOLEAN PRIMARY KEY DEFAULT true CHECK (id),
+    modified_at DATETIME NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ', 'now'))
+);
+
+INSERT INTO todos_modified DEFAULT VALUES;
+
+CREATE TRIGGER todos_modified_on_todos_insert AFTER INSERT ON todos
+BEGIN
+    UPDATE todos_modified SET modified_at = strftime('%Y-%m-%dT%H:%M:%fZ', 'now');
+END;
+
+CREATE TRIGGER todos_modified_on_todos_update AFTER UPDATE ON todos
+BEGIN
+    UPDATE todos_modified SET modified_at = strftime('%Y-%m-%dT%H:%M:%fZ', 'now');
+END;
+
+CREATE TRIGGER todos_modified_on_todos_delete AFTER DELETE ON todos
+BEGIN
+    UPDATE todos_modified SET modified_at = strftime('%Y-%m-%dT%H:%M:%fZ', 'now');
+END;
+
+CREATE TRIGGER todos_modified_on_todo_labels_insert AFTER INSERT ON todo_labels
+BEGIN
+    UPDATE todos_modified SET modified_at = strftime('%Y-%m-%dT%H:%M:%fZ', 'now');
+END;
+
+CREATE TRIGGER todos_modified_on_todo_labels_delete AFTER DELETE ON todo_labels
+BEGIN
+    UPDATE todos_modified SET modified_at = strftime('%Y-%m-%dT%H:%M:%fZ', 'now');
+END;
+
+CREATE TRIGGER todos_modified_on_todo_labels_update AFTER UPDATE ON todo_labels
+BEGIN
+    UPDATE todos_modified SET modified_at = strftime('%Y-%m-%dT%H:%M:%fZ', 'now');
+END;
@@ -0,0 +1,5 @@
+ALTER TABLE todos ADD COLUMN owner_id INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE labels ADD COLUMN owner_id INTEGER NOT NULL DEFAULT 0;
+
+CREATE INDEX todos_owner_id_idx ON todos (owner_id);
+CREATE INDEX labels_owner_id_idx ON labels (owner_id);
@@ -0,0 +1,2 @@
+ALTER TABLE todos ADD COLUMN due_date DATETIME;
+ALTER TABLE todos ADD COLUMN priority INTEGER;
@@ -0,0 +1,8 @@
+-- Columns added by ALTER TABLE can't default to the current time, so inserts set them.
+ALTER TABLE todos ADD COLUMN created_at DATETIME NOT NULL DEFAULT '1970-01-01T00:00:00.000Z';
+ALTER TABLE todos ADD COLUMN updated_at DATETIME NOT NULL DEFAULT '1970-01-01T00:00:00.000Z';
+
+UPDATE todos SET created_at = strftime('%Y-%m-%dT%H:%M:%fZ', 'now'),
+                 updated_at = strftime('%Y-%m-%dT%H:%M:%fZ', 'now');
+
+CREATE INDEX todos_owner_id_created_at_idx ON todos (owner_id, created_at DESC, id DESC);
@@ -0,0 +1,11 @@
+CREATE TABLE todo_history
+(
+    id         INTEGER PRIMARY KEY AUTOINCREMENT,
+    todo_id    INTEGER  NOT NULL,
+    changed_at DATETIME NOT NULL,
+    field      TEXT     NOT NULL,
+    old        TEXT     NOT NULL,
+    new        TEXT     NOT NULL
+);
+
+CREATE INDEX todo_history_todo_id_idx ON todo_history (todo_id, changed_at, id);
@@ -0,0 +1,9 @@
+-- Deleted todos, kept so that incremental sync can tell clients to drop them.
+CREATE TABLE todo_tombstones
+(
+    todo_id    INTEGER PRIMARY KEY,
+    owner_id   INTEGER  NOT NULL,
+    deleted_at DATETIME NOT NULL
+);
+
+CREATE INDEX todo_tombstones_owner_id_deleted_at_idx ON todo_tombstones (owner_id, deleted_at);
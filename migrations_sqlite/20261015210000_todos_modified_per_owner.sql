@@ -0,0 +1,83 @@
+-- One row per owner in place of the single one, labels count too as todos are listed with
+-- their names.
+DROP TRIGGER todos_modified_on_todos_insert;
+DROP TRIGGER todos_modified_on_todos_update;
+DROP TRIGGER todos_modified_on_todos_delete;
+DROP TRIGGER todos_modified_on_todo_labels_insert;
+DROP TRIGGER todos_modified_on_todo_labels_delete;
+DROP TRIGGER todos_modified_on_todo_labels_update;
+DROP TABLE todos_modified;
+
+CREATE TABLE todos_modified
+(
+    owner_id    INTEGER PRIMARY KEY,
+    modified_at DATETIME NOT NULL
+);
+
+INSERT INTO todos_modified (owner_id, modified_at)
+SELECT owner_id, strftime('%Y-%m-%dT%H:%M:%fZ', 'now') FROM todos
+UNION
+SELECT owner_id, strftime('%Y-%m-%dT%H:%M:%fZ', 'now') FROM labels;
+
+CREATE TRIGGER todos_modified_on_todos_insert AFTER INSERT ON todos
+BEGIN
+    INSERT INTO todos_modified (owner_id, modified_at)
+    VALUES (NEW.owner_id, strftime('%Y-%m-%dT%H:%M:%fZ', 'now'))
+    ON CONFLICT (owner_id) DO UPDATE SET modified_at = excluded.modified_at;
+END;
+
+CREATE TRIGGER todos_modified_on_todos_update AFTER UPDATE ON todos
+BEGIN
+    INSERT INTO todos_modified (owner_id, modified_at)
+    VALUES (NEW.owner_id, strftime('%Y-%m-%dT%H:%M:%fZ', 'now'))
+    ON CONFLICT (owner_id) DO UPDATE SET modified_at = excluded.modified_at;
+END;
+
+CREATE TRIGGER todos_modified_on_todos_delete AFTER DELETE ON todos
+BEGIN
+    INSERT INTO todos_modified (owner_id, modified_at)
+    VALUES (OLD.owner_id, strftime('%Y-%m-%dT%H:%M:%fZ', 'now'))
+    ON CONFLICT (owner_id) DO UPDATE SET modified_at = excluded.modified_at;
+END;
+
+CREATE TRIGGER todos_modified_on_labels_insert AFTER INSERT ON labels
+BEGIN
+    INSERT INTO todos_modified (owner_id, modified_at)
+    VALUES (NEW.owner_id, strftime('%Y-%m-%dT%H:%M:%fZ', 'now'))
+    ON CONFLICT (owner_id) DO UPDATE SET modified_at = excluded.modified_at;
+END;
+
+CREATE TRIGGER todos_modified_on_labels_update AFTER UPDATE ON labels
+BEGIN
+    INSERT INTO todos_modified (owner_id, modified_at)
+    VALUES (NEW.owner_id, strftime('%Y-%m-%dT%H:%M:%fZ', 'now'))
+    ON CONFLICT (owner_id) DO UPDATE SET modified_at = excluded.modified_at;
+END;
+
+CREATE TRIGGER todos_modified_on_labels_delete AFTER DELETE ON labels
+BEGIN
+    INSERT INTO todos_modified (owner_id, modified_at)
+    VALUES (OLD.owner_id, strftime('%Y-%m-%dT%H:%M:%fZ', 'now'))
+    ON CONFLICT (owner_id) DO UPDATE SET modified_at = excluded.modified_at;
+END;
+
+CREATE TRIGGER todos_modified_on_todo_labels_insert AFTER INSERT ON todo_labels
+BEGIN
+    INSERT INTO todos_modified (owner_id, modified_at)
+    SELECT owner_id, strftime('%Y-%m-%dT%H:%M:%fZ', 'now') FROM todos WHERE id = NEW.todo_id
+    ON CONFLICT (owner_id) DO UPDATE SET modified_at = excluded.modified_at;
+END;
+
+CREATE TRIGGER todos_modified_on_todo_labels_update AFTER UPDATE ON todo_labels
+BEGIN
+    INSERT INTO todos_modified (owner_id, modified_at)
+    SELECT owner_id, strftime('%Y-%m-%dT%H:%M:%fZ', 'now') FROM todos WHERE id = NEW.todo_id
+    ON CONFLICT (owner_id) DO UPDATE SET modified_at = excluded.modified_at;
+END;
+
+CREATE TRIGGER todos_modified_on_todo_labels_delete AFTER DELETE ON todo_labels
+BEGIN
+    INSERT INTO todos_modified (owner_id, modified_at)
+    SELECT owner_id, strftime('%Y-%m-%dT%H:%M:%fZ', 'now') FROM todos WHERE id = OLD.todo_id
+    ON CONFLICT (owner_id) DO UPDATE SET modified_at = excluded.modified_at;
+END;
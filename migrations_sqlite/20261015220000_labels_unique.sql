@@ -0,0 +1,18 @@
+-- Stops concurrent creates from giving an owner two labels of one name, folding the copies
+-- already made into the first one.
+CREATE TEMPORARY TABLE label_copies AS
+SELECT labels.id AS id,
+       (SELECT MIN(kept.id) FROM labels kept
+        WHERE kept.owner_id = labels.owner_id AND kept.name = labels.name) AS kept_id
+FROM labels;
+DELETE FROM label_copies WHERE id = kept_id;
+
+INSERT OR IGNORE INTO todo_labels (todo_id, label_id)
+SELECT todo_labels.todo_id, label_copies.kept_id
+FROM todo_labels
+JOIN label_copies ON label_copies.id = todo_labels.label_id;
+DELETE FROM todo_labels WHERE label_id IN (SELECT id FROM label_copies);
+DELETE FROM labels WHERE id IN (SELECT id FROM label_copies);
+DROP TABLE label_copies;
+
+CREATE UNIQUE INDEX labels_owner_id_name_idx ON labels (owner_id, name);
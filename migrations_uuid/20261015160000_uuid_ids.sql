@@ -0,0 +1,31 @@
+-- Switches todos and labels to UUID ids for the `uuid` feature, keeping the associations.
+-- Run after `migrations/` with `sqlx migrate run --source migrations_uuid --ignore-missing`.
+ALTER TABLE todos ADD COLUMN uuid UUID NOT NULL DEFAULT gen_random_uuid();
+ALTER TABLE labels ADD COLUMN uuid UUID NOT NULL DEFAULT gen_random_uuid();
+
+ALTER TABLE todo_labels ADD COLUMN todo_uuid UUID;
+ALTER TABLE todo_labels ADD COLUMN label_uuid UUID;
+UPDATE todo_labels SET todo_uuid = todos.uuid FROM todos WHERE todos.id = todo_labels.todo_id;
+UPDATE todo_labels SET label_uuid = labels.uuid FROM labels WHERE labels.id = todo_labels.label_id;
+
+ALTER TABLE todo_labels DROP COLUMN todo_id;
+ALTER TABLE todo_labels DROP COLUMN label_id;
+ALTER TABLE todos DROP COLUMN id;
+ALTER TABLE labels DROP COLUMN id;
+
+ALTER TABLE todos RENAME COLUMN uuid TO id;
+ALTER TABLE todos ADD PRIMARY KEY (id);
+ALTER TABLE labels RENAME COLUMN uuid TO id;
+ALTER TABLE labels ADD PRIMARY KEY (id);
+
+ALTER TABLE todo_labels RENAME COLUMN todo_uuid TO todo_id;
+ALTER TABLE todo_labels RENAME COLUMN label_uuid TO label_id;
+ALTER TABLE todo_labels ALTER COLUMN todo_id SET NOT NULL;
+ALTER TABLE todo_labels ALTER COLUMN label_id SET NOT NULL;
+ALTER TABLE todo_labels
+    ADD FOREIGN KEY (todo_id) REFERENCES todos (id) DEFERRABLE INITIALLY DEFERRED;
+ALTER TABLE todo_labels
+    ADD FOREIGN KEY (label_id) REFERENCES labels (id) DEFERRABLE INITIALLY DEFERRED;
+
+-- Dropping the integer id took the listing index with it.
+CREATE INDEX todos_owner_id_created_at_idx ON todos (owner_id, created_at DESC, id DESC);
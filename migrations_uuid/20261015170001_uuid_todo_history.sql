@@ -0,0 +1,14 @@
+-- History recorded against integer ids can't follow the todos to their UUIDs, so it starts over.
+DROP TABLE todo_history;
+
+CREATE TABLE todo_history
+(
+    id         SERIAL PRIMARY KEY,
+    todo_id    UUID        NOT NULL,
+    changed_at TIMESTAMPTZ NOT NULL,
+    field      TEXT        NOT NULL,
+    old        JSONB       NOT NULL,
+    new        JSONB       NOT NULL
+);
+
+CREATE INDEX todo_history_todo_id_idx ON todo_history (todo_id, changed_at, id);
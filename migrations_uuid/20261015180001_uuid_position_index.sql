@@ -0,0 +1,3 @@
+-- Dropping the integer id took the position index with it.
+CREATE INDEX IF NOT EXISTS todos_owner_id_position_idx
+    ON todos (owner_id, position ASC NULLS LAST, created_at DESC, id DESC);
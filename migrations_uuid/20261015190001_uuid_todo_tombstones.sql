@@ -0,0 +1,11 @@
+-- Tombstones of integer ids name todos that no client holds under their UUIDs.
+DROP TABLE todo_tombstones;
+
+CREATE TABLE todo_tombstones
+(
+    todo_id    UUID PRIMARY KEY,
+    owner_id   INTEGER     NOT NULL,
+    deleted_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE INDEX todo_tombstones_owner_id_deleted_at_idx ON todo_tombstones (owner_id, deleted_at);
@@ -0,0 +1,3 @@
+-- Dropping the integer todo_id and label_id took the unique index with them.
+CREATE UNIQUE INDEX IF NOT EXISTS todo_labels_todo_id_label_id_idx
+    ON todo_labels (todo_id, label_id);
@@ -0,0 +1,382 @@
+use crate::handlers::cache::DEFAULT_CACHE_MAX_AGE_SECS;
+use crate::handlers::limit::{DEFAULT_MAX_CONCURRENT_REQUESTS, DEFAULT_MAX_QUEUED_REQUESTS};
+use crate::repositories::todo::{DEFAULT_MAX_JOINED_LABELS, DEFAULT_MAX_LABELS_PER_TODO};
+use crate::repositories::DEFAULT_SLOW_QUERY_MS;
+use crate::shutdown::DEFAULT_SHUTDOWN_GRACE_SECS;
+use crate::{default_cors_headers, default_cors_methods, DEFAULT_REQUEST_TIMEOUT_SECS};
+use axum::http::{HeaderName, HeaderValue, Method};
+use std::env;
+use std::fmt::Display;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::Duration;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogFormat {
+    Pretty,
+    Json,
+}
+
+impl FromStr for LogFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "pretty" => Ok(LogFormat::Pretty),
+            "json" => Ok(LogFormat::Json),
+            _ => Err(format!("unknown LOG_FORMAT [{}]", s)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PoolConfig {
+    pub max_connections: u32,
+    pub acquire_timeout: Duration,
+}
+
+/// PEM files of the certificate chain and private key served over HTTPS.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TlsFiles {
+    pub cert: PathBuf,
+    pub key: PathBuf,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Config {
+    pub database_url: String,
+    pub host: IpAddr,
+    pub port: u16,
+    pub cors_origins: Vec<HeaderValue>,
+    pub cors_methods: Vec<Method>,
+    pub cors_headers: Vec<HeaderName>,
+    pub cors_allow_credentials: bool,
+    pub log_format: LogFormat,
+    pub pool: PoolConfig,
+    pub readiness_timeout: Duration,
+    pub request_timeout: Duration,
+    pub default_label: Option<String>,
+    pub max_labels_per_todo: usize,
+    pub max_joined_labels: usize,
+    pub label_collation: Option<String>,
+    pub jwt_secret: Option<String>,
+    pub admin_api_key: Option<String>,
+    pub read_only: bool,
+    pub shutdown_grace: Duration,
+    pub seed_on_start: bool,
+    pub cache_max_age: Duration,
+    pub slow_query: Duration,
+    pub tls: Option<TlsFiles>,
+    /// Todos `find` keeps in memory with the `cache` feature, zero disables the cache.
+    pub todo_cache_capacity: usize,
+    pub todo_cache_ttl: Duration,
+    pub max_concurrent_requests: usize,
+    pub max_queued_requests: usize,
+}
+
+impl Config {
+    pub fn from_env() -> anyhow::Result<Config> {
+        Self::from_lookup(|key| env::var(key).ok())
+    }
+
+    /// Reads every setting through `lookup` and reports all invalid ones at once.
+    pub fn from_lookup(lookup: impl Fn(&str) -> Option<String>) -> anyhow::Result<Config> {
+        let mut vars = Vars {
+            lookup,
+            errors: vec![],
+        };
+        let database_url = vars.required("DATABASE_URL");
+        let cors_origins = vars.list(
+            "CORS_ORIGINS",
+            vec![HeaderValue::from_static("http://localhost:3000")],
+        );
+        let cors_methods = vars.list("CORS_ALLOWED_METHODS", default_cors_methods());
+        let cors_headers = vars.list("CORS_ALLOWED_HEADERS", default_cors_headers());
+        let tls = match ((vars.lookup)("TLS_CERT"), (vars.lookup)("TLS_KEY")) {
+            (Some(cert), Some(key)) => Some(TlsFiles {
+                cert: cert.into(),
+                key: key.into(),
+            }),
+            (None, None) => None,
+            _ => {
+                vars.errors
+                    .push("TLS_CERT and TLS_KEY must be set together".to_string());
+                None
+            }
+        };
+        let config = Config {
+            database_url,
+            host: vars.get("HOST", IpAddr::V4(Ipv4Addr::LOCALHOST)),
+            port: vars.get("PORT", 5000),
+            cors_origins,
+            cors_methods,
+            cors_headers,
+            cors_allow_credentials: vars.get("CORS_ALLOW_CREDENTIALS", false),
+            log_format: vars.get("LOG_FORMAT", LogFormat::Pretty),
+            pool: PoolConfig {
+                max_connections: vars.get("DB_MAX_CONNECTIONS", 10),
+                acquire_timeout: Duration::from_secs(vars.get("DB_ACQUIRE_TIMEOUT_SECS", 30)),
+            },
+            readiness_timeout: Duration::from_millis(vars.get("READINESS_TIMEOUT_MS", 1000)),
+            request_timeout: Duration::from_secs(
+                vars.get("REQUEST_TIMEOUT_SECS", DEFAULT_REQUEST_TIMEOUT_SECS),
+            ),
+            default_label: (vars.lookup)("DEFAULT_LABEL"),
+            max_labels_per_todo: vars.get("MAX_LABELS_PER_TODO", DEFAULT_MAX_LABELS_PER_TODO),
+            max_joined_labels: vars.get("MAX_JOINED_LABELS", DEFAULT_MAX_JOINED_LABELS),
+            label_collation: (vars.lookup)("LABEL_COLLATION").filter(|name| !name.is_empty()),
+            jwt_secret: (vars.lookup)("JWT_SECRET").filter(|secret| !secret.is_empty()),
+            admin_api_key: (vars.lookup)("ADMIN_API_KEY").filter(|key| !key.is_empty()),
+            read_only: vars.get("READ_ONLY", false),
+            shutdown_grace: Duration::from_secs(
+                vars.get("SHUTDOWN_GRACE_SECS", DEFAULT_SHUTDOWN_GRACE_SECS),
+            ),
+            seed_on_start: vars.get("SEED_ON_START", false),
+            cache_max_age: Duration::from_secs(
+                vars.get("CACHE_MAX_AGE_SECS", DEFAULT_CACHE_MAX_AGE_SECS),
+            ),
+            slow_query: Duration::from_millis(vars.get("SLOW_QUERY_MS", DEFAULT_SLOW_QUERY_MS)),
+            tls,
+            todo_cache_capacity: vars.get("TODO_CACHE_CAPACITY", 0),
+            todo_cache_ttl: Duration::from_secs(vars.get("TODO_CACHE_TTL_SECS", 60)),
+            max_concurrent_requests: vars
+                .get("MAX_CONCURRENT_REQUESTS", DEFAULT_MAX_CONCURRENT_REQUESTS),
+            max_queued_requests: vars.get("MAX_QUEUED_REQUESTS", DEFAULT_MAX_QUEUED_REQUESTS),
+        };
+
+        if !vars.errors.is_empty() {
+            anyhow::bail!("invalid configuration: [{}]", vars.errors.join(", "));
+        }
+        Ok(config)
+    }
+
+    pub fn addr(&self) -> SocketAddr {
+        SocketAddr::new(self.host, self.port)
+    }
+}
+
+struct Vars<F> {
+    lookup: F,
+    errors: Vec<String>,
+}
+
+impl<F: Fn(&str) -> Option<String>> Vars<F> {
+    fn required(&mut self, key: &str) -> String {
+        (self.lookup)(key).unwrap_or_else(|| {
+            self.errors.push(format!("undefined {}", key));
+            String::new()
+        })
+    }
+
+    fn get<T>(&mut self, key: &str, default: T) -> T
+    where
+        T: FromStr,
+        T::Err: Display,
+    {
+        match (self.lookup)(key) {
+            Some(value) => self.parse(key, &value).unwrap_or(default),
+            None => default,
+        }
+    }
+
+    /// Parses every item of a comma-separated value, skipping blank ones.
+    fn list<T>(&mut self, key: &str, default: Vec<T>) -> Vec<T>
+    where
+        T: FromStr,
+        T::Err: Display,
+    {
+        match (self.lookup)(key) {
+            Some(value) => value
+                .split(',')
+                .map(str::trim)
+                .filter(|item| !item.is_empty())
+                .filter_map(|item| self.parse(key, item))
+                .collect(),
+            None => default,
+        }
+    }
+
+    fn parse<T>(&mut self, key: &str, value: &str) -> Option<T>
+    where
+        T: FromStr,
+        T::Err: Display,
+    {
+        value
+            .parse()
+            .map_err(|e| {
+                self.errors
+                    .push(format!("{} [{}] is invalid: {}", key, value, e))
+            })
+            .ok()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use axum::http::header::CONTENT_TYPE;
+    use std::collections::HashMap;
+
+    fn config_from(vars: &[(&str, &str)]) -> anyhow::Result<Config> {
+        let vars: HashMap<String, String> = vars
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect();
+        Config::from_lookup(|key| vars.get(key).cloned())
+    }
+
+    #[test]
+    fn should_parse_log_format() {
+        assert_eq!(Ok(LogFormat::Pretty), "pretty".parse());
+        assert_eq!(Ok(LogFormat::Json), "json".parse());
+        assert!("xml".parse::<LogFormat>().is_err());
+    }
+
+    #[test]
+    fn should_default_optional_settings() {
+        let config = config_from(&[("DATABASE_URL", "postgres://localhost/todo")]).unwrap();
+        assert_eq!(
+            Config {
+                database_url: "postgres://localhost/todo".to_string(),
+                host: IpAddr::V4(Ipv4Addr::LOCALHOST),
+                port: 5000,
+                cors_origins: vec![HeaderValue::from_static("http://localhost:3000")],
+                cors_methods: default_cors_methods(),
+                cors_headers: default_cors_headers(),
+                cors_allow_credentials: false,
+                log_format: LogFormat::Pretty,
+                pool: PoolConfig {
+                    max_connections: 10,
+                    acquire_timeout: Duration::from_secs(30),
+                },
+                readiness_timeout: Duration::from_millis(1000),
+                request_timeout: Duration::from_secs(30),
+                default_label: None,
+                max_labels_per_todo: DEFAULT_MAX_LABELS_PER_TODO,
+                max_joined_labels: DEFAULT_MAX_JOINED_LABELS,
+                label_collation: None,
+                jwt_secret: None,
+                admin_api_key: None,
+                read_only: false,
+                shutdown_grace: Duration::from_secs(30),
+                seed_on_start: false,
+                cache_max_age: Duration::from_secs(5),
+                slow_query: Duration::from_millis(200),
+                tls: None,
+                todo_cache_capacity: 0,
+                todo_cache_ttl: Duration::from_secs(60),
+                max_concurrent_requests: DEFAULT_MAX_CONCURRENT_REQUESTS,
+                max_queued_requests: DEFAULT_MAX_QUEUED_REQUESTS,
+            },
+            config
+        );
+        assert_eq!(
+            "127.0.0.1:5000".parse::<SocketAddr>().unwrap(),
+            config.addr()
+        );
+    }
+
+    #[test]
+    fn should_read_settings() {
+        let config = config_from(&[
+            ("DATABASE_URL", "postgres://localhost/todo"),
+            ("HOST", "0.0.0.0"),
+            ("PORT", "8080"),
+            ("CORS_ORIGINS", "http://a.example, http://b.example"),
+            ("CORS_ALLOWED_METHODS", "GET, POST"),
+            ("CORS_ALLOWED_HEADERS", "Content-Type,X-Custom"),
+            ("CORS_ALLOW_CREDENTIALS", "true"),
+            ("LOG_FORMAT", "json"),
+            ("DB_MAX_CONNECTIONS", "3"),
+            ("DEFAULT_LABEL", "inbox"),
+            ("LABEL_COLLATION", "ja-x-icu"),
+            ("JWT_SECRET", "secret"),
+            ("ADMIN_API_KEY", "admin"),
+            ("READ_ONLY", "true"),
+            ("SHUTDOWN_GRACE_SECS", "5"),
+            ("SEED_ON_START", "true"),
+            ("CACHE_MAX_AGE_SECS", "60"),
+            ("SLOW_QUERY_MS", "50"),
+            ("TLS_CERT", "cert.pem"),
+            ("TLS_KEY", "key.pem"),
+            ("TODO_CACHE_CAPACITY", "100"),
+            ("TODO_CACHE_TTL_SECS", "10"),
+            ("MAX_CONCURRENT_REQUESTS", "8"),
+            ("MAX_QUEUED_REQUESTS", "16"),
+        ])
+        .unwrap();
+        assert_eq!("0.0.0.0:8080".parse::<SocketAddr>().unwrap(), config.addr());
+        assert_eq!(
+            vec![
+                HeaderValue::from_static("http://a.example"),
+                HeaderValue::from_static("http://b.example")
+            ],
+            config.cors_origins
+        );
+        assert_eq!(vec![Method::GET, Method::POST], config.cors_methods);
+        assert_eq!(
+            vec![CONTENT_TYPE, HeaderName::from_static("x-custom")],
+            config.cors_headers
+        );
+        assert!(config.cors_allow_credentials);
+        assert_eq!(LogFormat::Json, config.log_format);
+        assert_eq!(3, config.pool.max_connections);
+        assert_eq!(Some("inbox".to_string()), config.default_label);
+        assert_eq!(Some("ja-x-icu".to_string()), config.label_collation);
+        assert_eq!(Some("secret".to_string()), config.jwt_secret);
+        assert_eq!(Some("admin".to_string()), config.admin_api_key);
+        assert!(config.read_only);
+        assert_eq!(Duration::from_secs(5), config.shutdown_grace);
+        assert!(config.seed_on_start);
+        assert_eq!(Duration::from_secs(60), config.cache_max_age);
+        assert_eq!(Duration::from_millis(50), config.slow_query);
+        assert_eq!(
+            Some(TlsFiles {
+                cert: "cert.pem".into(),
+                key: "key.pem".into(),
+            }),
+            config.tls
+        );
+        assert_eq!(100, config.todo_cache_capacity);
+        assert_eq!(Duration::from_secs(10), config.todo_cache_ttl);
+        assert_eq!(8, config.max_concurrent_requests);
+        assert_eq!(16, config.max_queued_requests);
+    }
+
+    #[test]
+    fn should_reject_invalid_cors_headers() {
+        let e = config_from(&[
+            ("DATABASE_URL", "postgres://localhost/todo"),
+            ("CORS_ALLOWED_HEADERS", "content-type, x owner"),
+        ])
+        .unwrap_err();
+        assert_eq!(
+            "invalid configuration: [CORS_ALLOWED_HEADERS [x owner] is invalid: invalid HTTP header name]",
+            e.to_string()
+        );
+    }
+
+    #[test]
+    fn should_reject_missing_database_url() {
+        let e = config_from(&[]).unwrap_err();
+        assert_eq!(
+            "invalid configuration: [undefined DATABASE_URL]",
+            e.to_string()
+        );
+    }
+
+    #[test]
+    fn should_report_every_invalid_setting() {
+        let e = config_from(&[
+            ("PORT", "http"),
+            ("LOG_FORMAT", "xml"),
+            ("TLS_CERT", "cert.pem"),
+        ])
+        .unwrap_err();
+        assert_eq!(
+            "invalid configuration: [undefined DATABASE_URL, TLS_CERT and TLS_KEY must be set together, PORT [http] is invalid: invalid digit found in string, LOG_FORMAT [xml] is invalid: unknown LOG_FORMAT [xml]]",
+            e.to_string()
+        );
+    }
+}
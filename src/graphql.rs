@@ -0,0 +1,220 @@
+use crate::handlers::hook::{HookRejection, TodoHook};
+use crate::handlers::maintenance::{ReadOnly, MAINTENANCE_MESSAGE};
+use crate::handlers::Owner;
+use crate::repositories::label::{Label, LabelId, LabelRepository};
+use crate::repositories::todo::{
+    CreateTodo, TodoEntity, TodoId, TodoQuery, TodoRepository, UpdateTodo,
+};
+use crate::repositories::{OwnerId, OwnerScoped, RawId};
+use async_graphql::parser::parse_query;
+use async_graphql::parser::types::OperationType;
+use async_graphql::{Context, EmptySubscription, Object, Schema};
+use async_graphql_axum::{GraphQLRequest, GraphQLResponse};
+use axum::response::{IntoResponse, Response};
+use axum::Extension;
+use chrono::{DateTime, Utc};
+use hyper::StatusCode;
+use std::marker::PhantomData;
+use std::sync::Arc;
+use validator::Validate;
+
+pub type TodoSchema<Todo, Label> =
+    Schema<QueryRoot<Todo, Label>, MutationRoot<Todo>, EmptySubscription>;
+
+pub fn build_schema<Todo: TodoRepository, Label: LabelRepository>(
+    todo_repo: Arc<Todo>,
+    label_repo: Arc<Label>,
+) -> TodoSchema<Todo, Label> {
+    Schema::build(
+        QueryRoot(PhantomData),
+        MutationRoot(PhantomData),
+        EmptySubscription,
+    )
+    .data(todo_repo)
+    .data(label_repo)
+    .finish()
+}
+
+pub async fn graphql_handler<Todo: TodoRepository, Label: LabelRepository>(
+    Extension(schema): Extension<TodoSchema<Todo, Label>>,
+    Extension(hook): Extension<Arc<dyn TodoHook>>,
+    Extension(read_only): Extension<ReadOnly>,
+    Owner(owner): Owner,
+    req: GraphQLRequest,
+) -> Response {
+    let req = req.into_inner();
+    if read_only.is_enabled() && has_mutation(&req.query) {
+        return (StatusCode::SERVICE_UNAVAILABLE, MAINTENANCE_MESSAGE).into_response();
+    }
+    GraphQLResponse::from(schema.execute(req.data(owner).data(hook)).await).into_response()
+}
+
+/// Whether the document has a mutation. A document that does not parse is left to the schema
+/// to report.
+fn has_mutation(query: &str) -> bool {
+    parse_query(query).is_ok_and(|doc| {
+        doc.operations
+            .iter()
+            .any(|(_, operation)| operation.node.ty == OperationType::Mutation)
+    })
+}
+
+/// Repository from the schema data, restricted to the owner of the current request.
+fn scoped<T: OwnerScoped>(ctx: &Context<'_>) -> async_graphql::Result<T> {
+    let owner = ctx.data::<OwnerId>()?;
+    Ok(ctx.data::<Arc<T>>()?.scoped(*owner))
+}
+
+fn graphql_error(e: anyhow::Error) -> async_graphql::Error {
+    async_graphql::Error::new(e.to_string())
+}
+
+fn hook_error(rejection: HookRejection) -> async_graphql::Error {
+    async_graphql::Error::new(rejection.0)
+}
+
+pub struct TodoObject(TodoEntity);
+
+#[Object(name = "Todo")]
+impl TodoObject {
+    async fn id(&self) -> RawId {
+        self.0.id.0
+    }
+
+    async fn text(&self) -> &str {
+        &self.0.text
+    }
+
+    async fn completed(&self) -> bool {
+        self.0.completed
+    }
+
+    async fn completed_at(&self) -> Option<DateTime<Utc>> {
+        self.0.completed_at
+    }
+
+    async fn archived(&self) -> bool {
+        self.0.archived
+    }
+
+    async fn due_date(&self) -> Option<DateTime<Utc>> {
+        self.0.due_date
+    }
+
+    async fn priority(&self) -> Option<i16> {
+        self.0.priority
+    }
+
+    async fn position(&self) -> Option<i32> {
+        self.0.position
+    }
+
+    async fn created_at(&self) -> DateTime<Utc> {
+        self.0.created_at
+    }
+
+    async fn updated_at(&self) -> DateTime<Utc> {
+        self.0.updated_at
+    }
+
+    async fn labels(&self) -> Vec<LabelObject> {
+        self.0.labels.iter().cloned().map(LabelObject).collect()
+    }
+}
+
+pub struct LabelObject(Label);
+
+#[Object(name = "Label")]
+impl LabelObject {
+    async fn id(&self) -> RawId {
+        self.0.id.0
+    }
+
+    async fn name(&self) -> &str {
+        &self.0.name
+    }
+}
+
+pub struct QueryRoot<Todo, Label>(PhantomData<(Todo, Label)>);
+
+#[Object(name = "Query")]
+impl<Todo: TodoRepository, Label: LabelRepository> QueryRoot<Todo, Label> {
+    async fn todos(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(default)] include_archived: bool,
+    ) -> async_graphql::Result<Vec<TodoObject>> {
+        let repo = scoped::<Todo>(ctx)?;
+        let query = TodoQuery {
+            include_archived,
+            ..Default::default()
+        };
+        let todos = repo.all(query).await.map_err(graphql_error)?;
+        Ok(todos.into_iter().map(TodoObject).collect())
+    }
+
+    async fn todo(&self, ctx: &Context<'_>, id: RawId) -> async_graphql::Result<TodoObject> {
+        let repo = scoped::<Todo>(ctx)?;
+        let todo = repo.find(TodoId(id)).await.map_err(graphql_error)?;
+        Ok(TodoObject(todo))
+    }
+
+    async fn labels(&self, ctx: &Context<'_>) -> async_graphql::Result<Vec<LabelObject>> {
+        let repo = scoped::<Label>(ctx)?;
+        let labels = repo.all().await.map_err(graphql_error)?;
+        Ok(labels.into_iter().map(LabelObject).collect())
+    }
+}
+
+pub struct MutationRoot<Todo>(PhantomData<Todo>);
+
+#[Object(name = "Mutation")]
+impl<Todo: TodoRepository> MutationRoot<Todo> {
+    async fn create_todo(
+        &self,
+        ctx: &Context<'_>,
+        text: String,
+        #[graphql(default)] labels: Vec<RawId>,
+    ) -> async_graphql::Result<TodoObject> {
+        let repo = scoped::<Todo>(ctx)?;
+        let payload = CreateTodo::new(
+            text.trim().to_string(),
+            labels.into_iter().map(LabelId).collect(),
+        );
+        payload.validate()?;
+        let hook = ctx.data::<Arc<dyn TodoHook>>()?;
+        let payload = hook.before_create(payload).await.map_err(hook_error)?;
+        let todo = repo.create(payload).await.map_err(graphql_error)?;
+        Ok(TodoObject(todo))
+    }
+
+    async fn update_todo(
+        &self,
+        ctx: &Context<'_>,
+        id: RawId,
+        text: Option<String>,
+        completed: Option<bool>,
+        labels: Option<Vec<RawId>>,
+    ) -> async_graphql::Result<TodoObject> {
+        let repo = scoped::<Todo>(ctx)?;
+        let payload = UpdateTodo::new(
+            text.map(|text| text.trim().to_string()),
+            completed,
+            labels.map(|labels| labels.into_iter().map(LabelId).collect()),
+        );
+        payload.validate()?;
+        let hook = ctx.data::<Arc<dyn TodoHook>>()?;
+        let payload = hook.before_update(payload).await.map_err(hook_error)?;
+        let todo = repo
+            .update(TodoId(id), payload)
+            .await
+            .map_err(graphql_error)?;
+        Ok(TodoObject(todo))
+    }
+
+    async fn delete_todo(&self, ctx: &Context<'_>, id: RawId) -> async_graphql::Result<bool> {
+        let repo = scoped::<Todo>(ctx)?;
+        repo.delete(TodoId(id)).await.map_err(graphql_error)?;
+        Ok(true)
+    }
+}
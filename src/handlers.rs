@@ -1,12 +1,35 @@
+pub mod accept;
+pub mod admin;
+pub mod auth;
+pub mod cache;
+pub mod deadline;
+pub mod health;
+pub mod hook;
 pub mod label;
+pub mod limit;
+pub mod locale;
+pub mod maintenance;
+#[cfg(feature = "schema")]
+pub mod schema;
 pub mod todo;
+pub mod tx;
+#[cfg(feature = "xml")]
+pub mod xml;
 
-use axum::extract::FromRequest;
-use axum::http::Request;
-use axum::{async_trait, BoxError, Json};
+use crate::handlers::auth::{Claims, JwtKeys};
+use crate::handlers::deadline::Deadline;
+use crate::handlers::locale::Locale;
+use crate::repositories::{OwnerId, OwnerScoped, Positive, RepositoryError};
+use axum::extract::{FromRequest, FromRequestParts, Path};
+use axum::http::request::Parts;
+use axum::http::{header, HeaderMap, HeaderName, Request, Uri};
+use axum::response::{IntoResponse, Response};
+use axum::{async_trait, BoxError, Extension, Json};
+use chrono::{DateTime, Utc};
 use hyper::StatusCode;
 use serde::de::DeserializeOwned;
-use validator::Validate;
+use serde::{Deserialize, Serialize};
+use validator::{Validate, ValidationErrors};
 
 #[derive(Debug)]
 pub struct ValidatedJson<T>(T);
@@ -20,19 +43,343 @@ where
     B::Error: Into<BoxError>,
     S: Send + Sync,
 {
-    type Rejection = (StatusCode, String);
+    type Rejection = JsonRejection;
 
     async fn from_request(req: Request<B>, state: &S) -> Result<Self, Self::Rejection> {
+        let locale = Locale::from_headers(req.headers());
+        let UnvalidatedJson(value) = UnvalidatedJson::<T>::from_request(req, state).await?;
+        value.validate().map_err(|mut errors| {
+            locale.localize(&mut errors);
+            JsonRejection::Invalid(errors)
+        })?;
+        Ok(ValidatedJson(value))
+    }
+}
+
+/// Why a JSON body was refused: a body that cannot be read as the payload is a client
+/// error (400), one that parses but breaks the validation rules is unprocessable (422).
+#[derive(Debug)]
+pub enum JsonRejection {
+    UnsupportedMediaType,
+    Malformed(String),
+    Invalid(ValidationErrors),
+}
+
+impl IntoResponse for JsonRejection {
+    fn into_response(self) -> Response {
+        match self {
+            JsonRejection::UnsupportedMediaType => (
+                StatusCode::UNSUPPORTED_MEDIA_TYPE,
+                "Expected request with `Content-Type: application/json`",
+            )
+                .into_response(),
+            JsonRejection::Malformed(rejection) => (
+                StatusCode::BAD_REQUEST,
+                format!("Json parse error: [{}]", rejection),
+            )
+                .into_response(),
+            JsonRejection::Invalid(errors) => (
+                StatusCode::UNPROCESSABLE_ENTITY,
+                format!("Validation error: [{}]", errors).replace('\n', ", "),
+            )
+                .into_response(),
+        }
+    }
+}
+
+/// JSON body that passed the content type and parse checks of [`ValidatedJson`] but is
+/// left for the handler to validate.
+#[derive(Debug)]
+pub struct UnvalidatedJson<T>(T);
+
+#[async_trait]
+impl<T, S, B> FromRequest<S, B> for UnvalidatedJson<T>
+where
+    T: DeserializeOwned,
+    B: http_body::Body + Send + 'static,
+    B::Data: Send,
+    B::Error: Into<BoxError>,
+    S: Send + Sync,
+{
+    type Rejection = JsonRejection;
+
+    async fn from_request(req: Request<B>, state: &S) -> Result<Self, Self::Rejection> {
+        if !has_json_content_type(req.headers()) {
+            return Err(JsonRejection::UnsupportedMediaType);
+        }
         let Json(value) = Json::<T>::from_request(req, state)
             .await
-            .map_err(|rejection| {
-                let message = format!("Json parse error: [{}]", rejection);
-                (StatusCode::BAD_REQUEST, message)
+            .map_err(|rejection| JsonRejection::Malformed(rejection.to_string()))?;
+        Ok(UnvalidatedJson(value))
+    }
+}
+
+pub static OWNER_ID_HEADER: HeaderName = HeaderName::from_static("x-owner-id");
+
+/// Owner of the data a request works on. It is the `sub` of the bearer token when
+/// `JWT_SECRET` is configured, and the `x-owner-id` header otherwise.
+#[derive(Debug, Clone, Copy)]
+pub struct Owner(pub OwnerId);
+
+#[async_trait]
+impl<S> FromRequestParts<S> for Owner
+where
+    S: Send + Sync,
+{
+    type Rejection = Response;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        if parts.extensions.get::<JwtKeys>().is_some() {
+            let claims = Claims::from_request_parts(parts, state)
+                .await
+                .map_err(IntoResponse::into_response)?;
+            return Ok(Owner(claims.sub));
+        }
+        let owner = parts
+            .headers
+            .get(&OWNER_ID_HEADER)
+            .ok_or_else(|| {
+                let message = format!("Missing `{}` header", OWNER_ID_HEADER);
+                (StatusCode::UNAUTHORIZED, message).into_response()
+            })?
+            .to_str()
+            .ok()
+            .and_then(|value| value.parse().ok())
+            .ok_or_else(|| {
+                let message = format!("Invalid `{}` header", OWNER_ID_HEADER);
+                (StatusCode::UNAUTHORIZED, message).into_response()
             })?;
-        value.validate().map_err(|rejection| {
-            let message = format!("Validation error: [{}]", rejection).replace('\n', ", ");
-            (StatusCode::BAD_REQUEST, message)
-        })?;
-        Ok(ValidatedJson(value))
+        Ok(Owner(owner))
+    }
+}
+
+/// Repository from the `Extension` layer, restricted to the data of the requesting [`Owner`]
+/// and abandoning its queries at the [`Deadline`] of the request.
+#[derive(Debug)]
+pub struct Scoped<T>(pub T);
+
+#[async_trait]
+impl<T, S> FromRequestParts<S> for Scoped<T>
+where
+    T: OwnerScoped,
+    S: Send + Sync,
+{
+    type Rejection = Response;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let Owner(owner) = Owner::from_request_parts(parts, state).await?;
+        let Extension(repository) =
+            Extension::<std::sync::Arc<T>>::from_request_parts(parts, state)
+                .await
+                .map_err(IntoResponse::into_response)?;
+        let repository = repository.scoped(owner);
+        Ok(Scoped(match parts.extensions.get::<Deadline>() {
+            Some(Deadline(deadline)) => repository.with_deadline(*deadline),
+            None => repository,
+        }))
+    }
+}
+
+/// `Path` of the ids of a route, rejected with 400 when one of them is not [`Positive`] so
+/// that no query is spent on an id that cannot exist.
+#[derive(Debug)]
+pub struct PositiveId<T>(pub T);
+
+#[async_trait]
+impl<T, S> FromRequestParts<S> for PositiveId<T>
+where
+    T: DeserializeOwned + Positive + Send,
+    S: Send + Sync,
+{
+    type Rejection = Response;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let Path(id) = Path::<T>::from_request_parts(parts, state)
+            .await
+            .map_err(IntoResponse::into_response)?;
+        if !id.is_positive() {
+            let message = "Ids must be positive".to_string();
+            return Err((StatusCode::BAD_REQUEST, message).into_response());
+        }
+        Ok(PositiveId(id))
+    }
+}
+
+fn has_json_content_type(headers: &HeaderMap) -> bool {
+    let Some(mime) = headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<mime::Mime>().ok())
+    else {
+        return false;
+    };
+    mime.type_() == mime::APPLICATION
+        && (mime.subtype() == mime::JSON || mime.suffix() == Some(mime::JSON))
+}
+
+fn http_date(date: DateTime<Utc>) -> String {
+    date.format("%a, %d %b %Y %H:%M:%S GMT").to_string()
+}
+
+/// `If-Modified-Since` only has second precision, so sub-second changes are compared
+/// against the truncated timestamp.
+fn not_modified_since(headers: &HeaderMap, modified_at: DateTime<Utc>) -> bool {
+    headers
+        .get(header::IF_MODIFIED_SINCE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| DateTime::parse_from_rfc2822(value).ok())
+        .map(|since| modified_at.timestamp() <= since.timestamp())
+        .unwrap_or(false)
+}
+
+/// Envelope of the lists of `GET /todos` and `GET /labels`: `items` are the `limit` items
+/// from `offset` of the `total` ones, all of them when the list is not paginated.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct Paginated<T> {
+    pub items: Vec<T>,
+    pub total: i64,
+    pub limit: i64,
+    pub offset: i64,
+}
+
+impl<T> Paginated<T> {
+    pub fn new(items: Vec<T>, total: i64, limit: i64, offset: i64) -> Self {
+        Self {
+            items,
+            total,
+            limit,
+            offset,
+        }
+    }
+
+    /// A whole list in one page.
+    pub fn all(items: Vec<T>) -> Self {
+        let total = items.len() as i64;
+        Self::new(items, total, total, 0)
+    }
+
+    /// The `limit` items from `offset` of a whole list.
+    pub fn slice(items: Vec<T>, limit: i64, offset: i64) -> Self {
+        let total = items.len() as i64;
+        let items = items
+            .into_iter()
+            .skip(offset as usize)
+            .take(limit as usize)
+            .collect();
+        Self::new(items, total, limit, offset)
+    }
+}
+
+/// `?envelope=false` answers with the bare array of `items`, as lists were before
+/// [`Paginated`].
+#[derive(Debug, Deserialize)]
+pub struct EnvelopeQuery {
+    #[serde(default = "envelope_by_default")]
+    envelope: bool,
+}
+
+fn envelope_by_default() -> bool {
+    true
+}
+
+impl EnvelopeQuery {
+    fn wrap<T: Serialize>(&self, page: Paginated<T>) -> Response {
+        if self.envelope {
+            Json(page).into_response()
+        } else {
+            Json(page.items).into_response()
+        }
+    }
+}
+
+/// Builds a GitHub-style `Link` header pointing at other pages of the current request.
+fn pagination_link(uri: &Uri, page: i64, total_pages: i64) -> String {
+    let params: Vec<(String, String)> =
+        serde_urlencoded::from_str(uri.query().unwrap_or_default()).unwrap_or_default();
+    let page_uri = |page: i64| -> String {
+        let mut params: Vec<(String, String)> = params
+            .iter()
+            .filter(|(key, _)| key != "page")
+            .cloned()
+            .collect();
+        params.push(("page".to_string(), page.to_string()));
+        let query = serde_urlencoded::to_string(params).expect("params are always encodable");
+        format!("{}?{}", uri.path(), query)
+    };
+
+    let mut links = vec![];
+    if page < total_pages {
+        links.push(format!("<{}>; rel=\"next\"", page_uri(page + 1)));
+    }
+    if page > 1 {
+        links.push(format!(
+            "<{}>; rel=\"prev\"",
+            page_uri((page - 1).min(total_pages))
+        ));
+    }
+    links.push(format!("<{}>; rel=\"first\"", page_uri(1)));
+    links.push(format!("<{}>; rel=\"last\"", page_uri(total_pages)));
+    links.join(", ")
+}
+
+/// Status a failed repository call answers with. An unexpected failure carries the
+/// operation the repository named through `anyhow::Context` as its body, never the SQL
+/// error below it.
+#[derive(Debug)]
+pub struct RepositoryFailure {
+    status: StatusCode,
+    operation: Option<&'static str>,
+}
+
+impl From<StatusCode> for RepositoryFailure {
+    fn from(status: StatusCode) -> Self {
+        RepositoryFailure {
+            status,
+            operation: None,
+        }
+    }
+}
+
+impl IntoResponse for RepositoryFailure {
+    fn into_response(self) -> Response {
+        match self.operation {
+            Some(operation) => (self.status, format!("fail {}", operation)).into_response(),
+            None => self.status.into_response(),
+        }
+    }
+}
+
+fn repository_failure(e: anyhow::Error) -> RepositoryFailure {
+    let classified = e.downcast_ref::<sqlx::Error>().map(RepositoryError::from);
+    match classified
+        .as_ref()
+        .or_else(|| e.downcast_ref::<RepositoryError>())
+    {
+        Some(RepositoryError::NotFound(_) | RepositoryError::MissingReference(_)) => {
+            StatusCode::NOT_FOUND.into()
+        }
+        Some(
+            RepositoryError::Duplicate(_)
+            | RepositoryError::DuplicateNames(_)
+            | RepositoryError::InUse(..)
+            | RepositoryError::Conflict(_),
+        ) => StatusCode::CONFLICT.into(),
+        Some(RepositoryError::TooManyLabels(_)) => StatusCode::UNPROCESSABLE_ENTITY.into(),
+        Some(RepositoryError::Stale(_)) => StatusCode::PRECONDITION_FAILED.into(),
+        Some(RepositoryError::Unavailable(_)) => {
+            tracing::warn!("repository unavailable: {:?}", e);
+            StatusCode::SERVICE_UNAVAILABLE.into()
+        }
+        Some(RepositoryError::DeadlineExceeded) => {
+            tracing::warn!("repository call abandoned: {:?}", e);
+            StatusCode::GATEWAY_TIMEOUT.into()
+        }
+        _ => {
+            tracing::error!("unexpected repository error: {:?}", e);
+            RepositoryFailure {
+                status: StatusCode::INTERNAL_SERVER_ERROR,
+                operation: e.downcast_ref::<&'static str>().copied(),
+            }
+        }
     }
 }
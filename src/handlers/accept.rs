@@ -0,0 +1,89 @@
+use axum::body::Body;
+use axum::http::{header, Request};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use hyper::StatusCode;
+
+/// Media types the responses come in, XML only with the `xml` feature.
+pub const SUPPORTED_TYPES: &[&str] = &[
+    "application/json",
+    #[cfg(feature = "xml")]
+    "application/xml",
+    #[cfg(feature = "xml")]
+    "text/xml",
+];
+
+/// Media ranges of an `Accept` value with their quality, lowercased and in the order listed.
+pub fn media_ranges(accept: &str) -> impl Iterator<Item = (String, f32)> + '_ {
+    accept.split(',').filter_map(|range| {
+        let mut params = range.split(';');
+        let essence = params.next().unwrap_or_default().trim();
+        if essence.is_empty() {
+            return None;
+        }
+        let quality = match params.find_map(|param| param.trim().strip_prefix("q=")) {
+            Some(q) => q.parse().unwrap_or(0.0),
+            None => 1.0,
+        };
+        Some((essence.to_ascii_lowercase(), quality))
+    })
+}
+
+fn matches(range: &str, media_type: &str) -> bool {
+    match range.strip_suffix("/*") {
+        Some("*") => true,
+        Some(type_) => media_type.split('/').next() == Some(type_),
+        None => range == media_type,
+    }
+}
+
+/// Whether one of the ranges of `accept` with a quality above 0 covers a type of
+/// [`SUPPORTED_TYPES`]. An empty value asks for nothing in particular, like no header.
+fn is_acceptable(accept: &str) -> bool {
+    let mut ranges = media_ranges(accept).peekable();
+    if ranges.peek().is_none() {
+        return true;
+    }
+    ranges.any(|(range, quality)| {
+        quality > 0.0
+            && SUPPORTED_TYPES
+                .iter()
+                .any(|supported| matches(&range, supported))
+    })
+}
+
+/// Answers 406 when `Accept` rules out every type of [`SUPPORTED_TYPES`], before the
+/// handler runs. Requests without one get JSON.
+pub async fn reject_unacceptable(req: Request<Body>, next: Next<Body>) -> Response {
+    let acceptable = match req.headers().get(header::ACCEPT) {
+        Some(accept) => accept.to_str().is_ok_and(is_acceptable),
+        None => true,
+    };
+    if !acceptable {
+        let message = format!(
+            "Not acceptable, responses are one of [{}]",
+            SUPPORTED_TYPES.join(", ")
+        );
+        return (StatusCode::NOT_ACCEPTABLE, message).into_response();
+    }
+    next.run(req).await
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn should_accept_supported_types() {
+        assert!(is_acceptable("application/json"));
+        assert!(is_acceptable("Application/JSON; charset=utf-8"));
+        assert!(is_acceptable("*/*"));
+        assert!(is_acceptable("application/*"));
+        assert!(is_acceptable("text/html, */*;q=0.1"));
+        assert!(is_acceptable(""));
+        assert!(!is_acceptable("text/html"));
+        assert!(!is_acceptable("image/*"));
+        assert!(!is_acceptable("application/json;q=0"));
+        assert_eq!(cfg!(feature = "xml"), is_acceptable("text/xml"));
+    }
+}
@@ -0,0 +1,75 @@
+use crate::handlers::{repository_failure, RepositoryFailure};
+use crate::repositories::health::HealthRepository;
+use axum::async_trait;
+use axum::extract::FromRequestParts;
+use axum::http::request::Parts;
+use axum::http::HeaderName;
+use axum::response::IntoResponse;
+use axum::{Extension, Json};
+use hyper::StatusCode;
+use std::sync::Arc;
+
+pub static API_KEY_HEADER: HeaderName = HeaderName::from_static("x-api-key");
+
+/// Key the `x-api-key` header must carry on `/admin` routes, which only exist with one.
+#[derive(Clone, PartialEq, Eq)]
+pub struct AdminKey(String);
+
+impl AdminKey {
+    pub fn new(key: impl Into<String>) -> Self {
+        Self(key.into())
+    }
+
+    /// Compares every byte whatever the first mismatch, not to leak the key through timing.
+    fn matches(&self, candidate: &[u8]) -> bool {
+        let key = self.0.as_bytes();
+        key.len() == candidate.len()
+            && key
+                .iter()
+                .zip(candidate)
+                .fold(0, |diff, (a, b)| diff | (a ^ b))
+                == 0
+    }
+}
+
+impl std::fmt::Debug for AdminKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("AdminKey(..)")
+    }
+}
+
+/// Proof that the request carries the admin key.
+#[derive(Debug, Clone, Copy)]
+pub struct Admin;
+
+#[async_trait]
+impl<S> FromRequestParts<S> for Admin
+where
+    S: Send + Sync,
+{
+    type Rejection = (StatusCode, &'static str);
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let Some(key) = parts.extensions.get::<AdminKey>() else {
+            tracing::error!("`Admin` extracted without an `AdminKey` extension");
+            return Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Admin key is not configured",
+            ));
+        };
+        match parts.headers.get(&API_KEY_HEADER) {
+            Some(value) if key.matches(value.as_bytes()) => Ok(Admin),
+            Some(_) => Err((StatusCode::UNAUTHORIZED, "Invalid API key")),
+            None => Err((StatusCode::UNAUTHORIZED, "Missing API key")),
+        }
+    }
+}
+
+/// Lists the `todo_labels` rows whose todo or label is gone.
+pub async fn orphans<T: HealthRepository>(
+    _admin: Admin,
+    Extension(repo): Extension<Arc<T>>,
+) -> Result<impl IntoResponse, RepositoryFailure> {
+    let orphans = repo.orphans().await.map_err(repository_failure)?;
+    Ok((StatusCode::OK, Json(orphans)))
+}
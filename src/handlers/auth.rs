@@ -0,0 +1,150 @@
+use crate::repositories::OwnerId;
+use axum::async_trait;
+use axum::extract::FromRequestParts;
+use axum::http::header;
+use axum::http::request::Parts;
+use axum::response::{IntoResponse, Response};
+use hyper::StatusCode;
+use jsonwebtoken::errors::ErrorKind;
+use jsonwebtoken::{Algorithm, DecodingKey, EncodingKey, Header, Validation};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::sync::Arc;
+
+/// HS256 keys derived from `JWT_SECRET`, shared with the extractors as an extension.
+#[derive(Clone)]
+pub struct JwtKeys(Arc<(EncodingKey, DecodingKey)>);
+
+impl fmt::Debug for JwtKeys {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("JwtKeys(..)")
+    }
+}
+
+impl JwtKeys {
+    pub fn new(secret: &[u8]) -> Self {
+        Self(Arc::new((
+            EncodingKey::from_secret(secret),
+            DecodingKey::from_secret(secret),
+        )))
+    }
+
+    pub fn encode(&self, claims: &Claims) -> anyhow::Result<String> {
+        let token = jsonwebtoken::encode(&Header::new(Algorithm::HS256), claims, &self.0 .0)?;
+        Ok(token)
+    }
+
+    pub fn decode(&self, token: &str) -> Result<Claims, AuthError> {
+        let validation = Validation::new(Algorithm::HS256);
+        jsonwebtoken::decode::<Claims>(token, &self.0 .1, &validation)
+            .map(|data| data.claims)
+            .map_err(|e| match e.kind() {
+                ErrorKind::ExpiredSignature => AuthError::Expired,
+                _ => AuthError::InvalidToken,
+            })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Claims {
+    /// Owner the token was issued to.
+    pub sub: OwnerId,
+    /// Expiry as seconds since the epoch.
+    pub exp: i64,
+}
+
+impl Claims {
+    pub fn new(sub: OwnerId, expires_in: chrono::Duration) -> Self {
+        Self {
+            sub,
+            exp: (chrono::Utc::now() + expires_in).timestamp(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuthError {
+    MissingToken,
+    InvalidToken,
+    Expired,
+    NotConfigured,
+}
+
+impl IntoResponse for AuthError {
+    fn into_response(self) -> Response {
+        let message = match self {
+            AuthError::MissingToken => "Missing bearer token",
+            AuthError::InvalidToken => "Invalid bearer token",
+            AuthError::Expired => "Expired bearer token",
+            AuthError::NotConfigured => {
+                tracing::error!("claims extracted but JWT_SECRET is not configured");
+                return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+            }
+        };
+        (
+            StatusCode::UNAUTHORIZED,
+            [(header::WWW_AUTHENTICATE, "Bearer")],
+            message,
+        )
+            .into_response()
+    }
+}
+
+#[async_trait]
+impl<S> FromRequestParts<S> for Claims
+where
+    S: Send + Sync,
+{
+    type Rejection = AuthError;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let keys = parts
+            .extensions
+            .get::<JwtKeys>()
+            .ok_or(AuthError::NotConfigured)?;
+        let token = parts
+            .headers
+            .get(header::AUTHORIZATION)
+            .ok_or(AuthError::MissingToken)?
+            .to_str()
+            .ok()
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .ok_or(AuthError::InvalidToken)?;
+        keys.decode(token.trim())
+    }
+}
+
+#[cfg(feature = "dev-token")]
+#[derive(Debug, Deserialize)]
+pub struct TokenRequest {
+    sub: OwnerId,
+    #[serde(default = "default_expires_in_secs")]
+    expires_in_secs: i64,
+}
+
+#[cfg(feature = "dev-token")]
+fn default_expires_in_secs() -> i64 {
+    3600
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TokenResponse {
+    pub token: String,
+}
+
+/// Mints a token for any owner, so it only exists behind the `dev-token` feature.
+#[cfg(feature = "dev-token")]
+pub async fn issue_token(
+    axum::Extension(keys): axum::Extension<JwtKeys>,
+    axum::Json(payload): axum::Json<TokenRequest>,
+) -> Result<impl IntoResponse, StatusCode> {
+    let claims = Claims::new(
+        payload.sub,
+        chrono::Duration::seconds(payload.expires_in_secs),
+    );
+    let token = keys.encode(&claims).map_err(|e| {
+        tracing::error!("fail encode token: {:?}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+    Ok((StatusCode::OK, axum::Json(TokenResponse { token })))
+}
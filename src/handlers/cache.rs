@@ -0,0 +1,69 @@
+use axum::body::Body;
+use axum::http::{header, HeaderName, HeaderValue, Method, Request};
+use axum::middleware::Next;
+use axum::response::Response;
+use hyper::StatusCode;
+use std::time::Duration;
+
+pub const DEFAULT_CACHE_MAX_AGE_SECS: u64 = 5;
+
+/// How long clients may reuse a successful `GET`, read by [`set_cache_control`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CacheMaxAge(pub Duration);
+
+impl Default for CacheMaxAge {
+    fn default() -> Self {
+        Self(Duration::from_secs(DEFAULT_CACHE_MAX_AGE_SECS))
+    }
+}
+
+/// Request headers a response may differ on: the CORS headers echo `Origin`, `Accept`
+/// picks the format and `Accept-Encoding` the compression a proxy may apply.
+const VARY_ON: [HeaderName; 3] = [header::ORIGIN, header::ACCEPT, header::ACCEPT_ENCODING];
+
+/// Adds the headers of [`VARY_ON`] missing from `Vary` so that caches keep one entry per
+/// origin and format. Must wrap the `CorsLayer`, which replaces the `Vary` set below it.
+pub async fn set_vary(req: Request<Body>, next: Next<Body>) -> Response {
+    let mut res = next.run(req).await;
+    let present: Vec<String> = res
+        .headers()
+        .get_all(header::VARY)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(|name| name.trim().to_ascii_lowercase())
+        .collect();
+    if present.iter().any(|name| name == "*") {
+        return res;
+    }
+    for name in VARY_ON {
+        if !present.iter().any(|present| present == name.as_str()) {
+            res.headers_mut()
+                .append(header::VARY, HeaderValue::from(name));
+        }
+    }
+    res
+}
+
+/// Sets `Cache-Control` unless the handler did: `max-age` on successful and not modified
+/// `GET`s and `no-store` on everything else, health checks included since they must
+/// always reach the server.
+pub async fn set_cache_control(req: Request<Body>, next: Next<Body>) -> Response {
+    let max_age = req.extensions().get::<CacheMaxAge>().copied();
+    let cacheable = *req.method() == Method::GET && !req.uri().path().starts_with("/health");
+    let mut res = next.run(req).await;
+    let value = match max_age {
+        Some(CacheMaxAge(max_age))
+            if cacheable
+                && (res.status().is_success() || res.status() == StatusCode::NOT_MODIFIED) =>
+        {
+            HeaderValue::from_str(&format!("max-age={}", max_age.as_secs()))
+                .expect("max-age is a valid header value")
+        }
+        _ => HeaderValue::from_static("no-store"),
+    };
+    res.headers_mut()
+        .entry(header::CACHE_CONTROL)
+        .or_insert(value);
+    res
+}
@@ -0,0 +1,67 @@
+use axum::body::Body;
+use axum::http::Request;
+use axum::middleware::Next;
+use axum::response::Response;
+use std::time::Duration;
+use tokio::time::Instant;
+
+/// Time the timeout layer gives a request, read by [`set_deadline`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RequestTimeout(pub Duration);
+
+/// Instant a request must be answered by, after which the timeout layer answers 504 and
+/// the repositories of [`crate::handlers::Scoped`] abandon their queries.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Deadline(pub Instant);
+
+/// Sets the `Deadline` of the request from its `RequestTimeout`. Must run right inside the
+/// timeout layer so that both count from the same instant, time in the queue included.
+pub async fn set_deadline(mut req: Request<Body>, next: Next<Body>) -> Response {
+    if let Some(RequestTimeout(timeout)) = req.extensions().get::<RequestTimeout>().copied() {
+        req.extensions_mut()
+            .insert(Deadline(Instant::now() + timeout));
+    }
+    next.run(req).await
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use axum::middleware::from_fn;
+    use axum::routing::get;
+    use axum::{Extension, Router};
+    use hyper::StatusCode;
+    use tower::ServiceExt;
+
+    async fn remaining(deadline: Option<Extension<Deadline>>) -> String {
+        match deadline {
+            Some(Extension(Deadline(deadline))) => {
+                let remaining = deadline.saturating_duration_since(Instant::now());
+                remaining.as_secs().to_string()
+            }
+            None => "none".to_string(),
+        }
+    }
+
+    async fn body(app: Router) -> String {
+        let req = Request::builder().uri("/").body(Body::empty()).unwrap();
+        let res = app.oneshot(req).await.unwrap();
+        assert_eq!(StatusCode::OK, res.status());
+        let bytes = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        String::from_utf8(bytes.to_vec()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn should_set_deadline_from_timeout() {
+        let app = Router::new()
+            .route("/", get(remaining))
+            .layer(from_fn(set_deadline))
+            .layer(Extension(RequestTimeout(Duration::from_secs(30))));
+        assert_eq!("29", body(app).await);
+
+        let app = Router::new()
+            .route("/", get(remaining))
+            .layer(from_fn(set_deadline));
+        assert_eq!("none", body(app).await);
+    }
+}
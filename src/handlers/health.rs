@@ -0,0 +1,63 @@
+use crate::repositories::health::HealthRepository;
+use axum::response::IntoResponse;
+use axum::{Extension, Json};
+use hyper::StatusCode;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use std::time::Instant;
+
+pub async fn ready<T: HealthRepository>(
+    Extension(repo): Extension<Arc<T>>,
+) -> Result<impl IntoResponse, StatusCode> {
+    let status = repo.check().await.map_err(|e| {
+        tracing::warn!("readiness probe failed: {:?}", e);
+        StatusCode::SERVICE_UNAVAILABLE
+    })?;
+    Ok((StatusCode::OK, Json(status)))
+}
+
+/// When the app was built, to report its uptime.
+#[derive(Debug, Clone, Copy)]
+pub struct StartedAt(pub Instant);
+
+/// Routes listed by `GET /`, leaving out the ones behind optional features.
+pub const ENDPOINTS: [&str; 16] = [
+    "/health/ready",
+    "/todos",
+    "/todos/by-label",
+    "/todos/search",
+    "/todos/validate",
+    "/todos/complete-all",
+    "/todos/uncomplete-all",
+    "/todos/reorder",
+    "/todos/:id",
+    "/todos/:id/history",
+    "/todos/:id/archive",
+    "/todos/:id/unarchive",
+    "/labels",
+    "/labels/bulk",
+    "/labels/:id",
+    "/labels/:id/merge/:other_id",
+];
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct ServiceInfo {
+    pub name: String,
+    pub version: String,
+    pub uptime_seconds: u64,
+    pub endpoints: Vec<String>,
+}
+
+pub async fn service_info(
+    Extension(StartedAt(started_at)): Extension<StartedAt>,
+) -> Json<ServiceInfo> {
+    Json(ServiceInfo {
+        name: env!("CARGO_PKG_NAME").to_string(),
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        uptime_seconds: started_at.elapsed().as_secs(),
+        endpoints: ENDPOINTS
+            .iter()
+            .map(|endpoint| endpoint.to_string())
+            .collect(),
+    })
+}
@@ -0,0 +1,114 @@
+use crate::repositories::label::LabelId;
+use crate::repositories::todo::{CreateTodo, UpdateTodo};
+use axum::async_trait;
+use axum::response::{IntoResponse, Response};
+use hyper::StatusCode;
+use std::fmt::Debug;
+
+/// Runs before the todos created or updated through `/todos` or GraphQL are persisted, and
+/// may rewrite the validated payload or turn the request down; `PUT` counts as an update
+/// and a duplicate as a create. What a hook returns is not validated again.
+#[async_trait]
+pub trait TodoHook: Debug + Send + Sync + 'static {
+    async fn before_create(&self, payload: CreateTodo) -> Result<CreateTodo, HookRejection> {
+        Ok(payload)
+    }
+
+    async fn before_update(&self, payload: UpdateTodo) -> Result<UpdateTodo, HookRejection> {
+        Ok(payload)
+    }
+}
+
+/// Why a hook turned a payload down, answered as a 422 with the message as its body.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HookRejection(pub String);
+
+impl IntoResponse for HookRejection {
+    fn into_response(self) -> Response {
+        (StatusCode::UNPROCESSABLE_ENTITY, self.0).into_response()
+    }
+}
+
+/// Hook of apps that set none, persisting payloads as they come.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NoopHook;
+
+impl TodoHook for NoopHook {}
+
+/// Adds `label` to todos whose text contains `keyword`, ignoring case. Updates only get it
+/// when they replace the labels, as the ones kept otherwise are not known here.
+#[derive(Debug, Clone)]
+pub struct KeywordLabelHook {
+    keyword: String,
+    label: LabelId,
+}
+
+impl KeywordLabelHook {
+    pub fn new(keyword: impl Into<String>, label: LabelId) -> Self {
+        Self {
+            keyword: keyword.into().to_lowercase(),
+            label,
+        }
+    }
+
+    fn with_label(&self, text: &str, labels: &[LabelId]) -> Option<Vec<LabelId>> {
+        if !text.to_lowercase().contains(&self.keyword) || labels.contains(&self.label) {
+            return None;
+        }
+        let mut labels = labels.to_vec();
+        labels.push(self.label);
+        Some(labels)
+    }
+}
+
+#[async_trait]
+impl TodoHook for KeywordLabelHook {
+    async fn before_create(&self, payload: CreateTodo) -> Result<CreateTodo, HookRejection> {
+        Ok(match self.with_label(payload.text(), payload.labels()) {
+            Some(labels) => payload.with_labels(labels),
+            None => payload,
+        })
+    }
+
+    async fn before_update(&self, payload: UpdateTodo) -> Result<UpdateTodo, HookRejection> {
+        let labels = match (payload.text(), payload.labels()) {
+            (Some(text), Some(labels)) => self.with_label(text, labels),
+            _ => None,
+        };
+        Ok(match labels {
+            Some(labels) => payload.with_labels(Some(labels)),
+            None => payload,
+        })
+    }
+}
+
+#[cfg(test)]
+#[cfg(not(feature = "uuid"))]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn should_add_label_on_keyword() {
+        let hook = KeywordLabelHook::new("urgent", LabelId(9));
+
+        let payload = CreateTodo::new("Fix it, URGENT".to_string(), vec![LabelId(1)]);
+        let payload = hook.before_create(payload).await.unwrap();
+        assert_eq!(&[LabelId(1), LabelId(9)], payload.labels());
+
+        let payload = CreateTodo::new("urgent".to_string(), vec![LabelId(9)]);
+        let payload = hook.before_create(payload).await.unwrap();
+        assert_eq!(&[LabelId(9)], payload.labels());
+
+        let payload = CreateTodo::new("whenever".to_string(), vec![]);
+        let payload = hook.before_create(payload).await.unwrap();
+        assert!(payload.labels().is_empty());
+
+        let payload = UpdateTodo::new(Some("urgent".to_string()), None, Some(vec![]));
+        let payload = hook.before_update(payload).await.unwrap();
+        assert_eq!(Some(&[LabelId(9)][..]), payload.labels());
+
+        let payload = UpdateTodo::new(Some("urgent".to_string()), None, None);
+        let payload = hook.before_update(payload).await.unwrap();
+        assert_eq!(None, payload.labels());
+    }
+}
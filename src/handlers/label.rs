@@ -1,35 +1,205 @@
-use crate::handlers::ValidatedJson;
-use crate::repositories::label::{CreateLabel, LabelRepository};
-use axum::extract::Path;
-use axum::response::IntoResponse;
-use axum::{Extension, Json};
+use crate::handlers::tx::Tx;
+use crate::handlers::{
+    repository_failure, EnvelopeQuery, Owner, Paginated, PositiveId, RepositoryFailure, Scoped,
+    ValidatedJson,
+};
+use crate::repositories::label::{
+    merge_labels, CreateLabel, CreateLabels, LabelId, LabelRepository, LabelSort, UpdateLabel,
+    UpdateLabels,
+};
+use crate::repositories::todo::{AttachLabel, TodoRepository, DEFAULT_LIMIT, MAX_LIMIT};
+use crate::repositories::RepositoryError;
+use axum::extract::Query;
+use axum::response::{IntoResponse, Response};
+use axum::Json;
 use hyper::StatusCode;
-use std::sync::Arc;
+use serde::{Deserialize, Serialize};
+use validator::Validate;
 
+/// Answers 201 with the new label, or 409 when the owner already has a label of that name;
+/// `POST /labels/bulk` is the endpoint that hands back existing labels instead.
 pub async fn create_label<T: LabelRepository>(
-    Extension(repo): Extension<Arc<T>>,
+    Scoped(repo): Scoped<T>,
     ValidatedJson(payload): ValidatedJson<CreateLabel>,
-) -> Result<impl IntoResponse, StatusCode> {
-    let label = repo
-        .create(payload)
-        .await
-        .or(Err(StatusCode::INTERNAL_SERVER_ERROR))?;
+) -> Result<impl IntoResponse, RepositoryFailure> {
+    let label = repo.create(payload).await.map_err(repository_failure)?;
     Ok((StatusCode::CREATED, Json(label)))
 }
 
-pub async fn all_label<T: LabelRepository>(
-    Extension(repo): Extension<Arc<T>>,
-) -> Result<impl IntoResponse, StatusCode> {
-    let labels = repo.all().await.unwrap();
+pub async fn create_labels<T: LabelRepository>(
+    Scoped(repo): Scoped<T>,
+    ValidatedJson(payload): ValidatedJson<CreateLabels>,
+) -> Result<impl IntoResponse, RepositoryFailure> {
+    let labels = repo
+        .create_many(payload.into_inner())
+        .await
+        .map_err(repository_failure)?;
     Ok((StatusCode::OK, Json(labels)))
 }
 
+/// Body of the 409 returned when a bulk rename would give several labels the same name,
+/// listing the entries of the batch that asked for those names.
+#[derive(Debug, Serialize, Deserialize, Clone, Eq, PartialEq)]
+pub struct LabelConflicts {
+    pub conflicts: Vec<UpdateLabel>,
+}
+
+pub async fn update_labels<T: LabelRepository>(
+    Scoped(repo): Scoped<T>,
+    ValidatedJson(payload): ValidatedJson<UpdateLabels>,
+) -> Response {
+    let payloads = payload.into_inner();
+    let e = match repo.update_many(payloads.clone()).await {
+        Ok(labels) => return (StatusCode::OK, Json(labels)).into_response(),
+        Err(e) => e,
+    };
+    match e.downcast_ref::<RepositoryError>() {
+        Some(RepositoryError::DuplicateNames(names)) => {
+            let conflicts = payloads
+                .into_iter()
+                .filter(|payload| names.iter().any(|name| name == payload.name()))
+                .collect();
+            (StatusCode::CONFLICT, Json(LabelConflicts { conflicts })).into_response()
+        }
+        _ => repository_failure(e).into_response(),
+    }
+}
+
+/// `?with_counts=true` lists labels with their usage counts, always by id. `limit` and
+/// `offset` page the list like they page todos.
+#[derive(Debug, Default, Deserialize, Validate)]
+pub struct LabelListQuery {
+    #[serde(default)]
+    with_counts: bool,
+    #[serde(default)]
+    sort: LabelSort,
+    #[validate(range(
+        min = 1,
+        max = "MAX_LIMIT",
+        code = "page_size_range",
+        message = "Must be between 1 and 100"
+    ))]
+    limit: Option<i64>,
+    #[validate(range(min = 0, code = "negative", message = "Can not be negative"))]
+    offset: Option<i64>,
+}
+
+impl LabelListQuery {
+    /// The `limit` and `offset` asked for, `None` for the whole list.
+    fn paging(&self) -> Option<(i64, i64)> {
+        match (self.limit, self.offset) {
+            (None, None) => None,
+            (limit, offset) => Some((limit.unwrap_or(DEFAULT_LIMIT), offset.unwrap_or_default())),
+        }
+    }
+}
+
+pub async fn all_label<T: LabelRepository>(
+    Scoped(repo): Scoped<T>,
+    Query(query): Query<LabelListQuery>,
+    Query(envelope): Query<EnvelopeQuery>,
+) -> Result<Response, RepositoryFailure> {
+    if let Err(errors) = query.validate() {
+        let message = format!("Invalid pagination: [{}]", errors).replace('\n', ", ");
+        return Ok((StatusCode::BAD_REQUEST, message).into_response());
+    }
+    if query.with_counts {
+        let labels = match query.paging() {
+            None => repo.all_with_counts().await.map(Paginated::all),
+            Some((limit, offset)) => repo
+                .page_with_counts(limit, offset)
+                .await
+                .map(|page| Paginated::new(page.items, page.total, limit, offset)),
+        }
+        .map_err(repository_failure)?;
+        return Ok(envelope.wrap(labels));
+    }
+    let labels = match (query.paging(), query.sort) {
+        (None, LabelSort::Id) => repo.all().await.map(Paginated::all),
+        (None, LabelSort::Name) => repo.all_by_name().await.map(Paginated::all),
+        (Some((limit, offset)), sort) => repo
+            .page(sort, limit, offset)
+            .await
+            .map(|page| Paginated::new(page.items, page.total, limit, offset)),
+    }
+    .map_err(repository_failure)?;
+    Ok(envelope.wrap(labels))
+}
+
+pub async fn find_label<T: LabelRepository>(
+    Scoped(repo): Scoped<T>,
+    PositiveId(id): PositiveId<LabelId>,
+) -> Result<impl IntoResponse, RepositoryFailure> {
+    let label = repo.find(id).await.map_err(repository_failure)?;
+    Ok((StatusCode::OK, Json(label)))
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct DeleteLabelQuery {
+    #[serde(default)]
+    force: bool,
+}
+
+/// Body of the 409 returned when a label is still attached to todos.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Eq, PartialEq)]
+pub struct LabelInUse {
+    pub affected_todos: i64,
+}
+
 pub async fn delete_label<T: LabelRepository>(
-    Path(id): Path<i32>,
-    Extension(repo): Extension<Arc<T>>,
-) -> StatusCode {
-    repo.delete(id)
+    PositiveId(id): PositiveId<LabelId>,
+    Query(query): Query<DeleteLabelQuery>,
+    Scoped(repo): Scoped<T>,
+) -> Response {
+    let Err(e) = repo.delete(id, query.force).await else {
+        return StatusCode::NO_CONTENT.into_response();
+    };
+    match e.downcast_ref::<RepositoryError>() {
+        Some(RepositoryError::InUse(_, affected_todos)) => (
+            StatusCode::CONFLICT,
+            Json(LabelInUse {
+                affected_todos: *affected_todos,
+            }),
+        )
+            .into_response(),
+        _ => repository_failure(e).into_response(),
+    }
+}
+
+pub async fn merge_label<T: LabelRepository>(
+    PositiveId((id, other_id)): PositiveId<(LabelId, LabelId)>,
+    Scoped(repo): Scoped<T>,
+) -> Result<impl IntoResponse, RepositoryFailure> {
+    if id == other_id {
+        return Err(StatusCode::BAD_REQUEST.into());
+    }
+    let label = repo.merge(id, other_id).await.map_err(repository_failure)?;
+    Ok((StatusCode::OK, Json(label)))
+}
+
+pub async fn attach_label<T: TodoRepository>(
+    Scoped(repo): Scoped<T>,
+    PositiveId(id): PositiveId<LabelId>,
+    ValidatedJson(payload): ValidatedJson<AttachLabel>,
+) -> Result<impl IntoResponse, RepositoryFailure> {
+    let attached = repo
+        .attach_label(id, payload.todo_ids)
+        .await
+        .map_err(repository_failure)?;
+    Ok((StatusCode::OK, Json(attached)))
+}
+
+/// [`merge_label`] on the request transaction, served when the app is given a Postgres pool.
+pub async fn merge_label_in_tx(
+    PositiveId((id, other_id)): PositiveId<(LabelId, LabelId)>,
+    Owner(owner): Owner,
+    mut tx: Tx,
+) -> Result<impl IntoResponse, RepositoryFailure> {
+    if id == other_id {
+        return Err(StatusCode::BAD_REQUEST.into());
+    }
+    let label = merge_labels(&mut tx, owner, id, other_id)
         .await
-        .map(|_| StatusCode::NO_CONTENT)
-        .unwrap_or(StatusCode::INTERNAL_SERVER_ERROR)
+        .map_err(repository_failure)?;
+    Ok((StatusCode::OK, Json(label)))
 }
@@ -0,0 +1,118 @@
+use axum::body::Body;
+use axum::http::Request;
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use hyper::StatusCode;
+use std::sync::Arc;
+use tokio::sync::Semaphore;
+
+pub const DEFAULT_MAX_CONCURRENT_REQUESTS: usize = 64;
+pub const DEFAULT_MAX_QUEUED_REQUESTS: usize = 256;
+
+/// Slots of the requests handled at once, shared by every clone so that the limit holds
+/// across all routes. Requests beyond them wait in a queue of bounded length.
+#[derive(Debug, Clone)]
+pub struct ConcurrencyLimit {
+    running: Arc<Semaphore>,
+    admitted: Arc<Semaphore>,
+}
+
+impl ConcurrencyLimit {
+    pub fn new(max_running: usize, max_queued: usize) -> Self {
+        Self {
+            running: Arc::new(Semaphore::new(max_running)),
+            admitted: Arc::new(Semaphore::new(max_running + max_queued)),
+        }
+    }
+}
+
+impl Default for ConcurrencyLimit {
+    fn default() -> Self {
+        Self::new(DEFAULT_MAX_CONCURRENT_REQUESTS, DEFAULT_MAX_QUEUED_REQUESTS)
+    }
+}
+
+/// Runs the request once a slot of the `ConcurrencyLimit` extension is free, and sheds it
+/// with 503 when the queue waiting for one is full too.
+pub async fn limit_concurrency(req: Request<Body>, next: Next<Body>) -> Response {
+    let Some(limit) = req.extensions().get::<ConcurrencyLimit>().cloned() else {
+        return next.run(req).await;
+    };
+    let Ok(_admitted) = limit.admitted.try_acquire() else {
+        tracing::warn!("request queue is full, shed the request");
+        return StatusCode::SERVICE_UNAVAILABLE.into_response();
+    };
+    let _running = limit
+        .running
+        .acquire()
+        .await
+        .expect("concurrency limit semaphore is never closed");
+    next.run(req).await
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use axum::middleware::from_fn;
+    use axum::routing::get;
+    use axum::{Extension, Router};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+    use tower::ServiceExt;
+
+    /// Requests in the stub handler now and the most there ever were at once.
+    #[derive(Debug, Clone, Default)]
+    struct InHandler {
+        now: Arc<AtomicUsize>,
+        max: Arc<AtomicUsize>,
+    }
+
+    async fn slow(Extension(in_handler): Extension<InHandler>) -> StatusCode {
+        let now = in_handler.now.fetch_add(1, Ordering::SeqCst) + 1;
+        in_handler.max.fetch_max(now, Ordering::SeqCst);
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        in_handler.now.fetch_sub(1, Ordering::SeqCst);
+        StatusCode::OK
+    }
+
+    async fn fire(limit: ConcurrencyLimit, in_handler: InHandler, count: usize) -> Vec<StatusCode> {
+        let app = Router::new()
+            .route("/", get(slow))
+            .layer(from_fn(limit_concurrency))
+            .layer(Extension(limit))
+            .layer(Extension(in_handler));
+        let requests = (0..count).map(|_| {
+            let req = Request::builder().uri("/").body(Body::empty()).unwrap();
+            let app = app.clone();
+            tokio::spawn(async move { app.oneshot(req).await.unwrap().status() })
+        });
+        let mut statuses = vec![];
+        for request in requests.collect::<Vec<_>>() {
+            statuses.push(request.await.unwrap());
+        }
+        statuses
+    }
+
+    #[tokio::test]
+    async fn should_queue_requests_beyond_limit() {
+        let in_handler = InHandler::default();
+        let statuses = fire(ConcurrencyLimit::new(2, 10), in_handler.clone(), 8).await;
+
+        assert!(statuses.iter().all(|status| *status == StatusCode::OK));
+        assert_eq!(2, in_handler.max.load(Ordering::SeqCst));
+    }
+
+    #[tokio::test]
+    async fn should_shed_requests_beyond_queue() {
+        let in_handler = InHandler::default();
+        let statuses = fire(ConcurrencyLimit::new(1, 1), in_handler.clone(), 6).await;
+
+        let ok = statuses.iter().filter(|s| **s == StatusCode::OK).count();
+        let shed = statuses
+            .iter()
+            .filter(|s| **s == StatusCode::SERVICE_UNAVAILABLE)
+            .count();
+        assert_eq!((2, 4), (ok, shed));
+        assert_eq!(1, in_handler.max.load(Ordering::SeqCst));
+    }
+}
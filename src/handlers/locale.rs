@@ -0,0 +1,117 @@
+use axum::async_trait;
+use axum::extract::FromRequestParts;
+use axum::http::request::Parts;
+use axum::http::{header, HeaderMap};
+use std::convert::Infallible;
+use validator::{ValidationErrors, ValidationErrorsKind};
+
+/// Language of validation messages, negotiated from `Accept-Language`. English messages
+/// are the ones written on the validation rules, other locales look them up by error code.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Locale {
+    #[default]
+    En,
+    Ja,
+}
+
+impl Locale {
+    /// Supported locale with the highest quality, English when none is acceptable.
+    pub fn from_headers(headers: &HeaderMap) -> Self {
+        headers
+            .get(header::ACCEPT_LANGUAGE)
+            .and_then(|value| value.to_str().ok())
+            .map(Self::negotiate)
+            .unwrap_or_default()
+    }
+
+    fn negotiate(accept_language: &str) -> Self {
+        let mut best: Option<(Locale, f32)> = None;
+        for range in accept_language.split(',') {
+            let mut params = range.split(';');
+            let tag = params.next().unwrap_or_default().trim();
+            let quality = match params.find_map(|param| param.trim().strip_prefix("q=")) {
+                Some(q) => q.parse().unwrap_or(0.0),
+                None => 1.0,
+            };
+            let primary = tag.split('-').next().unwrap_or_default();
+            let locale = match primary.to_ascii_lowercase().as_str() {
+                "en" => Locale::En,
+                "ja" => Locale::Ja,
+                _ => continue,
+            };
+            if quality > 0.0 && best.is_none_or(|(_, q)| quality > q) {
+                best = Some((locale, quality));
+            }
+        }
+        best.map(|(locale, _)| locale).unwrap_or_default()
+    }
+
+    fn message(self, code: &str) -> Option<&'static str> {
+        match self {
+            Locale::En => None,
+            Locale::Ja => match code {
+                "empty" => Some("空にはできません"),
+                "too_long" => Some("文字数が多すぎます"),
+                "control_character" => Some("制御文字は使用できません"),
+                "priority_range" => Some("優先度が範囲外です"),
+                "page_range" => Some("1 以上を指定してください"),
+                "page_size_range" => Some("1 から 100 の間で指定してください"),
+                "negative" => Some("負の値は指定できません"),
+                "label_count" => Some("ラベルは 1 件から 100 件まで指定できます"),
+                "not_found" => Some("ラベルが存在しません"),
+                "duplicate" => Some("ID が重複しています"),
+                "no_fields" => Some("少なくとも 1 つの項目を指定してください"),
+                _ => None,
+            },
+        }
+    }
+
+    /// Replaces the messages of `errors`, nested ones included, with those of this locale.
+    /// Codes missing from the catalog keep their English message.
+    pub fn localize(self, errors: &mut ValidationErrors) {
+        for kind in errors.errors_mut().values_mut() {
+            match kind {
+                ValidationErrorsKind::Field(errors) => {
+                    for error in errors.iter_mut() {
+                        if let Some(message) = self.message(&error.code) {
+                            error.message = Some(message.into());
+                        }
+                    }
+                }
+                ValidationErrorsKind::Struct(errors) => self.localize(errors),
+                ValidationErrorsKind::List(list) => {
+                    for errors in list.values_mut() {
+                        self.localize(errors);
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl<S> FromRequestParts<S> for Locale
+where
+    S: Send + Sync,
+{
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        Ok(Locale::from_headers(&parts.headers))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn should_negotiate_locale() {
+        assert_eq!(Locale::Ja, Locale::negotiate("ja"));
+        assert_eq!(Locale::Ja, Locale::negotiate("ja-JP,ja;q=0.9,en;q=0.8"));
+        assert_eq!(Locale::En, Locale::negotiate("en-US,ja;q=0.5"));
+        assert_eq!(Locale::Ja, Locale::negotiate("fr, ja;q=0.3"));
+        assert_eq!(Locale::En, Locale::negotiate("ja;q=0, fr"));
+        assert_eq!(Locale::En, Locale::negotiate("fr-CA"));
+    }
+}
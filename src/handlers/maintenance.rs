@@ -0,0 +1,51 @@
+use axum::body::Body;
+use axum::http::{Method, Request};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use hyper::StatusCode;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// Body of the 503 returned for writes while the app is read-only.
+pub const MAINTENANCE_MESSAGE: &str = "Under maintenance, the service is read-only for now";
+
+/// `POST` routes that only read, served in read-only mode too. `/graphql` is among them as its
+/// handler turns away the mutations itself.
+const READING_POSTS: [&str; 3] = ["/todos/search", "/todos/validate", "/graphql"];
+
+/// Switch of the read-only mode, shared between the router and whoever holds a clone so
+/// that it can be flipped while the app is running.
+#[derive(Debug, Clone, Default)]
+pub struct ReadOnly(Arc<AtomicBool>);
+
+impl ReadOnly {
+    pub fn new(enabled: bool) -> Self {
+        Self(Arc::new(AtomicBool::new(enabled)))
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    pub fn set(&self, enabled: bool) {
+        self.0.store(enabled, Ordering::Relaxed);
+    }
+}
+
+/// Answers `POST`, `PUT`, `PATCH` and `DELETE` with 503 while the `ReadOnly` extension is on,
+/// except the `POST`s of `READING_POSTS`.
+pub async fn reject_writes(req: Request<Body>, next: Next<Body>) -> Response {
+    let read_only = req
+        .extensions()
+        .get::<ReadOnly>()
+        .is_some_and(ReadOnly::is_enabled);
+    let write = match *req.method() {
+        Method::POST => !READING_POSTS.contains(&req.uri().path()),
+        Method::PUT | Method::PATCH | Method::DELETE => true,
+        _ => false,
+    };
+    if read_only && write {
+        return (StatusCode::SERVICE_UNAVAILABLE, MAINTENANCE_MESSAGE).into_response();
+    }
+    next.run(req).await
+}
@@ -0,0 +1,8 @@
+use axum::Json;
+use schemars::schema::RootSchema;
+use schemars::JsonSchema;
+
+/// Serves the JSON Schema of a request payload so clients can reuse its validation rules.
+pub async fn json_schema<T: JsonSchema>() -> Json<RootSchema> {
+    Json(schemars::schema_for!(T))
+}
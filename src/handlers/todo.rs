@@ -1,52 +1,491 @@
-use crate::handlers::ValidatedJson;
-use crate::repositories::todo::{CreateTodo, TodoRepository, UpdateTodo};
-use axum::extract::Path;
-use axum::response::IntoResponse;
-use axum::{Extension, Json};
+use crate::handlers::hook::TodoHook;
+use crate::handlers::locale::Locale;
+#[cfg(feature = "xml")]
+use crate::handlers::xml::{TodoXml, TodosXml, WantsXml, Xml};
+use crate::handlers::{
+    http_date, not_modified_since, pagination_link, repository_failure, EnvelopeQuery, Paginated,
+    PositiveId, RepositoryFailure, Scoped, UnvalidatedJson, ValidatedJson,
+};
+use crate::repositories::label::LabelRepository;
+use crate::repositories::todo::{
+    group_by_label, CreateTodo, ReorderTodos, ReplaceTodo, TodoEntity, TodoFilter, TodoId,
+    TodoQuery, TodoRepository, TodoSearchCriteria, UpdateTodo,
+};
+use axum::extract::{FromRequestParts, Query};
+use axum::http::request::Parts;
+use axum::http::{header, HeaderMap, HeaderValue, Uri};
+use axum::response::{IntoResponse, Response};
+use axum::{async_trait, Extension, Json};
+use chrono::{DateTime, Utc};
 use hyper::StatusCode;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
 use std::sync::Arc;
+use validator::{Validate, ValidationError};
+
+const TODO_FIELDS: [&str; 12] = [
+    "id",
+    "text",
+    "completed",
+    "completed_at",
+    "archived",
+    "due_date",
+    "priority",
+    "position",
+    "created_at",
+    "updated_at",
+    "labels",
+    "labels_truncated",
+];
+
+/// `?updated_after=<rfc3339>` turns the listing into an incremental sync.
+#[derive(Debug, Default, Deserialize)]
+pub struct SyncQuery {
+    updated_after: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Deserialize)]
+struct FieldsQuery {
+    fields: Option<String>,
+}
+
+/// Subset of todo keys requested with `?fields=id,text`; `None` keeps the full object.
+#[derive(Debug, Default)]
+pub struct TodoFields(Option<Vec<String>>);
+
+#[async_trait]
+impl<S> FromRequestParts<S> for TodoFields
+where
+    S: Send + Sync,
+{
+    type Rejection = (StatusCode, String);
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let Query(query) = Query::<FieldsQuery>::from_request_parts(parts, state)
+            .await
+            .map_err(|rejection| {
+                let message = format!("Query parse error: [{}]", rejection);
+                (StatusCode::BAD_REQUEST, message)
+            })?;
+        let fields: Vec<String> = query
+            .fields
+            .unwrap_or_default()
+            .split(',')
+            .map(|field| field.trim().to_string())
+            .filter(|field| !field.is_empty())
+            .collect();
+        if fields.is_empty() {
+            return Ok(TodoFields(None));
+        }
+        if let Some(field) = fields.iter().find(|f| !TODO_FIELDS.contains(&f.as_str())) {
+            let message = format!(
+                "Unknown field: [{}], allowed fields are [{}]",
+                field,
+                TODO_FIELDS.join(", ")
+            );
+            return Err((StatusCode::BAD_REQUEST, message));
+        }
+        Ok(TodoFields(Some(fields)))
+    }
+}
+
+impl TodoFields {
+    fn project<T: Serialize>(&self, todo: T) -> Value {
+        let value = serde_json::to_value(todo).expect("todo is always serializable");
+        match (&self.0, value) {
+            (Some(fields), Value::Object(mut map)) => Value::Object(
+                fields
+                    .iter()
+                    .filter_map(|field| map.remove_entry(field))
+                    .collect(),
+            ),
+            (_, value) => value,
+        }
+    }
+}
 
 pub async fn create_todo<T: TodoRepository>(
-    Extension(repo): Extension<Arc<T>>,
+    Scoped(repo): Scoped<T>,
+    Extension(hook): Extension<Arc<dyn TodoHook>>,
     ValidatedJson(payload): ValidatedJson<CreateTodo>,
-) -> Result<impl IntoResponse, StatusCode> {
-    let todo = repo.create(payload).await.or(Err(StatusCode::NOT_FOUND))?;
+) -> Result<impl IntoResponse, Response> {
+    let payload = hook
+        .before_create(payload)
+        .await
+        .map_err(IntoResponse::into_response)?;
+    let todo = repo
+        .create(payload)
+        .await
+        .map_err(|e| repository_failure(e).into_response())?;
     Ok((StatusCode::CREATED, Json(todo)))
 }
 
+/// Runs the checks of `create_todo` without persisting anything: 204 when the payload
+/// would be accepted, otherwise 422 with the validation errors keyed by field.
+pub async fn validate_todo<T: LabelRepository>(
+    Scoped(repo): Scoped<T>,
+    locale: Locale,
+    UnvalidatedJson(payload): UnvalidatedJson<CreateTodo>,
+) -> Result<StatusCode, Response> {
+    let mut errors = payload.validate().err().unwrap_or_default();
+    let missing = repo
+        .missing(payload.labels())
+        .await
+        .map_err(|e| repository_failure(e).into_response())?;
+    if !missing.is_empty() {
+        let mut error = ValidationError::new("not_found");
+        error.message = Some("Label does not exist".into());
+        error.add_param("ids".into(), &missing);
+        errors.add("labels", error);
+    }
+    if !errors.is_empty() {
+        locale.localize(&mut errors);
+        return Err((StatusCode::UNPROCESSABLE_ENTITY, Json(errors)).into_response());
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Digest of the representation of the todo, the same across processes and releases.
+pub fn todo_etag(todo: &TodoEntity) -> String {
+    let json = serde_json::to_vec(todo).expect("todo is always serializable");
+    format!("\"{:x}\"", Sha256::digest(json))
+}
+
+fn if_match(headers: &HeaderMap, etag: &str) -> bool {
+    let Some(value) = headers.get(header::IF_MATCH) else {
+        return true;
+    };
+    let Ok(value) = value.to_str() else {
+        return false;
+    };
+    value
+        .split(',')
+        .map(str::trim)
+        .any(|candidate| candidate == "*" || candidate == etag)
+}
+
+/// Checks `If-Match` against the current todo, returning the version it matched so the update
+/// can be made conditional on it, `None` without the header.
+async fn matched_version<T: TodoRepository>(
+    repo: &T,
+    id: TodoId,
+    headers: &HeaderMap,
+) -> Result<Option<DateTime<Utc>>, Response> {
+    if !headers.contains_key(header::IF_MATCH) {
+        return Ok(None);
+    }
+    let current = repo
+        .find(id)
+        .await
+        .map_err(|e| repository_failure(e).into_response())?;
+    if !if_match(headers, &todo_etag(&current)) {
+        return Err(StatusCode::PRECONDITION_FAILED.into_response());
+    }
+    Ok(Some(current.updated_at))
+}
+
 pub async fn find_todo<T: TodoRepository>(
-    Extension(repo): Extension<Arc<T>>,
-    Path(id): Path<i32>,
-) -> Result<impl IntoResponse, StatusCode> {
-    let todo = repo.find(id).await.or(Err(StatusCode::NOT_FOUND))?;
-    Ok((StatusCode::OK, Json(todo)))
+    Scoped(repo): Scoped<T>,
+    PositiveId(id): PositiveId<TodoId>,
+    fields: TodoFields,
+    #[cfg(feature = "xml")] WantsXml(xml): WantsXml,
+) -> Result<Response, RepositoryFailure> {
+    let todo = repo.find(id).await.map_err(repository_failure)?;
+    let etag = todo_etag(&todo);
+    #[cfg(feature = "xml")]
+    if xml {
+        let body = Xml(TodoXml::from(&todo));
+        return Ok((StatusCode::OK, [(header::ETAG, etag)], body).into_response());
+    }
+    Ok((
+        StatusCode::OK,
+        [(header::ETAG, etag)],
+        Json(fields.project(todo)),
+    )
+        .into_response())
 }
 
+#[cfg_attr(feature = "xml", allow(clippy::too_many_arguments))]
 pub async fn all_todo<T: TodoRepository>(
-    Extension(repo): Extension<Arc<T>>,
-) -> Result<impl IntoResponse, StatusCode> {
-    let todos = repo.all().await.unwrap();
-    Ok((StatusCode::OK, Json(todos)))
+    Scoped(repo): Scoped<T>,
+    Query(query): Query<TodoQuery>,
+    Query(sync): Query<SyncQuery>,
+    Query(envelope): Query<EnvelopeQuery>,
+    fields: TodoFields,
+    headers: HeaderMap,
+    uri: Uri,
+    #[cfg(feature = "xml")] WantsXml(xml): WantsXml,
+) -> Result<Response, RepositoryFailure> {
+    if let Err(errors) = query.validate() {
+        let message = format!("Invalid query: [{}]", errors).replace('\n', ", ");
+        return Ok((StatusCode::BAD_REQUEST, message).into_response());
+    }
+    let modified_at = repo.last_modified().await.map_err(repository_failure)?;
+    if not_modified_since(&headers, modified_at) {
+        return Ok(StatusCode::NOT_MODIFIED.into_response());
+    }
+    let mut res_headers = HeaderMap::new();
+    res_headers.insert(
+        header::LAST_MODIFIED,
+        HeaderValue::from_str(&http_date(modified_at)).expect("http date is a valid header"),
+    );
+    if let Some(since) = sync.updated_after {
+        let changes = repo
+            .changed_since(since)
+            .await
+            .map_err(repository_failure)?;
+        return Ok((StatusCode::OK, res_headers, Json(changes)).into_response());
+    }
+    let criteria = query.search_criteria();
+    if let Some(Err(errors)) = criteria.as_ref().map(Validate::validate) {
+        let message = format!("Invalid pagination: [{}]", errors).replace('\n', ", ");
+        return Ok((StatusCode::BAD_REQUEST, message).into_response());
+    }
+    let page = match (&query.q, criteria) {
+        _ if !query.ids.is_empty() => repo.find_many(&query.ids).await.map(Paginated::all),
+        // ranked by similarity rather than sorted, and paged out of the whole ranking
+        (Some(q), criteria) if query.fuzzy => repo
+            .search_ranked(q, query.include_archived)
+            .await
+            .map(|todos| match criteria {
+                Some(criteria) => Paginated::slice(todos, criteria.page_size, criteria.offset()),
+                None => Paginated::all(todos),
+            }),
+        (_, Some(criteria)) => {
+            let by_offset = criteria.offset.is_some();
+            let offset = criteria.offset();
+            let result = repo.search(criteria).await.map_err(repository_failure)?;
+            let total_pages = result.total_pages();
+            res_headers.insert("x-total-pages", HeaderValue::from(total_pages));
+            // pages don't line up with an arbitrary offset, so there are no links to them
+            if !by_offset {
+                let link = pagination_link(&uri, result.page, total_pages);
+                res_headers.insert(
+                    header::LINK,
+                    HeaderValue::from_str(&link).expect("link is a valid header"),
+                );
+            }
+            Ok(Paginated::new(
+                result.items,
+                result.total,
+                result.page_size,
+                offset,
+            ))
+        }
+        _ => repo.all(query.clone()).await.map(Paginated::all),
+    }
+    .map_err(repository_failure)?;
+    let todos = if query.skip_labels {
+        // searches join the labels regardless, they are dropped for the same shape as `all`
+        page.items
+            .into_iter()
+            .map(TodoEntity::without_labels)
+            .collect()
+    } else {
+        page.items
+    };
+    #[cfg(feature = "xml")]
+    if xml {
+        let body = Xml(TodosXml::from(&todos[..]));
+        return Ok((StatusCode::OK, res_headers, body).into_response());
+    }
+    let todos: Vec<Value> = todos.into_iter().map(|todo| fields.project(todo)).collect();
+    let page = Paginated::new(todos, page.total, page.limit, page.offset);
+    Ok((StatusCode::OK, res_headers, envelope.wrap(page)).into_response())
+}
+
+pub async fn all_todo_by_label<T: TodoRepository>(
+    Scoped(repo): Scoped<T>,
+) -> Result<impl IntoResponse, RepositoryFailure> {
+    let todos = repo
+        .all(TodoQuery::default())
+        .await
+        .map_err(repository_failure)?;
+    Ok((StatusCode::OK, Json(group_by_label(todos))))
+}
+
+pub async fn search_todo<T: TodoRepository>(
+    Scoped(repo): Scoped<T>,
+    ValidatedJson(criteria): ValidatedJson<TodoSearchCriteria>,
+) -> Result<impl IntoResponse, RepositoryFailure> {
+    let result = repo.search(criteria).await.map_err(repository_failure)?;
+    Ok((StatusCode::OK, Json(result)))
 }
 
 pub async fn update_todo<T: TodoRepository>(
-    Extension(repo): Extension<Arc<T>>,
-    Path(id): Path<i32>,
+    Scoped(repo): Scoped<T>,
+    PositiveId(id): PositiveId<TodoId>,
+    Extension(hook): Extension<Arc<dyn TodoHook>>,
+    headers: HeaderMap,
     ValidatedJson(payload): ValidatedJson<UpdateTodo>,
-) -> Result<impl IntoResponse, StatusCode> {
+) -> Result<impl IntoResponse, Response> {
+    let matched = matched_version(&repo, id, &headers).await?;
+    let mut payload = hook
+        .before_update(payload)
+        .await
+        .map_err(IntoResponse::into_response)?;
+    if let Some(updated_at) = matched {
+        payload = payload.if_updated_at(updated_at);
+    }
     let todo = repo
         .update(id, payload)
         .await
-        .or(Err(StatusCode::NOT_FOUND))?;
+        .map_err(|e| repository_failure(e).into_response())?;
+    let etag = todo_etag(&todo);
+    Ok((StatusCode::CREATED, [(header::ETAG, etag)], Json(todo)))
+}
+
+/// Replaces every field of the todo, unlike `update_todo` which keeps the omitted ones. The
+/// hook sees the replacement as an update setting every field.
+pub async fn replace_todo<T: TodoRepository>(
+    Scoped(repo): Scoped<T>,
+    PositiveId(id): PositiveId<TodoId>,
+    Extension(hook): Extension<Arc<dyn TodoHook>>,
+    headers: HeaderMap,
+    ValidatedJson(payload): ValidatedJson<ReplaceTodo>,
+) -> Result<impl IntoResponse, Response> {
+    let matched = matched_version(&repo, id, &headers).await?;
+    let mut payload = hook
+        .before_update(payload.into())
+        .await
+        .map_err(IntoResponse::into_response)?;
+    if let Some(updated_at) = matched {
+        payload = payload.if_updated_at(updated_at);
+    }
+    let todo = repo
+        .update(id, payload)
+        .await
+        .map_err(|e| repository_failure(e).into_response())?;
+    let etag = todo_etag(&todo);
+    Ok((StatusCode::OK, [(header::ETAG, etag)], Json(todo)))
+}
+
+/// Changes made to a todo by updates, oldest first.
+pub async fn todo_history<T: TodoRepository>(
+    Scoped(repo): Scoped<T>,
+    PositiveId(id): PositiveId<TodoId>,
+) -> Result<impl IntoResponse, RepositoryFailure> {
+    let history = repo.history(id).await.map_err(repository_failure)?;
+    Ok((StatusCode::OK, Json(history)))
+}
+
+/// Applies the manual order of `ordered_ids` and returns the todos in their new order.
+pub async fn reorder_todo<T: TodoRepository>(
+    Scoped(repo): Scoped<T>,
+    ValidatedJson(payload): ValidatedJson<ReorderTodos>,
+) -> Result<impl IntoResponse, RepositoryFailure> {
+    repo.reorder(payload.ordered_ids)
+        .await
+        .map_err(repository_failure)?;
+    let todos = repo
+        .all(TodoQuery::default())
+        .await
+        .map_err(repository_failure)?;
+    Ok((StatusCode::OK, Json(todos)))
+}
+
+/// Marks the todo as changed for incremental sync without editing it.
+pub async fn touch_todo<T: TodoRepository>(
+    Scoped(repo): Scoped<T>,
+    PositiveId(id): PositiveId<TodoId>,
+) -> Result<impl IntoResponse, RepositoryFailure> {
+    let todo = repo.touch(id).await.map_err(repository_failure)?;
+    Ok((StatusCode::OK, Json(todo)))
+}
+
+/// The copy goes through `before_create` as the payload that would create it, whatever the
+/// hook changes in it is then updated on the copy.
+pub async fn duplicate_todo<T: TodoRepository>(
+    Scoped(repo): Scoped<T>,
+    PositiveId(id): PositiveId<TodoId>,
+    Extension(hook): Extension<Arc<dyn TodoHook>>,
+) -> Result<impl IntoResponse, Response> {
+    let source = repo
+        .find(id)
+        .await
+        .map_err(|e| repository_failure(e).into_response())?;
+    let payload = CreateTodo::copy_of(&source);
+    let hooked = hook
+        .before_create(payload.clone())
+        .await
+        .map_err(IntoResponse::into_response)?;
+    let mut todo = repo
+        .duplicate(id)
+        .await
+        .map_err(|e| repository_failure(e).into_response())?;
+    if hooked != payload {
+        todo = repo
+            .update(todo.id, hooked.into())
+            .await
+            .map_err(|e| repository_failure(e).into_response())?;
+    }
     Ok((StatusCode::CREATED, Json(todo)))
 }
 
+pub async fn archive_todo<T: TodoRepository>(
+    Scoped(repo): Scoped<T>,
+    PositiveId(id): PositiveId<TodoId>,
+) -> Result<impl IntoResponse, RepositoryFailure> {
+    let todo = repo
+        .update(id, UpdateTodo::archive(true))
+        .await
+        .map_err(repository_failure)?;
+    Ok((StatusCode::OK, Json(todo)))
+}
+
+pub async fn unarchive_todo<T: TodoRepository>(
+    Scoped(repo): Scoped<T>,
+    PositiveId(id): PositiveId<TodoId>,
+) -> Result<impl IntoResponse, RepositoryFailure> {
+    let todo = repo
+        .update(id, UpdateTodo::archive(false))
+        .await
+        .map_err(repository_failure)?;
+    Ok((StatusCode::OK, Json(todo)))
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Eq, PartialEq)]
+pub struct UpdatedCount {
+    pub updated: u64,
+}
+
+pub async fn complete_all_todo<T: TodoRepository>(
+    Scoped(repo): Scoped<T>,
+    Query(filter): Query<TodoFilter>,
+) -> Result<impl IntoResponse, RepositoryFailure> {
+    let updated = repo
+        .set_completed_all(filter, true)
+        .await
+        .map_err(repository_failure)?;
+    Ok((StatusCode::OK, Json(UpdatedCount { updated })))
+}
+
+pub async fn uncomplete_all_todo<T: TodoRepository>(
+    Scoped(repo): Scoped<T>,
+    Query(filter): Query<TodoFilter>,
+) -> Result<impl IntoResponse, RepositoryFailure> {
+    let updated = repo
+        .set_completed_all(filter, false)
+        .await
+        .map_err(repository_failure)?;
+    Ok((StatusCode::OK, Json(UpdatedCount { updated })))
+}
+
+pub async fn todo_summary<T: TodoRepository>(
+    Scoped(repo): Scoped<T>,
+    Query(filter): Query<TodoFilter>,
+) -> Result<impl IntoResponse, RepositoryFailure> {
+    let summary = repo.summary(filter).await.map_err(repository_failure)?;
+    Ok((StatusCode::OK, Json(summary)))
+}
+
 pub async fn delete_todo<T: TodoRepository>(
-    Extension(repo): Extension<Arc<T>>,
-    Path(id): Path<i32>,
-) -> StatusCode {
+    Scoped(repo): Scoped<T>,
+    PositiveId(id): PositiveId<TodoId>,
+) -> Result<StatusCode, RepositoryFailure> {
     repo.delete(id)
         .await
         .map(|_| StatusCode::NO_CONTENT)
-        .unwrap_or(StatusCode::NOT_FOUND)
+        .map_err(repository_failure)
 }
@@ -0,0 +1,205 @@
+use crate::handlers::{repository_failure, RepositoryFailure};
+use axum::body::Body;
+use axum::extract::FromRequestParts;
+use axum::http::request::Parts;
+use axum::http::Request;
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use axum::{async_trait, Extension};
+use hyper::StatusCode;
+use sqlx::{PgPool, Postgres, Transaction};
+use std::ops::{Deref, DerefMut};
+use std::sync::Arc;
+use tokio::sync::{Mutex, OwnedMutexGuard};
+
+/// Request extension holding the transaction of the request once a [`Tx`] began it.
+#[derive(Clone, Default)]
+struct TxSlot(Arc<Mutex<Option<Transaction<'static, Postgres>>>>);
+
+/// Transaction shared by everything a handler runs on it, begun from the `PgPool` extension
+/// on first extraction. [`finish_tx`] commits it once the handler answered with a success
+/// status and rolls it back otherwise.
+pub struct Tx(OwnedMutexGuard<Option<Transaction<'static, Postgres>>>);
+
+impl Deref for Tx {
+    type Target = Transaction<'static, Postgres>;
+
+    fn deref(&self) -> &Self::Target {
+        self.0.as_ref().expect("transaction is begun on extraction")
+    }
+}
+
+impl DerefMut for Tx {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.0.as_mut().expect("transaction is begun on extraction")
+    }
+}
+
+#[async_trait]
+impl<S> FromRequestParts<S> for Tx
+where
+    S: Send + Sync,
+{
+    type Rejection = RepositoryFailure;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let slot = parts.extensions.get::<TxSlot>().cloned().ok_or_else(|| {
+            tracing::error!("`Tx` extracted outside of the `finish_tx` layer");
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+        let Extension(pool) = Extension::<PgPool>::from_request_parts(parts, state)
+            .await
+            .map_err(|_| {
+                tracing::error!("`Tx` extracted without a `PgPool` extension");
+                RepositoryFailure::from(StatusCode::INTERNAL_SERVER_ERROR)
+            })?;
+        let mut tx = slot.0.lock_owned().await;
+        if tx.is_none() {
+            let begun = pool
+                .begin()
+                .await
+                .map_err(|e| repository_failure(e.into()))?;
+            *tx = Some(begun);
+        }
+        Ok(Tx(tx))
+    }
+}
+
+/// Finishes the transaction a handler began through [`Tx`], if any.
+pub async fn finish_tx(mut req: Request<Body>, next: Next<Body>) -> Response {
+    let slot = TxSlot::default();
+    req.extensions_mut().insert(slot.clone());
+    let res = next.run(req).await;
+    let Some(tx) = slot.0.lock().await.take() else {
+        return res;
+    };
+    if !res.status().is_success() {
+        if let Err(e) = tx.rollback().await {
+            tracing::warn!("fail roll back request transaction: {:?}", e);
+        }
+        return res;
+    }
+    match tx.commit().await {
+        Ok(()) => res,
+        Err(e) => repository_failure(e.into()).into_response(),
+    }
+}
+
+#[cfg(test)]
+#[cfg(feature = "database-test")]
+#[cfg(not(feature = "uuid"))]
+mod test {
+    use super::*;
+    use crate::repositories::health::HealthRepositoryForDb;
+    use crate::repositories::label::{CreateLabel, LabelRepository, LabelRepositoryForDb};
+    use crate::repositories::todo::{CreateTodo, TodoRepository, TodoRepositoryForDb};
+    use crate::repositories::{reset_database, OwnerId, OwnerScoped};
+    use crate::{create_app_with_options, AppOptions};
+    use axum::middleware::from_fn;
+    use axum::routing::post;
+    use axum::Router;
+    use tower::ServiceExt;
+
+    const OWNER: i32 = 109;
+
+    async fn insert_label(mut tx: Tx, name: &'static str, status: StatusCode) -> StatusCode {
+        sqlx::query(r#"INSERT INTO labels (name, owner_id) VALUES ($1, $2)"#)
+            .bind(name)
+            .bind(OWNER)
+            .execute(&mut *tx)
+            .await
+            .expect("[insert] returned Err");
+        status
+    }
+
+    #[tokio::test]
+    async fn tx_scenario() {
+        let (pool, _db) = reset_database().await;
+        let app = Router::new()
+            .route(
+                "/commit",
+                post(|tx: Tx| insert_label(tx, "[tx] committed", StatusCode::CREATED)),
+            )
+            .route(
+                "/rollback",
+                post(|tx: Tx| insert_label(tx, "[tx] rolled back", StatusCode::CONFLICT)),
+            )
+            .layer(from_fn(finish_tx))
+            .layer(Extension(pool.clone()));
+
+        for (path, status) in [
+            ("/commit", StatusCode::CREATED),
+            ("/rollback", StatusCode::CONFLICT),
+        ] {
+            let req = Request::post(path).body(Body::empty()).unwrap();
+            let res = app.clone().oneshot(req).await.unwrap();
+            assert_eq!(status, res.status());
+        }
+
+        let names: Vec<(String,)> =
+            sqlx::query_as(r#"SELECT name FROM labels WHERE owner_id = $1"#)
+                .bind(OWNER)
+                .fetch_all(&pool)
+                .await
+                .unwrap();
+        assert_eq!(vec![("[tx] committed".to_string(),)], names);
+
+        sqlx::query(r#"DELETE FROM labels WHERE owner_id = $1"#)
+            .bind(OWNER)
+            .execute(&pool)
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn merge_in_tx_scenario() {
+        let (pool, _db) = reset_database().await;
+        let owner = OwnerId(OWNER + 1);
+        let label_repo = LabelRepositoryForDb::new(pool.clone()).scoped(owner);
+        let todo_repo = TodoRepositoryForDb::new(pool.clone()).scoped(owner);
+        let keep = label_repo
+            .create(CreateLabel::new("[merge_in_tx] keep".to_string()))
+            .await
+            .unwrap();
+        let remove = label_repo
+            .create(CreateLabel::new("[merge_in_tx] remove".to_string()))
+            .await
+            .unwrap();
+        let todo = todo_repo
+            .create(CreateTodo::new(
+                "[merge_in_tx] todo".to_string(),
+                vec![remove.id],
+            ))
+            .await
+            .unwrap();
+        let app = create_app_with_options(
+            TodoRepositoryForDb::new(pool.clone()),
+            LabelRepositoryForDb::new(pool.clone()),
+            HealthRepositoryForDb::new(pool.clone(), std::time::Duration::from_secs(1)),
+            AppOptions {
+                pool: Some(pool.clone()),
+                ..Default::default()
+            },
+        );
+        let merge = |keep: i32, remove: i32| {
+            let req = Request::post(format!("/labels/{}/merge/{}", keep, remove))
+                .header("x-owner-id", owner.0.to_string())
+                .body(Body::empty())
+                .unwrap();
+            app.clone().oneshot(req)
+        };
+
+        let res = merge(keep.id.0, i32::MAX).await.unwrap();
+        assert_eq!(StatusCode::NOT_FOUND, res.status());
+        let res = merge(keep.id.0, remove.id.0).await.unwrap();
+        assert_eq!(StatusCode::OK, res.status());
+
+        let labels = label_repo.all().await.unwrap();
+        assert_eq!(vec![keep.clone()], labels);
+        let todo = todo_repo.find(todo.id).await.unwrap();
+        assert_eq!(vec![keep.clone()], todo.labels);
+
+        todo_repo.delete(todo.id).await.unwrap();
+        label_repo.delete(keep.id, true).await.unwrap();
+    }
+}
@@ -0,0 +1,150 @@
+use crate::handlers::accept::media_ranges;
+use crate::repositories::label::Label;
+use crate::repositories::todo::{TodoEntity, TodoId};
+use axum::async_trait;
+use axum::extract::FromRequestParts;
+use axum::http::request::Parts;
+use axum::http::{header, HeaderMap, HeaderValue};
+use axum::response::{IntoResponse, Response};
+use chrono::{DateTime, Utc};
+use hyper::StatusCode;
+use serde::Serialize;
+use std::convert::Infallible;
+
+/// Whether `Accept` prefers XML to JSON. Media ranges are ranked by quality, ties go to the
+/// one listed first and anything else than XML, wildcards included, means JSON.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct WantsXml(pub bool);
+
+impl WantsXml {
+    pub fn from_headers(headers: &HeaderMap) -> Self {
+        let Some(accept) = headers
+            .get(header::ACCEPT)
+            .and_then(|value| value.to_str().ok())
+        else {
+            return Self::default();
+        };
+        let mut best: Option<(bool, f32)> = None;
+        for (essence, quality) in media_ranges(accept) {
+            let xml = matches!(essence.as_str(), "application/xml" | "text/xml");
+            if quality > 0.0 && best.is_none_or(|(_, q)| quality > q) {
+                best = Some((xml, quality));
+            }
+        }
+        Self(best.is_some_and(|(xml, _)| xml))
+    }
+}
+
+#[async_trait]
+impl<S> FromRequestParts<S> for WantsXml
+where
+    S: Send + Sync,
+{
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        Ok(WantsXml::from_headers(&parts.headers))
+    }
+}
+
+/// XML counterpart of `Json`, serialized with `quick-xml`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Xml<T>(pub T);
+
+impl<T: Serialize> IntoResponse for Xml<T> {
+    fn into_response(self) -> Response {
+        match quick_xml::se::to_string(&self.0) {
+            Ok(body) => (
+                [(
+                    header::CONTENT_TYPE,
+                    HeaderValue::from_static("application/xml"),
+                )],
+                body,
+            )
+                .into_response(),
+            Err(e) => {
+                tracing::error!("fail serialize xml: {:?}", e);
+                StatusCode::INTERNAL_SERVER_ERROR.into_response()
+            }
+        }
+    }
+}
+
+/// `<todo>` element; labels are wrapped in `<labels>` with a `<label>` child each. It always
+/// has every field, `?fields=` only applies to JSON.
+#[derive(Debug, Serialize)]
+#[serde(rename = "todo")]
+pub struct TodoXml<'a> {
+    id: TodoId,
+    text: &'a str,
+    completed: bool,
+    completed_at: Option<DateTime<Utc>>,
+    archived: bool,
+    due_date: Option<DateTime<Utc>>,
+    priority: Option<i16>,
+    position: Option<i32>,
+    created_at: DateTime<Utc>,
+    updated_at: DateTime<Utc>,
+    labels: LabelsXml<'a>,
+}
+
+#[derive(Debug, Serialize)]
+struct LabelsXml<'a> {
+    label: &'a [Label],
+}
+
+impl<'a> From<&'a TodoEntity> for TodoXml<'a> {
+    fn from(todo: &'a TodoEntity) -> Self {
+        Self {
+            id: todo.id,
+            text: &todo.text,
+            completed: todo.completed,
+            completed_at: todo.completed_at,
+            archived: todo.archived,
+            due_date: todo.due_date,
+            priority: todo.priority,
+            position: todo.position,
+            created_at: todo.created_at,
+            updated_at: todo.updated_at,
+            labels: LabelsXml {
+                label: &todo.labels,
+            },
+        }
+    }
+}
+
+/// `<todos>` element with a `<todo>` child each.
+#[derive(Debug, Serialize)]
+#[serde(rename = "todos")]
+pub struct TodosXml<'a> {
+    todo: Vec<TodoXml<'a>>,
+}
+
+impl<'a> From<&'a [TodoEntity]> for TodosXml<'a> {
+    fn from(todos: &'a [TodoEntity]) -> Self {
+        Self {
+            todo: todos.iter().map(TodoXml::from).collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn wants_xml(accept: &str) -> bool {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::ACCEPT, HeaderValue::from_str(accept).unwrap());
+        WantsXml::from_headers(&headers).0
+    }
+
+    #[test]
+    fn should_negotiate_xml() {
+        assert!(wants_xml("application/xml"));
+        assert!(wants_xml("text/xml, application/json;q=0.5"));
+        assert!(!wants_xml("application/json, application/xml"));
+        assert!(!wants_xml("*/*"));
+        assert!(!wants_xml("application/xml;q=0"));
+        assert!(!WantsXml::from_headers(&HeaderMap::new()).0);
+    }
+}
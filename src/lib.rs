@@ -0,0 +1,3964 @@
+pub mod config;
+#[cfg(feature = "graphql")]
+pub mod graphql;
+pub mod handlers;
+pub mod repositories;
+pub mod seed;
+pub mod shutdown;
+#[cfg(feature = "tls")]
+pub mod tls;
+
+use crate::config::Config;
+use crate::handlers::accept::reject_unacceptable;
+use crate::handlers::admin::{orphans, AdminKey, API_KEY_HEADER};
+use crate::handlers::auth::JwtKeys;
+use crate::handlers::cache::{set_cache_control, set_vary, CacheMaxAge};
+use crate::handlers::deadline::{set_deadline, RequestTimeout};
+use crate::handlers::health::{ready, service_info, StartedAt};
+use crate::handlers::hook::{NoopHook, TodoHook};
+use crate::handlers::label::{
+    all_label, attach_label, create_label, create_labels, delete_label, find_label, merge_label,
+    merge_label_in_tx, update_labels,
+};
+use crate::handlers::limit::{limit_concurrency, ConcurrencyLimit};
+use crate::handlers::maintenance::{reject_writes, ReadOnly};
+use crate::handlers::todo::{
+    all_todo, all_todo_by_label, archive_todo, complete_all_todo, create_todo, delete_todo,
+    duplicate_todo, find_todo, reorder_todo, replace_todo, search_todo, todo_history, todo_summary,
+    touch_todo, unarchive_todo, uncomplete_all_todo, update_todo, validate_todo,
+};
+use crate::handlers::tx::finish_tx;
+use crate::handlers::OWNER_ID_HEADER;
+use crate::repositories::health::HealthRepository;
+use crate::repositories::label::{LabelId, LabelRepository};
+use crate::repositories::todo::TodoRepository;
+use axum::body::Body;
+use axum::http::{HeaderValue, Method, Request, StatusCode, Uri};
+use axum::{
+    error_handling::HandleErrorLayer,
+    extract::Extension,
+    middleware::{from_fn, map_response, Next},
+    response::{IntoResponse, Response},
+    routing::{get, post},
+    BoxError, Json, Router,
+};
+use hyper::header::{HeaderName, AUTHORIZATION, CONTENT_TYPE, ETAG, IF_MATCH, LINK, RETRY_AFTER};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tower::timeout::error::Elapsed;
+use tower::ServiceBuilder;
+use tower_http::catch_panic::CatchPanicLayer;
+use tower_http::cors::{AllowOrigin, CorsLayer};
+use tower_http::request_id::{
+    MakeRequestUuid, PropagateRequestIdLayer, RequestId, SetRequestIdLayer,
+};
+use tower_http::trace::TraceLayer;
+use tracing::Span;
+
+/// Settings of the HTTP layer that are not tied to a repository.
+#[derive(Debug, Clone)]
+pub struct AppOptions {
+    pub cors_origins: Vec<HeaderValue>,
+    /// Methods and request headers preflights allow from those origins.
+    pub cors_methods: Vec<Method>,
+    pub cors_headers: Vec<HeaderName>,
+    /// Lets browsers send cookies and `Authorization` along with cross-origin requests.
+    pub cors_allow_credentials: bool,
+    pub request_timeout: Duration,
+    /// Owners come from bearer tokens signed with these keys instead of `x-owner-id`.
+    pub jwt_keys: Option<JwtKeys>,
+    /// Writes are answered with 503 while this is on; clones share the switch.
+    pub read_only: ReadOnly,
+    /// Pool the [`handlers::tx::Tx`] extractor begins transactions from; handlers that
+    /// need one fall back to their repository counterparts without it.
+    pub pool: Option<PgPool>,
+    /// `max-age` of the `Cache-Control` set on successful `GET`s.
+    pub cache_max_age: CacheMaxAge,
+    /// Enables the `/admin` routes for requests carrying it in `x-api-key`.
+    pub admin_key: Option<AdminKey>,
+    /// Requests handled at once across all routes, and queued for a slot before 503s.
+    pub concurrency: ConcurrencyLimit,
+    /// Sees todo payloads before they are created or updated.
+    pub todo_hook: Arc<dyn TodoHook>,
+}
+
+impl Default for AppOptions {
+    fn default() -> Self {
+        Self {
+            cors_origins: vec![HeaderValue::from_static("http://localhost:3000")],
+            cors_methods: default_cors_methods(),
+            cors_headers: default_cors_headers(),
+            cors_allow_credentials: false,
+            request_timeout: Duration::from_secs(DEFAULT_REQUEST_TIMEOUT_SECS),
+            jwt_keys: None,
+            read_only: ReadOnly::default(),
+            pool: None,
+            cache_max_age: CacheMaxAge::default(),
+            admin_key: None,
+            concurrency: ConcurrencyLimit::default(),
+            todo_hook: Arc::new(NoopHook),
+        }
+    }
+}
+
+impl From<&Config> for AppOptions {
+    fn from(config: &Config) -> Self {
+        Self {
+            cors_origins: config.cors_origins.clone(),
+            cors_methods: config.cors_methods.clone(),
+            cors_headers: config.cors_headers.clone(),
+            cors_allow_credentials: config.cors_allow_credentials,
+            request_timeout: config.request_timeout,
+            jwt_keys: config
+                .jwt_secret
+                .as_ref()
+                .map(|secret| JwtKeys::new(secret.as_bytes())),
+            read_only: ReadOnly::new(config.read_only),
+            pool: None,
+            cache_max_age: CacheMaxAge(config.cache_max_age),
+            admin_key: config.admin_api_key.clone().map(AdminKey::new),
+            concurrency: ConcurrencyLimit::new(
+                config.max_concurrent_requests,
+                config.max_queued_requests,
+            ),
+            todo_hook: Arc::new(NoopHook),
+        }
+    }
+}
+
+pub fn create_app<Todo: TodoRepository, Label: LabelRepository, Health: HealthRepository>(
+    todo_repo: Todo,
+    label_repo: Label,
+    health_repo: Health,
+) -> Router {
+    create_app_with_options(todo_repo, label_repo, health_repo, AppOptions::default())
+}
+
+pub fn create_app_with_options<
+    Todo: TodoRepository,
+    Label: LabelRepository,
+    Health: HealthRepository,
+>(
+    todo_repo: Todo,
+    label_repo: Label,
+    health_repo: Health,
+    options: AppOptions,
+) -> Router {
+    let todo_repo = Arc::new(todo_repo);
+    let label_repo = Arc::new(label_repo);
+    let merge = match options.pool {
+        Some(_) => post(merge_label_in_tx),
+        None => post(merge_label::<Label>),
+    };
+    let router = Router::new()
+        .route("/", get(service_info))
+        .route("/health/ready", get(ready::<Health>))
+        .route("/todos", post(create_todo::<Todo>).get(all_todo::<Todo>))
+        .route("/todos/by-label", get(all_todo_by_label::<Todo>))
+        .route("/todos/search", post(search_todo::<Todo>))
+        .route("/todos/summary", get(todo_summary::<Todo>))
+        .route("/todos/validate", post(validate_todo::<Label>))
+        .route("/todos/complete-all", post(complete_all_todo::<Todo>))
+        .route("/todos/uncomplete-all", post(uncomplete_all_todo::<Todo>))
+        .route("/todos/reorder", post(reorder_todo::<Todo>))
+        .route(
+            "/todos/:id",
+            get(find_todo::<Todo>)
+                .delete(delete_todo::<Todo>)
+                .patch(update_todo::<Todo>)
+                .put(replace_todo::<Todo>),
+        )
+        .route("/todos/:id/history", get(todo_history::<Todo>))
+        .route("/todos/:id/duplicate", post(duplicate_todo::<Todo>))
+        .route("/todos/:id/archive", post(archive_todo::<Todo>))
+        .route("/todos/:id/unarchive", post(unarchive_todo::<Todo>))
+        .route("/todos/:id/touch", post(touch_todo::<Todo>))
+        .route(
+            "/labels",
+            post(create_label::<Label>).get(all_label::<Label>),
+        )
+        .route(
+            "/labels/bulk",
+            post(create_labels::<Label>).patch(update_labels::<Label>),
+        )
+        .route(
+            "/labels/:id",
+            get(find_label::<Label>).delete(delete_label::<Label>),
+        )
+        .route("/labels/:id/attach", post(attach_label::<Todo>))
+        .route("/labels/:id/merge/:other_id", merge);
+    #[cfg(feature = "schema")]
+    let router = {
+        use crate::handlers::schema::json_schema;
+        use crate::repositories::label::CreateLabel;
+        use crate::repositories::todo::{CreateTodo, ReplaceTodo, UpdateTodo};
+        router
+            .route("/schema/todo", get(json_schema::<CreateTodo>))
+            .route("/schema/todo/update", get(json_schema::<UpdateTodo>))
+            .route("/schema/todo/replace", get(json_schema::<ReplaceTodo>))
+            .route("/schema/label", get(json_schema::<CreateLabel>))
+    };
+    #[cfg(feature = "graphql")]
+    let router = router
+        .route("/graphql", post(graphql::graphql_handler::<Todo, Label>))
+        .layer(Extension(graphql::build_schema(
+            todo_repo.clone(),
+            label_repo.clone(),
+        )));
+
+    let router = match options.jwt_keys {
+        Some(keys) => {
+            #[cfg(feature = "dev-token")]
+            let router = router.route("/auth/token", post(handlers::auth::issue_token));
+            router.layer(Extension(keys))
+        }
+        None => router,
+    };
+    let router = match options.admin_key {
+        Some(key) => router
+            .route("/admin/orphans", get(orphans::<Health>))
+            .layer(Extension(key)),
+        None => router,
+    };
+    let router = match options.pool {
+        Some(pool) => router.layer(Extension(pool)),
+        None => router,
+    };
+
+    let router = router
+        .layer(from_fn(finish_tx))
+        .layer(from_fn(limit_concurrency))
+        .layer(Extension(options.concurrency))
+        .layer(from_fn(set_deadline))
+        .layer(Extension(RequestTimeout(options.request_timeout)))
+        .layer(
+            ServiceBuilder::new()
+                .layer(HandleErrorLayer::new(handle_timeout_error))
+                .timeout(options.request_timeout),
+        )
+        .layer(from_fn(reject_writes))
+        .layer(from_fn(reject_unacceptable))
+        .layer(map_response(set_retry_after))
+        .layer(Extension(options.read_only))
+        .layer(from_fn(set_cache_control))
+        .layer(Extension(options.cache_max_age))
+        .layer(Extension(todo_repo))
+        .layer(Extension(options.todo_hook))
+        .layer(Extension(label_repo))
+        .layer(Extension(Arc::new(health_repo)))
+        .layer(Extension(StartedAt(Instant::now())))
+        .layer(from_fn(set_response_time))
+        .layer(CatchPanicLayer::custom(handle_panic))
+        .layer(map_response(set_json_charset))
+        .layer(
+            ServiceBuilder::new()
+                .layer(SetRequestIdLayer::x_request_id(MakeRequestUuid))
+                .layer(TraceLayer::new_for_http().make_span_with(make_request_span))
+                .layer(PropagateRequestIdLayer::x_request_id()),
+        )
+        .layer(
+            CorsLayer::new()
+                .allow_origin(AllowOrigin::list(options.cors_origins))
+                .allow_methods(options.cors_methods)
+                .allow_headers(options.cors_headers)
+                .allow_credentials(options.cors_allow_credentials)
+                .expose_headers(vec![
+                    ETAG,
+                    LINK,
+                    HeaderName::from_static("x-total-pages"),
+                    X_RESPONSE_TIME.clone(),
+                ]),
+        )
+        .layer(from_fn(set_vary));
+    // the router matches before running its own layers, so `/todos/` is trimmed outside it
+    Router::new().fallback_service(
+        ServiceBuilder::new()
+            .map_request(trim_trailing_slash)
+            .service(router),
+    )
+}
+
+pub async fn resolve_default_label<Label: LabelRepository>(
+    label_repo: &Label,
+    name: Option<String>,
+) -> Option<LabelId> {
+    let name = name?;
+    match label_repo.find_by_name(&name).await {
+        Ok(Some(label)) => Some(label.id),
+        Ok(None) => {
+            tracing::warn!("default label [{}] does not exist, skip it", name);
+            None
+        }
+        Err(e) => {
+            tracing::warn!("fail resolve default label [{}]: {:?}", name, e);
+            None
+        }
+    }
+}
+
+pub const DEFAULT_REQUEST_TIMEOUT_SECS: u64 = 30;
+
+/// Methods of every route, used when `CORS_ALLOWED_METHODS` is unset.
+pub fn default_cors_methods() -> Vec<Method> {
+    vec![
+        Method::GET,
+        Method::POST,
+        Method::PUT,
+        Method::PATCH,
+        Method::DELETE,
+    ]
+}
+
+/// Request headers the handlers read, used when `CORS_ALLOWED_HEADERS` is unset.
+pub fn default_cors_headers() -> Vec<HeaderName> {
+    vec![
+        AUTHORIZATION,
+        CONTENT_TYPE,
+        IF_MATCH,
+        OWNER_ID_HEADER.clone(),
+        API_KEY_HEADER.clone(),
+    ]
+}
+
+async fn handle_timeout_error(e: BoxError) -> StatusCode {
+    if e.is::<Elapsed>() {
+        tracing::warn!("request timed out");
+        StatusCode::GATEWAY_TIMEOUT
+    } else {
+        tracing::error!("unhandled middleware error: {:?}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    }
+}
+
+/// Body of the 500 answering a request whose handler panicked, which says nothing of the
+/// panic itself.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct PanicError {
+    pub error: String,
+}
+
+/// Message a panic was raised with, for the `&str` and `String` payloads of `panic!`.
+pub fn panic_message(payload: &(dyn std::any::Any + Send)) -> &str {
+    payload
+        .downcast_ref::<&str>()
+        .copied()
+        .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+        .unwrap_or("Box<dyn Any>")
+}
+
+/// Turns a panic caught by `CatchPanicLayer` into a 500 instead of a dropped connection.
+/// The panic hook of the binary logs the message along with where it happened.
+fn handle_panic(_payload: Box<dyn std::any::Any + Send + 'static>) -> Response {
+    (
+        StatusCode::INTERNAL_SERVER_ERROR,
+        Json(PanicError {
+            error: "Internal Server Error".to_string(),
+        }),
+    )
+        .into_response()
+}
+
+/// Seconds clients are asked to wait before retrying a 503.
+const RETRY_AFTER_SECS: u64 = 5;
+
+async fn set_retry_after(mut res: Response) -> Response {
+    if res.status() == StatusCode::SERVICE_UNAVAILABLE {
+        res.headers_mut()
+            .entry(RETRY_AFTER)
+            .or_insert_with(|| HeaderValue::from(RETRY_AFTER_SECS));
+    }
+    res
+}
+
+/// `Content-Type` of the JSON responses, naming the charset for clients that do not default
+/// to UTF-8 and garble multibyte text otherwise.
+static APPLICATION_JSON_UTF_8: HeaderValue =
+    HeaderValue::from_static("application/json; charset=utf-8");
+
+async fn set_json_charset(mut res: Response) -> Response {
+    let bare_json = res
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<mime::Mime>().ok())
+        .is_some_and(|mime| {
+            mime.essence_str() == mime::APPLICATION_JSON.essence_str()
+                && mime.get_param(mime::CHARSET).is_none()
+        });
+    if bare_json {
+        res.headers_mut()
+            .insert(CONTENT_TYPE, APPLICATION_JSON_UTF_8.clone());
+    }
+    res
+}
+
+pub static X_RESPONSE_TIME: HeaderName = HeaderName::from_static("x-response-time");
+
+/// Sets `X-Response-Time` to the milliseconds the rest of the stack took, errors included.
+async fn set_response_time(req: Request<Body>, next: Next<Body>) -> Response {
+    let started = Instant::now();
+    let mut res = next.run(req).await;
+    let millis = started.elapsed().as_secs_f64() * 1000.0;
+    if let Ok(value) = HeaderValue::from_str(&format!("{:.3}", millis)) {
+        res.headers_mut().insert(X_RESPONSE_TIME.clone(), value);
+    }
+    res
+}
+
+/// Resolves `/todos/` like `/todos`, keeping the query and the `/` root as they are.
+fn trim_trailing_slash(mut req: Request<Body>) -> Request<Body> {
+    let path = req.uri().path();
+    if path.len() <= 1 || !path.ends_with('/') {
+        return req;
+    }
+    let trimmed = match path.trim_end_matches('/') {
+        "" => "/",
+        trimmed => trimmed,
+    };
+    let path_and_query = match req.uri().query() {
+        Some(query) => format!("{}?{}", trimmed, query),
+        None => trimmed.to_string(),
+    };
+    let mut parts = req.uri().clone().into_parts();
+    if let Ok(path_and_query) = path_and_query.parse() {
+        parts.path_and_query = Some(path_and_query);
+        if let Ok(uri) = Uri::from_parts(parts) {
+            *req.uri_mut() = uri;
+        }
+    }
+    req
+}
+
+fn make_request_span(req: &Request<Body>) -> Span {
+    let request_id = req
+        .extensions()
+        .get::<RequestId>()
+        .and_then(|id| id.header_value().to_str().ok())
+        .unwrap_or_default();
+    tracing::info_span!(
+        "request",
+        method = %req.method(),
+        uri = %req.uri(),
+        request_id,
+    )
+}
+
+#[cfg(test)]
+#[cfg(not(feature = "uuid"))]
+mod test {
+    use super::*;
+    use crate::handlers::auth::Claims;
+    use crate::handlers::health::ServiceInfo;
+    use crate::handlers::hook::{HookRejection, KeywordLabelHook};
+    use crate::handlers::label::{LabelConflicts, LabelInUse};
+    use crate::handlers::maintenance::MAINTENANCE_MESSAGE;
+    use crate::handlers::todo::UpdatedCount;
+    use crate::handlers::Paginated;
+    use crate::repositories::health::test_utils::HealthRepositoryForMemory;
+    use crate::repositories::health::{OrphanLink, Orphans, PoolStatus};
+    use crate::repositories::label::test_utils::{
+        FailingLabelRepository, LabelRepositoryForMemory,
+    };
+    use crate::repositories::label::{CreateLabel, Label, LabelId, LabelWithCount, UpdateLabel};
+    use crate::repositories::todo::{
+        test_utils::{FailingTodoRepository, TodoRepositoryForMemory},
+        AttachedLabel, CreateTodo, LabelGroup, SyncedTodo, TodoChange, TodoEntity, TodoId,
+        TodoSearchResult, TodoSummary, TodosByLabel, UpdateTodo, DEFAULT_MAX_LABELS_PER_TODO,
+        MAX_LIMIT,
+    };
+    use crate::repositories::{OwnerId, OwnerScoped, RepositoryError};
+    use axum::async_trait;
+    use axum::{
+        http::{
+            header::{
+                ACCEPT, ACCEPT_LANGUAGE, ACCESS_CONTROL_ALLOW_CREDENTIALS,
+                ACCESS_CONTROL_ALLOW_HEADERS, ACCESS_CONTROL_ALLOW_METHODS,
+                ACCESS_CONTROL_ALLOW_ORIGIN, ACCESS_CONTROL_REQUEST_METHOD, CACHE_CONTROL,
+                IF_MODIFIED_SINCE, LAST_MODIFIED, LINK, ORIGIN, VARY, WWW_AUTHENTICATE,
+            },
+            Method, StatusCode,
+        },
+        response::Response,
+    };
+    use chrono::{DateTime, SecondsFormat, TimeZone, Utc};
+    use std::vec;
+    use tower::ServiceExt;
+
+    fn build_req_with_json(path: &str, method: Method, json_body: String) -> Request<Body> {
+        Request::builder()
+            .uri(path)
+            .method(method)
+            .header(&OWNER_ID_HEADER, "0")
+            .header(CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
+            .body(Body::from(json_body))
+            .unwrap()
+    }
+
+    fn build_req_with_empty(method: Method, path: &str) -> Request<Body> {
+        Request::builder()
+            .uri(path)
+            .method(method)
+            .header(&OWNER_ID_HEADER, "0")
+            .body(Body::empty())
+            .unwrap()
+    }
+
+    /// Time of every todo of the memory repositories that snapshot their responses.
+    fn fixed_clock() -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2024, 1, 2, 3, 4, 5).unwrap()
+    }
+
+    async fn res_to_todo(res: Response) -> TodoEntity {
+        let bytes = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        let body: String = String::from_utf8(bytes.to_vec()).unwrap();
+        let todo: TodoEntity = serde_json::from_str(&body)
+            .unwrap_or_else(|_| panic!("cannot convert Todo instance. body: {}", body));
+        todo
+    }
+
+    async fn res_to_todos(res: Response) -> Vec<TodoEntity> {
+        let bytes = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        let body: String = String::from_utf8(bytes.to_vec()).unwrap();
+        let todos: Vec<TodoEntity> = serde_json::from_str(&body)
+            .unwrap_or_else(|_| panic!("cannot convert Todo instances. body: {}", body));
+        todos
+    }
+
+    async fn res_to_page(res: Response) -> Paginated<TodoEntity> {
+        let bytes = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        let body: String = String::from_utf8(bytes.to_vec()).unwrap();
+        let page: Paginated<TodoEntity> = serde_json::from_str(&body)
+            .unwrap_or_else(|_| panic!("cannot convert Todo page. body: {}", body));
+        page
+    }
+
+    async fn res_to_label(res: Response) -> Label {
+        let bytes = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        let body: String = String::from_utf8(bytes.to_vec()).unwrap();
+        let label: Label = serde_json::from_str(&body)
+            .unwrap_or_else(|_| panic!("cannot convert Label instance. body: {}", body));
+        label
+    }
+
+    async fn res_to_updated_count(res: Response) -> UpdatedCount {
+        let bytes = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        let body: String = String::from_utf8(bytes.to_vec()).unwrap();
+        let count: UpdatedCount = serde_json::from_str(&body)
+            .unwrap_or_else(|_| panic!("cannot convert UpdatedCount instance. body: {}", body));
+        count
+    }
+
+    #[tokio::test]
+    async fn should_resolve_default_label() {
+        let label_repo = LabelRepositoryForMemory::new();
+        let inbox = label_repo
+            .create(CreateLabel::new("inbox".to_string()))
+            .await
+            .expect("failed create label");
+
+        let label_id = resolve_default_label(&label_repo, Some("inbox".to_string())).await;
+        assert_eq!(Some(inbox.id), label_id);
+        let label_id = resolve_default_label(&label_repo, Some("missing".to_string())).await;
+        assert_eq!(None, label_id);
+        let label_id = resolve_default_label(&label_repo, None).await;
+        assert_eq!(None, label_id);
+    }
+
+    #[tokio::test]
+    async fn should_return_pool_status_when_ready() {
+        let req = build_req_with_empty(Method::GET, "/health/ready");
+        let res = create_app(
+            TodoRepositoryForMemory::new(vec![]),
+            LabelRepositoryForMemory::new(),
+            HealthRepositoryForMemory::new(),
+        )
+        .oneshot(req)
+        .await
+        .unwrap();
+        assert_eq!(StatusCode::OK, res.status());
+
+        let bytes = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        let status: PoolStatus = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(
+            PoolStatus {
+                pool_size: 1,
+                idle: 1,
+                db_latency_ms: 0,
+            },
+            status
+        );
+    }
+
+    #[tokio::test]
+    async fn should_return_503_when_not_ready() {
+        let req = build_req_with_empty(Method::GET, "/health/ready");
+        let res = create_app(
+            TodoRepositoryForMemory::new(vec![]),
+            LabelRepositoryForMemory::new(),
+            HealthRepositoryForMemory::unhealthy(),
+        )
+        .oneshot(req)
+        .await
+        .unwrap();
+        assert_eq!(StatusCode::SERVICE_UNAVAILABLE, res.status());
+    }
+
+    #[derive(Debug, Clone)]
+    struct SlowHealthRepository(Duration);
+
+    #[async_trait]
+    impl HealthRepository for SlowHealthRepository {
+        async fn check(&self) -> anyhow::Result<PoolStatus> {
+            tokio::time::sleep(self.0).await;
+            HealthRepositoryForMemory::new().check().await
+        }
+
+        async fn orphans(&self) -> anyhow::Result<Orphans> {
+            HealthRepositoryForMemory::new().orphans().await
+        }
+    }
+
+    #[tokio::test]
+    async fn should_return_504_when_request_times_out() {
+        let req = build_req_with_empty(Method::GET, "/health/ready");
+        let res = create_app_with_options(
+            TodoRepositoryForMemory::new(vec![]),
+            LabelRepositoryForMemory::new(),
+            SlowHealthRepository(Duration::from_secs(5)),
+            AppOptions {
+                request_timeout: Duration::from_millis(10),
+                ..Default::default()
+            },
+        )
+        .oneshot(req)
+        .await
+        .unwrap();
+        assert_eq!(StatusCode::GATEWAY_TIMEOUT, res.status());
+    }
+
+    #[tokio::test]
+    async fn should_reject_writes_in_read_only_mode() {
+        let read_only = ReadOnly::new(true);
+        let app = create_app_with_options(
+            TodoRepositoryForMemory::new(vec![]),
+            LabelRepositoryForMemory::new(),
+            HealthRepositoryForMemory::new(),
+            AppOptions {
+                read_only: read_only.clone(),
+                ..Default::default()
+            },
+        );
+        let create = || {
+            let req = build_req_with_json(
+                "/todos",
+                Method::POST,
+                r#"{ "text": "should_reject_writes" }"#.to_string(),
+            );
+            app.clone().oneshot(req)
+        };
+
+        let res = create().await.unwrap();
+        assert_eq!(StatusCode::SERVICE_UNAVAILABLE, res.status());
+        assert!(res.headers().contains_key(RETRY_AFTER));
+        let bytes = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        assert_eq!(MAINTENANCE_MESSAGE.as_bytes(), &bytes[..]);
+        for (method, path) in [(Method::PATCH, "/todos/1"), (Method::DELETE, "/labels/1")] {
+            let req = build_req_with_json(path, method, "{}".to_string());
+            let res = app.clone().oneshot(req).await.unwrap();
+            assert_eq!(StatusCode::SERVICE_UNAVAILABLE, res.status());
+        }
+        let req = build_req_with_empty(Method::GET, "/todos");
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(StatusCode::OK, res.status());
+        let req = build_req_with_json("/todos/search", Method::POST, "{}".to_string());
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(StatusCode::OK, res.status());
+        let req = build_req_with_json(
+            "/todos/validate",
+            Method::POST,
+            r#"{ "text": "should_validate" }"#.to_string(),
+        );
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(StatusCode::NO_CONTENT, res.status());
+        #[cfg(feature = "graphql")]
+        {
+            let req = build_req_with_json(
+                "/graphql",
+                Method::POST,
+                r#"{ "query": "{ todos { id } }" }"#.to_string(),
+            );
+            let res = app.clone().oneshot(req).await.unwrap();
+            assert_eq!(StatusCode::OK, res.status());
+            let req = build_req_with_json(
+                "/graphql",
+                Method::POST,
+                r#"{ "query": "mutation { deleteTodo(id: 1) }" }"#.to_string(),
+            );
+            let res = app.clone().oneshot(req).await.unwrap();
+            assert_eq!(StatusCode::SERVICE_UNAVAILABLE, res.status());
+        }
+
+        read_only.set(false);
+        let res = create().await.unwrap();
+        assert_eq!(StatusCode::CREATED, res.status());
+        let req = build_req_with_empty(Method::GET, "/todos");
+        let todos = res_to_page(app.oneshot(req).await.unwrap()).await.items;
+        assert_eq!(1, todos.len());
+    }
+
+    #[tokio::test]
+    async fn should_return_generated_request_id() {
+        let req = build_req_with_empty(Method::GET, "/");
+        let res = create_app(
+            TodoRepositoryForMemory::new(vec![]),
+            LabelRepositoryForMemory::new(),
+            HealthRepositoryForMemory::new(),
+        )
+        .oneshot(req)
+        .await
+        .unwrap();
+
+        let request_id = res
+            .headers()
+            .get("x-request-id")
+            .expect("x-request-id header is missing");
+        assert!(!request_id.is_empty());
+    }
+
+    #[tokio::test]
+    async fn should_echo_request_id() {
+        let req = Request::builder()
+            .uri("/")
+            .method(Method::GET)
+            .header("x-request-id", "should-echo-request-id")
+            .body(Body::empty())
+            .unwrap();
+        let res = create_app(
+            TodoRepositoryForMemory::new(vec![]),
+            LabelRepositoryForMemory::new(),
+            HealthRepositoryForMemory::new(),
+        )
+        .oneshot(req)
+        .await
+        .unwrap();
+
+        assert_eq!(
+            "should-echo-request-id",
+            res.headers().get("x-request-id").unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn should_describe_service_at_root() {
+        let req = build_req_with_empty(Method::GET, "/");
+        let res = create_app(
+            TodoRepositoryForMemory::new(vec![]),
+            LabelRepositoryForMemory::new(),
+            HealthRepositoryForMemory::new(),
+        )
+        .oneshot(req)
+        .await
+        .unwrap();
+        assert_eq!(StatusCode::OK, res.status());
+
+        let bytes = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        let info: ServiceInfo = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(env!("CARGO_PKG_NAME"), info.name);
+        assert_eq!(env!("CARGO_PKG_VERSION"), info.version);
+        assert_eq!(0, info.uptime_seconds);
+        assert!(info.endpoints.contains(&"/todos".to_string()));
+    }
+
+    #[tokio::test]
+    async fn should_created_todo() {
+        let labels = vec![Label::new(LabelId(2), "test label".to_string())];
+        let expected = TodoEntity::new(
+            TodoId(1),
+            "should_return_created_todo".to_string(),
+            false,
+            labels.clone(),
+        );
+        let req = build_req_with_json(
+            "/todos",
+            Method::POST,
+            r#"{ "text": "should_return_created_todo", "labels": [2] }"#.to_string(),
+        );
+        let res = create_app(
+            TodoRepositoryForMemory::new(labels),
+            LabelRepositoryForMemory::new(),
+            HealthRepositoryForMemory::new(),
+        )
+        .oneshot(req)
+        .await
+        .unwrap();
+
+        let todo = res_to_todo(res).await;
+        assert_eq!(expected.with_timestamps_of(&todo), todo);
+    }
+
+    #[tokio::test]
+    async fn should_return_json_in_utf8() {
+        let req = build_req_with_json(
+            "/todos",
+            Method::POST,
+            r#"{ "text": "牛乳を買う 🥛" }"#.to_string(),
+        );
+        let res = create_app(
+            TodoRepositoryForMemory::new(vec![]),
+            LabelRepositoryForMemory::new(),
+            HealthRepositoryForMemory::new(),
+        )
+        .oneshot(req)
+        .await
+        .unwrap();
+
+        assert_eq!(StatusCode::CREATED, res.status());
+        assert_eq!(
+            "application/json; charset=utf-8",
+            res.headers()[CONTENT_TYPE]
+        );
+        let todo = res_to_todo(res).await;
+        assert_eq!("牛乳を買う 🥛", todo.text);
+    }
+
+    #[tokio::test]
+    async fn should_created_todo_with_unique_labels() {
+        let labels = vec![
+            Label::new(LabelId(1), "label 1".to_string()),
+            Label::new(LabelId(2), "label 2".to_string()),
+        ];
+        let expected = TodoEntity::new(
+            TodoId(1),
+            "should_created_todo_with_unique_labels".to_string(),
+            false,
+            labels.clone(),
+        );
+        let req = build_req_with_json(
+            "/todos",
+            Method::POST,
+            r#"{ "text": "should_created_todo_with_unique_labels", "labels": [1, 1, 2] }"#
+                .to_string(),
+        );
+        let res = create_app(
+            TodoRepositoryForMemory::new(labels),
+            LabelRepositoryForMemory::new(),
+            HealthRepositoryForMemory::new(),
+        )
+        .oneshot(req)
+        .await
+        .unwrap();
+
+        let todo = res_to_todo(res).await;
+        assert_eq!(expected.with_timestamps_of(&todo), todo);
+    }
+
+    #[tokio::test]
+    async fn should_return_400_for_malformed_json() {
+        let app = create_app(
+            TodoRepositoryForMemory::new(vec![]),
+            LabelRepositoryForMemory::new(),
+            HealthRepositoryForMemory::new(),
+        );
+
+        for body in [r#"{ "text": "#, r#"{ "text": 1, "labels": [] }"#] {
+            let req = build_req_with_json("/todos", Method::POST, body.to_string());
+            let res = app.clone().oneshot(req).await.unwrap();
+            assert_eq!(StatusCode::BAD_REQUEST, res.status(), "{}", body);
+        }
+    }
+
+    #[tokio::test]
+    async fn should_return_406_for_unsupported_accept() {
+        let app = create_app(
+            TodoRepositoryForMemory::new(vec![]),
+            LabelRepositoryForMemory::new(),
+            HealthRepositoryForMemory::new(),
+        );
+        let req = |accept: &str| {
+            Request::builder()
+                .uri("/todos")
+                .method(Method::GET)
+                .header(&OWNER_ID_HEADER, "0")
+                .header(ACCEPT, accept)
+                .body(Body::empty())
+                .unwrap()
+        };
+
+        for accept in ["application/json", "*/*", "text/html, application/*;q=0.5"] {
+            let res = app.clone().oneshot(req(accept)).await.unwrap();
+            assert_eq!(StatusCode::OK, res.status(), "{}", accept);
+        }
+
+        for accept in ["text/html", "application/json;q=0"] {
+            let res = app.clone().oneshot(req(accept)).await.unwrap();
+            assert_eq!(StatusCode::NOT_ACCEPTABLE, res.status(), "{}", accept);
+        }
+        let res = app.oneshot(req("image/png")).await.unwrap();
+        let bytes = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        assert!(String::from_utf8(bytes.to_vec())
+            .unwrap()
+            .contains("application/json"));
+    }
+
+    #[tokio::test]
+    async fn should_return_415_without_json_content_type() {
+        let app = create_app(
+            TodoRepositoryForMemory::new(vec![]),
+            LabelRepositoryForMemory::new(),
+            HealthRepositoryForMemory::new(),
+        );
+        let body = r#"{ "text": "should_return_415", "labels": [] }"#;
+
+        let req = Request::builder()
+            .uri("/todos")
+            .method(Method::POST)
+            .header(&OWNER_ID_HEADER, "0")
+            .body(Body::from(body))
+            .unwrap();
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(StatusCode::UNSUPPORTED_MEDIA_TYPE, res.status());
+        let bytes = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        assert_eq!(
+            "Expected request with `Content-Type: application/json`",
+            String::from_utf8(bytes.to_vec()).unwrap()
+        );
+
+        let req = Request::builder()
+            .uri("/todos")
+            .method(Method::POST)
+            .header(&OWNER_ID_HEADER, "0")
+            .header(CONTENT_TYPE, mime::TEXT_PLAIN.as_ref())
+            .body(Body::from(body))
+            .unwrap();
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(StatusCode::UNSUPPORTED_MEDIA_TYPE, res.status());
+
+        let req = Request::builder()
+            .uri("/todos")
+            .method(Method::POST)
+            .header(&OWNER_ID_HEADER, "0")
+            .header(CONTENT_TYPE, "application/json; charset=utf-8")
+            .body(Body::from(body))
+            .unwrap();
+        let res = app.oneshot(req).await.unwrap();
+        assert_eq!(StatusCode::CREATED, res.status());
+    }
+
+    #[tokio::test]
+    async fn should_create_todo_with_label_names() {
+        let label_repo = LabelRepositoryForMemory::new();
+        let existing = label_repo
+            .create(CreateLabel::new("work".to_string()))
+            .await
+            .expect("failed create label");
+        let app = create_app(
+            TodoRepositoryForMemory::with_label_repository(label_repo.clone()),
+            label_repo,
+            HealthRepositoryForMemory::new(),
+        );
+
+        let req = build_req_with_json(
+            "/todos",
+            Method::POST,
+            r#"{ "text": "should_create", "label_names": ["Work", "home", "HOME"] }"#.to_string(),
+        );
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(StatusCode::CREATED, res.status());
+        let todo = res_to_todo(res).await;
+        assert_eq!(
+            vec![existing, Label::new(LabelId(2), "home".to_string())],
+            todo.labels
+        );
+
+        let req = build_req_with_empty(Method::GET, "/labels");
+        let res = app.clone().oneshot(req).await.unwrap();
+        let bytes = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        let labels: Paginated<Label> = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(2, labels.items.len());
+
+        let req = build_req_with_json(
+            "/todos",
+            Method::POST,
+            r#"{ "text": "should_reject", "label_names": ["  "] }"#.to_string(),
+        );
+        let res = app.oneshot(req).await.unwrap();
+        assert_eq!(StatusCode::UNPROCESSABLE_ENTITY, res.status());
+    }
+
+    #[tokio::test]
+    async fn should_label_urgent_todos_with_hook() {
+        let urgent = Label::new(LabelId(1), "urgent".to_string());
+        let app = create_app_with_options(
+            TodoRepositoryForMemory::new(vec![urgent.clone()]),
+            LabelRepositoryForMemory::new(),
+            HealthRepositoryForMemory::new(),
+            AppOptions {
+                todo_hook: Arc::new(KeywordLabelHook::new("urgent", urgent.id)),
+                ..Default::default()
+            },
+        );
+
+        let req = build_req_with_json(
+            "/todos",
+            Method::POST,
+            r#"{ "text": "Urgent: call back" }"#.to_string(),
+        );
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(StatusCode::CREATED, res.status());
+        assert_eq!(vec![urgent.clone()], res_to_todo(res).await.labels);
+
+        let req = build_req_with_json(
+            "/todos",
+            Method::POST,
+            r#"{ "text": "call back" }"#.to_string(),
+        );
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert!(res_to_todo(res).await.labels.is_empty());
+
+        let req = build_req_with_json(
+            "/todos/2",
+            Method::PATCH,
+            r#"{ "text": "call back, urgent", "labels": [] }"#.to_string(),
+        );
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(StatusCode::CREATED, res.status());
+        assert_eq!(vec![urgent.clone()], res_to_todo(res).await.labels);
+
+        let req = build_req_with_json(
+            "/todos/2",
+            Method::PUT,
+            r#"{ "text": "urgent again", "completed": false, "labels": [] }"#.to_string(),
+        );
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(StatusCode::OK, res.status());
+        assert_eq!(vec![urgent.clone()], res_to_todo(res).await.labels);
+
+        // updates without the text keep the labels they are given
+        let req = build_req_with_json("/todos/2", Method::PATCH, r#"{ "labels": [] }"#.to_string());
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert!(res_to_todo(res).await.labels.is_empty());
+        let req = build_req_with_empty(Method::POST, "/todos/2/duplicate");
+        let res = app.oneshot(req).await.unwrap();
+        assert_eq!(StatusCode::CREATED, res.status());
+        let copy = res_to_todo(res).await;
+        assert_eq!("urgent again", copy.text);
+        assert_eq!(vec![urgent], copy.labels);
+    }
+
+    #[derive(Debug)]
+    struct RejectingHook;
+
+    #[async_trait]
+    impl TodoHook for RejectingHook {
+        async fn before_create(&self, _payload: CreateTodo) -> Result<CreateTodo, HookRejection> {
+            Err(HookRejection("todos are frozen".to_string()))
+        }
+
+        async fn before_update(&self, _payload: UpdateTodo) -> Result<UpdateTodo, HookRejection> {
+            Err(HookRejection("todos are frozen".to_string()))
+        }
+    }
+
+    #[tokio::test]
+    async fn should_reject_payload_refused_by_hook() {
+        let todo_repo = TodoRepositoryForMemory::new(vec![]);
+        todo_repo
+            .create(CreateTodo::new("should_reject".to_string(), vec![]))
+            .await
+            .expect("failed create todo");
+        let app = create_app_with_options(
+            todo_repo,
+            LabelRepositoryForMemory::new(),
+            HealthRepositoryForMemory::new(),
+            AppOptions {
+                todo_hook: Arc::new(RejectingHook),
+                ..Default::default()
+            },
+        );
+
+        let req = build_req_with_json(
+            "/todos/1",
+            Method::PATCH,
+            r#"{ "completed": true }"#.to_string(),
+        );
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(StatusCode::UNPROCESSABLE_ENTITY, res.status());
+        let bytes = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        assert_eq!(b"todos are frozen", &bytes[..]);
+
+        for req in [
+            build_req_with_json(
+                "/todos/1",
+                Method::PUT,
+                r#"{ "text": "should_reject", "completed": true, "labels": [] }"#.to_string(),
+            ),
+            build_req_with_empty(Method::POST, "/todos/1/duplicate"),
+        ] {
+            let res = app.clone().oneshot(req).await.unwrap();
+            assert_eq!(StatusCode::UNPROCESSABLE_ENTITY, res.status());
+        }
+        #[cfg(feature = "graphql")]
+        {
+            let req = build_req_with_json(
+                "/graphql",
+                Method::POST,
+                r#"{ "query": "mutation { updateTodo(id: 1, completed: true) { completed } }" }"#
+                    .to_string(),
+            );
+            let res = app.clone().oneshot(req).await.unwrap();
+            let bytes = hyper::body::to_bytes(res.into_body()).await.unwrap();
+            let body: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+            assert_eq!("todos are frozen", body["errors"][0]["message"]);
+        }
+
+        let req = build_req_with_empty(Method::GET, "/todos");
+        let todos = res_to_page(app.oneshot(req).await.unwrap()).await.items;
+        assert_eq!(1, todos.len());
+        assert!(!todos[0].completed);
+    }
+
+    #[tokio::test]
+    async fn should_limit_labels_per_todo() {
+        let labels: Vec<Label> = (1..=DEFAULT_MAX_LABELS_PER_TODO as i32 + 1)
+            .map(|i| Label::new(LabelId(i), format!("label {}", i)))
+            .collect();
+        let app = create_app(
+            TodoRepositoryForMemory::new(labels),
+            LabelRepositoryForMemory::new(),
+            HealthRepositoryForMemory::new(),
+        );
+        let label_ids = |n: usize| -> String {
+            let ids: Vec<String> = (1..=n).map(|i| i.to_string()).collect();
+            ids.join(", ")
+        };
+
+        let req = build_req_with_json(
+            "/todos",
+            Method::POST,
+            format!(
+                r#"{{ "text": "should_limit", "labels": [{}] }}"#,
+                label_ids(DEFAULT_MAX_LABELS_PER_TODO)
+            ),
+        );
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(StatusCode::CREATED, res.status());
+
+        let req = build_req_with_json(
+            "/todos",
+            Method::POST,
+            format!(
+                r#"{{ "text": "should_limit", "labels": [{}] }}"#,
+                label_ids(DEFAULT_MAX_LABELS_PER_TODO + 1)
+            ),
+        );
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(StatusCode::UNPROCESSABLE_ENTITY, res.status());
+
+        let req = build_req_with_json(
+            "/todos/1",
+            Method::PATCH,
+            format!(
+                r#"{{ "labels": [{}] }}"#,
+                label_ids(DEFAULT_MAX_LABELS_PER_TODO + 1)
+            ),
+        );
+        let res = app.oneshot(req).await.unwrap();
+        assert_eq!(StatusCode::UNPROCESSABLE_ENTITY, res.status());
+    }
+
+    #[tokio::test]
+    async fn should_trim_todo_text() {
+        let req = build_req_with_json(
+            "/todos",
+            Method::POST,
+            r#"{ "text": "  should_trim_todo_text\n ", "labels": [] }"#.to_string(),
+        );
+        let res = create_app(
+            TodoRepositoryForMemory::new(vec![]),
+            LabelRepositoryForMemory::new(),
+            HealthRepositoryForMemory::new(),
+        )
+        .oneshot(req)
+        .await
+        .unwrap();
+
+        let todo = res_to_todo(res).await;
+        assert_eq!("should_trim_todo_text", todo.text);
+    }
+
+    #[tokio::test]
+    async fn should_localize_validation_messages() {
+        let app = create_app(
+            TodoRepositoryForMemory::new(vec![]),
+            LabelRepositoryForMemory::new(),
+            HealthRepositoryForMemory::new(),
+        );
+        let send = |accept_language: &'static str| {
+            let mut req = build_req_with_json(
+                "/todos",
+                Method::POST,
+                r#"{ "text": "", "labels": [] }"#.to_string(),
+            );
+            req.headers_mut()
+                .insert(ACCEPT_LANGUAGE, HeaderValue::from_static(accept_language));
+            let app = app.clone();
+            async move {
+                let res = app.oneshot(req).await.unwrap();
+                assert_eq!(StatusCode::UNPROCESSABLE_ENTITY, res.status());
+                let bytes = hyper::body::to_bytes(res.into_body()).await.unwrap();
+                String::from_utf8(bytes.to_vec()).unwrap()
+            }
+        };
+
+        assert_eq!(
+            "Validation error: [text: 空にはできません]",
+            send("ja-JP,ja;q=0.9").await
+        );
+        assert_eq!(
+            "Validation error: [text: Can not be empty]",
+            send("fr").await
+        );
+
+        let mut req = build_req_with_json(
+            "/todos/validate",
+            Method::POST,
+            r#"{ "text": "", "labels": [1] }"#.to_string(),
+        );
+        req.headers_mut()
+            .insert(ACCEPT_LANGUAGE, HeaderValue::from_static("ja"));
+        let res = app.oneshot(req).await.unwrap();
+        let bytes = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        let errors: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!("空にはできません", errors["text"][0]["message"]);
+        assert_eq!("ラベルが存在しません", errors["labels"][0]["message"]);
+    }
+
+    #[tokio::test]
+    async fn should_reject_control_characters_in_todo_text() {
+        let todo_repo = TodoRepositoryForMemory::new(vec![]);
+        todo_repo
+            .create(CreateTodo::new("todo".to_string(), vec![]))
+            .await
+            .expect("failed create todo");
+        let app = create_app(
+            todo_repo,
+            LabelRepositoryForMemory::new(),
+            HealthRepositoryForMemory::new(),
+        );
+
+        for (method, path) in [(Method::POST, "/todos"), (Method::PATCH, "/todos/1")] {
+            let req = build_req_with_json(
+                path,
+                method.clone(),
+                r#"{ "text": "null\u0000byte" }"#.to_string(),
+            );
+            let res = app.clone().oneshot(req).await.unwrap();
+            assert_eq!(StatusCode::UNPROCESSABLE_ENTITY, res.status(), "{}", method);
+        }
+
+        let req = build_req_with_json(
+            "/todos",
+            Method::POST,
+            r#"{ "text": "first line\n\tsecond line" }"#.to_string(),
+        );
+        let res = app.oneshot(req).await.unwrap();
+        assert_eq!(StatusCode::CREATED, res.status());
+        assert_eq!("first line\n\tsecond line", res_to_todo(res).await.text);
+    }
+
+    #[tokio::test]
+    async fn should_reject_whitespace_only_todo_text() {
+        let todo_repo = TodoRepositoryForMemory::new(vec![]);
+        todo_repo
+            .create(CreateTodo::new("todo".to_string(), vec![]))
+            .await
+            .expect("failed create todo");
+        let app = create_app(
+            todo_repo,
+            LabelRepositoryForMemory::new(),
+            HealthRepositoryForMemory::new(),
+        );
+
+        let req = build_req_with_json(
+            "/todos",
+            Method::POST,
+            r#"{ "text": "   ", "labels": [] }"#.to_string(),
+        );
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(StatusCode::UNPROCESSABLE_ENTITY, res.status());
+
+        let req = build_req_with_json(
+            "/todos/1",
+            Method::PATCH,
+            r#"{ "text": " \t " }"#.to_string(),
+        );
+        let res = app.oneshot(req).await.unwrap();
+        assert_eq!(StatusCode::UNPROCESSABLE_ENTITY, res.status());
+    }
+
+    #[tokio::test]
+    async fn should_validate_todo_without_creating_it() {
+        let labels = vec![Label::new(LabelId(1), "test label".to_string())];
+        let todo_repo = TodoRepositoryForMemory::new(vec![]);
+        let app = create_app(
+            todo_repo.clone(),
+            LabelRepositoryForMemory::with_labels(labels),
+            HealthRepositoryForMemory::new(),
+        );
+
+        let req = build_req_with_json(
+            "/todos/validate",
+            Method::POST,
+            r#"{ "text": "should validate", "labels": [1] }"#.to_string(),
+        );
+        let res = app.oneshot(req).await.unwrap();
+        assert_eq!(StatusCode::NO_CONTENT, res.status());
+        let todos = todo_repo.all(Default::default()).await.unwrap();
+        assert!(todos.is_empty());
+    }
+
+    #[tokio::test]
+    async fn should_return_422_when_validate_todo_fails() {
+        let app = create_app(
+            TodoRepositoryForMemory::new(vec![]),
+            LabelRepositoryForMemory::new(),
+            HealthRepositoryForMemory::new(),
+        );
+
+        let req = build_req_with_json(
+            "/todos/validate",
+            Method::POST,
+            r#"{ "text": "  ", "labels": [1] }"#.to_string(),
+        );
+        let res = app.oneshot(req).await.unwrap();
+        assert_eq!(StatusCode::UNPROCESSABLE_ENTITY, res.status());
+        let bytes = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        let errors: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!("empty", errors["text"][0]["code"]);
+        assert_eq!("not_found", errors["labels"][0]["code"]);
+        assert_eq!(serde_json::json!([1]), errors["labels"][0]["params"]["ids"]);
+        insta::assert_json_snapshot!("validation_errors", errors);
+    }
+
+    #[tokio::test]
+    async fn should_list_todo_history() {
+        let todo_repo = TodoRepositoryForMemory::new(vec![]);
+        todo_repo
+            .create(CreateTodo::new("should_list_history".to_string(), vec![]))
+            .await
+            .expect("failed create todo");
+        let app = create_app(
+            todo_repo,
+            LabelRepositoryForMemory::new(),
+            HealthRepositoryForMemory::new(),
+        );
+        let history = |path: &str| {
+            let req = build_req_with_empty(Method::GET, path);
+            let app = app.clone();
+            async move {
+                let res = app.oneshot(req).await.unwrap();
+                assert_eq!(StatusCode::OK, res.status());
+                let bytes = hyper::body::to_bytes(res.into_body()).await.unwrap();
+                serde_json::from_slice::<Vec<TodoChange>>(&bytes).unwrap()
+            }
+        };
+        assert!(history("/todos/1/history").await.is_empty());
+
+        for body in [r#"{ "text": "renamed" }"#, r#"{ "completed": true }"#] {
+            let req = build_req_with_json("/todos/1", Method::PATCH, body.to_string());
+            let res = app.clone().oneshot(req).await.unwrap();
+            assert_eq!(StatusCode::CREATED, res.status());
+        }
+
+        let changes = history("/todos/1/history").await;
+        assert_eq!(2, changes.len());
+        assert_eq!(
+            ("text", "should_list_history".into(), "renamed".into()),
+            (
+                changes[0].field.as_str(),
+                changes[0].old.clone(),
+                changes[0].new.clone()
+            )
+        );
+        assert_eq!(
+            ("completed", false.into(), true.into()),
+            (
+                changes[1].field.as_str(),
+                changes[1].old.clone(),
+                changes[1].new.clone()
+            )
+        );
+
+        let req = build_req_with_empty(Method::GET, "/todos/2/history");
+        let res = app.oneshot(req).await.unwrap();
+        assert_eq!(StatusCode::NOT_FOUND, res.status());
+    }
+
+    #[tokio::test]
+    async fn should_reorder_todos() {
+        let todo_repo = TodoRepositoryForMemory::new(vec![]);
+        for text in ["first", "second", "third"] {
+            todo_repo
+                .create(CreateTodo::new(text.to_string(), vec![]))
+                .await
+                .expect("failed create todo");
+        }
+        let app = create_app(
+            todo_repo,
+            LabelRepositoryForMemory::new(),
+            HealthRepositoryForMemory::new(),
+        );
+        let reorder = |body: &str| {
+            let req = build_req_with_json("/todos/reorder", Method::POST, body.to_string());
+            app.clone().oneshot(req)
+        };
+
+        let res = reorder(r#"{ "ordered_ids": [3, 1] }"#).await.unwrap();
+        assert_eq!(StatusCode::OK, res.status());
+        let todos = res_to_todos(res).await;
+        let texts: Vec<&str> = todos.iter().map(|todo| todo.text.as_str()).collect();
+        assert_eq!(vec!["third", "first", "second"], texts);
+        let positions: Vec<Option<i32>> = todos.iter().map(|todo| todo.position).collect();
+        assert_eq!(vec![Some(1), Some(2), None], positions);
+
+        let res = reorder(r#"{ "ordered_ids": [2, 4] }"#).await.unwrap();
+        assert_eq!(StatusCode::NOT_FOUND, res.status());
+        let res = reorder(r#"{ "ordered_ids": [1, 2, 1] }"#).await.unwrap();
+        assert_eq!(StatusCode::UNPROCESSABLE_ENTITY, res.status());
+        let bytes = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        let body = String::from_utf8(bytes.to_vec()).unwrap();
+        assert!(body.contains("Contains duplicate ids"), "{}", body);
+
+        let req = build_req_with_empty(Method::GET, "/todos");
+        let todos = res_to_page(app.oneshot(req).await.unwrap()).await.items;
+        let texts: Vec<&str> = todos.iter().map(|todo| todo.text.as_str()).collect();
+        assert_eq!(vec!["third", "first", "second"], texts);
+    }
+
+    #[tokio::test]
+    async fn should_sync_todos_updated_after() {
+        let todo_repo = TodoRepositoryForMemory::new(vec![]);
+        for text in ["unchanged", "updated", "deleted"] {
+            todo_repo
+                .create(CreateTodo::new(text.to_string(), vec![]))
+                .await
+                .expect("failed create todo");
+        }
+        tokio::time::sleep(Duration::from_millis(5)).await;
+        let since = Utc::now();
+        tokio::time::sleep(Duration::from_millis(5)).await;
+        let app = create_app(
+            todo_repo,
+            LabelRepositoryForMemory::new(),
+            HealthRepositoryForMemory::new(),
+        );
+        let req = build_req_with_json(
+            "/todos/2",
+            Method::PATCH,
+            r#"{ "completed": true }"#.to_string(),
+        );
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(StatusCode::CREATED, res.status());
+        let req = build_req_with_empty(Method::DELETE, "/todos/3");
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(StatusCode::NO_CONTENT, res.status());
+
+        let path = format!(
+            "/todos?updated_after={}",
+            since.to_rfc3339_opts(SecondsFormat::Micros, true)
+        );
+        let req = build_req_with_empty(Method::GET, &path);
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(StatusCode::OK, res.status());
+        let bytes = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        let changes: Vec<SyncedTodo> = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(
+            vec![TodoId(2), TodoId(3)],
+            changes.iter().map(SyncedTodo::id).collect::<Vec<_>>()
+        );
+        let changes: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(serde_json::json!(false), changes[0]["deleted"]);
+        assert_eq!(serde_json::json!(true), changes[0]["completed"]);
+        assert_eq!(serde_json::json!(true), changes[1]["deleted"]);
+        assert!(changes[1].get("text").is_none());
+
+        let req = build_req_with_empty(Method::GET, "/todos?updated_after=yesterday");
+        let res = app.oneshot(req).await.unwrap();
+        assert_eq!(StatusCode::BAD_REQUEST, res.status());
+    }
+
+    #[cfg(feature = "xml")]
+    #[tokio::test]
+    async fn should_return_todos_as_xml() {
+        let labels = vec![
+            Label::new(LabelId(1), "work".to_string()),
+            Label::new(LabelId(2), "home".to_string()),
+        ];
+        let todo_repo = TodoRepositoryForMemory::new(labels);
+        todo_repo
+            .create(CreateTodo::new(
+                "should_return_xml".to_string(),
+                vec![LabelId(1), LabelId(2)],
+            ))
+            .await
+            .expect("failed create todo");
+        let app = create_app(
+            todo_repo,
+            LabelRepositoryForMemory::new(),
+            HealthRepositoryForMemory::new(),
+        );
+        let xml = |path: &str| {
+            let mut req = build_req_with_empty(Method::GET, path);
+            req.headers_mut().insert(
+                hyper::header::ACCEPT,
+                HeaderValue::from_static("application/xml"),
+            );
+            app.clone().oneshot(req)
+        };
+
+        let res = xml("/todos/1").await.unwrap();
+        assert_eq!(StatusCode::OK, res.status());
+        assert_eq!("application/xml", res.headers()[CONTENT_TYPE]);
+        let bytes = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        let body = String::from_utf8(bytes.to_vec()).unwrap();
+        assert!(
+            body.starts_with("<todo><id>1</id><text>should_return_xml</text>"),
+            "{}",
+            body
+        );
+        assert!(
+            body.ends_with("<labels><label><id>1</id><name>work</name></label><label><id>2</id><name>home</name></label></labels></todo>"),
+            "{}",
+            body
+        );
+
+        let res = xml("/todos").await.unwrap();
+        assert_eq!(StatusCode::OK, res.status());
+        let bytes = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        let body = String::from_utf8(bytes.to_vec()).unwrap();
+        assert!(body.starts_with("<todos><todo><id>1</id>"), "{}", body);
+        assert!(body.ends_with("</todo></todos>"), "{}", body);
+
+        let req = build_req_with_empty(Method::GET, "/todos/1");
+        let res = app.oneshot(req).await.unwrap();
+        assert_eq!(
+            "application/json; charset=utf-8",
+            res.headers()[CONTENT_TYPE]
+        );
+    }
+
+    #[tokio::test]
+    async fn should_find_todo() {
+        let labels = vec![Label::new(LabelId(1000), "test label".to_string())];
+        let expected = TodoEntity::new(
+            TodoId(1),
+            "should_find_todo".to_string(),
+            false,
+            labels.clone(),
+        );
+        let todo_repo = TodoRepositoryForMemory::new(labels).with_clock(fixed_clock);
+        todo_repo
+            .create(CreateTodo::new(
+                "should_find_todo".to_string(),
+                vec![LabelId(1000)],
+            ))
+            .await
+            .expect("failed create todo");
+        let req = build_req_with_empty(Method::GET, "/todos/1");
+        let res = create_app(
+            todo_repo,
+            LabelRepositoryForMemory::new(),
+            HealthRepositoryForMemory::new(),
+        )
+        .oneshot(req)
+        .await
+        .unwrap();
+
+        // the validator outlives the process, so it must not change with it or with a release
+        assert_eq!(
+            "\"d1a0846e971c00de9ce003ab7330f5fa15854ef54fddebaab5f23d83d257f821\"",
+            res.headers()[ETAG]
+        );
+        let todo = res_to_todo(res).await;
+        assert_eq!(expected.with_timestamps_of(&todo), todo);
+        insta::assert_json_snapshot!("todo", todo);
+    }
+
+    #[tokio::test]
+    async fn should_not_find_todo_of_another_owner() {
+        let todo_repo = TodoRepositoryForMemory::new(vec![]);
+        todo_repo
+            .scoped(OwnerId(1))
+            .create(CreateTodo::new("owned by 1".to_string(), vec![]))
+            .await
+            .expect("failed create todo");
+        let app = create_app(
+            todo_repo,
+            LabelRepositoryForMemory::new(),
+            HealthRepositoryForMemory::new(),
+        );
+
+        let req = Request::builder()
+            .uri("/todos/1")
+            .method(Method::GET)
+            .header(&OWNER_ID_HEADER, "2")
+            .body(Body::empty())
+            .unwrap();
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(StatusCode::NOT_FOUND, res.status());
+
+        let req = Request::builder()
+            .uri("/todos/1")
+            .method(Method::GET)
+            .header(&OWNER_ID_HEADER, "1")
+            .body(Body::empty())
+            .unwrap();
+        let res = app.oneshot(req).await.unwrap();
+        assert_eq!("owned by 1", res_to_todo(res).await.text);
+    }
+
+    #[tokio::test]
+    async fn should_return_401_without_owner() {
+        let app = create_app(
+            TodoRepositoryForMemory::new(vec![]),
+            LabelRepositoryForMemory::new(),
+            HealthRepositoryForMemory::new(),
+        );
+        for owner in [None, Some("alice")] {
+            let mut req = Request::builder().uri("/todos").method(Method::GET);
+            if let Some(owner) = owner {
+                req = req.header(&OWNER_ID_HEADER, owner);
+            }
+            let res = app
+                .clone()
+                .oneshot(req.body(Body::empty()).unwrap())
+                .await
+                .unwrap();
+            assert_eq!(StatusCode::UNAUTHORIZED, res.status());
+        }
+    }
+
+    async fn send_with_token(token: &str) -> Response {
+        let todo_repo = TodoRepositoryForMemory::new(vec![]);
+        todo_repo
+            .scoped(OwnerId(1))
+            .create(CreateTodo::new("owned by 1".to_string(), vec![]))
+            .await
+            .expect("failed create todo");
+        let req = Request::builder()
+            .uri("/todos/1")
+            .method(Method::GET)
+            .header(AUTHORIZATION, format!("Bearer {}", token))
+            .body(Body::empty())
+            .unwrap();
+        create_app_with_options(
+            todo_repo,
+            LabelRepositoryForMemory::new(),
+            HealthRepositoryForMemory::new(),
+            AppOptions {
+                jwt_keys: Some(JwtKeys::new(b"secret")),
+                ..Default::default()
+            },
+        )
+        .oneshot(req)
+        .await
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn should_take_owner_from_valid_token() {
+        let token = JwtKeys::new(b"secret")
+            .encode(&Claims::new(OwnerId(1), chrono::Duration::minutes(5)))
+            .unwrap();
+        let res = send_with_token(&token).await;
+        assert_eq!("owned by 1", res_to_todo(res).await.text);
+
+        let token = JwtKeys::new(b"secret")
+            .encode(&Claims::new(OwnerId(2), chrono::Duration::minutes(5)))
+            .unwrap();
+        let res = send_with_token(&token).await;
+        assert_eq!(StatusCode::NOT_FOUND, res.status());
+    }
+
+    #[tokio::test]
+    async fn should_return_401_with_expired_token() {
+        let token = JwtKeys::new(b"secret")
+            .encode(&Claims::new(OwnerId(1), chrono::Duration::hours(-1)))
+            .unwrap();
+        let res = send_with_token(&token).await;
+        assert_eq!(StatusCode::UNAUTHORIZED, res.status());
+        assert_eq!("Bearer", res.headers()[WWW_AUTHENTICATE]);
+        let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        assert_eq!("Expired bearer token", body);
+    }
+
+    #[tokio::test]
+    async fn should_return_401_with_tampered_token() {
+        let token = JwtKeys::new(b"another secret")
+            .encode(&Claims::new(OwnerId(1), chrono::Duration::minutes(5)))
+            .unwrap();
+        let res = send_with_token(&token).await;
+        assert_eq!(StatusCode::UNAUTHORIZED, res.status());
+
+        let token = JwtKeys::new(b"secret")
+            .encode(&Claims::new(OwnerId(2), chrono::Duration::minutes(5)))
+            .unwrap();
+        let forged = JwtKeys::new(b"secret")
+            .encode(&Claims::new(OwnerId(1), chrono::Duration::minutes(5)))
+            .unwrap();
+        let (_, signature) = token.rsplit_once('.').unwrap();
+        let (payload, _) = forged.rsplit_once('.').unwrap();
+        let res = send_with_token(&format!("{}.{}", payload, signature)).await;
+        assert_eq!(StatusCode::UNAUTHORIZED, res.status());
+        let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        assert_eq!("Invalid bearer token", body);
+    }
+
+    #[cfg(feature = "dev-token")]
+    #[tokio::test]
+    async fn should_issue_dev_token() {
+        use crate::handlers::auth::TokenResponse;
+
+        let keys = JwtKeys::new(b"secret");
+        let req = build_req_with_json("/auth/token", Method::POST, r#"{ "sub": 1 }"#.to_string());
+        let res = create_app_with_options(
+            TodoRepositoryForMemory::new(vec![]),
+            LabelRepositoryForMemory::new(),
+            HealthRepositoryForMemory::new(),
+            AppOptions {
+                jwt_keys: Some(keys.clone()),
+                ..Default::default()
+            },
+        )
+        .oneshot(req)
+        .await
+        .unwrap();
+        assert_eq!(StatusCode::OK, res.status());
+        let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        let TokenResponse { token } = serde_json::from_slice(&body).unwrap();
+        assert_eq!(OwnerId(1), keys.decode(&token).unwrap().sub);
+    }
+
+    #[tokio::test]
+    async fn should_reject_non_numeric_todo_id() {
+        let req = build_req_with_empty(Method::GET, "/todos/abc");
+        let res = create_app(
+            TodoRepositoryForMemory::new(vec![]),
+            LabelRepositoryForMemory::new(),
+            HealthRepositoryForMemory::new(),
+        )
+        .oneshot(req)
+        .await
+        .unwrap();
+        assert_eq!(StatusCode::BAD_REQUEST, res.status());
+    }
+
+    #[tokio::test]
+    async fn should_get_all_todos() {
+        let labels = vec![Label::new(LabelId(1000), "test label".to_string())];
+        let todo_repo = TodoRepositoryForMemory::new(labels.clone());
+        let created = todo_repo
+            .create(CreateTodo::new(
+                "should_get_all_todo".to_string(),
+                vec![LabelId(1000)],
+            ))
+            .await
+            .expect("failed create todo");
+        let expected =
+            vec![
+                TodoEntity::new(TodoId(1), "should_get_all_todo".to_string(), false, labels)
+                    .with_timestamps_of(&created),
+            ];
+        let app = create_app(
+            todo_repo,
+            LabelRepositoryForMemory::new(),
+            HealthRepositoryForMemory::new(),
+        );
+
+        let req = build_req_with_empty(Method::GET, "/todos?expand=labels");
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(
+            Paginated::new(expected.clone(), 1, 1, 0),
+            res_to_page(res).await
+        );
+
+        let req = build_req_with_empty(Method::GET, "/todos?expand=labels&envelope=false");
+        let res = app.clone().oneshot(req).await.unwrap();
+        let bytes = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        let body: String = String::from_utf8(bytes.to_vec()).unwrap();
+        let todos: Vec<TodoEntity> = serde_json::from_str(&body)
+            .unwrap_or_else(|_| panic!("cannot convert Todo instances. body: {}", body));
+        assert_eq!(expected, todos);
+
+        // labels are only embedded on request
+        for uri in [
+            "/todos",
+            "/todos?page=1",
+            "/todos?q=should_get_all_todo&fuzzy=true",
+        ] {
+            let req = build_req_with_empty(Method::GET, uri);
+            let res = app.clone().oneshot(req).await.unwrap();
+            let todos = res_to_page(res).await.items;
+            let expected: Vec<TodoEntity> = expected
+                .iter()
+                .cloned()
+                .map(TodoEntity::without_labels)
+                .collect();
+            assert_eq!(expected, todos, "{}", uri);
+        }
+    }
+
+    #[tokio::test]
+    async fn should_get_todos_by_ids() {
+        let todo_repo = TodoRepositoryForMemory::new(vec![]);
+        for text in ["first", "second", "third"] {
+            todo_repo
+                .create(CreateTodo::new(text.to_string(), vec![]))
+                .await
+                .expect("failed create todo");
+        }
+        let app = create_app(
+            todo_repo,
+            LabelRepositoryForMemory::new(),
+            HealthRepositoryForMemory::new(),
+        );
+
+        let req = build_req_with_empty(Method::GET, "/todos?ids=3,10,1");
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(StatusCode::OK, res.status());
+        let page = res_to_page(res).await;
+        let texts: Vec<&str> = page.items.iter().map(|todo| todo.text.as_str()).collect();
+        assert_eq!(vec!["third", "first"], texts);
+        assert_eq!(2, page.total);
+
+        let ids = vec!["1"; MAX_LIMIT as usize + 1].join(",");
+        let req = build_req_with_empty(Method::GET, &format!("/todos?ids={}", ids));
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(StatusCode::BAD_REQUEST, res.status());
+
+        let req = build_req_with_empty(Method::GET, "/todos?ids=1,x");
+        let res = app.oneshot(req).await.unwrap();
+        assert_eq!(StatusCode::BAD_REQUEST, res.status());
+    }
+
+    #[tokio::test]
+    async fn should_sort_todos_by_created_at() {
+        let todo_repo = TodoRepositoryForMemory::new(vec![]);
+        for text in ["first", "second"] {
+            todo_repo
+                .create(CreateTodo::new(text.to_string(), vec![]))
+                .await
+                .expect("failed create todo");
+        }
+        let app = create_app(
+            todo_repo,
+            LabelRepositoryForMemory::new(),
+            HealthRepositoryForMemory::new(),
+        );
+
+        for (path, expected) in [
+            ("/todos", vec!["second", "first"]),
+            ("/todos?sort=created_at&order=desc", vec!["second", "first"]),
+            ("/todos?sort=created_at&order=asc", vec!["first", "second"]),
+            (
+                "/todos?sort=created_at&order=asc&page=1",
+                vec!["first", "second"],
+            ),
+            ("/todos?sort=id&order=asc&limit=1", vec!["first"]),
+            ("/todos?order=asc&limit=1&offset=1", vec!["second"]),
+        ] {
+            let req = build_req_with_empty(Method::GET, path);
+            let todos = res_to_page(app.clone().oneshot(req).await.unwrap())
+                .await
+                .items;
+            let texts: Vec<&str> = todos.iter().map(|todo| todo.text.as_str()).collect();
+            assert_eq!(expected, texts, "{}", path);
+        }
+
+        let req = build_req_with_empty(Method::GET, "/todos?sort=priority");
+        let res = app.oneshot(req).await.unwrap();
+        assert_eq!(StatusCode::BAD_REQUEST, res.status());
+    }
+
+    #[tokio::test]
+    async fn should_return_304_when_todos_not_modified() {
+        let todo_repo = TodoRepositoryForMemory::new(vec![]);
+        todo_repo
+            .create(CreateTodo::new("should_return_304".to_string(), vec![]))
+            .await
+            .expect("failed create todo");
+        let app = create_app(
+            todo_repo,
+            LabelRepositoryForMemory::new(),
+            HealthRepositoryForMemory::new(),
+        );
+        let build_req = |since: &HeaderValue| -> Request<Body> {
+            Request::builder()
+                .uri("/todos")
+                .method(Method::GET)
+                .header(&OWNER_ID_HEADER, "0")
+                .header(IF_MODIFIED_SINCE, since)
+                .body(Body::empty())
+                .unwrap()
+        };
+
+        let req = build_req_with_empty(Method::GET, "/todos");
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(StatusCode::OK, res.status());
+        let last_modified = res
+            .headers()
+            .get(LAST_MODIFIED)
+            .expect("Last-Modified is not set")
+            .clone();
+
+        let res = app
+            .clone()
+            .oneshot(build_req(&last_modified))
+            .await
+            .unwrap();
+        assert_eq!(StatusCode::NOT_MODIFIED, res.status());
+
+        // Last-Modified has second precision
+        tokio::time::sleep(std::time::Duration::from_millis(1100)).await;
+        let req = build_req_with_json(
+            "/todos/1",
+            Method::PATCH,
+            r#"{ "completed": true }"#.to_string(),
+        );
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(StatusCode::CREATED, res.status());
+
+        let res = app.oneshot(build_req(&last_modified)).await.unwrap();
+        assert_eq!(StatusCode::OK, res.status());
+        assert_ne!(last_modified, res.headers()[LAST_MODIFIED]);
+        assert!(res_to_page(res).await.items[0].completed);
+    }
+
+    #[cfg(feature = "schema")]
+    #[tokio::test]
+    async fn should_return_todo_json_schema() {
+        let req = build_req_with_empty(Method::GET, "/schema/todo");
+        let res = create_app(
+            TodoRepositoryForMemory::new(vec![]),
+            LabelRepositoryForMemory::new(),
+            HealthRepositoryForMemory::new(),
+        )
+        .oneshot(req)
+        .await
+        .unwrap();
+        assert_eq!(StatusCode::OK, res.status());
+        let bytes = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        let schema: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+        let text = &schema["properties"]["text"];
+        assert_eq!(serde_json::json!(1), text["minLength"]);
+        assert_eq!(serde_json::json!(100), text["maxLength"]);
+    }
+
+    #[cfg(feature = "graphql")]
+    #[tokio::test]
+    async fn should_query_todos_with_graphql() {
+        let labels = vec![Label::new(LabelId(1), "test label".to_string())];
+        let todo_repo = TodoRepositoryForMemory::new(labels);
+        todo_repo
+            .create(CreateTodo::new(
+                "should_query".to_string(),
+                vec![LabelId(1)],
+            ))
+            .await
+            .expect("failed create todo");
+        let app = create_app(
+            todo_repo,
+            LabelRepositoryForMemory::new(),
+            HealthRepositoryForMemory::new(),
+        );
+
+        let req = build_req_with_json(
+            "/graphql",
+            Method::POST,
+            r#"{ "query": "{ todos { id text labels { name } } }" }"#.to_string(),
+        );
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(StatusCode::OK, res.status());
+        let bytes = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(
+            serde_json::json!({
+                "data": {
+                    "todos": [{ "id": 1, "text": "should_query", "labels": [{ "name": "test label" }] }]
+                }
+            }),
+            body
+        );
+
+        let req = build_req_with_json(
+            "/graphql",
+            Method::POST,
+            r#"{ "query": "mutation { updateTodo(id: 1, completed: true) { completed } deleteTodo(id: 1) }" }"#
+                .to_string(),
+        );
+        let res = app.oneshot(req).await.unwrap();
+        let bytes = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(
+            serde_json::json!({
+                "data": { "updateTodo": { "completed": true }, "deleteTodo": true }
+            }),
+            body
+        );
+    }
+
+    async fn todo_error_status(error: RepositoryError, req: Request<Body>) -> StatusCode {
+        create_app(
+            FailingTodoRepository::new(error),
+            LabelRepositoryForMemory::new(),
+            HealthRepositoryForMemory::new(),
+        )
+        .oneshot(req)
+        .await
+        .unwrap()
+        .status()
+    }
+
+    async fn label_error_status(error: RepositoryError, req: Request<Body>) -> StatusCode {
+        create_app(
+            TodoRepositoryForMemory::new(vec![]),
+            FailingLabelRepository::new(error),
+            HealthRepositoryForMemory::new(),
+        )
+        .oneshot(req)
+        .await
+        .unwrap()
+        .status()
+    }
+
+    fn unexpected() -> RepositoryError {
+        RepositoryError::Unexpected("unexpected".to_string())
+    }
+
+    #[tokio::test]
+    async fn should_reject_non_positive_ids_before_repository() {
+        for (method, path) in [
+            (Method::GET, "/todos/0"),
+            (Method::GET, "/todos/-5"),
+            (Method::DELETE, "/todos/0"),
+            (Method::GET, "/todos/-1/history"),
+            (Method::POST, "/todos/0/duplicate"),
+        ] {
+            let req = build_req_with_empty(method, path);
+            assert_eq!(
+                StatusCode::BAD_REQUEST,
+                todo_error_status(unexpected(), req).await,
+                "{}",
+                path
+            );
+        }
+        for (method, path) in [
+            (Method::DELETE, "/labels/0"),
+            (Method::POST, "/labels/1/merge/-2"),
+        ] {
+            let req = build_req_with_empty(method, path);
+            assert_eq!(
+                StatusCode::BAD_REQUEST,
+                label_error_status(unexpected(), req).await,
+                "{}",
+                path
+            );
+        }
+
+        let req = build_req_with_empty(Method::GET, "/todos/5");
+        assert_eq!(
+            StatusCode::NOT_FOUND,
+            todo_error_status(RepositoryError::NotFound(TodoId(5).into()), req).await
+        );
+    }
+
+    #[tokio::test]
+    async fn should_return_404_when_create_todo_label_not_found() {
+        let req = build_req_with_json(
+            "/todos",
+            Method::POST,
+            r#"{ "text": "todo", "labels": [1] }"#.to_string(),
+        );
+        let status = todo_error_status(RepositoryError::NotFound(TodoId(1).into()), req).await;
+        assert_eq!(StatusCode::NOT_FOUND, status);
+
+        let req = build_req_with_json(
+            "/todos",
+            Method::POST,
+            r#"{ "text": "todo", "labels": [1] }"#.to_string(),
+        );
+        let error = RepositoryError::MissingReference("todo_labels_label_id_fkey".to_string());
+        assert_eq!(StatusCode::NOT_FOUND, todo_error_status(error, req).await);
+    }
+
+    #[tokio::test]
+    async fn should_keep_serving_after_unknown_label() {
+        let app = create_app(
+            TodoRepositoryForMemory::new(vec![]),
+            LabelRepositoryForMemory::new(),
+            HealthRepositoryForMemory::new(),
+        );
+
+        let req = build_req_with_json(
+            "/todos",
+            Method::POST,
+            r#"{ "text": "todo", "labels": [999] }"#.to_string(),
+        );
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(StatusCode::NOT_FOUND, res.status());
+
+        let req = build_req_with_json("/todos", Method::POST, r#"{ "text": "todo" }"#.to_string());
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(StatusCode::CREATED, res.status());
+
+        let req = build_req_with_json(
+            "/todos/1",
+            Method::PATCH,
+            r#"{ "labels": [999] }"#.to_string(),
+        );
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(StatusCode::NOT_FOUND, res.status());
+
+        let req = build_req_with_empty(Method::GET, "/todos");
+        let res = app.oneshot(req).await.unwrap();
+        assert_eq!(StatusCode::OK, res.status());
+        assert_eq!(1, res_to_page(res).await.items.len());
+    }
+
+    #[tokio::test]
+    async fn should_return_404_when_find_todo_not_found() {
+        let req = build_req_with_empty(Method::GET, "/todos/1");
+        let status = todo_error_status(RepositoryError::NotFound(TodoId(1).into()), req).await;
+        assert_eq!(StatusCode::NOT_FOUND, status);
+    }
+
+    #[tokio::test]
+    async fn should_return_504_when_deadline_exceeded() {
+        let req = build_req_with_empty(Method::GET, "/todos/1");
+        let status = todo_error_status(RepositoryError::DeadlineExceeded, req).await;
+        assert_eq!(StatusCode::GATEWAY_TIMEOUT, status);
+    }
+
+    #[tokio::test]
+    async fn should_return_503_with_retry_after_when_unavailable() {
+        let req = build_req_with_empty(Method::GET, "/todos");
+        let res = create_app(
+            FailingTodoRepository::new(RepositoryError::Unavailable("pool timed out".to_string())),
+            LabelRepositoryForMemory::new(),
+            HealthRepositoryForMemory::new(),
+        )
+        .oneshot(req)
+        .await
+        .unwrap();
+        assert_eq!(StatusCode::SERVICE_UNAVAILABLE, res.status());
+        assert_eq!("5", res.headers()[RETRY_AFTER]);
+    }
+
+    #[tokio::test]
+    async fn should_report_orphans_to_admins() {
+        let orphan = OrphanLink {
+            id: 1,
+            todo_id: TodoId(2),
+            label_id: LabelId(3),
+        };
+        let orphans = Orphans {
+            missing_todos: vec![orphan],
+            missing_labels: vec![],
+        };
+        let app = |admin_key: Option<AdminKey>| {
+            create_app_with_options(
+                TodoRepositoryForMemory::new(vec![]),
+                LabelRepositoryForMemory::new(),
+                HealthRepositoryForMemory::new().with_orphans(orphans.clone()),
+                AppOptions {
+                    admin_key,
+                    ..Default::default()
+                },
+            )
+        };
+        let req = |key: Option<&str>| {
+            let builder = Request::get("/admin/orphans");
+            let builder = match key {
+                Some(key) => builder.header(&API_KEY_HEADER, key),
+                None => builder,
+            };
+            builder.body(Body::empty()).unwrap()
+        };
+
+        let res = app(None).oneshot(req(Some("secret"))).await.unwrap();
+        assert_eq!(StatusCode::NOT_FOUND, res.status());
+        let app = app(Some(AdminKey::new("secret")));
+        for key in [None, Some("wrong")] {
+            let res = app.clone().oneshot(req(key)).await.unwrap();
+            assert_eq!(StatusCode::UNAUTHORIZED, res.status());
+        }
+        let res = app.oneshot(req(Some("secret"))).await.unwrap();
+        assert_eq!(StatusCode::OK, res.status());
+        let bytes = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        let body: Orphans = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(orphans, body);
+    }
+
+    #[tokio::test]
+    async fn should_set_cache_control() {
+        let app = create_app_with_options(
+            TodoRepositoryForMemory::new(vec![]),
+            LabelRepositoryForMemory::new(),
+            HealthRepositoryForMemory::new(),
+            AppOptions {
+                cache_max_age: CacheMaxAge(Duration::from_secs(60)),
+                ..Default::default()
+            },
+        );
+        let req = build_req_with_empty(Method::GET, "/labels");
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(StatusCode::OK, res.status());
+        assert_eq!("max-age=60", res.headers()[CACHE_CONTROL]);
+
+        let req = build_req_with_json(
+            "/labels",
+            Method::POST,
+            r#"{ "name": "should_set_cache_control" }"#.to_string(),
+        );
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(StatusCode::CREATED, res.status());
+        assert_eq!("no-store", res.headers()[CACHE_CONTROL]);
+
+        let req = build_req_with_empty(Method::GET, "/todos/1");
+        let res = app.oneshot(req).await.unwrap();
+        assert_eq!(StatusCode::NOT_FOUND, res.status());
+        assert_eq!("no-store", res.headers()[CACHE_CONTROL]);
+    }
+
+    #[tokio::test]
+    async fn should_vary_on_origin_and_format() {
+        let app = create_app(
+            TodoRepositoryForMemory::new(vec![]),
+            LabelRepositoryForMemory::new(),
+            HealthRepositoryForMemory::new(),
+        );
+        let mut req = build_req_with_empty(Method::GET, "/todos");
+        req.headers_mut()
+            .insert(ORIGIN, HeaderValue::from_static("http://localhost:3000"));
+        let res = app.oneshot(req).await.unwrap();
+        assert_eq!(StatusCode::OK, res.status());
+        assert_eq!(
+            "http://localhost:3000",
+            res.headers()[ACCESS_CONTROL_ALLOW_ORIGIN]
+        );
+        let vary: Vec<&str> = res
+            .headers()
+            .get_all(VARY)
+            .iter()
+            .flat_map(|value| value.to_str().unwrap().split(','))
+            .map(str::trim)
+            .collect();
+        for name in ["origin", "accept", "accept-encoding"] {
+            assert_eq!(
+                1,
+                vary.iter().filter(|vary| **vary == name).count(),
+                "{} in {:?}",
+                name,
+                vary
+            );
+        }
+    }
+
+    fn build_preflight(path: &str, method: Method) -> Request<Body> {
+        Request::builder()
+            .uri(path)
+            .method(Method::OPTIONS)
+            .header(ORIGIN, "http://localhost:3000")
+            .header(ACCESS_CONTROL_REQUEST_METHOD, method.as_str())
+            .body(Body::empty())
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn should_answer_preflight_with_default_cors() {
+        let app = create_app(
+            TodoRepositoryForMemory::new(vec![]),
+            LabelRepositoryForMemory::new(),
+            HealthRepositoryForMemory::new(),
+        );
+        let res = app
+            .oneshot(build_preflight("/todos/1", Method::PATCH))
+            .await
+            .unwrap();
+        assert_eq!(StatusCode::OK, res.status());
+        assert_eq!(
+            "GET,POST,PUT,PATCH,DELETE",
+            res.headers()[ACCESS_CONTROL_ALLOW_METHODS]
+        );
+        assert_eq!(
+            "authorization,content-type,if-match,x-owner-id,x-api-key",
+            res.headers()[ACCESS_CONTROL_ALLOW_HEADERS]
+        );
+        assert!(res
+            .headers()
+            .get(ACCESS_CONTROL_ALLOW_CREDENTIALS)
+            .is_none());
+    }
+
+    #[tokio::test]
+    async fn should_answer_preflight_with_configured_cors() {
+        let app = create_app_with_options(
+            TodoRepositoryForMemory::new(vec![]),
+            LabelRepositoryForMemory::new(),
+            HealthRepositoryForMemory::new(),
+            AppOptions {
+                cors_methods: vec![Method::GET, Method::POST],
+                cors_headers: vec![CONTENT_TYPE, HeaderName::from_static("x-custom")],
+                cors_allow_credentials: true,
+                ..AppOptions::default()
+            },
+        );
+        let res = app
+            .oneshot(build_preflight("/todos", Method::POST))
+            .await
+            .unwrap();
+        assert_eq!(StatusCode::OK, res.status());
+        assert_eq!(
+            "http://localhost:3000",
+            res.headers()[ACCESS_CONTROL_ALLOW_ORIGIN]
+        );
+        assert_eq!("GET,POST", res.headers()[ACCESS_CONTROL_ALLOW_METHODS]);
+        assert_eq!(
+            "content-type,x-custom",
+            res.headers()[ACCESS_CONTROL_ALLOW_HEADERS]
+        );
+        assert_eq!("true", res.headers()[ACCESS_CONTROL_ALLOW_CREDENTIALS]);
+    }
+
+    #[tokio::test]
+    async fn should_ignore_trailing_slash() {
+        let todo_repo = TodoRepositoryForMemory::new(vec![]);
+        todo_repo
+            .create(CreateTodo::new(
+                "should_ignore_trailing_slash".to_string(),
+                vec![],
+            ))
+            .await
+            .expect("failed create todo");
+        let app = create_app(
+            todo_repo,
+            LabelRepositoryForMemory::new(),
+            HealthRepositoryForMemory::new(),
+        );
+
+        for path in ["/todos", "/labels", "/todos/1", "/todos/2"] {
+            let res = app
+                .clone()
+                .oneshot(build_req_with_empty(Method::GET, path))
+                .await
+                .unwrap();
+            let status = res.status();
+            let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+            let slashed = app
+                .clone()
+                .oneshot(build_req_with_empty(Method::GET, &format!("{}/", path)))
+                .await
+                .unwrap();
+            assert_eq!(status, slashed.status(), "{}/", path);
+            assert_eq!(
+                body,
+                hyper::body::to_bytes(slashed.into_body()).await.unwrap(),
+                "{}/",
+                path
+            );
+        }
+
+        let req = build_req_with_empty(Method::GET, "/todos/?q=nothing");
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert!(res_to_page(res).await.items.is_empty());
+
+        let req = build_req_with_json(
+            "/labels/",
+            Method::POST,
+            r#"{ "name": "should_ignore_trailing_slash" }"#.to_string(),
+        );
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(StatusCode::CREATED, res.status());
+
+        let res = app
+            .oneshot(build_req_with_empty(Method::GET, "/"))
+            .await
+            .unwrap();
+        assert_eq!(StatusCode::OK, res.status());
+    }
+
+    #[tokio::test]
+    async fn should_set_response_time() {
+        let app = create_app(
+            TodoRepositoryForMemory::new(vec![]),
+            LabelRepositoryForMemory::new(),
+            HealthRepositoryForMemory::new(),
+        );
+        for (path, status) in [
+            ("/todos", StatusCode::OK),
+            ("/todos/1", StatusCode::NOT_FOUND),
+        ] {
+            let req = build_req_with_empty(Method::GET, path);
+            let res = app.clone().oneshot(req).await.unwrap();
+            assert_eq!(status, res.status());
+            let millis: f64 = res.headers()[&X_RESPONSE_TIME]
+                .to_str()
+                .unwrap()
+                .parse()
+                .unwrap();
+            assert!(millis >= 0.0);
+        }
+    }
+
+    #[tokio::test]
+    async fn should_answer_panicking_handler_with_500() {
+        async fn panicking() -> StatusCode {
+            panic!("deliberate panic")
+        }
+        let app = Router::new()
+            .route("/panic", get(panicking))
+            .layer(CatchPanicLayer::custom(handle_panic));
+        let req = build_req_with_empty(Method::GET, "/panic");
+        let res = app.oneshot(req).await.unwrap();
+
+        assert_eq!(StatusCode::INTERNAL_SERVER_ERROR, res.status());
+        let bytes = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        let body: PanicError = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!("Internal Server Error", body.error);
+
+        let payload =
+            std::panic::catch_unwind(|| -> StatusCode { panic!("{} panic", "formatted") })
+                .unwrap_err();
+        assert_eq!("formatted panic", panic_message(payload.as_ref()));
+    }
+
+    #[tokio::test]
+    async fn should_return_500_when_all_todos_fails() {
+        let req = build_req_with_empty(Method::GET, "/todos");
+        let status = todo_error_status(unexpected(), req).await;
+        assert_eq!(StatusCode::INTERNAL_SERVER_ERROR, status);
+    }
+
+    #[tokio::test]
+    async fn should_return_500_when_todos_by_label_fails() {
+        let req = build_req_with_empty(Method::GET, "/todos/by-label");
+        let status = todo_error_status(unexpected(), req).await;
+        assert_eq!(StatusCode::INTERNAL_SERVER_ERROR, status);
+    }
+
+    #[tokio::test]
+    async fn should_name_failing_operation_in_500_body() {
+        let res = create_app(
+            FailingTodoRepository::new(unexpected()).with_context("find todo"),
+            LabelRepositoryForMemory::new(),
+            HealthRepositoryForMemory::new(),
+        )
+        .oneshot(build_req_with_empty(Method::GET, "/todos/1"))
+        .await
+        .unwrap();
+        assert_eq!(StatusCode::INTERNAL_SERVER_ERROR, res.status());
+        let bytes = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        assert_eq!("fail find todo", String::from_utf8(bytes.to_vec()).unwrap());
+    }
+
+    #[tokio::test]
+    async fn should_return_404_when_update_todo_not_found() {
+        let req = build_req_with_json(
+            "/todos/1",
+            Method::PATCH,
+            r#"{ "completed": true }"#.to_string(),
+        );
+        let status = todo_error_status(RepositoryError::NotFound(TodoId(1).into()), req).await;
+        assert_eq!(StatusCode::NOT_FOUND, status);
+    }
+
+    #[tokio::test]
+    async fn should_return_500_when_delete_todo_fails() {
+        let req = build_req_with_empty(Method::DELETE, "/todos/1");
+        let status = todo_error_status(unexpected(), req).await;
+        assert_eq!(StatusCode::INTERNAL_SERVER_ERROR, status);
+    }
+
+    #[tokio::test]
+    async fn should_get_todos_by_label() {
+        let label_1 = Label::new(LabelId(1), "label 1".to_string());
+        let label_2 = Label::new(LabelId(2), "label 2".to_string());
+        let todo_repo = TodoRepositoryForMemory::new(vec![label_1.clone(), label_2.clone()]);
+        let multi_label_todo = todo_repo
+            .create(CreateTodo::new(
+                "multi label".to_string(),
+                vec![LabelId(1), LabelId(2)],
+            ))
+            .await
+            .expect("failed create todo");
+        let unlabeled_todo = todo_repo
+            .create(CreateTodo::new("unlabeled".to_string(), vec![]))
+            .await
+            .expect("failed create todo");
+        let expected = TodosByLabel {
+            labels: vec![
+                LabelGroup {
+                    label: label_1,
+                    todos: vec![multi_label_todo.clone()],
+                },
+                LabelGroup {
+                    label: label_2,
+                    todos: vec![multi_label_todo],
+                },
+            ],
+            unlabeled: vec![unlabeled_todo],
+        };
+        let req = build_req_with_empty(Method::GET, "/todos/by-label");
+        let res = create_app(
+            todo_repo,
+            LabelRepositoryForMemory::new(),
+            HealthRepositoryForMemory::new(),
+        )
+        .oneshot(req)
+        .await
+        .unwrap();
+
+        let bytes = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        let body: String = String::from_utf8(bytes.to_vec()).unwrap();
+        let todos: TodosByLabel = serde_json::from_str(&body)
+            .unwrap_or_else(|_| panic!("cannot convert TodosByLabel instance. body: {}", body));
+        assert_eq!(expected, todos);
+    }
+
+    #[tokio::test]
+    async fn should_summarize_todos() {
+        let labels = vec![Label::new(LabelId(1000), "work".to_string())];
+        let todo_repo = TodoRepositoryForMemory::new(labels);
+        let app = create_app(
+            todo_repo.clone(),
+            LabelRepositoryForMemory::new(),
+            HealthRepositoryForMemory::new(),
+        );
+
+        let req = build_req_with_empty(Method::GET, "/todos/summary");
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(StatusCode::OK, res.status());
+        let bytes = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        let summary: TodoSummary = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(TodoSummary::new(0, 0), summary);
+        assert_eq!(0.0, summary.completion_rate);
+
+        for (text, labels, completed) in [
+            ("first", vec![LabelId(1000)], true),
+            ("second", vec![LabelId(1000)], false),
+            ("third", vec![], true),
+            ("fourth", vec![], false),
+        ] {
+            let todo = todo_repo
+                .create(CreateTodo::new(text.to_string(), labels))
+                .await
+                .expect("failed create todo");
+            if completed {
+                todo_repo
+                    .update(todo.id, UpdateTodo::new(None, Some(true), None))
+                    .await
+                    .expect("failed update todo");
+            }
+        }
+        todo_repo
+            .update(TodoId(4), UpdateTodo::archive(true))
+            .await
+            .expect("failed archive todo");
+
+        let req = build_req_with_empty(Method::GET, "/todos/summary");
+        let res = app.clone().oneshot(req).await.unwrap();
+        let bytes = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        let summary: TodoSummary = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(
+            TodoSummary {
+                total: 3,
+                completed: 2,
+                active: 1,
+                completion_rate: 2.0 / 3.0,
+            },
+            summary
+        );
+
+        let req = build_req_with_empty(Method::GET, "/todos/summary?label_id=1000");
+        let res = app.oneshot(req).await.unwrap();
+        let bytes = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        let summary: TodoSummary = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(TodoSummary::new(2, 1), summary);
+        assert_eq!(0.5, summary.completion_rate);
+    }
+
+    #[tokio::test]
+    async fn should_touch_todo() {
+        let labels = vec![Label::new(LabelId(1), "label".to_string())];
+        let todo_repo = TodoRepositoryForMemory::new(labels);
+        let created = todo_repo
+            .create(CreateTodo::new(
+                "should_touch_todo".to_string(),
+                vec![LabelId(1)],
+            ))
+            .await
+            .expect("failed create todo");
+        let app = create_app(
+            todo_repo,
+            LabelRepositoryForMemory::new(),
+            HealthRepositoryForMemory::new(),
+        );
+
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        let req = build_req_with_empty(Method::POST, "/todos/1/touch");
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(StatusCode::OK, res.status());
+        let touched = res_to_todo(res).await;
+        assert!(touched.updated_at > created.updated_at);
+        let updated_at = touched.updated_at;
+        assert_eq!(
+            TodoEntity {
+                updated_at,
+                ..created
+            },
+            touched
+        );
+
+        let req = build_req_with_empty(Method::POST, "/todos/2/touch");
+        let res = app.oneshot(req).await.unwrap();
+        assert_eq!(StatusCode::NOT_FOUND, res.status());
+    }
+
+    #[tokio::test]
+    async fn should_duplicate_todo() {
+        let labels = vec![
+            Label::new(LabelId(1000), "first".to_string()),
+            Label::new(LabelId(1001), "second".to_string()),
+        ];
+        let todo_repo = TodoRepositoryForMemory::new(labels.clone());
+        let todo = todo_repo
+            .create(CreateTodo::new(
+                "should_duplicate_todo".to_string(),
+                vec![LabelId(1000), LabelId(1001)],
+            ))
+            .await
+            .expect("failed create todo");
+        todo_repo
+            .update(todo.id, UpdateTodo::new(None, Some(true), None))
+            .await
+            .expect("failed update todo");
+        let app = create_app(
+            todo_repo,
+            LabelRepositoryForMemory::new(),
+            HealthRepositoryForMemory::new(),
+        );
+
+        let req = build_req_with_empty(Method::POST, "/todos/1/duplicate");
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(StatusCode::CREATED, res.status());
+        let copy = res_to_todo(res).await;
+        assert_eq!(TodoId(2), copy.id);
+        assert_eq!("should_duplicate_todo", copy.text);
+        assert_eq!(labels, copy.labels);
+        assert!(!copy.completed);
+
+        let req = build_req_with_empty(Method::POST, "/todos/9/duplicate");
+        let res = app.oneshot(req).await.unwrap();
+        assert_eq!(StatusCode::NOT_FOUND, res.status());
+    }
+
+    #[tokio::test]
+    async fn should_hide_archived_todos() {
+        let todo_repo = TodoRepositoryForMemory::new(vec![]);
+        todo_repo
+            .create(CreateTodo::new(
+                "should_hide_archived_todos".to_string(),
+                vec![],
+            ))
+            .await
+            .expect("failed create todo");
+        let app = create_app(
+            todo_repo,
+            LabelRepositoryForMemory::new(),
+            HealthRepositoryForMemory::new(),
+        );
+
+        let req = build_req_with_empty(Method::POST, "/todos/1/archive");
+        let res = app.clone().oneshot(req).await.unwrap();
+        let todo = res_to_todo(res).await;
+        assert!(todo.archived);
+
+        let req = build_req_with_empty(Method::GET, "/todos");
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(Vec::<TodoEntity>::new(), res_to_page(res).await.items);
+
+        let req = build_req_with_empty(Method::GET, "/todos?include_archived=true");
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(vec![todo], res_to_page(res).await.items);
+
+        let req = build_req_with_empty(Method::POST, "/todos/1/unarchive");
+        let res = app.clone().oneshot(req).await.unwrap();
+        let todo = res_to_todo(res).await;
+        assert!(!todo.archived);
+
+        let req = build_req_with_empty(Method::GET, "/todos");
+        let res = app.oneshot(req).await.unwrap();
+        assert_eq!(vec![todo], res_to_page(res).await.items);
+    }
+
+    #[tokio::test]
+    async fn should_filter_todos_by_query() {
+        let todo_repo = TodoRepositoryForMemory::new(vec![]);
+        for text in ["Buy grocery", "Write report"] {
+            todo_repo
+                .create(CreateTodo::new(text.to_string(), vec![]))
+                .await
+                .expect("failed create todo");
+        }
+        let app = create_app(
+            todo_repo,
+            LabelRepositoryForMemory::new(),
+            HealthRepositoryForMemory::new(),
+        );
+
+        let req = build_req_with_empty(Method::GET, "/todos?q=grocery");
+        let res = app.clone().oneshot(req).await.unwrap();
+        let todos = res_to_page(res).await.items;
+        assert_eq!(
+            vec![TodoId(1)],
+            todos.iter().map(|t| t.id).collect::<Vec<_>>()
+        );
+
+        let req = build_req_with_empty(Method::GET, "/todos?q=grocry");
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert!(res_to_page(res).await.items.is_empty());
+
+        let req = build_req_with_empty(Method::GET, "/todos?q=grocry&fuzzy=true");
+        let res = app.oneshot(req).await.unwrap();
+        let todos = res_to_page(res).await.items;
+        assert_eq!(
+            vec![TodoId(1)],
+            todos.iter().map(|t| t.id).collect::<Vec<_>>()
+        );
+    }
+
+    #[tokio::test]
+    async fn should_page_fuzzy_todos() {
+        let todo_repo = TodoRepositoryForMemory::new(vec![]);
+        for text in ["Buy grocery", "Groceries list"] {
+            todo_repo
+                .create(CreateTodo::new(text.to_string(), vec![]))
+                .await
+                .expect("failed create todo");
+        }
+        todo_repo
+            .update(TodoId(1), UpdateTodo::archive(true))
+            .await
+            .expect("failed archive todo");
+        let app = create_app(
+            todo_repo,
+            LabelRepositoryForMemory::new(),
+            HealthRepositoryForMemory::new(),
+        );
+
+        for (uri, expected, total) in [
+            ("/todos?q=grocerie&fuzzy=true", vec![2], 1),
+            (
+                "/todos?q=grocerie&fuzzy=true&include_archived=true",
+                vec![2, 1],
+                2,
+            ),
+            (
+                "/todos?q=grocerie&fuzzy=true&include_archived=true&limit=1&offset=1",
+                vec![1],
+                2,
+            ),
+            (
+                "/todos?q=grocerie&fuzzy=true&include_archived=true&page=1&limit=1",
+                vec![2],
+                2,
+            ),
+        ] {
+            let req = build_req_with_empty(Method::GET, uri);
+            let page = res_to_page(app.clone().oneshot(req).await.unwrap()).await;
+            let ids: Vec<TodoId> = page.items.iter().map(|t| t.id).collect();
+            let expected: Vec<TodoId> = expected.into_iter().map(TodoId).collect();
+            assert_eq!(expected, ids, "{}", uri);
+            assert_eq!(total, page.total, "{}", uri);
+        }
+
+        let req = build_req_with_empty(Method::GET, "/todos?q=grocerie&fuzzy=true&page=0");
+        let res = app.oneshot(req).await.unwrap();
+        assert_eq!(StatusCode::BAD_REQUEST, res.status());
+    }
+
+    #[tokio::test]
+    async fn should_filter_todos_by_labels_and_query() {
+        let labels = vec![
+            Label::new(LabelId(1), "work".to_string()),
+            Label::new(LabelId(2), "home".to_string()),
+        ];
+        let todo_repo = TodoRepositoryForMemory::new(labels);
+        for (text, labels) in [
+            ("Write report", vec![LabelId(1), LabelId(2)]),
+            ("Read report", vec![]),
+            ("Print report", vec![LabelId(2)]),
+        ] {
+            todo_repo
+                .create(CreateTodo::new(text.to_string(), labels))
+                .await
+                .expect("failed create todo");
+        }
+        todo_repo
+            .update(TodoId(3), UpdateTodo::new(None, Some(true), None))
+            .await
+            .expect("failed update todo");
+        let app = create_app(
+            todo_repo,
+            LabelRepositoryForMemory::new(),
+            HealthRepositoryForMemory::new(),
+        );
+
+        for (uri, expected) in [
+            ("/todos?label_ids=1,2&q=report&completed=false", vec![1]),
+            ("/todos?label_ids=1,2&sort=id&order=asc", vec![1, 3]),
+            ("/todos?label_ids=2&page=1", vec![3, 1]),
+            ("/todos?label_ids=1&completed=true", vec![]),
+            ("/todos?unlabeled=true&q=report", vec![2]),
+            ("/todos?unlabeled=true&page=1", vec![2]),
+            ("/todos?unlabeled=true&completed=true", vec![]),
+        ] {
+            let req = build_req_with_empty(Method::GET, uri);
+            let res = app.clone().oneshot(req).await.unwrap();
+            let todos = res_to_page(res).await.items;
+            let ids: Vec<TodoId> = todos.iter().map(|t| t.id).collect();
+            let expected: Vec<TodoId> = expected.into_iter().map(TodoId).collect();
+            assert_eq!(expected, ids, "{}", uri);
+        }
+
+        for uri in ["/todos?label_ids=1,x", "/todos?label_ids=1&unlabeled=true"] {
+            let req = build_req_with_empty(Method::GET, uri);
+            let res = app.clone().oneshot(req).await.unwrap();
+            assert_eq!(StatusCode::BAD_REQUEST, res.status(), "{}", uri);
+        }
+    }
+
+    #[tokio::test]
+    async fn should_complete_all_todos() {
+        let labels = vec![Label::new(LabelId(1), "test label".to_string())];
+        let todo_repo = TodoRepositoryForMemory::new(labels);
+        for labels in [vec![LabelId(1)], vec![]] {
+            todo_repo
+                .create(CreateTodo::new("should_complete_all".to_string(), labels))
+                .await
+                .expect("failed create todo");
+        }
+        let app = create_app(
+            todo_repo,
+            LabelRepositoryForMemory::new(),
+            HealthRepositoryForMemory::new(),
+        );
+        let completed_ids = |todos: Vec<TodoEntity>| -> Vec<TodoId> {
+            todos
+                .iter()
+                .filter(|todo| todo.completed)
+                .map(|todo| todo.id)
+                .collect()
+        };
+
+        let req = build_req_with_empty(Method::POST, "/todos/complete-all?label_id=1");
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(StatusCode::OK, res.status());
+        assert_eq!(UpdatedCount { updated: 1 }, res_to_updated_count(res).await);
+        let req = build_req_with_empty(Method::GET, "/todos");
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(vec![TodoId(1)], completed_ids(res_to_page(res).await.items));
+
+        let req = build_req_with_empty(Method::POST, "/todos/complete-all");
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(UpdatedCount { updated: 1 }, res_to_updated_count(res).await);
+        let req = build_req_with_empty(Method::GET, "/todos");
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(
+            vec![TodoId(2), TodoId(1)],
+            completed_ids(res_to_page(res).await.items)
+        );
+
+        let req = build_req_with_empty(Method::POST, "/todos/uncomplete-all?label_id=1");
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(UpdatedCount { updated: 1 }, res_to_updated_count(res).await);
+        let req = build_req_with_empty(Method::GET, "/todos");
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(vec![TodoId(2)], completed_ids(res_to_page(res).await.items));
+
+        let req = build_req_with_empty(Method::POST, "/todos/uncomplete-all");
+        let res = app.oneshot(req).await.unwrap();
+        assert_eq!(UpdatedCount { updated: 1 }, res_to_updated_count(res).await);
+    }
+
+    #[tokio::test]
+    async fn should_return_500_when_complete_all_todos_fails() {
+        let req = build_req_with_empty(Method::POST, "/todos/complete-all");
+        let status = todo_error_status(unexpected(), req).await;
+        assert_eq!(StatusCode::INTERNAL_SERVER_ERROR, status);
+    }
+
+    #[tokio::test]
+    async fn should_project_todo_fields() {
+        let todo_repo = TodoRepositoryForMemory::new(vec![]);
+        todo_repo
+            .create(CreateTodo::new("should_project".to_string(), vec![]))
+            .await
+            .expect("failed create todo");
+        let app = create_app(
+            todo_repo,
+            LabelRepositoryForMemory::new(),
+            HealthRepositoryForMemory::new(),
+        );
+
+        let req = build_req_with_empty(Method::GET, "/todos?fields=id,text");
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(StatusCode::OK, res.status());
+        let bytes = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(
+            serde_json::json!({
+                "items": [{ "id": 1, "text": "should_project" }],
+                "total": 1,
+                "limit": 1,
+                "offset": 0,
+            }),
+            body
+        );
+
+        let req = build_req_with_empty(Method::GET, "/todos/1?fields=completed,%20labels");
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(StatusCode::OK, res.status());
+        let bytes = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(
+            serde_json::json!({ "completed": false, "labels": [] }),
+            body
+        );
+
+        let req = build_req_with_empty(Method::GET, "/todos/1");
+        let res = app.oneshot(req).await.unwrap();
+        let todo = res_to_todo(res).await;
+        assert_eq!(
+            TodoEntity::new(TodoId(1), "should_project".to_string(), false, vec![])
+                .with_timestamps_of(&todo),
+            todo
+        );
+    }
+
+    #[tokio::test]
+    async fn should_reject_unknown_todo_field() {
+        let req = build_req_with_empty(Method::GET, "/todos?fields=id,owner");
+        let res = create_app(
+            TodoRepositoryForMemory::new(vec![]),
+            LabelRepositoryForMemory::new(),
+            HealthRepositoryForMemory::new(),
+        )
+        .oneshot(req)
+        .await
+        .unwrap();
+        assert_eq!(StatusCode::BAD_REQUEST, res.status());
+        let bytes = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        let body = String::from_utf8(bytes.to_vec()).unwrap();
+        assert_eq!(
+            "Unknown field: [owner], allowed fields are [id, text, completed, completed_at, archived, due_date, priority, position, created_at, updated_at, labels, labels_truncated]",
+            body
+        );
+    }
+
+    #[tokio::test]
+    async fn should_paginate_todos_with_link_header() {
+        let todo_repo = TodoRepositoryForMemory::new(vec![]);
+        for i in 1..=3 {
+            todo_repo
+                .create(CreateTodo::new(format!("should_paginate {}", i), vec![]))
+                .await
+                .expect("failed create todo");
+        }
+        let app = create_app(
+            todo_repo,
+            LabelRepositoryForMemory::new(),
+            HealthRepositoryForMemory::new(),
+        );
+
+        let req = build_req_with_empty(Method::GET, "/todos?q=paginate&page=1&page_size=2");
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(StatusCode::OK, res.status());
+        assert_eq!("2", res.headers()["x-total-pages"]);
+        assert_eq!(
+            concat!(
+                r#"</todos?q=paginate&page_size=2&page=2>; rel="next", "#,
+                r#"</todos?q=paginate&page_size=2&page=1>; rel="first", "#,
+                r#"</todos?q=paginate&page_size=2&page=2>; rel="last""#
+            ),
+            res.headers()[LINK]
+        );
+        let page = res_to_page(res).await;
+        assert_eq!((3, 2, 0), (page.total, page.limit, page.offset));
+        let ids: Vec<TodoId> = page.items.iter().map(|t| t.id).collect();
+        assert_eq!(vec![TodoId(3), TodoId(2)], ids);
+
+        let req = build_req_with_empty(Method::GET, "/todos?page=2&page_size=2");
+        let res = app.clone().oneshot(req).await.unwrap();
+        let link = res.headers()[LINK].to_str().unwrap().to_string();
+        assert!(link.contains(r#"</todos?page_size=2&page=1>; rel="prev""#));
+        assert!(!link.contains(r#"rel="next""#));
+        let ids: Vec<TodoId> = res_to_page(res).await.items.iter().map(|t| t.id).collect();
+        assert_eq!(vec![TodoId(1)], ids);
+
+        let req = build_req_with_empty(Method::GET, "/todos?page=0");
+        let res = app.oneshot(req).await.unwrap();
+        assert_eq!(StatusCode::BAD_REQUEST, res.status());
+    }
+
+    #[tokio::test]
+    async fn should_cap_todos_limit() {
+        let todo_repo = TodoRepositoryForMemory::new(vec![]);
+        for i in 1..=3 {
+            todo_repo
+                .create(CreateTodo::new(format!("should_cap_limit {}", i), vec![]))
+                .await
+                .expect("failed create todo");
+        }
+        let app = create_app(
+            todo_repo,
+            LabelRepositoryForMemory::new(),
+            HealthRepositoryForMemory::new(),
+        );
+
+        let req = build_req_with_empty(Method::GET, &format!("/todos?limit={}", MAX_LIMIT));
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(StatusCode::OK, res.status());
+        assert_eq!(3, res_to_page(res).await.items.len());
+
+        let req = build_req_with_empty(Method::GET, "/todos?limit=2&offset=1");
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(StatusCode::OK, res.status());
+        assert!(!res.headers().contains_key(LINK));
+        let page = res_to_page(res).await;
+        assert_eq!((3, 2, 1), (page.total, page.limit, page.offset));
+        let ids: Vec<TodoId> = page.items.iter().map(|t| t.id).collect();
+        assert_eq!(vec![TodoId(2), TodoId(1)], ids);
+
+        for path in [
+            format!("/todos?limit={}", MAX_LIMIT + 1),
+            "/todos?limit=100000".to_string(),
+            "/todos?limit=0".to_string(),
+            "/todos?offset=-1".to_string(),
+        ] {
+            let req = build_req_with_empty(Method::GET, &path);
+            let res = app.clone().oneshot(req).await.unwrap();
+            assert_eq!(StatusCode::BAD_REQUEST, res.status(), "{}", path);
+            let bytes = hyper::body::to_bytes(res.into_body()).await.unwrap();
+            let body = String::from_utf8(bytes.to_vec()).unwrap();
+            assert!(body.starts_with("Invalid pagination"), "{}", body);
+        }
+    }
+
+    #[tokio::test]
+    async fn should_search_todos() {
+        let labels = vec![Label::new(LabelId(1), "test label".to_string())];
+        let todo_repo = TodoRepositoryForMemory::new(labels);
+        let expected = todo_repo
+            .create(CreateTodo::new(
+                "should_search_todos".to_string(),
+                vec![LabelId(1)],
+            ))
+            .await
+            .expect("failed create todo");
+        todo_repo
+            .create(CreateTodo::new("other".to_string(), vec![]))
+            .await
+            .expect("failed create todo");
+        let req = build_req_with_json(
+            "/todos/search",
+            Method::POST,
+            r#"{ "q": "search", "label_ids": [1], "page": 1, "page_size": 10 }"#.to_string(),
+        );
+        let res = create_app(
+            todo_repo,
+            LabelRepositoryForMemory::new(),
+            HealthRepositoryForMemory::new(),
+        )
+        .oneshot(req)
+        .await
+        .unwrap();
+
+        let bytes = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        let body: String = String::from_utf8(bytes.to_vec()).unwrap();
+        let result: TodoSearchResult = serde_json::from_str(&body)
+            .unwrap_or_else(|_| panic!("cannot convert TodoSearchResult instance. body: {}", body));
+        assert_eq!(
+            TodoSearchResult {
+                items: vec![expected],
+                total: 1,
+                page: 1,
+                page_size: 10,
+            },
+            result
+        );
+    }
+
+    #[tokio::test]
+    async fn should_reject_invalid_search_page() {
+        let req = build_req_with_json(
+            "/todos/search",
+            Method::POST,
+            r#"{ "page": 0 }"#.to_string(),
+        );
+        let res = create_app(
+            TodoRepositoryForMemory::new(vec![]),
+            LabelRepositoryForMemory::new(),
+            HealthRepositoryForMemory::new(),
+        )
+        .oneshot(req)
+        .await
+        .unwrap();
+        assert_eq!(StatusCode::UNPROCESSABLE_ENTITY, res.status());
+    }
+
+    #[tokio::test]
+    async fn should_update_todo() {
+        let labels = vec![Label::new(LabelId(1000), "test label".to_string())];
+        let expected = TodoEntity::new(
+            TodoId(1),
+            "should_update_todo".to_string(),
+            false,
+            labels.clone(),
+        );
+        let todo_repo = TodoRepositoryForMemory::new(labels);
+        todo_repo
+            .create(CreateTodo::new(
+                "before_update_todo".to_string(),
+                vec![LabelId(1000)],
+            ))
+            .await
+            .expect("failed create todo");
+        let req = build_req_with_json(
+            "/todos/1",
+            Method::PATCH,
+            r#"
+        {
+            "text": "should_update_todo",
+            "completed": false
+        }"#
+            .to_string(),
+        );
+        let res = create_app(
+            todo_repo,
+            LabelRepositoryForMemory::new(),
+            HealthRepositoryForMemory::new(),
+        )
+        .oneshot(req)
+        .await
+        .unwrap();
+        let todo = res_to_todo(res).await;
+        assert_eq!(expected.with_timestamps_of(&todo), todo);
+    }
+
+    #[tokio::test]
+    async fn should_replace_todo_unlike_update() {
+        let labels = vec![
+            Label::new(LabelId(1000), "first".to_string()),
+            Label::new(LabelId(1001), "second".to_string()),
+        ];
+        let todo_repo = TodoRepositoryForMemory::new(labels.clone());
+        todo_repo
+            .create(
+                CreateTodo::new(
+                    "should_replace_todo".to_string(),
+                    vec![LabelId(1000), LabelId(1001)],
+                )
+                .with_priority(Some(3)),
+            )
+            .await
+            .expect("failed create todo");
+        let app = create_app(
+            todo_repo,
+            LabelRepositoryForMemory::new(),
+            HealthRepositoryForMemory::new(),
+        );
+
+        // PATCH leaves the omitted labels and priority as they are
+        let req = build_req_with_json(
+            "/todos/1",
+            Method::PATCH,
+            r#"{ "text": "patched" }"#.to_string(),
+        );
+        let todo = res_to_todo(app.clone().oneshot(req).await.unwrap()).await;
+        assert_eq!("patched", todo.text);
+        assert_eq!(labels, todo.labels);
+        assert_eq!(Some(3), todo.priority);
+
+        // PUT resets the labels to the given set and the omitted fields to their defaults
+        let req = build_req_with_json(
+            "/todos/1",
+            Method::PUT,
+            r#"{ "text": "replaced", "completed": true, "labels": [1001] }"#.to_string(),
+        );
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(StatusCode::OK, res.status());
+        let todo = res_to_todo(res).await;
+        assert_eq!("replaced", todo.text);
+        assert!(todo.completed);
+        assert_eq!(labels[1..].to_vec(), todo.labels);
+        assert_eq!(None, todo.priority);
+
+        let req = build_req_with_json(
+            "/todos/1",
+            Method::PUT,
+            r#"{ "text": "replaced" }"#.to_string(),
+        );
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(StatusCode::UNPROCESSABLE_ENTITY, res.status());
+        let bytes = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        let body = String::from_utf8(bytes.to_vec()).unwrap();
+        assert!(body.contains("completed"), "{}", body);
+        assert!(body.contains("labels"), "{}", body);
+
+        let req = build_req_with_json(
+            "/todos/9",
+            Method::PUT,
+            r#"{ "text": "replaced", "completed": false, "labels": [] }"#.to_string(),
+        );
+        let res = app.oneshot(req).await.unwrap();
+        assert_eq!(StatusCode::NOT_FOUND, res.status());
+    }
+
+    #[tokio::test]
+    async fn should_reject_empty_update() {
+        let todo_repo = TodoRepositoryForMemory::new(vec![]);
+        todo_repo
+            .create(CreateTodo::new(
+                "should_reject_empty_update".to_string(),
+                vec![],
+            ))
+            .await
+            .expect("failed create todo");
+        let app = create_app(
+            todo_repo,
+            LabelRepositoryForMemory::new(),
+            HealthRepositoryForMemory::new(),
+        );
+        let update = |body: &str| {
+            let req = build_req_with_json("/todos/1", Method::PATCH, body.to_string());
+            app.clone().oneshot(req)
+        };
+
+        let res = update("{}").await.unwrap();
+        assert_eq!(StatusCode::UNPROCESSABLE_ENTITY, res.status());
+        let bytes = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        let body = String::from_utf8(bytes.to_vec()).unwrap();
+        assert!(body.contains("At least one field required"), "{}", body);
+
+        let res = update(r#"{ "completed": true }"#).await.unwrap();
+        assert_eq!(StatusCode::CREATED, res.status());
+        assert!(res_to_todo(res).await.completed);
+        let res = update(r#"{ "priority": null }"#).await.unwrap();
+        assert_eq!(StatusCode::CREATED, res.status());
+    }
+
+    #[tokio::test]
+    async fn should_keep_or_clear_nullable_fields_on_update() {
+        let due_date: chrono::DateTime<chrono::Utc> = "2026-11-01T09:00:00Z".parse().unwrap();
+        let todo_repo = TodoRepositoryForMemory::new(vec![]);
+        todo_repo
+            .create(
+                CreateTodo::new("todo".to_string(), vec![])
+                    .with_due_date(Some(due_date))
+                    .with_priority(Some(3)),
+            )
+            .await
+            .expect("failed create todo");
+        let app = create_app(
+            todo_repo,
+            LabelRepositoryForMemory::new(),
+            HealthRepositoryForMemory::new(),
+        );
+        let patch = |body: &str| {
+            let req = build_req_with_json("/todos/1", Method::PATCH, body.to_string());
+            let app = app.clone();
+            async move { res_to_todo(app.oneshot(req).await.unwrap()).await }
+        };
+
+        let todo = patch(r#"{ "text": "renamed" }"#).await;
+        assert_eq!((Some(due_date), Some(3)), (todo.due_date, todo.priority));
+
+        let todo = patch(r#"{ "priority": null }"#).await;
+        assert_eq!((Some(due_date), None), (todo.due_date, todo.priority));
+
+        let todo = patch(r#"{ "due_date": null, "priority": 1 }"#).await;
+        assert_eq!((None, Some(1)), (todo.due_date, todo.priority));
+
+        let req = build_req_with_json(
+            "/todos/1",
+            Method::PATCH,
+            r#"{ "priority": 9 }"#.to_string(),
+        );
+        let res = app.oneshot(req).await.unwrap();
+        assert_eq!(StatusCode::UNPROCESSABLE_ENTITY, res.status());
+    }
+
+    #[tokio::test]
+    async fn should_honor_if_match_on_update() {
+        let todo_repo = TodoRepositoryForMemory::new(vec![]);
+        todo_repo
+            .create(CreateTodo::new("should_honor_if_match".to_string(), vec![]))
+            .await
+            .expect("failed create todo");
+        let app = create_app(
+            todo_repo,
+            LabelRepositoryForMemory::new(),
+            HealthRepositoryForMemory::new(),
+        );
+        let build_req = |etag: &HeaderValue, text: &str| -> Request<Body> {
+            Request::builder()
+                .uri("/todos/1")
+                .method(Method::PATCH)
+                .header(&OWNER_ID_HEADER, "0")
+                .header(CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
+                .header(IF_MATCH, etag)
+                .body(Body::from(format!(r#"{{ "text": "{}" }}"#, text)))
+                .unwrap()
+        };
+
+        let req = build_req_with_empty(Method::GET, "/todos/1");
+        let res = app.clone().oneshot(req).await.unwrap();
+        let stale = res.headers().get(ETAG).expect("ETag is not set").clone();
+
+        let res = app
+            .clone()
+            .oneshot(build_req(&stale, "matched"))
+            .await
+            .unwrap();
+        assert_eq!(StatusCode::CREATED, res.status());
+        let current = res.headers().get(ETAG).expect("ETag is not set").clone();
+        assert_ne!(stale, current);
+        assert_eq!("matched", res_to_todo(res).await.text);
+
+        let res = app
+            .clone()
+            .oneshot(build_req(&stale, "stale"))
+            .await
+            .unwrap();
+        assert_eq!(StatusCode::PRECONDITION_FAILED, res.status());
+
+        let req = build_req_with_empty(Method::GET, "/todos/1");
+        let res = app.oneshot(req).await.unwrap();
+        assert_eq!(current, res.headers()[ETAG]);
+        assert_eq!("matched", res_to_todo(res).await.text);
+    }
+
+    #[tokio::test]
+    async fn should_delete_todo() {
+        let labels = vec![Label::new(LabelId(1000), "test label".to_string())];
+        let todo_repo = TodoRepositoryForMemory::new(labels);
+        todo_repo
+            .create(CreateTodo::new(
+                "should_delete_todo".to_string(),
+                vec![LabelId(1000)],
+            ))
+            .await
+            .expect("failed create todo");
+        let req = build_req_with_empty(Method::DELETE, "/todos/1");
+        let res = create_app(
+            todo_repo,
+            LabelRepositoryForMemory::new(),
+            HealthRepositoryForMemory::new(),
+        )
+        .oneshot(req)
+        .await
+        .unwrap();
+        assert_eq!(StatusCode::NO_CONTENT, res.status());
+    }
+
+    #[tokio::test]
+    async fn should_create_label() {
+        let expected = Label::new(LabelId(1), "should create label".to_string());
+        let req = build_req_with_json(
+            "/labels",
+            Method::POST,
+            r#"{ "name": "should create label" }"#.to_string(),
+        );
+        let res = create_app(
+            TodoRepositoryForMemory::new(vec![]),
+            LabelRepositoryForMemory::new(),
+            HealthRepositoryForMemory::new(),
+        )
+        .oneshot(req)
+        .await
+        .unwrap();
+
+        let label = res_to_label(res).await;
+        assert_eq!(expected, label);
+    }
+
+    #[tokio::test]
+    async fn should_return_409_when_label_name_taken() {
+        let app = create_app(
+            TodoRepositoryForMemory::new(vec![]),
+            LabelRepositoryForMemory::new(),
+            HealthRepositoryForMemory::new(),
+        );
+        let post = || {
+            build_req_with_json(
+                "/labels",
+                Method::POST,
+                r#"{ "name": "taken label" }"#.to_string(),
+            )
+        };
+
+        let res = app.clone().oneshot(post()).await.unwrap();
+        assert_eq!(StatusCode::CREATED, res.status());
+        let res = app.oneshot(post()).await.unwrap();
+        assert_eq!(StatusCode::CONFLICT, res.status());
+    }
+
+    #[tokio::test]
+    async fn should_attach_label_to_todos() {
+        let labels = vec![
+            Label::new(LabelId(1), "attached".to_string()),
+            Label::new(LabelId(2), "other".to_string()),
+        ];
+        let todo_repo = TodoRepositoryForMemory::new(labels);
+        for labels in [vec![LabelId(1)], vec![LabelId(2)]] {
+            todo_repo
+                .create(CreateTodo::new("should_attach_label".to_string(), labels))
+                .await
+                .expect("failed create todo");
+        }
+        let app = create_app(
+            todo_repo,
+            LabelRepositoryForMemory::new(),
+            HealthRepositoryForMemory::new(),
+        );
+
+        let req = build_req_with_json(
+            "/labels/1/attach",
+            Method::POST,
+            r#"{ "todo_ids": [1, 2, 3] }"#.to_string(),
+        );
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(StatusCode::OK, res.status());
+        let bytes = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        let attached: AttachedLabel = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(
+            AttachedLabel {
+                attached: 1,
+                not_found: vec![TodoId(3)],
+            },
+            attached
+        );
+        let req = build_req_with_empty(Method::GET, "/todos/2");
+        let res = app.clone().oneshot(req).await.unwrap();
+        let todo = res_to_todo(res).await;
+        let label_ids: Vec<LabelId> = todo.labels.iter().map(|label| label.id).collect();
+        assert_eq!(vec![LabelId(1), LabelId(2)], label_ids);
+
+        let req = build_req_with_json(
+            "/labels/3/attach",
+            Method::POST,
+            r#"{ "todo_ids": [1] }"#.to_string(),
+        );
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(StatusCode::NOT_FOUND, res.status());
+        let req = build_req_with_json(
+            "/labels/1/attach",
+            Method::POST,
+            r#"{ "todo_ids": [1, 1] }"#.to_string(),
+        );
+        let res = app.oneshot(req).await.unwrap();
+        assert_eq!(StatusCode::UNPROCESSABLE_ENTITY, res.status());
+    }
+
+    #[tokio::test]
+    async fn should_normalize_label_name() {
+        let req = build_req_with_json(
+            "/labels",
+            Method::POST,
+            r#"{ "name": "  should   normalize label " }"#.to_string(),
+        );
+        let res = create_app(
+            TodoRepositoryForMemory::new(vec![]),
+            LabelRepositoryForMemory::new(),
+            HealthRepositoryForMemory::new(),
+        )
+        .oneshot(req)
+        .await
+        .unwrap();
+
+        let label = res_to_label(res).await;
+        assert_eq!("should normalize label", label.name);
+    }
+
+    #[tokio::test]
+    async fn should_create_labels_in_bulk() {
+        let req = build_req_with_json(
+            "/labels/bulk",
+            Method::POST,
+            r#"[{ "name": "existing" }, { "name": "new" }, { "name": " new " }]"#.to_string(),
+        );
+        let res = create_app(
+            TodoRepositoryForMemory::new(vec![]),
+            LabelRepositoryForMemory::with_labels(vec![Label::new(
+                LabelId(1),
+                "existing".to_string(),
+            )]),
+            HealthRepositoryForMemory::new(),
+        )
+        .oneshot(req)
+        .await
+        .unwrap();
+        assert_eq!(StatusCode::OK, res.status());
+
+        let bytes = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        let labels: Vec<Label> = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(
+            vec![
+                Label::new(LabelId(1), "existing".to_string()),
+                Label::new(LabelId(2), "new".to_string()),
+            ],
+            labels
+        );
+    }
+
+    #[tokio::test]
+    async fn should_reject_empty_bulk_labels() {
+        let req = build_req_with_json("/labels/bulk", Method::POST, "[]".to_string());
+        let res = create_app(
+            TodoRepositoryForMemory::new(vec![]),
+            LabelRepositoryForMemory::new(),
+            HealthRepositoryForMemory::new(),
+        )
+        .oneshot(req)
+        .await
+        .unwrap();
+        assert_eq!(StatusCode::UNPROCESSABLE_ENTITY, res.status());
+    }
+
+    #[tokio::test]
+    async fn should_get_labels_sorted_by_name() {
+        let label_repo = LabelRepositoryForMemory::with_labels(vec![
+            Label::new(LabelId(1), "cherry".to_string()),
+            Label::new(LabelId(2), "Banana".to_string()),
+            Label::new(LabelId(3), "apple".to_string()),
+        ]);
+        let app = create_app(
+            TodoRepositoryForMemory::new(vec![]),
+            label_repo,
+            HealthRepositoryForMemory::new(),
+        );
+        let req = build_req_with_empty(Method::GET, "/labels?sort=name");
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(StatusCode::OK, res.status());
+
+        let bytes = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        let labels: Paginated<Label> = serde_json::from_slice(&bytes).unwrap();
+        let names: Vec<&str> = labels
+            .items
+            .iter()
+            .map(|label| label.name.as_str())
+            .collect();
+        assert_eq!(vec!["apple", "Banana", "cherry"], names);
+
+        let req = build_req_with_empty(Method::GET, "/labels?sort=size");
+        let res = app.oneshot(req).await.unwrap();
+        assert_eq!(StatusCode::BAD_REQUEST, res.status());
+    }
+
+    #[tokio::test]
+    async fn should_page_labels() {
+        let label_repo = LabelRepositoryForMemory::with_labels(vec![
+            Label::new(LabelId(1), "cherry".to_string()),
+            Label::new(LabelId(2), "Banana".to_string()),
+            Label::new(LabelId(3), "apple".to_string()),
+        ]);
+        let app = create_app(
+            TodoRepositoryForMemory::new(vec![]),
+            label_repo,
+            HealthRepositoryForMemory::new(),
+        );
+        let get = |path: &str| {
+            let req = build_req_with_empty(Method::GET, path);
+            app.clone().oneshot(req)
+        };
+
+        let res = get("/labels?sort=name&limit=2&offset=1").await.unwrap();
+        assert_eq!(StatusCode::OK, res.status());
+        let bytes = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        let labels: Paginated<Label> = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(
+            Paginated::new(
+                vec![
+                    Label::new(LabelId(2), "Banana".to_string()),
+                    Label::new(LabelId(1), "cherry".to_string()),
+                ],
+                3,
+                2,
+                1
+            ),
+            labels
+        );
+
+        let res = get("/labels?with_counts=true&offset=2").await.unwrap();
+        let bytes = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        let labels: Paginated<LabelWithCount> = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!((3, 20, 2), (labels.total, labels.limit, labels.offset));
+        assert_eq!(1, labels.items.len());
+
+        for path in ["/labels?limit=0", "/labels?limit=101", "/labels?offset=-1"] {
+            let res = get(path).await.unwrap();
+            assert_eq!(StatusCode::BAD_REQUEST, res.status(), "{}", path);
+        }
+    }
+
+    #[tokio::test]
+    async fn should_rename_labels_in_bulk() {
+        let label_repo = LabelRepositoryForMemory::with_labels(vec![
+            Label::new(LabelId(1), "first".to_string()),
+            Label::new(LabelId(2), "second".to_string()),
+        ]);
+        let req = build_req_with_json(
+            "/labels/bulk",
+            Method::PATCH,
+            r#"[{ "id": 1, "name": "second" }, { "id": 2, "name": " first " }]"#.to_string(),
+        );
+        let res = create_app(
+            TodoRepositoryForMemory::new(vec![]),
+            label_repo.clone(),
+            HealthRepositoryForMemory::new(),
+        )
+        .oneshot(req)
+        .await
+        .unwrap();
+        assert_eq!(StatusCode::OK, res.status());
+
+        let bytes = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        let labels: Vec<Label> = serde_json::from_slice(&bytes).unwrap();
+        let expected = vec![
+            Label::new(LabelId(1), "second".to_string()),
+            Label::new(LabelId(2), "first".to_string()),
+        ];
+        assert_eq!(expected, labels);
+        let mut stored = label_repo.all().await.unwrap();
+        stored.sort_by_key(|label| label.id);
+        assert_eq!(expected, stored);
+    }
+
+    #[tokio::test]
+    async fn should_reject_conflicting_bulk_renames() {
+        let existing = vec![
+            Label::new(LabelId(1), "first".to_string()),
+            Label::new(LabelId(2), "second".to_string()),
+            Label::new(LabelId(3), "third".to_string()),
+        ];
+        let label_repo = LabelRepositoryForMemory::with_labels(existing.clone());
+        let req = build_req_with_json(
+            "/labels/bulk",
+            Method::PATCH,
+            r#"[{ "id": 1, "name": "renamed" }, { "id": 2, "name": "third" }]"#.to_string(),
+        );
+        let res = create_app(
+            TodoRepositoryForMemory::new(vec![]),
+            label_repo.clone(),
+            HealthRepositoryForMemory::new(),
+        )
+        .oneshot(req)
+        .await
+        .unwrap();
+        assert_eq!(StatusCode::CONFLICT, res.status());
+
+        let bytes = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        let body: LabelConflicts = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(
+            LabelConflicts {
+                conflicts: vec![UpdateLabel::new(LabelId(2), "third".to_string())],
+            },
+            body
+        );
+        let mut stored = label_repo.all().await.unwrap();
+        stored.sort_by_key(|label| label.id);
+        assert_eq!(existing, stored);
+    }
+
+    #[tokio::test]
+    async fn should_reject_whitespace_only_label_name() {
+        let req = build_req_with_json("/labels", Method::POST, r#"{ "name": "  " }"#.to_string());
+        let res = create_app(
+            TodoRepositoryForMemory::new(vec![]),
+            LabelRepositoryForMemory::new(),
+            HealthRepositoryForMemory::new(),
+        )
+        .oneshot(req)
+        .await
+        .unwrap();
+        assert_eq!(StatusCode::UNPROCESSABLE_ENTITY, res.status());
+    }
+
+    #[tokio::test]
+    async fn should_get_all_labels() {
+        let expected = vec![Label::new(LabelId(1), "should get all labels".to_string())];
+        let label_repo = LabelRepositoryForMemory::new();
+        label_repo
+            .create(CreateLabel::new("should get all labels".to_string()))
+            .await
+            .expect("failed create label");
+        let app = create_app(
+            TodoRepositoryForMemory::new(vec![]),
+            label_repo,
+            HealthRepositoryForMemory::new(),
+        );
+
+        let req = build_req_with_empty(Method::GET, "/labels");
+        let res = app.clone().oneshot(req).await.unwrap();
+        let bytes = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        let body: String = String::from_utf8(bytes.to_vec()).unwrap();
+        let labels: Paginated<Label> = serde_json::from_str(&body)
+            .unwrap_or_else(|_| panic!("cannot convert Label page. body: {}", body));
+        assert_eq!(Paginated::new(expected.clone(), 1, 1, 0), labels);
+
+        let req = build_req_with_empty(Method::GET, "/labels?envelope=false");
+        let res = app.oneshot(req).await.unwrap();
+        let bytes = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        let body: String = String::from_utf8(bytes.to_vec()).unwrap();
+        let labels: Vec<Label> = serde_json::from_str(&body)
+            .unwrap_or_else(|_| panic!("cannot convert Label instances. body: {}", body));
+        assert_eq!(expected, labels);
+    }
+
+    #[tokio::test]
+    async fn should_find_label() {
+        let label_repo = LabelRepositoryForMemory::new();
+        let label = label_repo
+            .create(CreateLabel::new("should find label".to_string()))
+            .await
+            .expect("failed create label");
+        let app = create_app(
+            TodoRepositoryForMemory::new(vec![]),
+            label_repo,
+            HealthRepositoryForMemory::new(),
+        );
+
+        let req = build_req_with_empty(Method::GET, "/labels/1");
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(StatusCode::OK, res.status());
+        let found = res_to_label(res).await;
+        assert_eq!(label, found);
+        insta::assert_json_snapshot!("label", found);
+
+        let req = build_req_with_empty(Method::GET, "/labels/2");
+        let res = app.oneshot(req).await.unwrap();
+        let status = res.status();
+        assert_eq!(StatusCode::NOT_FOUND, status);
+        let bytes = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        let body = String::from_utf8(bytes.to_vec()).unwrap();
+        insta::assert_snapshot!("not_found", format!("{}\n{}", status, body));
+    }
+
+    #[tokio::test]
+    async fn should_get_all_labels_with_counts() {
+        let label_repo = LabelRepositoryForMemory::new();
+        label_repo
+            .create(CreateLabel::new("should get counts".to_string()))
+            .await
+            .expect("failed create label");
+        let req = build_req_with_empty(Method::GET, "/labels?with_counts=true");
+        let res = create_app(
+            TodoRepositoryForMemory::new(vec![]),
+            label_repo,
+            HealthRepositoryForMemory::new(),
+        )
+        .oneshot(req)
+        .await
+        .unwrap();
+
+        let bytes = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        let labels: Paginated<LabelWithCount> = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(
+            vec![LabelWithCount {
+                id: LabelId(1),
+                name: "should get counts".to_string(),
+                usage_count: 0,
+            }],
+            labels.items
+        );
+    }
+
+    #[tokio::test]
+    async fn should_return_409_when_create_label_duplicated() {
+        let req = build_req_with_json(
+            "/labels",
+            Method::POST,
+            r#"{ "name": "duplicated label" }"#.to_string(),
+        );
+        let status = label_error_status(RepositoryError::Duplicate(LabelId(1).into()), req).await;
+        assert_eq!(StatusCode::CONFLICT, status);
+    }
+
+    #[tokio::test]
+    async fn should_return_500_when_all_labels_fails() {
+        let req = build_req_with_empty(Method::GET, "/labels");
+        let status = label_error_status(unexpected(), req).await;
+        assert_eq!(StatusCode::INTERNAL_SERVER_ERROR, status);
+    }
+
+    #[tokio::test]
+    async fn should_return_404_when_delete_label_not_found() {
+        let req = build_req_with_empty(Method::DELETE, "/labels/1");
+        let status = label_error_status(RepositoryError::NotFound(TodoId(1).into()), req).await;
+        assert_eq!(StatusCode::NOT_FOUND, status);
+    }
+
+    #[tokio::test]
+    async fn should_delete_label() {
+        let label_repo = LabelRepositoryForMemory::new();
+        label_repo
+            .create(CreateLabel::new("should delete label".to_string()))
+            .await
+            .expect("failed create label");
+        let req = build_req_with_empty(Method::DELETE, "/labels/1");
+        let res = create_app(
+            TodoRepositoryForMemory::new(vec![]),
+            label_repo,
+            HealthRepositoryForMemory::new(),
+        )
+        .oneshot(req)
+        .await
+        .unwrap();
+        assert_eq!(StatusCode::NO_CONTENT, res.status());
+    }
+
+    #[tokio::test]
+    async fn should_return_409_with_affected_todos_when_label_in_use() {
+        let req = build_req_with_empty(Method::DELETE, "/labels/1");
+        let res = create_app(
+            TodoRepositoryForMemory::new(vec![]),
+            FailingLabelRepository::new(RepositoryError::InUse(LabelId(1).into(), 3)),
+            HealthRepositoryForMemory::new(),
+        )
+        .oneshot(req)
+        .await
+        .unwrap();
+        assert_eq!(StatusCode::CONFLICT, res.status());
+        let bytes = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        let body: LabelInUse = serde_json::from_slice(&bytes).expect("cannot convert LabelInUse");
+        assert_eq!(LabelInUse { affected_todos: 3 }, body);
+    }
+
+    #[tokio::test]
+    async fn should_force_delete_label() {
+        let label_repo = LabelRepositoryForMemory::new();
+        label_repo
+            .create(CreateLabel::new("should force delete label".to_string()))
+            .await
+            .expect("failed create label");
+        let req = build_req_with_empty(Method::DELETE, "/labels/1?force=true");
+        let res = create_app(
+            TodoRepositoryForMemory::new(vec![]),
+            label_repo.clone(),
+            HealthRepositoryForMemory::new(),
+        )
+        .oneshot(req)
+        .await
+        .unwrap();
+        assert_eq!(StatusCode::NO_CONTENT, res.status());
+        assert_eq!(Vec::<Label>::new(), label_repo.all().await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn should_merge_labels() {
+        let label_repo = LabelRepositoryForMemory::new();
+        let keep = label_repo
+            .create(CreateLabel::new("keep".to_string()))
+            .await
+            .expect("failed create label");
+        label_repo
+            .create(CreateLabel::new("remove".to_string()))
+            .await
+            .expect("failed create label");
+        let app = create_app(
+            TodoRepositoryForMemory::new(vec![]),
+            label_repo,
+            HealthRepositoryForMemory::new(),
+        );
+
+        let req = build_req_with_empty(Method::POST, "/labels/1/merge/2");
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(StatusCode::OK, res.status());
+        assert_eq!(keep, res_to_label(res).await);
+
+        let req = build_req_with_empty(Method::POST, "/labels/1/merge/2");
+        let res = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(StatusCode::NOT_FOUND, res.status());
+
+        let req = build_req_with_empty(Method::POST, "/labels/1/merge/1");
+        let res = app.oneshot(req).await.unwrap();
+        assert_eq!(StatusCode::BAD_REQUEST, res.status());
+    }
+
+    #[tokio::test]
+    async fn should_return_500_when_merge_labels_fails() {
+        let req = build_req_with_empty(Method::POST, "/labels/1/merge/2");
+        let status = label_error_status(unexpected(), req).await;
+        assert_eq!(StatusCode::INTERNAL_SERVER_ERROR, status);
+    }
+}
+
+#[cfg(test)]
+#[cfg(feature = "uuid")]
+mod uuid_test {
+    use super::*;
+    use crate::repositories::health::test_utils::HealthRepositoryForMemory;
+    use crate::repositories::label::test_utils::LabelRepositoryForMemory;
+    use crate::repositories::label::Label;
+    use crate::repositories::todo::{test_utils::TodoRepositoryForMemory, TodoEntity};
+    use axum::http::{Method, StatusCode};
+    use serde::de::DeserializeOwned;
+    use tower::ServiceExt;
+
+    async fn send<T: DeserializeOwned>(
+        app: &Router,
+        method: Method,
+        path: &str,
+        json_body: Option<String>,
+    ) -> (StatusCode, T) {
+        let req = Request::builder()
+            .uri(path)
+            .method(method)
+            .header(&OWNER_ID_HEADER, "0")
+            .header(CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
+            .body(json_body.map(Body::from).unwrap_or_else(Body::empty))
+            .unwrap();
+        let res = app.clone().oneshot(req).await.unwrap();
+        let status = res.status();
+        let bytes = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        (status, serde_json::from_slice(&bytes).unwrap())
+    }
+
+    #[tokio::test]
+    async fn should_round_trip_uuid_ids() {
+        let label_repo = LabelRepositoryForMemory::new();
+        let app = create_app(
+            TodoRepositoryForMemory::with_label_repository(label_repo.clone()),
+            label_repo,
+            HealthRepositoryForMemory::new(),
+        );
+
+        let body = r#"{ "name": "uuid label" }"#.to_string();
+        let (status, label): (_, Label) = send(&app, Method::POST, "/labels", Some(body)).await;
+        assert_eq!(StatusCode::CREATED, status);
+
+        let body = format!(r#"{{ "text": "uuid todo", "labels": ["{}"] }}"#, label.id);
+        let (status, created): (_, TodoEntity) =
+            send(&app, Method::POST, "/todos", Some(body)).await;
+        assert_eq!(StatusCode::CREATED, status);
+
+        let path = format!("/todos/{}", created.id);
+        let (status, todo): (_, TodoEntity) = send(&app, Method::GET, &path, None).await;
+        assert_eq!(StatusCode::OK, status);
+        assert_eq!(created, todo);
+        assert_eq!(vec![label], todo.labels);
+    }
+}
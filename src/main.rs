@@ -1,305 +1,243 @@
-mod handlers;
-mod repositories;
-
-use crate::handlers::label::{all_label, create_label, delete_label};
-use crate::handlers::todo::{all_todo, create_todo, delete_todo, find_todo, update_todo};
-use crate::repositories::label::{LabelRepository, LabelRepositoryForDb};
-use crate::repositories::todo::{TodoRepository, TodoRepositoryForDb};
-use axum::http::HeaderValue;
-use axum::{
-    extract::Extension,
-    routing::{delete, get, post},
-    Router,
+use axum::middleware::from_fn;
+use axum::{Extension, Router};
+use axum_tutorial::config::{Config, LogFormat, TlsFiles};
+use axum_tutorial::repositories::health::HealthRepositoryForDb;
+use axum_tutorial::repositories::label::{
+    resolve_collation, LabelRepository, LabelRepositoryForDb,
 };
+use axum_tutorial::repositories::todo::{TodoRepository, TodoRepositoryForDb};
+use axum_tutorial::resolve_default_label;
+use axum_tutorial::seed::{seed, Seeded, DEMO_OWNER};
+use axum_tutorial::shutdown::{drain, shutdown_signal, track_in_flight, Drained, InFlight};
+use axum_tutorial::{create_app_with_options, panic_message, AppOptions};
 use dotenv::dotenv;
-use hyper::header::CONTENT_TYPE;
-use sqlx::PgPool;
+use sqlx::postgres::PgPoolOptions;
 use std::env;
 use std::net::SocketAddr;
-use std::sync::Arc;
-use tower_http::cors::{Any, CorsLayer};
+use std::panic;
+use tokio::sync::oneshot;
+use tracing_subscriber::EnvFilter;
 
 #[tokio::main]
 async fn main() {
-    let log_level = env::var("RUST_LOG").unwrap_or("info".to_string());
-    env::set_var("RUST_LOG", log_level);
-    tracing_subscriber::fmt::init();
     dotenv().ok();
+    // `seed` only writes the demo data and exits
+    let seed_only = match env::args().nth(1).as_deref() {
+        None => false,
+        Some("seed") => true,
+        Some(command) => panic!("unknown command [{}], expected `seed`", command),
+    };
+    let config = Config::from_env().unwrap_or_else(|e| panic!("{}", e));
+    init_tracing(config.log_format);
 
-    let database_url = &env::var("DATABASE_URL").expect("undefined DATABASE_URL");
     tracing::info!("start connect database ...");
-    let pool = PgPool::connect(database_url)
-        .await
-        .unwrap_or_else(|_| panic!("fail connect database, url is [{}]", database_url));
-    let app = create_app(
-        TodoRepositoryForDb::new(pool.clone()),
-        LabelRepositoryForDb::new(pool.clone()),
-    );
-    let addr = SocketAddr::from(([127, 0, 0, 1], 5000));
-    tracing::info!("listening on {}", addr);
+    let seed = seed_only || config.seed_on_start;
+    let app = if config.database_url.starts_with("sqlite:") {
+        sqlite_app(&config, seed).await
+    } else {
+        postgres_app(&config, seed).await
+    };
+    if seed_only {
+        return;
+    }
+    let in_flight = InFlight::default();
+    let app = app
+        .layer(from_fn(track_in_flight))
+        .layer(Extension(in_flight.clone()));
+
+    let (shutdown, stop) = oneshot::channel();
+    if let Drained::Finished(res) = drain(
+        serve(app, &config, stop),
+        shutdown_signal(),
+        shutdown,
+        config.shutdown_grace,
+        &in_flight,
+    )
+    .await
+    {
+        res.unwrap();
+    }
+}
 
+async fn serve(app: Router, config: &Config, stop: oneshot::Receiver<()>) -> anyhow::Result<()> {
+    let addr = config.addr();
+    if let Some(files) = &config.tls {
+        tracing::info!("listening on https://{}", addr);
+        return serve_https(app, addr, files, stop).await;
+    }
+    tracing::info!("listening on {}", addr);
     axum::Server::bind(&addr)
         .serve(app.into_make_service())
-        .await
-        .unwrap();
+        .with_graceful_shutdown(async {
+            stop.await.ok();
+        })
+        .await?;
+    Ok(())
 }
 
-fn create_app<Todo: TodoRepository, Label: LabelRepository>(
-    todo_repo: Todo,
-    label_repo: Label,
-) -> Router {
-    Router::new()
-        .route("/", get(root))
-        .route("/todos", post(create_todo::<Todo>).get(all_todo::<Todo>))
-        .route(
-            "/todos/:id",
-            get(find_todo::<Todo>)
-                .delete(delete_todo::<Todo>)
-                .patch(update_todo::<Todo>),
-        )
-        .route(
-            "/labels",
-            post(create_label::<Label>).get(all_label::<Label>),
-        )
-        .route("/labels/:id", delete(delete_label::<Label>))
-        .layer(Extension(Arc::new(todo_repo)))
-        .layer(Extension(Arc::new(label_repo)))
-        .layer(
-            CorsLayer::new()
-                .allow_origin("http://localhost:3000".parse::<HeaderValue>().unwrap())
-                .allow_methods(Any)
-                .allow_headers(vec![CONTENT_TYPE]),
-        )
-}
+#[cfg(feature = "tls")]
+async fn serve_https(
+    app: Router,
+    addr: SocketAddr,
+    files: &TlsFiles,
+    stop: oneshot::Receiver<()>,
+) -> anyhow::Result<()> {
+    use axum_tutorial::tls::{load_tls_config, serve_tls};
 
-async fn root() -> &'static str {
-    "Hello, world!"
+    let tls_config = load_tls_config(&files.cert, &files.key)
+        .await
+        .unwrap_or_else(|e| panic!("fail load TLS_CERT and TLS_KEY: {}", e));
+    serve_tls(app, addr, tls_config, axum_server::Handle::new(), stop).await?;
+    Ok(())
 }
 
-#[cfg(test)]
-mod test {
-    use super::*;
-    use crate::repositories::label::test_utils::LabelRepositoryForMemory;
-    use crate::repositories::label::{CreateLabel, Label};
-    use crate::repositories::todo::{test_utils::TodoRepositoryForMemory, CreateTodo, TodoEntity};
-    use axum::{
-        body::Body,
-        http::{Method, Request, StatusCode},
-        response::Response,
-    };
-    use std::vec;
-    use tower::ServiceExt;
-
-    fn build_req_with_json(path: &str, method: Method, json_body: String) -> Request<Body> {
-        Request::builder()
-            .uri(path)
-            .method(method)
-            .header(CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
-            .body(Body::from(json_body))
-            .unwrap()
-    }
-
-    fn build_req_with_empty(method: Method, path: &str) -> Request<Body> {
-        Request::builder()
-            .uri(path)
-            .method(method)
-            .body(Body::empty())
-            .unwrap()
-    }
-
-    async fn res_to_todo(res: Response) -> TodoEntity {
-        let bytes = hyper::body::to_bytes(res.into_body()).await.unwrap();
-        let body: String = String::from_utf8(bytes.to_vec()).unwrap();
-        let todo: TodoEntity = serde_json::from_str(&body)
-            .unwrap_or_else(|_| panic!("cannot convert Todo instance. body: {}", body));
-        todo
-    }
+#[cfg(not(feature = "tls"))]
+async fn serve_https(
+    _app: Router,
+    _addr: SocketAddr,
+    _files: &TlsFiles,
+    _stop: oneshot::Receiver<()>,
+) -> anyhow::Result<()> {
+    panic!("TLS_CERT requires building with `--features tls`")
+}
 
-    async fn res_to_label(res: Response) -> Label {
-        let bytes = hyper::body::to_bytes(res.into_body()).await.unwrap();
-        let body: String = String::from_utf8(bytes.to_vec()).unwrap();
-        let label: Label = serde_json::from_str(&body)
-            .unwrap_or_else(|_| panic!("cannot convert Label instance. body: {}", body));
-        label
+async fn postgres_app(config: &Config, seed: bool) -> Router {
+    let pool = PgPoolOptions::new()
+        .max_connections(config.pool.max_connections)
+        .acquire_timeout(config.pool.acquire_timeout)
+        .connect(&config.database_url)
+        .await
+        .unwrap_or_else(|_| panic!("fail connect database, url is [{}]", config.database_url));
+    let collation = resolve_collation(&pool, config.label_collation.clone()).await;
+    let label_repo = LabelRepositoryForDb::new(pool.clone())
+        .with_collation(collation)
+        .with_slow_query(config.slow_query);
+    let default_label = resolve_default_label(&label_repo, config.default_label.clone()).await;
+    let todo_repo = TodoRepositoryForDb::new(pool.clone())
+        .with_default_label(default_label)
+        .with_max_labels(config.max_labels_per_todo)
+        .with_max_joined_labels(config.max_joined_labels)
+        .with_slow_query(config.slow_query);
+    if seed {
+        seed_demo_data(&todo_repo, &label_repo).await;
     }
+    create_app_with_options(
+        cache_todos(todo_repo, config),
+        label_repo,
+        HealthRepositoryForDb::new(pool.clone(), config.readiness_timeout),
+        AppOptions {
+            pool: Some(pool.clone()),
+            ..config.into()
+        },
+    )
+}
 
-    #[tokio::test]
-    async fn should_created_todo() {
-        let labels = vec![Label::new(2, "test label".to_string())];
-        let expected = TodoEntity::new(
-            1,
-            "should_return_created_todo".to_string(),
-            false,
-            labels.clone(),
-        );
-        let req = build_req_with_json(
-            "/todos",
-            Method::POST,
-            r#"{ "text": "should_return_created_todo", "labels": [2] }"#.to_string(),
-        );
-        let res = create_app(
-            TodoRepositoryForMemory::new(labels),
-            LabelRepositoryForMemory::new(),
-        )
-        .oneshot(req)
+#[cfg(feature = "sqlite")]
+async fn sqlite_app(config: &Config, seed: bool) -> Router {
+    use axum_tutorial::repositories::health::sqlite::HealthRepositoryForSqlite;
+    use axum_tutorial::repositories::label::sqlite::LabelRepositoryForSqlite;
+    use axum_tutorial::repositories::migrate_sqlite;
+    use axum_tutorial::repositories::todo::sqlite::TodoRepositoryForSqlite;
+    use sqlx::sqlite::SqlitePoolOptions;
+
+    let pool = SqlitePoolOptions::new()
+        .max_connections(config.pool.max_connections)
+        .acquire_timeout(config.pool.acquire_timeout)
+        .connect(&config.database_url)
         .await
-        .unwrap();
-
-        let todo = res_to_todo(res).await;
-        assert_eq!(expected, todo);
+        .unwrap_or_else(|_| panic!("fail connect database, url is [{}]", config.database_url));
+    migrate_sqlite(&pool)
+        .await
+        .expect("fail migrate sqlite database");
+    let label_repo = LabelRepositoryForSqlite::new(pool.clone());
+    let default_label = resolve_default_label(&label_repo, config.default_label.clone()).await;
+    let todo_repo = TodoRepositoryForSqlite::new(pool.clone())
+        .with_default_label(default_label)
+        .with_max_labels(config.max_labels_per_todo)
+        .with_max_joined_labels(config.max_joined_labels);
+    if seed {
+        seed_demo_data(&todo_repo, &label_repo).await;
     }
+    create_app_with_options(
+        cache_todos(todo_repo, config),
+        label_repo,
+        HealthRepositoryForSqlite::new(pool.clone(), config.readiness_timeout),
+        config.into(),
+    )
+}
 
-    #[tokio::test]
-    async fn should_find_todo() {
-        let labels = vec![Label::new(1000, "test label".to_string())];
-        let expected = TodoEntity::new(1, "should_find_todo".to_string(), false, labels.clone());
-        let todo_repo = TodoRepositoryForMemory::new(labels);
-        todo_repo
-            .create(CreateTodo::new("should_find_todo".to_string(), vec![1000]))
-            .await
-            .expect("failed create todo");
-        let req = build_req_with_empty(Method::GET, "/todos/1");
-        let res = create_app(todo_repo, LabelRepositoryForMemory::new())
-            .oneshot(req)
-            .await
-            .unwrap();
+#[cfg(not(feature = "sqlite"))]
+async fn sqlite_app(_config: &Config, _seed: bool) -> Router {
+    panic!("a sqlite DATABASE_URL requires building with `--features sqlite`")
+}
 
-        let todo = res_to_todo(res).await;
-        assert_eq!(expected, todo);
-    }
+#[cfg(feature = "cache")]
+fn cache_todos<T: TodoRepository>(todo_repo: T, config: &Config) -> impl TodoRepository {
+    use axum_tutorial::repositories::cache::CachedTodoRepository;
 
-    #[tokio::test]
-    async fn should_get_all_todos() {
-        let labels = vec![Label::new(1000, "test label".to_string())];
-        let expected = vec![TodoEntity::new(
-            1,
-            "should_get_all_todo".to_string(),
-            false,
-            labels.clone(),
-        )];
-        let todo_repo = TodoRepositoryForMemory::new(labels);
-        todo_repo
-            .create(CreateTodo::new(
-                "should_get_all_todo".to_string(),
-                vec![1000],
-            ))
-            .await
-            .expect("failed create todo");
-        let req = build_req_with_empty(Method::GET, "/todos");
-        let res = create_app(todo_repo, LabelRepositoryForMemory::new())
-            .oneshot(req)
-            .await
-            .unwrap();
+    CachedTodoRepository::new(todo_repo, config.todo_cache_capacity, config.todo_cache_ttl)
+}
 
-        let bytes = hyper::body::to_bytes(res.into_body()).await.unwrap();
-        let body: String = String::from_utf8(bytes.to_vec()).unwrap();
-        let todos: Vec<TodoEntity> = serde_json::from_str(&body)
-            .unwrap_or_else(|_| panic!("cannot convert Todo instances. body: {}", body));
-        assert_eq!(expected, todos);
+#[cfg(not(feature = "cache"))]
+fn cache_todos<T: TodoRepository>(todo_repo: T, config: &Config) -> impl TodoRepository {
+    if config.todo_cache_capacity > 0 {
+        panic!("TODO_CACHE_CAPACITY requires building with `--features cache`")
     }
+    todo_repo
+}
 
-    #[tokio::test]
-    async fn should_update_todo() {
-        let labels = vec![Label::new(1000, "test label".to_string())];
-        let expected = TodoEntity::new(1, "should_update_todo".to_string(), false, labels.clone());
-        let todo_repo = TodoRepositoryForMemory::new(labels);
-        todo_repo
-            .create(CreateTodo::new(
-                "before_update_todo".to_string(),
-                vec![1000],
-            ))
-            .await
-            .expect("failed create todo");
-        let req = build_req_with_json(
-            "/todos/1",
-            Method::PATCH,
-            r#"
-        {
-            "text": "should_update_todo",
-            "completed": false
-        }"#
-            .to_string(),
-        );
-        let res = create_app(todo_repo, LabelRepositoryForMemory::new())
-            .oneshot(req)
-            .await
-            .unwrap();
-        let todo = res_to_todo(res).await;
-        assert_eq!(expected, todo);
+async fn seed_demo_data<T: TodoRepository, L: LabelRepository>(todo_repo: &T, label_repo: &L) {
+    let seeded = seed(
+        &todo_repo.scoped(DEMO_OWNER),
+        &label_repo.scoped(DEMO_OWNER),
+    )
+    .await
+    .expect("fail seed demo data");
+    match seeded {
+        Seeded::Skipped => tracing::info!("todos already exist, skip seeding"),
+        Seeded::Inserted { labels, todos } => {
+            tracing::info!("seeded {} labels and {} todos", labels, todos)
+        }
     }
+}
 
-    #[tokio::test]
-    async fn should_delete_todo() {
-        let labels = vec![Label::new(1000, "test label".to_string())];
-        let todo_repo = TodoRepositoryForMemory::new(labels);
-        todo_repo
-            .create(CreateTodo::new(
-                "should_delete_todo".to_string(),
-                vec![1000],
-            ))
-            .await
-            .expect("failed create todo");
-        let req = build_req_with_empty(Method::DELETE, "/todos/1");
-        let res = create_app(todo_repo, LabelRepositoryForMemory::new())
-            .oneshot(req)
-            .await
-            .unwrap();
-        assert_eq!(StatusCode::NO_CONTENT, res.status());
+fn build_subscriber(format: LogFormat) -> Box<dyn tracing::Subscriber + Send + Sync> {
+    let builder = tracing_subscriber::fmt().with_env_filter(EnvFilter::from_default_env());
+    match format {
+        LogFormat::Pretty => Box::new(builder.finish()),
+        LogFormat::Json => Box::new(builder.json().finish()),
     }
+}
 
-    #[tokio::test]
-    async fn should_create_label() {
-        let expected = Label::new(1, "should create label".to_string());
-        let req = build_req_with_json(
-            "/labels",
-            Method::POST,
-            r#"{ "name": "should create label" }"#.to_string(),
+fn init_tracing(format: LogFormat) {
+    let log_level = env::var("RUST_LOG").unwrap_or("info".to_string());
+    env::set_var("RUST_LOG", log_level);
+    tracing::subscriber::set_global_default(build_subscriber(format))
+        .expect("fail set tracing subscriber");
+    // handlers that panic are answered with 500, this keeps the reason in the logs
+    panic::set_hook(Box::new(|info| {
+        let location = info
+            .location()
+            .map(|location| location.to_string())
+            .unwrap_or_default();
+        tracing::error!(
+            "panicked at {}: {}",
+            location,
+            panic_message(info.payload())
         );
-        let res = create_app(
-            TodoRepositoryForMemory::new(vec![]),
-            LabelRepositoryForMemory::new(),
-        )
-        .oneshot(req)
-        .await
-        .unwrap();
-
-        let label = res_to_label(res).await;
-        assert_eq!(expected, label);
-    }
-
-    #[tokio::test]
-    async fn should_get_all_labels() {
-        let expected = vec![Label::new(1, "should get all labels".to_string())];
-        let label_repo = LabelRepositoryForMemory::new();
-        label_repo
-            .create(CreateLabel::new("should get all labels".to_string()))
-            .await
-            .expect("failed create label");
-        let req = build_req_with_empty(Method::GET, "/labels");
-        let res = create_app(TodoRepositoryForMemory::new(vec![]), label_repo)
-            .oneshot(req)
-            .await
-            .unwrap();
+    }));
+}
 
-        let bytes = hyper::body::to_bytes(res.into_body()).await.unwrap();
-        let body: String = String::from_utf8(bytes.to_vec()).unwrap();
-        let labels: Vec<Label> = serde_json::from_str(&body)
-            .unwrap_or_else(|_| panic!("cannot convert Label instances. body: {}", body));
-        assert_eq!(expected, labels);
-    }
+#[cfg(test)]
+mod test {
+    use super::*;
 
-    #[tokio::test]
-    async fn should_delete_label() {
-        let label_repo = LabelRepositoryForMemory::new();
-        label_repo
-            .create(CreateLabel::new("should delete label".to_string()))
-            .await
-            .expect("failed create label");
-        let req = build_req_with_empty(Method::DELETE, "/labels/1");
-        let res = create_app(TodoRepositoryForMemory::new(vec![]), label_repo)
-            .oneshot(req)
-            .await
-            .unwrap();
-        assert_eq!(StatusCode::NO_CONTENT, res.status());
+    #[test]
+    fn should_build_subscriber_for_each_log_format() {
+        for format in [LogFormat::Pretty, LogFormat::Json] {
+            tracing::subscriber::with_default(build_subscriber(format), || {
+                tracing::info!("log with {:?} format", format);
+            });
+        }
     }
 }
@@ -1,14 +1,618 @@
+#[cfg(feature = "cache")]
+pub mod cache;
+pub mod health;
 pub mod label;
 pub mod todo;
 
+use crate::repositories::label::LabelId;
+use crate::repositories::todo::TodoId;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
+use std::future::Future;
+use std::time::{Duration, Instant};
 use thiserror::Error;
 
-#[derive(Debug, Error)]
-enum RepositoryError {
+/// Primary key of todos and labels: a sequential integer, or a random UUID with the `uuid`
+/// feature so that ids give away neither the creation order nor the number of rows.
+#[cfg(not(feature = "uuid"))]
+pub type RawId = i32;
+#[cfg(feature = "uuid")]
+pub type RawId = uuid::Uuid;
+
+macro_rules! id_type {
+    ($name:ident) => {
+        id_type!($name, i32);
+    };
+    ($name:ident, $raw:ty) => {
+        #[derive(
+            Debug,
+            Clone,
+            Copy,
+            PartialEq,
+            Eq,
+            PartialOrd,
+            Ord,
+            Hash,
+            serde::Serialize,
+            serde::Deserialize,
+        )]
+        #[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+        #[serde(transparent)]
+        pub struct $name(pub $raw);
+
+        impl sqlx::Type<sqlx::Postgres> for $name {
+            fn type_info() -> sqlx::postgres::PgTypeInfo {
+                <$raw as sqlx::Type<sqlx::Postgres>>::type_info()
+            }
+        }
+
+        impl sqlx::postgres::PgHasArrayType for $name {
+            fn array_type_info() -> sqlx::postgres::PgTypeInfo {
+                <$raw as sqlx::postgres::PgHasArrayType>::array_type_info()
+            }
+        }
+
+        impl<'q> sqlx::Encode<'q, sqlx::Postgres> for $name {
+            fn encode_by_ref(
+                &self,
+                buf: &mut sqlx::postgres::PgArgumentBuffer,
+            ) -> sqlx::encode::IsNull {
+                <$raw as sqlx::Encode<'q, sqlx::Postgres>>::encode_by_ref(&self.0, buf)
+            }
+        }
+
+        impl<'r> sqlx::Decode<'r, sqlx::Postgres> for $name {
+            fn decode(
+                value: sqlx::postgres::PgValueRef<'r>,
+            ) -> Result<Self, sqlx::error::BoxDynError> {
+                <$raw as sqlx::Decode<'r, sqlx::Postgres>>::decode(value).map($name)
+            }
+        }
+
+        #[cfg(feature = "sqlite")]
+        impl sqlx::Type<sqlx::Sqlite> for $name {
+            fn type_info() -> sqlx::sqlite::SqliteTypeInfo {
+                <$raw as sqlx::Type<sqlx::Sqlite>>::type_info()
+            }
+
+            fn compatible(ty: &sqlx::sqlite::SqliteTypeInfo) -> bool {
+                <$raw as sqlx::Type<sqlx::Sqlite>>::compatible(ty)
+            }
+        }
+
+        #[cfg(feature = "sqlite")]
+        impl<'q> sqlx::Encode<'q, sqlx::Sqlite> for $name {
+            fn encode_by_ref(
+                &self,
+                buf: &mut Vec<sqlx::sqlite::SqliteArgumentValue<'q>>,
+            ) -> sqlx::encode::IsNull {
+                <$raw as sqlx::Encode<'q, sqlx::Sqlite>>::encode_by_ref(&self.0, buf)
+            }
+        }
+
+        #[cfg(feature = "sqlite")]
+        impl<'r> sqlx::Decode<'r, sqlx::Sqlite> for $name {
+            fn decode(
+                value: sqlx::sqlite::SqliteValueRef<'r>,
+            ) -> Result<Self, sqlx::error::BoxDynError> {
+                <$raw as sqlx::Decode<'r, sqlx::Sqlite>>::decode(value).map($name)
+            }
+        }
+
+        impl std::fmt::Display for $name {
+            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                write!(f, "{}", self.0)
+            }
+        }
+
+        impl crate::repositories::Positive for $name {
+            fn is_positive(&self) -> bool {
+                crate::repositories::Positive::is_positive(&self.0)
+            }
+        }
+
+        impl std::str::FromStr for $name {
+            type Err = <$raw as std::str::FromStr>::Err;
+
+            fn from_str(s: &str) -> Result<Self, Self::Err> {
+                s.parse().map($name)
+            }
+        }
+    };
+}
+pub(crate) use id_type;
+
+/// Ids that may name a row: serials start at 1, so zero and negative ids are known to be
+/// missing without asking the database.
+pub trait Positive {
+    fn is_positive(&self) -> bool;
+}
+
+impl Positive for i32 {
+    fn is_positive(&self) -> bool {
+        *self > 0
+    }
+}
+
+/// UUIDs have no sign, any of them may name a row.
+#[cfg(feature = "uuid")]
+impl Positive for uuid::Uuid {
+    fn is_positive(&self) -> bool {
+        true
+    }
+}
+
+impl<A: Positive, B: Positive> Positive for (A, B) {
+    fn is_positive(&self) -> bool {
+        self.0.is_positive() && self.1.is_positive()
+    }
+}
+
+id_type!(OwnerId);
+
+/// Owner of rows that predate multi-tenancy, and of repositories that were never scoped.
+impl Default for OwnerId {
+    fn default() -> Self {
+        OwnerId(0)
+    }
+}
+
+/// Id numbered `n` in the type the ids have, for tests naming rows whichever type it is.
+#[cfg(test)]
+pub(crate) fn test_id(n: i32) -> RawId {
+    #[cfg(not(feature = "uuid"))]
+    return n;
+    #[cfg(feature = "uuid")]
+    return uuid::Uuid::from_u128(n as u128);
+}
+
+/// Id for the next row of an in-memory repository holding `len` rows.
+#[cfg(any(test, feature = "testing"))]
+pub(crate) fn next_memory_id(len: usize) -> RawId {
+    #[cfg(not(feature = "uuid"))]
+    return (len + 1) as RawId;
+    #[cfg(feature = "uuid")]
+    return {
+        let _ = len;
+        uuid::Uuid::new_v4()
+    };
+}
+
+/// Empties the tables of todos and labels and restarts their ids from 1, so that a
+/// scenario sees the same ids on every run. The database stays locked for the scenario
+/// until the returned guard drops, otherwise parallel tests would empty it under each other.
+#[cfg(test)]
+#[cfg(feature = "database-test")]
+pub(crate) async fn reset_database() -> (sqlx::PgPool, tokio::sync::MutexGuard<'static, ()>) {
+    static DATABASE: tokio::sync::Mutex<()> = tokio::sync::Mutex::const_new(());
+
+    let guard = DATABASE.lock().await;
+    dotenv::dotenv().ok();
+    let database_url = &std::env::var("DATABASE_URL").expect("undefined [DATABASE_URL]");
+    let pool = sqlx::PgPool::connect(database_url)
+        .await
+        .unwrap_or_else(|_| panic!("fail connect database. url is [{}]", database_url));
+    sqlx::query(
+        r#"TRUNCATE todos, labels, todo_labels, todo_history, todo_tombstones, todos_modified RESTART IDENTITY"#,
+    )
+    .execute(&pool)
+    .await
+    .expect("fail reset database");
+    (pool, guard)
+}
+
+/// Repositories hand out copies of themselves restricted to the data of one owner.
+pub trait OwnerScoped: Clone + Send + Sync + 'static {
+    fn scoped(&self, owner: OwnerId) -> Self;
+
+    /// Copy whose queries are abandoned at `deadline`, the instant the request must be
+    /// answered by. Repositories that don't wait on a database ignore it.
+    fn with_deadline(&self, deadline: tokio::time::Instant) -> Self {
+        let _ = deadline;
+        self.clone()
+    }
+}
+
+#[cfg(all(feature = "sqlite", feature = "uuid"))]
+compile_error!("the SQLite schema only has integer ids, `uuid` works with Postgres");
+
+/// Creates the SQLite schema, which lives apart from the Postgres migrations.
+#[cfg(feature = "sqlite")]
+pub async fn migrate_sqlite(pool: &sqlx::SqlitePool) -> anyhow::Result<()> {
+    sqlx::migrate!("./migrations_sqlite").run(pool).await?;
+    Ok(())
+}
+
+pub const DEFAULT_SLOW_QUERY_MS: u64 = 200;
+
+/// Awaits `fut` until `deadline` at most, then drops it with `DeadlineExceeded` so that the
+/// query stops waiting on the database and its connection goes back to the pool.
+pub async fn until_deadline<F, T>(
+    deadline: Option<tokio::time::Instant>,
+    fut: F,
+) -> anyhow::Result<T>
+where
+    F: Future<Output = anyhow::Result<T>>,
+{
+    match deadline {
+        Some(deadline) => tokio::time::timeout_at(deadline, fut)
+            .await
+            .unwrap_or_else(|_| Err(RepositoryError::DeadlineExceeded.into())),
+        None => fut.await,
+    }
+}
+
+/// Awaits the database work of `fut` and warns with the `name` of the operation when it
+/// took longer than `threshold`.
+pub async fn timed<F: Future>(name: &'static str, threshold: Duration, fut: F) -> F::Output {
+    let started = Instant::now();
+    let output = fut.await;
+    let elapsed = started.elapsed();
+    if elapsed > threshold {
+        tracing::warn!(
+            operation = name,
+            elapsed_ms = elapsed.as_millis() as u64,
+            "slow query: {} took {:?}",
+            name,
+            elapsed
+        );
+    }
+    output
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EntityId {
+    Todo(TodoId),
+    Label(LabelId),
+}
+
+impl fmt::Display for EntityId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EntityId::Todo(id) => write!(f, "todo id is {}", id),
+            EntityId::Label(id) => write!(f, "label id is {}", id),
+        }
+    }
+}
+
+impl From<TodoId> for EntityId {
+    fn from(id: TodoId) -> Self {
+        EntityId::Todo(id)
+    }
+}
+
+impl From<LabelId> for EntityId {
+    fn from(id: LabelId) -> Self {
+        EntityId::Label(id)
+    }
+}
+
+#[derive(Debug, Clone, Error)]
+pub enum RepositoryError {
     #[error("Unexpected Error: [{0}]")]
     Unexpected(String),
-    #[error("NotFound, id is {0}")]
-    NotFound(i32),
-    #[error("Duplicate data, id is {0}")]
-    Duplicate(i32),
+    #[error("NotFound, {0}")]
+    NotFound(EntityId),
+    #[error("Duplicate data, {0}")]
+    Duplicate(EntityId),
+    #[error("Duplicate names [{}]", .0.join(", "))]
+    DuplicateNames(Vec<String>),
+    #[error("In use, {0} is referenced by {1} todos")]
+    InUse(EntityId, i64),
+    #[error("Too many labels, the limit is {0}")]
+    TooManyLabels(usize),
+    #[error("Unavailable: [{0}]")]
+    Unavailable(String),
+    /// A unique constraint refused the write, `Duplicate` is for checks that found the
+    /// entity already there.
+    #[error("Conflict on [{0}]")]
+    Conflict(String),
+    /// A foreign key refused the write, the row it points to does not exist.
+    #[error("Missing reference on [{0}]")]
+    MissingReference(String),
+    /// A conditional write found the entity changed since the version it was based on.
+    #[error("Stale, {0} changed since it was read")]
+    Stale(EntityId),
+    /// The request ran out of time and its query was abandoned.
+    #[error("Deadline exceeded")]
+    DeadlineExceeded,
+}
+
+/// Codes of a unique violation: Postgres, then the extended `SQLITE_CONSTRAINT_UNIQUE` and
+/// `SQLITE_CONSTRAINT_PRIMARYKEY` of SQLite.
+const UNIQUE_VIOLATION_CODES: [&str; 3] = ["23505", "2067", "1555"];
+
+/// Codes of a foreign key violation: Postgres, then `SQLITE_CONSTRAINT_FOREIGNKEY`.
+const FOREIGN_KEY_VIOLATION_CODES: [&str; 2] = ["23503", "787"];
+
+/// Classifies a database error the same way whichever query raised it. A `RowNotFound`
+/// is `Unexpected` as only `fetch_one` raises it; queries of an id that may be missing
+/// `fetch_optional` and report `NotFound` with that id.
+impl From<&sqlx::Error> for RepositoryError {
+    fn from(e: &sqlx::Error) -> Self {
+        match e {
+            sqlx::Error::PoolTimedOut | sqlx::Error::PoolClosed => {
+                RepositoryError::Unavailable(e.to_string())
+            }
+            sqlx::Error::Database(db)
+                if db
+                    .code()
+                    .is_some_and(|code| UNIQUE_VIOLATION_CODES.contains(&code.as_ref())) =>
+            {
+                RepositoryError::Conflict(db.constraint().unwrap_or(db.message()).to_string())
+            }
+            sqlx::Error::Database(db)
+                if db
+                    .code()
+                    .is_some_and(|code| FOREIGN_KEY_VIOLATION_CODES.contains(&code.as_ref())) =>
+            {
+                RepositoryError::MissingReference(
+                    db.constraint().unwrap_or(db.message()).to_string(),
+                )
+            }
+            _ => RepositoryError::Unexpected(e.to_string()),
+        }
+    }
+}
+
+impl From<sqlx::Error> for RepositoryError {
+    fn from(e: sqlx::Error) -> Self {
+        RepositoryError::from(&e)
+    }
+}
+
+/// Field of a partial update that tells an omitted value (`Undefined`, keep the current
+/// one) apart from an explicit `null` (`Null`, clear it).
+///
+/// Deserializing only ever yields `Null` or `Value`, so fields need `#[serde(default)]`
+/// to become `Undefined` when they are missing.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Patch<T> {
+    #[default]
+    Undefined,
+    Null,
+    Value(T),
+}
+
+impl<T> Patch<T> {
+    pub fn is_undefined(&self) -> bool {
+        matches!(self, Patch::Undefined)
+    }
+
+    pub fn as_ref(&self) -> Patch<&T> {
+        match self {
+            Patch::Undefined => Patch::Undefined,
+            Patch::Null => Patch::Null,
+            Patch::Value(value) => Patch::Value(value),
+        }
+    }
+
+    /// Value of the field once the patch is applied to `current`.
+    pub fn apply(self, current: Option<T>) -> Option<T> {
+        match self {
+            Patch::Undefined => current,
+            Patch::Null => None,
+            Patch::Value(value) => Some(value),
+        }
+    }
+}
+
+/// A replacement value: `None` clears the field.
+impl<T> From<Option<T>> for Patch<T> {
+    fn from(value: Option<T>) -> Self {
+        value.map_or(Patch::Null, Patch::Value)
+    }
+}
+
+impl<'de, T: Deserialize<'de>> Deserialize<'de> for Patch<T> {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        Option::<T>::deserialize(deserializer).map(Patch::from)
+    }
+}
+
+/// `Undefined` is expected to be skipped with `skip_serializing_if = "Patch::is_undefined"`,
+/// otherwise it is written as `null` like `Null`.
+impl<T: Serialize> Serialize for Patch<T> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        match self {
+            Patch::Value(value) => serializer.serialize_some(value),
+            Patch::Undefined | Patch::Null => serializer.serialize_none(),
+        }
+    }
+}
+
+#[cfg(feature = "schema")]
+impl<T: schemars::JsonSchema> schemars::JsonSchema for Patch<T> {
+    fn schema_name() -> String {
+        <Option<T>>::schema_name()
+    }
+
+    fn json_schema(gen: &mut schemars::gen::SchemaGenerator) -> schemars::schema::Schema {
+        <Option<T>>::json_schema(gen)
+    }
+
+    fn is_referenceable() -> bool {
+        false
+    }
+}
+
+fn deserialize_trimmed<'de, D>(deserializer: D) -> Result<String, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    String::deserialize(deserializer).map(|s| s.trim().to_string())
+}
+
+fn deserialize_trimmed_option<'de, D>(deserializer: D) -> Result<Option<String>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Option::<String>::deserialize(deserializer).map(|s| s.map(|s| s.trim().to_string()))
+}
+
+fn deserialize_collapsed<'de, D>(deserializer: D) -> Result<String, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    String::deserialize(deserializer).map(|s| s.split_whitespace().collect::<Vec<_>>().join(" "))
+}
+
+/// Reads a list written as one comma separated value, as in `?label_ids=1,2`.
+fn deserialize_comma_separated<'de, D, T>(deserializer: D) -> Result<Vec<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: std::str::FromStr,
+    T::Err: fmt::Display,
+{
+    String::deserialize(deserializer)?
+        .split(',')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+        .map(|item| item.parse().map_err(serde::de::Error::custom))
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io;
+    use std::sync::{Arc, Mutex};
+
+    /// Log output collected in memory.
+    #[derive(Clone, Default)]
+    struct Logs(Arc<Mutex<Vec<u8>>>);
+
+    impl io::Write for Logs {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn should_warn_on_slow_query() {
+        let logs = Logs::default();
+        let writer = logs.clone();
+        let subscriber = tracing_subscriber::fmt()
+            .with_writer(move || writer.clone())
+            .with_ansi(false)
+            .finish();
+        let _guard = tracing::subscriber::set_default(subscriber);
+
+        let slow = timed("todo.slow", Duration::from_millis(1), async {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            "slow"
+        })
+        .await;
+        assert_eq!("slow", slow);
+        timed("todo.fast", Duration::from_secs(60), async {}).await;
+
+        let logs = String::from_utf8(logs.0.lock().unwrap().clone()).unwrap();
+        assert!(logs.contains("WARN"), "{}", logs);
+        assert!(logs.contains("slow query: todo.slow took"), "{}", logs);
+        assert!(!logs.contains("todo.fast"), "{}", logs);
+    }
+
+    #[tokio::test]
+    async fn should_abandon_calls_past_deadline() {
+        let started = Instant::now();
+        let deadline = tokio::time::Instant::now() + Duration::from_millis(20);
+        let res = until_deadline(Some(deadline), std::future::pending::<anyhow::Result<()>>());
+        assert!(matches!(
+            res.await.unwrap_err().downcast_ref::<RepositoryError>(),
+            Some(RepositoryError::DeadlineExceeded)
+        ));
+        assert!(started.elapsed() < Duration::from_secs(1));
+
+        let res = until_deadline(Some(deadline + Duration::from_secs(60)), async { Ok(1) });
+        assert_eq!(1, res.await.unwrap());
+        assert_eq!(2, until_deadline(None, async { Ok(2) }).await.unwrap());
+    }
+
+    #[test]
+    fn should_classify_sqlx_errors() {
+        assert!(matches!(
+            RepositoryError::from(sqlx::Error::PoolTimedOut),
+            RepositoryError::Unavailable(_)
+        ));
+        assert!(matches!(
+            RepositoryError::from(sqlx::Error::PoolClosed),
+            RepositoryError::Unavailable(_)
+        ));
+        assert!(matches!(
+            RepositoryError::from(sqlx::Error::RowNotFound),
+            RepositoryError::Unexpected(_)
+        ));
+        assert!(matches!(
+            RepositoryError::from(sqlx::Error::ColumnNotFound("text".to_string())),
+            RepositoryError::Unexpected(message) if message.contains("text")
+        ));
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[tokio::test]
+    async fn should_classify_unique_violation() {
+        let pool = sqlx::SqlitePool::connect("sqlite::memory:").await.unwrap();
+        migrate_sqlite(&pool).await.unwrap();
+        let insert = || sqlx::query(r#"INSERT INTO labels (id, name) VALUES (1, 'label')"#);
+        insert().execute(&pool).await.unwrap();
+
+        let e = insert().execute(&pool).await.unwrap_err();
+        assert!(matches!(
+            RepositoryError::from(e),
+            RepositoryError::Conflict(_)
+        ));
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[tokio::test]
+    async fn should_classify_foreign_key_violation() {
+        let pool = sqlx::SqlitePool::connect("sqlite::memory:").await.unwrap();
+        migrate_sqlite(&pool).await.unwrap();
+
+        let e = sqlx::query(r#"INSERT INTO todo_labels (todo_id, label_id) VALUES (1, 1)"#)
+            .execute(&pool)
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            RepositoryError::from(e),
+            RepositoryError::MissingReference(_)
+        ));
+    }
+
+    #[derive(Debug, Deserialize, Serialize)]
+    struct Payload {
+        #[serde(default, skip_serializing_if = "Patch::is_undefined")]
+        value: Patch<i32>,
+    }
+
+    #[test]
+    fn should_tell_missing_from_null_patch() {
+        let from = |json: &str| serde_json::from_str::<Payload>(json).unwrap().value;
+        assert_eq!(Patch::Undefined, from("{}"));
+        assert_eq!(Patch::Null, from(r#"{ "value": null }"#));
+        assert_eq!(Patch::Value(1), from(r#"{ "value": 1 }"#));
+
+        assert_eq!(Some(2), from("{}").apply(Some(2)));
+        assert_eq!(None, from(r#"{ "value": null }"#).apply(Some(2)));
+        assert_eq!(Some(1), from(r#"{ "value": 1 }"#).apply(Some(2)));
+    }
+
+    #[test]
+    fn should_skip_undefined_patch() {
+        let to = |value| serde_json::to_string(&Payload { value }).unwrap();
+        assert_eq!("{}", to(Patch::Undefined));
+        assert_eq!(r#"{"value":null}"#, to(Patch::Null));
+        assert_eq!(r#"{"value":1}"#, to(Patch::Value(1)));
+    }
 }
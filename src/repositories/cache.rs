@@ -0,0 +1,384 @@
+use crate::repositories::label::LabelId;
+use crate::repositories::todo::{
+    AttachedLabel, CreateTodo, ReplaceTodo, SyncedTodo, TodoChange, TodoEntity, TodoFilter, TodoId,
+    TodoQuery, TodoRepository, TodoSearchCriteria, TodoSearchResult, TodoSummary, UpdateTodo,
+};
+use crate::repositories::{OwnerId, OwnerScoped};
+use axum::async_trait;
+use chrono::{DateTime, Utc};
+use hashlink::LruCache;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+type Entries = LruCache<(OwnerId, TodoId), (Instant, TodoEntity)>;
+
+/// Read-through cache of the todos `find` returns, wrapping either backend. Keeps at most
+/// `capacity` todos of every owner together for `ttl`, evicting the least recently used.
+///
+/// Writes through this repository invalidate the todos they change, but renamed labels and
+/// writes through another repository only show once the entry expires.
+#[derive(Debug, Clone)]
+pub struct CachedTodoRepository<T> {
+    inner: T,
+    owner: OwnerId,
+    ttl: Duration,
+    /// `None` with a capacity of zero, every call then goes to `inner`.
+    entries: Option<Arc<Mutex<Entries>>>,
+}
+
+impl<T: TodoRepository> CachedTodoRepository<T> {
+    pub fn new(inner: T, capacity: usize, ttl: Duration) -> Self {
+        CachedTodoRepository {
+            inner,
+            owner: OwnerId::default(),
+            ttl,
+            entries: (capacity > 0).then(|| Arc::new(Mutex::new(LruCache::new(capacity)))),
+        }
+    }
+
+    fn entries(&self) -> Option<MutexGuard<'_, Entries>> {
+        self.entries.as_ref().map(|entries| entries.lock().unwrap())
+    }
+
+    fn cached(&self, id: TodoId) -> Option<TodoEntity> {
+        let mut entries = self.entries()?;
+        let key = (self.owner, id);
+        match entries.get(&key) {
+            Some((stored_at, todo)) if stored_at.elapsed() < self.ttl => Some(todo.clone()),
+            Some(_) => {
+                entries.remove(&key);
+                None
+            }
+            None => None,
+        }
+    }
+
+    fn store(&self, todo: &TodoEntity) {
+        if let Some(mut entries) = self.entries() {
+            entries.insert((self.owner, todo.id), (Instant::now(), todo.clone()));
+        }
+    }
+
+    fn invalidate(&self, id: TodoId) {
+        if let Some(mut entries) = self.entries() {
+            entries.remove(&(self.owner, id));
+        }
+    }
+
+    /// Drops every entry after writes to many todos, which do not say which ones changed.
+    fn invalidate_all(&self) {
+        if let Some(mut entries) = self.entries() {
+            entries.clear();
+        }
+    }
+}
+
+impl<T: TodoRepository> OwnerScoped for CachedTodoRepository<T> {
+    fn scoped(&self, owner: OwnerId) -> Self {
+        Self {
+            inner: self.inner.scoped(owner),
+            owner,
+            ..self.clone()
+        }
+    }
+
+    fn with_deadline(&self, deadline: tokio::time::Instant) -> Self {
+        Self {
+            inner: self.inner.with_deadline(deadline),
+            ..self.clone()
+        }
+    }
+}
+
+#[async_trait]
+impl<T: TodoRepository> TodoRepository for CachedTodoRepository<T> {
+    async fn create(&self, payload: CreateTodo) -> anyhow::Result<TodoEntity> {
+        self.inner.create(payload).await
+    }
+
+    async fn find(&self, id: TodoId) -> anyhow::Result<TodoEntity> {
+        if let Some(todo) = self.cached(id) {
+            return Ok(todo);
+        }
+        let todo = self.inner.find(id).await?;
+        self.store(&todo);
+        Ok(todo)
+    }
+
+    async fn exists(&self, id: TodoId) -> anyhow::Result<bool> {
+        self.inner.exists(id).await
+    }
+
+    async fn find_many(&self, ids: &[TodoId]) -> anyhow::Result<Vec<TodoEntity>> {
+        self.inner.find_many(ids).await
+    }
+
+    async fn all(&self, query: TodoQuery) -> anyhow::Result<Vec<TodoEntity>> {
+        self.inner.all(query).await
+    }
+
+    async fn update(&self, id: TodoId, payload: UpdateTodo) -> anyhow::Result<TodoEntity> {
+        let res = self.inner.update(id, payload).await;
+        self.invalidate(id);
+        res
+    }
+
+    async fn replace(&self, id: TodoId, payload: ReplaceTodo) -> anyhow::Result<TodoEntity> {
+        let res = self.inner.replace(id, payload).await;
+        self.invalidate(id);
+        res
+    }
+
+    async fn delete(&self, id: TodoId) -> anyhow::Result<()> {
+        let res = self.inner.delete(id).await;
+        self.invalidate(id);
+        res
+    }
+
+    async fn touch(&self, id: TodoId) -> anyhow::Result<TodoEntity> {
+        let res = self.inner.touch(id).await;
+        self.invalidate(id);
+        res
+    }
+
+    async fn duplicate(&self, id: TodoId) -> anyhow::Result<TodoEntity> {
+        self.inner.duplicate(id).await
+    }
+
+    async fn history(&self, id: TodoId) -> anyhow::Result<Vec<TodoChange>> {
+        self.inner.history(id).await
+    }
+
+    async fn reorder(&self, ids: Vec<TodoId>) -> anyhow::Result<()> {
+        let res = self.inner.reorder(ids).await;
+        self.invalidate_all();
+        res
+    }
+
+    async fn changed_since(&self, since: DateTime<Utc>) -> anyhow::Result<Vec<SyncedTodo>> {
+        self.inner.changed_since(since).await
+    }
+
+    async fn search(&self, criteria: TodoSearchCriteria) -> anyhow::Result<TodoSearchResult> {
+        self.inner.search(criteria).await
+    }
+
+    async fn search_ranked(
+        &self,
+        q: &str,
+        include_archived: bool,
+    ) -> anyhow::Result<Vec<TodoEntity>> {
+        self.inner.search_ranked(q, include_archived).await
+    }
+
+    async fn set_completed_all(&self, filter: TodoFilter, completed: bool) -> anyhow::Result<u64> {
+        let res = self.inner.set_completed_all(filter, completed).await;
+        self.invalidate_all();
+        res
+    }
+
+    async fn attach_label(
+        &self,
+        label: LabelId,
+        ids: Vec<TodoId>,
+    ) -> anyhow::Result<AttachedLabel> {
+        let res = self.inner.attach_label(label, ids).await;
+        self.invalidate_all();
+        res
+    }
+
+    async fn summary(&self, filter: TodoFilter) -> anyhow::Result<TodoSummary> {
+        self.inner.summary(filter).await
+    }
+
+    async fn last_modified(&self) -> anyhow::Result<DateTime<Utc>> {
+        self.inner.last_modified().await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::repositories::todo::test_utils::TodoRepositoryForMemory;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// Memory repository counting the calls of `find` that reach it.
+    #[derive(Debug, Clone)]
+    struct CountingTodoRepository {
+        inner: TodoRepositoryForMemory,
+        finds: Arc<AtomicUsize>,
+    }
+
+    impl CountingTodoRepository {
+        fn new() -> Self {
+            CountingTodoRepository {
+                inner: TodoRepositoryForMemory::new(vec![]),
+                finds: Arc::default(),
+            }
+        }
+
+        fn finds(&self) -> usize {
+            self.finds.load(Ordering::SeqCst)
+        }
+    }
+
+    impl OwnerScoped for CountingTodoRepository {
+        fn scoped(&self, owner: OwnerId) -> Self {
+            Self {
+                inner: self.inner.scoped(owner),
+                ..self.clone()
+            }
+        }
+    }
+
+    #[async_trait]
+    impl TodoRepository for CountingTodoRepository {
+        async fn create(&self, payload: CreateTodo) -> anyhow::Result<TodoEntity> {
+            self.inner.create(payload).await
+        }
+
+        async fn find(&self, id: TodoId) -> anyhow::Result<TodoEntity> {
+            self.finds.fetch_add(1, Ordering::SeqCst);
+            self.inner.find(id).await
+        }
+
+        async fn exists(&self, id: TodoId) -> anyhow::Result<bool> {
+            self.inner.exists(id).await
+        }
+
+        async fn find_many(&self, ids: &[TodoId]) -> anyhow::Result<Vec<TodoEntity>> {
+            self.inner.find_many(ids).await
+        }
+
+        async fn all(&self, query: TodoQuery) -> anyhow::Result<Vec<TodoEntity>> {
+            self.inner.all(query).await
+        }
+
+        async fn update(&self, id: TodoId, payload: UpdateTodo) -> anyhow::Result<TodoEntity> {
+            self.inner.update(id, payload).await
+        }
+
+        async fn delete(&self, id: TodoId) -> anyhow::Result<()> {
+            self.inner.delete(id).await
+        }
+
+        async fn touch(&self, id: TodoId) -> anyhow::Result<TodoEntity> {
+            self.inner.touch(id).await
+        }
+
+        async fn duplicate(&self, id: TodoId) -> anyhow::Result<TodoEntity> {
+            self.inner.duplicate(id).await
+        }
+
+        async fn history(&self, id: TodoId) -> anyhow::Result<Vec<TodoChange>> {
+            self.inner.history(id).await
+        }
+
+        async fn reorder(&self, ids: Vec<TodoId>) -> anyhow::Result<()> {
+            self.inner.reorder(ids).await
+        }
+
+        async fn changed_since(&self, since: DateTime<Utc>) -> anyhow::Result<Vec<SyncedTodo>> {
+            self.inner.changed_since(since).await
+        }
+
+        async fn search(&self, criteria: TodoSearchCriteria) -> anyhow::Result<TodoSearchResult> {
+            self.inner.search(criteria).await
+        }
+
+        async fn search_ranked(
+            &self,
+            q: &str,
+            include_archived: bool,
+        ) -> anyhow::Result<Vec<TodoEntity>> {
+            self.inner.search_ranked(q, include_archived).await
+        }
+
+        async fn set_completed_all(
+            &self,
+            filter: TodoFilter,
+            completed: bool,
+        ) -> anyhow::Result<u64> {
+            self.inner.set_completed_all(filter, completed).await
+        }
+
+        async fn attach_label(
+            &self,
+            label: LabelId,
+            ids: Vec<TodoId>,
+        ) -> anyhow::Result<AttachedLabel> {
+            self.inner.attach_label(label, ids).await
+        }
+
+        async fn summary(&self, filter: TodoFilter) -> anyhow::Result<TodoSummary> {
+            self.inner.summary(filter).await
+        }
+
+        async fn last_modified(&self) -> anyhow::Result<DateTime<Utc>> {
+            self.inner.last_modified().await
+        }
+    }
+
+    async fn create(repo: &impl TodoRepository) -> TodoEntity {
+        repo.create(CreateTodo::new("cached".to_string(), vec![]))
+            .await
+            .expect("[create] returned Err")
+    }
+
+    #[tokio::test]
+    async fn should_hit_cache_on_second_find() {
+        let counting = CountingTodoRepository::new();
+        let repo = CachedTodoRepository::new(counting.clone(), 10, Duration::from_secs(60));
+        let todo = create(&repo).await;
+
+        assert_eq!(todo, repo.find(todo.id).await.unwrap());
+        assert_eq!(todo, repo.find(todo.id).await.unwrap());
+        assert_eq!(1, counting.finds());
+    }
+
+    #[tokio::test]
+    async fn should_invalidate_on_update_and_delete() {
+        let counting = CountingTodoRepository::new();
+        let repo = CachedTodoRepository::new(counting.clone(), 10, Duration::from_secs(60));
+        let todo = create(&repo).await;
+        repo.find(todo.id).await.unwrap();
+
+        repo.update(todo.id, UpdateTodo::new(None, Some(true), None))
+            .await
+            .expect("[update] returned Err");
+        assert!(repo.find(todo.id).await.unwrap().completed);
+        assert_eq!(2, counting.finds());
+
+        repo.delete(todo.id).await.expect("[delete] returned Err");
+        assert!(repo.find(todo.id).await.is_err());
+        assert_eq!(3, counting.finds());
+    }
+
+    #[tokio::test]
+    async fn should_expire_and_evict_entries() {
+        let counting = CountingTodoRepository::new();
+        let repo = CachedTodoRepository::new(counting.clone(), 1, Duration::ZERO);
+        let todo = create(&repo).await;
+        repo.find(todo.id).await.unwrap();
+        repo.find(todo.id).await.unwrap();
+        assert_eq!(2, counting.finds());
+
+        let repo = CachedTodoRepository::new(counting.clone(), 1, Duration::from_secs(60));
+        let other = create(&repo).await;
+        repo.find(todo.id).await.unwrap();
+        repo.find(other.id).await.unwrap();
+        repo.find(todo.id).await.unwrap();
+        assert_eq!(5, counting.finds());
+    }
+
+    #[tokio::test]
+    async fn should_keep_owners_apart() {
+        let counting = CountingTodoRepository::new();
+        let repo = CachedTodoRepository::new(counting.clone(), 10, Duration::from_secs(60));
+        let todo = create(&repo.scoped(OwnerId(1))).await;
+        repo.scoped(OwnerId(1)).find(todo.id).await.unwrap();
+
+        assert!(repo.scoped(OwnerId(2)).find(todo.id).await.is_err());
+        assert_eq!(2, counting.finds());
+    }
+}
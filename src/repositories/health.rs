@@ -0,0 +1,285 @@
+use crate::repositories::label::LabelId;
+use crate::repositories::todo::TodoId;
+use crate::repositories::RepositoryError;
+use anyhow::Context;
+use axum::async_trait;
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, PgConnection, PgPool};
+use std::time::{Duration, Instant};
+
+#[async_trait]
+pub trait HealthRepository: Clone + Send + Sync + 'static {
+    async fn check(&self) -> anyhow::Result<PoolStatus>;
+    /// Associations whose todo or label is gone, of every owner.
+    async fn orphans(&self) -> anyhow::Result<Orphans>;
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Eq, PartialEq)]
+pub struct PoolStatus {
+    pub pool_size: u32,
+    pub idle: usize,
+    pub db_latency_ms: u64,
+}
+
+/// `todo_labels` row pointing at a todo or a label that does not exist.
+#[derive(Debug, Serialize, Deserialize, Clone, Eq, PartialEq, FromRow)]
+pub struct OrphanLink {
+    pub id: i32,
+    pub todo_id: TodoId,
+    pub label_id: LabelId,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize, Clone, Eq, PartialEq)]
+pub struct Orphans {
+    pub missing_todos: Vec<OrphanLink>,
+    pub missing_labels: Vec<OrphanLink>,
+}
+
+const MISSING_TODOS: &str = r#"
+        SELECT t1.id, t1.todo_id, t1.label_id FROM todo_labels t1
+        WHERE NOT EXISTS (SELECT 1 FROM todos WHERE todos.id = t1.todo_id)
+        ORDER BY t1.id;"#;
+
+const MISSING_LABELS: &str = r#"
+        SELECT t1.id, t1.todo_id, t1.label_id FROM todo_labels t1
+        WHERE NOT EXISTS (SELECT 1 FROM labels WHERE labels.id = t1.label_id)
+        ORDER BY t1.id;"#;
+
+/// Runs the anti-joins of `orphans` on `conn`, which lets them see the writes of a
+/// transaction whose foreign keys are not checked yet.
+pub async fn find_orphans(conn: &mut PgConnection) -> anyhow::Result<Orphans> {
+    let missing_todos = sqlx::query_as::<_, OrphanLink>(MISSING_TODOS)
+        .fetch_all(&mut *conn)
+        .await
+        .context("find orphan todo labels")?;
+    let missing_labels = sqlx::query_as::<_, OrphanLink>(MISSING_LABELS)
+        .fetch_all(&mut *conn)
+        .await
+        .context("find orphan todo labels")?;
+    Ok(Orphans {
+        missing_todos,
+        missing_labels,
+    })
+}
+
+#[derive(Debug, Clone)]
+pub struct HealthRepositoryForDb {
+    pool: PgPool,
+    timeout: Duration,
+}
+
+impl HealthRepositoryForDb {
+    pub fn new(pool: PgPool, timeout: Duration) -> Self {
+        Self { pool, timeout }
+    }
+}
+
+#[async_trait]
+impl HealthRepository for HealthRepositoryForDb {
+    async fn check(&self) -> anyhow::Result<PoolStatus> {
+        let start = Instant::now();
+        tokio::time::timeout(self.timeout, sqlx::query(r#"SELECT 1"#).execute(&self.pool))
+            .await
+            .map_err(|_| {
+                RepositoryError::Unexpected(format!(
+                    "probe query exceeded {}ms",
+                    self.timeout.as_millis()
+                ))
+            })??;
+
+        Ok(PoolStatus {
+            pool_size: self.pool.size(),
+            idle: self.pool.num_idle(),
+            db_latency_ms: start.elapsed().as_millis() as u64,
+        })
+    }
+
+    async fn orphans(&self) -> anyhow::Result<Orphans> {
+        let mut conn = self
+            .pool
+            .acquire()
+            .await
+            .context("find orphan todo labels")?;
+        find_orphans(&mut conn).await
+    }
+}
+
+#[cfg(test)]
+#[cfg(feature = "database-test")]
+mod test {
+    use super::*;
+    use dotenv::dotenv;
+    use sqlx::postgres::PgPoolOptions;
+    use std::env;
+
+    async fn connect(max_connections: u32) -> PgPool {
+        dotenv().ok();
+        let database_url = &env::var("DATABASE_URL").expect("undefined [DATABASE_URL]");
+        PgPoolOptions::new()
+            .max_connections(max_connections)
+            .connect(database_url)
+            .await
+            .unwrap_or_else(|_| panic!("fail connect database. url is [{}]", database_url))
+    }
+
+    #[tokio::test]
+    async fn health_check_scenario() {
+        let repo = HealthRepositoryForDb::new(connect(5).await, Duration::from_secs(5));
+        let status = repo.check().await.expect("[check] returned Err");
+        assert!(status.pool_size >= 1);
+    }
+
+    #[tokio::test]
+    async fn health_check_timeout() {
+        let pool = connect(1).await;
+        let _conn = pool.acquire().await.expect("fail acquire connection");
+        let repo = HealthRepositoryForDb::new(pool.clone(), Duration::from_millis(100));
+        let res = repo.check().await;
+        assert!(res.is_err());
+    }
+
+    #[tokio::test]
+    #[cfg(not(feature = "uuid"))]
+    async fn orphans_scenario() {
+        let pool = connect(1).await;
+        let repo = HealthRepositoryForDb::new(pool.clone(), Duration::from_secs(5));
+        assert_eq!(Orphans::default(), repo.orphans().await.unwrap());
+
+        // the foreign keys are deferred, so the orphan lives until the end of the transaction
+        let mut tx = pool.begin().await.unwrap();
+        let (label_id,) = sqlx::query_as::<_, (LabelId,)>(
+            r#"INSERT INTO labels (name, owner_id) VALUES ('[orphans] label', 0) RETURNING id"#,
+        )
+        .fetch_one(&mut tx)
+        .await
+        .expect("Failed to insert label data");
+        let orphan = sqlx::query_as::<_, OrphanLink>(
+            r#"INSERT INTO todo_labels (todo_id, label_id) VALUES (-1, $1) RETURNING *"#,
+        )
+        .bind(label_id)
+        .fetch_one(&mut tx)
+        .await
+        .expect("Failed to insert todo_labels data");
+
+        let orphans = find_orphans(&mut tx).await.expect("[orphans] returned Err");
+        assert_eq!(
+            Orphans {
+                missing_todos: vec![orphan],
+                missing_labels: vec![],
+            },
+            orphans
+        );
+        tx.rollback().await.unwrap();
+    }
+}
+
+#[cfg(any(test, feature = "testing"))]
+pub mod test_utils {
+    use super::*;
+
+    #[derive(Debug, Clone)]
+    pub struct HealthRepositoryForMemory {
+        healthy: bool,
+        orphans: Orphans,
+    }
+
+    impl Default for HealthRepositoryForMemory {
+        fn default() -> Self {
+            Self::new()
+        }
+    }
+
+    impl HealthRepositoryForMemory {
+        pub fn new() -> Self {
+            Self {
+                healthy: true,
+                orphans: Orphans::default(),
+            }
+        }
+
+        pub fn unhealthy() -> Self {
+            Self {
+                healthy: false,
+                ..Self::new()
+            }
+        }
+
+        /// Reports `orphans`, the memory store having no associations of its own to check.
+        pub fn with_orphans(mut self, orphans: Orphans) -> Self {
+            self.orphans = orphans;
+            self
+        }
+    }
+
+    #[async_trait]
+    impl HealthRepository for HealthRepositoryForMemory {
+        async fn check(&self) -> anyhow::Result<PoolStatus> {
+            if !self.healthy {
+                return Err(RepositoryError::Unexpected("unhealthy".to_string()).into());
+            }
+            Ok(PoolStatus {
+                pool_size: 1,
+                idle: 1,
+                db_latency_ms: 0,
+            })
+        }
+
+        async fn orphans(&self) -> anyhow::Result<Orphans> {
+            Ok(self.orphans.clone())
+        }
+    }
+}
+
+#[cfg(feature = "sqlite")]
+pub mod sqlite {
+    use super::*;
+    use sqlx::SqlitePool;
+
+    #[derive(Debug, Clone)]
+    pub struct HealthRepositoryForSqlite {
+        pool: SqlitePool,
+        timeout: Duration,
+    }
+
+    impl HealthRepositoryForSqlite {
+        pub fn new(pool: SqlitePool, timeout: Duration) -> Self {
+            Self { pool, timeout }
+        }
+    }
+
+    #[async_trait]
+    impl HealthRepository for HealthRepositoryForSqlite {
+        async fn check(&self) -> anyhow::Result<PoolStatus> {
+            let start = Instant::now();
+            tokio::time::timeout(self.timeout, sqlx::query(r#"SELECT 1"#).execute(&self.pool))
+                .await
+                .map_err(|_| {
+                    RepositoryError::Unexpected(format!(
+                        "probe query exceeded {}ms",
+                        self.timeout.as_millis()
+                    ))
+                })??;
+
+            Ok(PoolStatus {
+                pool_size: self.pool.size(),
+                idle: self.pool.num_idle(),
+                db_latency_ms: start.elapsed().as_millis() as u64,
+            })
+        }
+
+        async fn orphans(&self) -> anyhow::Result<Orphans> {
+            let missing_todos = sqlx::query_as::<_, OrphanLink>(MISSING_TODOS)
+                .fetch_all(&self.pool)
+                .await
+                .context("find orphan todo labels")?;
+            let missing_labels = sqlx::query_as::<_, OrphanLink>(MISSING_LABELS)
+                .fetch_all(&self.pool)
+                .await
+                .context("find orphan todo labels")?;
+            Ok(Orphans {
+                missing_todos,
+                missing_labels,
+            })
+        }
+    }
+}
@@ -1,102 +1,610 @@
-use crate::repositories::RepositoryError;
+use crate::repositories::{
+    deserialize_collapsed, id_type, timed, OwnerId, OwnerScoped, RawId, RepositoryError,
+    DEFAULT_SLOW_QUERY_MS,
+};
+use anyhow::Context;
 use axum::async_trait;
 use serde::{Deserialize, Serialize};
-use sqlx::{FromRow, PgPool};
+use sqlx::{FromRow, PgConnection, PgPool};
+use std::time::Duration;
 use validator::Validate;
 
 #[async_trait]
-pub trait LabelRepository: Clone + Send + Sync + 'static {
+pub trait LabelRepository: OwnerScoped {
+    /// Fails with `Duplicate` naming the existing label when the owner already has one of
+    /// that name, which `POST /labels` answers with 409; `create_many` is the get-or-create.
+    /// Databases also refuse the name when a concurrent create wins, failing with `Conflict`.
     async fn create(&self, payload: CreateLabel) -> anyhow::Result<Label>;
+    /// Creates the labels in one transaction, returning one label per distinct name in the
+    /// order they were given; names that already exist return the existing label.
+    async fn create_many(&self, payloads: Vec<CreateLabel>) -> anyhow::Result<Vec<Label>>;
+    async fn find(&self, id: LabelId) -> anyhow::Result<Label>;
+    async fn find_by_name(&self, name: &str) -> anyhow::Result<Option<Label>>;
     async fn all(&self) -> anyhow::Result<Vec<Label>>;
-    async fn delete(&self, id: i32) -> anyhow::Result<()>;
+    /// Same labels as `all`, ordered alphabetically by name, then by id.
+    async fn all_by_name(&self) -> anyhow::Result<Vec<Label>>;
+    /// Same labels as `all`, each with the number of todos it is attached to.
+    async fn all_with_counts(&self) -> anyhow::Result<Vec<LabelWithCount>>;
+    /// Labels `offset..offset + limit` in the order of `all` or `all_by_name`, along with
+    /// the number of labels of the owner.
+    async fn page(
+        &self,
+        sort: LabelSort,
+        limit: i64,
+        offset: i64,
+    ) -> anyhow::Result<LabelPage<Label>>;
+    /// Labels `offset..offset + limit` of `all_with_counts`, along with the number of labels
+    /// of the owner.
+    async fn page_with_counts(
+        &self,
+        limit: i64,
+        offset: i64,
+    ) -> anyhow::Result<LabelPage<LabelWithCount>>;
+    /// Returns the given ids that do not belong to any label, in their original order.
+    async fn missing(&self, ids: &[LabelId]) -> anyhow::Result<Vec<LabelId>>;
+    /// Refuses to delete a label still attached to todos unless `force` is set, in which
+    /// case the associations are removed along with it.
+    async fn delete(&self, id: LabelId, force: bool) -> anyhow::Result<()>;
+    async fn merge(&self, keep: LabelId, remove: LabelId) -> anyhow::Result<Label>;
+    /// Renames the labels in one transaction, returning them in the order given. Nothing is
+    /// renamed when a new name would be shared with another label of the owner.
+    async fn update_many(&self, payloads: Vec<UpdateLabel>) -> anyhow::Result<Vec<Label>>;
+}
+
+id_type!(LabelId, RawId);
+
+/// Order of `GET /labels`, `Name` follows the collation the repository is configured with.
+#[derive(Debug, Default, Serialize, Deserialize, Clone, Copy, Eq, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum LabelSort {
+    #[default]
+    Id,
+    Name,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, FromRow)]
 pub struct Label {
-    pub id: i32,
+    pub id: LabelId,
+    pub name: String,
+}
+
+impl Label {
+    pub fn new(id: LabelId, name: String) -> Self {
+        Self { id, name }
+    }
+}
+
+/// Label listed by `GET /labels?with_counts=true`.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, FromRow)]
+pub struct LabelWithCount {
+    pub id: LabelId,
     pub name: String,
+    pub usage_count: i64,
+}
+
+/// One page of `GET /labels?limit=..&offset=..`, `total` counts the labels of every page.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LabelPage<T> {
+    pub items: Vec<T>,
+    pub total: i64,
 }
 
 #[derive(Serialize, Deserialize, Debug, Eq, PartialEq, Validate)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub struct CreateLabel {
-    #[validate(length(min = 1, message = "Cannot be empty"))]
-    #[validate(length(max = 100, message = "Over text length"))]
+    #[serde(deserialize_with = "deserialize_collapsed")]
+    #[validate(length(min = 1, code = "empty", message = "Cannot be empty"))]
+    #[validate(length(max = 100, code = "too_long", message = "Over text length"))]
     name: String,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone, Eq, PartialEq, sqlx::FromRow)]
+impl CreateLabel {
+    pub fn new(name: String) -> Self {
+        Self { name }
+    }
+}
+
+/// Body of `POST /labels/bulk`, a plain array of [`CreateLabel`].
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq, Validate)]
+#[serde(transparent)]
+pub struct CreateLabels {
+    #[validate(length(
+        min = 1,
+        max = 100,
+        code = "label_count",
+        message = "Between 1 and 100 labels"
+    ))]
+    #[validate]
+    labels: Vec<CreateLabel>,
+}
+
+impl CreateLabels {
+    pub fn new(labels: Vec<CreateLabel>) -> Self {
+        Self { labels }
+    }
+
+    pub fn into_inner(self) -> Vec<CreateLabel> {
+        self.labels
+    }
+}
+
+fn unique_names(payloads: Vec<CreateLabel>) -> Vec<String> {
+    let mut names: Vec<String> = Vec::with_capacity(payloads.len());
+    for payload in payloads {
+        if !names.contains(&payload.name) {
+            names.push(payload.name);
+        }
+    }
+    names
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Eq, PartialEq, sqlx::FromRow, Validate)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub struct UpdateLabel {
-    id: i32,
+    id: LabelId,
+    #[serde(deserialize_with = "deserialize_collapsed")]
+    #[validate(length(min = 1, code = "empty", message = "Cannot be empty"))]
+    #[validate(length(max = 100, code = "too_long", message = "Over text length"))]
     name: String,
 }
 
+impl UpdateLabel {
+    pub fn new(id: LabelId, name: String) -> Self {
+        Self { id, name }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+/// Body of `PATCH /labels/bulk`, a plain array of [`UpdateLabel`].
+#[derive(Serialize, Deserialize, Debug, Eq, PartialEq, Validate)]
+#[serde(transparent)]
+pub struct UpdateLabels {
+    #[validate(length(
+        min = 1,
+        max = 100,
+        code = "label_count",
+        message = "Between 1 and 100 labels"
+    ))]
+    #[validate]
+    labels: Vec<UpdateLabel>,
+}
+
+impl UpdateLabels {
+    pub fn new(labels: Vec<UpdateLabel>) -> Self {
+        Self { labels }
+    }
+
+    pub fn into_inner(self) -> Vec<UpdateLabel> {
+        self.labels
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct LabelRepositoryForDb {
     pool: PgPool,
+    owner: OwnerId,
+    collation: Option<String>,
+    slow_query: Duration,
 }
 
 impl LabelRepositoryForDb {
     pub fn new(pool: PgPool) -> Self {
-        Self { pool }
+        Self {
+            pool,
+            owner: OwnerId::default(),
+            collation: None,
+            slow_query: Duration::from_millis(DEFAULT_SLOW_QUERY_MS),
+        }
+    }
+
+    /// Duration over which `create`, `all`, `delete` and `update_many` are logged as slow.
+    pub fn with_slow_query(mut self, slow_query: Duration) -> Self {
+        self.slow_query = slow_query;
+        self
+    }
+
+    /// Collation `all_by_name` compares names with, such as `ja-x-icu`, instead of the one
+    /// of the database. It must exist, see [`resolve_collation`].
+    pub fn with_collation(mut self, collation: Option<String>) -> Self {
+        self.collation = collation;
+        self
+    }
+
+    async fn count(&self) -> anyhow::Result<i64> {
+        let (total,) =
+            sqlx::query_as::<_, (i64,)>(r#"SELECT count(*) FROM labels WHERE owner_id = $1"#)
+                .bind(self.owner)
+                .fetch_one(&self.pool)
+                .await
+                .context("count labels")?;
+        Ok(total)
+    }
+}
+
+/// `COLLATE` clause for `collation`, quoted since identifiers cannot be bound.
+fn collate_clause(collation: &str) -> String {
+    format!(r#" COLLATE "{}""#, collation.replace('"', r#""""#))
+}
+
+/// Returns `name` when the database can compare with such a collation; ICU ones are missing
+/// from servers built without ICU and from databases not encoded in UTF-8, in which case
+/// the default collation is kept.
+pub async fn resolve_collation(pool: &PgPool, name: Option<String>) -> Option<String> {
+    let name = name?;
+    let probe = format!("SELECT 'a' < 'b'{}", collate_clause(&name));
+    match sqlx::query(&probe).execute(pool).await {
+        Ok(_) => Some(name),
+        Err(e) => {
+            tracing::warn!(
+                "collation [{}] is unusable, use the default one: {}",
+                name,
+                e
+            );
+            None
+        }
+    }
+}
+
+impl OwnerScoped for LabelRepositoryForDb {
+    fn scoped(&self, owner: OwnerId) -> Self {
+        Self {
+            owner,
+            ..self.clone()
+        }
     }
 }
 
 #[async_trait]
 impl LabelRepository for LabelRepositoryForDb {
     async fn create(&self, payload: CreateLabel) -> anyhow::Result<Label> {
-        let optional_label = sqlx::query_as::<_, Label>(r#"SELECT * FROM labels WHERE name = $1"#)
+        timed("label.create", self.slow_query, async move {
+            if let Some(label) = self
+                .find_by_name(&payload.name)
+                .await
+                .context("create label")?
+            {
+                return Err(RepositoryError::Duplicate(label.id.into()).into());
+            }
+
+            let label = sqlx::query_as::<_, Label>(
+                r#"INSERT INTO labels (name, owner_id) VALUES ($1, $2) RETURNING *"#,
+            )
             .bind(payload.name.clone())
-            .fetch_optional(&self.pool)
-            .await?;
+            .bind(self.owner)
+            .fetch_one(&self.pool)
+            .await
+            .context("create label")?;
+            Ok(label)
+        })
+        .await
+    }
 
-        if let Some(label) = optional_label {
-            return Err(RepositoryError::Duplicate(label.id).into());
+    async fn create_many(&self, payloads: Vec<CreateLabel>) -> anyhow::Result<Vec<Label>> {
+        let mut tx = self.pool.begin().await.context("create labels")?;
+        let mut labels: Vec<Label> = Vec::with_capacity(payloads.len());
+        for name in unique_names(payloads) {
+            let existing = sqlx::query_as::<_, Label>(
+                r#"SELECT * FROM labels WHERE name = $1 AND owner_id = $2 ORDER BY id LIMIT 1"#,
+            )
+            .bind(&name)
+            .bind(self.owner)
+            .fetch_optional(&mut tx)
+            .await
+            .context("create labels")?;
+            let label = match existing {
+                Some(label) => label,
+                None => sqlx::query_as::<_, Label>(
+                    r#"INSERT INTO labels (name, owner_id) VALUES ($1, $2) RETURNING *"#,
+                )
+                .bind(name)
+                .bind(self.owner)
+                .fetch_one(&mut tx)
+                .await
+                .context("create labels")?,
+            };
+            labels.push(label);
         }
+        tx.commit().await.context("create labels")?;
+        Ok(labels)
+    }
 
+    async fn find(&self, id: LabelId) -> anyhow::Result<Label> {
         let label =
-            sqlx::query_as::<_, Label>(r#"INSERT INTO labels (name) VALUES ($1) RETURNING *"#)
-                .bind(payload.name.clone())
-                .fetch_one(&self.pool)
-                .await?;
+            sqlx::query_as::<_, Label>(r#"SELECT * FROM labels WHERE id = $1 AND owner_id = $2"#)
+                .bind(id)
+                .bind(self.owner)
+                .fetch_optional(&self.pool)
+                .await
+                .context("find label")?
+                .ok_or(RepositoryError::NotFound(id.into()))?;
+        Ok(label)
+    }
+
+    async fn find_by_name(&self, name: &str) -> anyhow::Result<Option<Label>> {
+        let label =
+            sqlx::query_as::<_, Label>(r#"SELECT * FROM labels WHERE name = $1 AND owner_id = $2"#)
+                .bind(name)
+                .bind(self.owner)
+                .fetch_optional(&self.pool)
+                .await
+                .context("find label by name")?;
         Ok(label)
     }
 
     async fn all(&self) -> anyhow::Result<Vec<Label>> {
-        let labels = sqlx::query_as::<_, Label>(r#"SELECT * FROM labels ORDER BY labels.id ASC"#)
+        timed("label.all", self.slow_query, async move {
+            let labels = sqlx::query_as::<_, Label>(
+                r#"SELECT * FROM labels WHERE owner_id = $1 ORDER BY labels.id ASC"#,
+            )
+            .bind(self.owner)
             .fetch_all(&self.pool)
-            .await?;
+            .await
+            .context("list labels")?;
+            Ok(labels)
+        })
+        .await
+    }
+
+    async fn all_by_name(&self) -> anyhow::Result<Vec<Label>> {
+        let collate = self
+            .collation
+            .as_deref()
+            .map(collate_clause)
+            .unwrap_or_default();
+        let labels = sqlx::query_as::<_, Label>(&format!(
+            r#"SELECT * FROM labels WHERE owner_id = $1 ORDER BY labels.name{} ASC, labels.id ASC"#,
+            collate
+        ))
+        .bind(self.owner)
+        .fetch_all(&self.pool)
+        .await
+        .context("list labels by name")?;
         Ok(labels)
     }
 
-    async fn delete(&self, id: i32) -> anyhow::Result<()> {
-        sqlx::query(r#"DELETE FROM labels WHERE id = $1"#)
-            .bind(id)
-            .execute(&self.pool)
+    async fn all_with_counts(&self) -> anyhow::Result<Vec<LabelWithCount>> {
+        let labels = sqlx::query_as::<_, LabelWithCount>(
+            r#"
+        SELECT labels.id, labels.name, COUNT(DISTINCT todos.id) AS usage_count FROM labels
+        LEFT OUTER JOIN todo_labels t1 on labels.id = t1.label_id
+        LEFT OUTER JOIN todos on todos.id = t1.todo_id AND todos.owner_id = labels.owner_id
+        WHERE labels.owner_id = $1
+        GROUP BY labels.id
+        ORDER BY labels.id ASC;"#,
+        )
+        .bind(self.owner)
+        .fetch_all(&self.pool)
+        .await
+        .context("count label usage")?;
+        Ok(labels)
+    }
+
+    async fn page(
+        &self,
+        sort: LabelSort,
+        limit: i64,
+        offset: i64,
+    ) -> anyhow::Result<LabelPage<Label>> {
+        let order = match sort {
+            LabelSort::Id => String::new(),
+            LabelSort::Name => format!(
+                "labels.name{} ASC, ",
+                self.collation
+                    .as_deref()
+                    .map(collate_clause)
+                    .unwrap_or_default()
+            ),
+        };
+        let items = sqlx::query_as::<_, Label>(&format!(
+            r#"SELECT * FROM labels WHERE owner_id = $1 ORDER BY {}labels.id ASC LIMIT $2 OFFSET $3"#,
+            order
+        ))
+        .bind(self.owner)
+        .bind(limit)
+        .bind(offset)
+        .fetch_all(&self.pool)
+        .await
+        .context("page labels")?;
+        Ok(LabelPage {
+            items,
+            total: self.count().await?,
+        })
+    }
+
+    async fn page_with_counts(
+        &self,
+        limit: i64,
+        offset: i64,
+    ) -> anyhow::Result<LabelPage<LabelWithCount>> {
+        let items = sqlx::query_as::<_, LabelWithCount>(
+            r#"
+        SELECT labels.id, labels.name, COUNT(DISTINCT todos.id) AS usage_count FROM labels
+        LEFT OUTER JOIN todo_labels t1 on labels.id = t1.label_id
+        LEFT OUTER JOIN todos on todos.id = t1.todo_id AND todos.owner_id = labels.owner_id
+        WHERE labels.owner_id = $1
+        GROUP BY labels.id
+        ORDER BY labels.id ASC
+        LIMIT $2 OFFSET $3;"#,
+        )
+        .bind(self.owner)
+        .bind(limit)
+        .bind(offset)
+        .fetch_all(&self.pool)
+        .await
+        .context("page label usage")?;
+        Ok(LabelPage {
+            items,
+            total: self.count().await?,
+        })
+    }
+
+    async fn missing(&self, ids: &[LabelId]) -> anyhow::Result<Vec<LabelId>> {
+        let missing = sqlx::query_as::<_, (LabelId,)>(
+            r#"
+        SELECT t.id FROM unnest($1) WITH ORDINALITY AS t(id, n)
+        WHERE NOT EXISTS (SELECT 1 FROM labels WHERE labels.id = t.id AND labels.owner_id = $2)
+        ORDER BY t.n;"#,
+        )
+        .bind(ids)
+        .bind(self.owner)
+        .fetch_all(&self.pool)
+        .await
+        .context("find missing labels")?;
+        Ok(missing.into_iter().map(|(id,)| id).collect())
+    }
+
+    async fn delete(&self, id: LabelId, force: bool) -> anyhow::Result<()> {
+        timed("label.delete", self.slow_query, async move {
+            let mut tx = self.pool.begin().await.context("delete label")?;
+            sqlx::query(r#"SELECT id FROM labels WHERE id = $1 AND owner_id = $2 FOR UPDATE"#)
+                .bind(id)
+                .bind(self.owner)
+                .fetch_optional(&mut tx)
+                .await
+                .context("delete label")?
+                .ok_or(RepositoryError::NotFound(id.into()))?;
+            if !force {
+                let (count,) = sqlx::query_as::<_, (i64,)>(
+                    r#"SELECT COUNT(DISTINCT todo_id) FROM todo_labels WHERE label_id = $1"#,
+                )
+                .bind(id)
+                .fetch_one(&mut tx)
+                .await
+                .context("delete label")?;
+                if count > 0 {
+                    return Err(RepositoryError::InUse(id.into(), count).into());
+                }
+            }
+
+            sqlx::query(r#"DELETE FROM todo_labels WHERE label_id = $1"#)
+                .bind(id)
+                .execute(&mut tx)
+                .await
+                .context("delete label")?;
+            sqlx::query(r#"DELETE FROM labels WHERE id = $1"#)
+                .bind(id)
+                .execute(&mut tx)
+                .await
+                .context("delete label")?;
+            tx.commit().await.context("delete label")?;
+
+            Ok(())
+        })
+        .await
+    }
+
+    async fn merge(&self, keep: LabelId, remove: LabelId) -> anyhow::Result<Label> {
+        let mut tx = self.pool.begin().await.context("merge labels")?;
+        let label = merge_labels(&mut tx, self.owner, keep, remove)
+            .await
+            .context("merge labels")?;
+        tx.commit().await.context("merge labels")?;
+
+        Ok(label)
+    }
+
+    async fn update_many(&self, payloads: Vec<UpdateLabel>) -> anyhow::Result<Vec<Label>> {
+        timed("label.update_many", self.slow_query, async move {
+            let mut tx = self.pool.begin().await.context("update labels")?;
+            let mut labels = Vec::with_capacity(payloads.len());
+            for payload in payloads.iter() {
+                let label = sqlx::query_as::<_, Label>(
+                    r#"UPDATE labels SET name = $1 WHERE id = $2 AND owner_id = $3 RETURNING *"#,
+                )
+                .bind(&payload.name)
+                .bind(payload.id)
+                .bind(self.owner)
+                .fetch_optional(&mut tx)
+                .await
+                .context("update labels")?
+                .ok_or(RepositoryError::NotFound(payload.id.into()))?;
+                labels.push(label);
+            }
+            let names: Vec<&str> = payloads.iter().map(UpdateLabel::name).collect();
+            let duplicates = sqlx::query_as::<_, (String,)>(
+                r#"
+            SELECT name FROM labels WHERE owner_id = $1 AND name = ANY($2)
+            GROUP BY name HAVING COUNT(*) > 1 ORDER BY name;"#,
+            )
+            .bind(self.owner)
+            .bind(&names)
+            .fetch_all(&mut tx)
             .await
-            .map_err(|e| match e {
-                sqlx::Error::RowNotFound => RepositoryError::NotFound(id),
-                _ => RepositoryError::Unexpected(e.to_string()),
-            })?;
+            .context("update labels")?;
+            if !duplicates.is_empty() {
+                let names = duplicates.into_iter().map(|(name,)| name).collect();
+                return Err(RepositoryError::DuplicateNames(names).into());
+            }
+            tx.commit().await.context("update labels")?;
 
-        Ok(())
+            Ok(labels)
+        })
+        .await
     }
 }
 
+/// Moves the todos of `remove` to `keep` and deletes `remove`, within the transaction the
+/// caller holds on `conn`.
+pub async fn merge_labels(
+    conn: &mut PgConnection,
+    owner: OwnerId,
+    keep: LabelId,
+    remove: LabelId,
+) -> anyhow::Result<Label> {
+    let label =
+        sqlx::query_as::<_, Label>(r#"SELECT * FROM labels WHERE id = $1 AND owner_id = $2"#)
+            .bind(keep)
+            .bind(owner)
+            .fetch_optional(&mut *conn)
+            .await
+            .context("merge labels")?
+            .ok_or(RepositoryError::NotFound(keep.into()))?;
+    sqlx::query(r#"SELECT id FROM labels WHERE id = $1 AND owner_id = $2"#)
+        .bind(remove)
+        .bind(owner)
+        .fetch_optional(&mut *conn)
+        .await
+        .context("merge labels")?
+        .ok_or(RepositoryError::NotFound(remove.into()))?;
+
+    sqlx::query(
+        r#"
+        DELETE FROM todo_labels WHERE label_id = $2
+        AND todo_id IN (SELECT todo_id FROM todo_labels WHERE label_id = $1);"#,
+    )
+    .bind(keep)
+    .bind(remove)
+    .execute(&mut *conn)
+    .await
+    .context("merge labels")?;
+    sqlx::query(r#"UPDATE todo_labels SET label_id = $1 WHERE label_id = $2"#)
+        .bind(keep)
+        .bind(remove)
+        .execute(&mut *conn)
+        .await
+        .context("merge labels")?;
+    sqlx::query(r#"DELETE FROM labels WHERE id = $1"#)
+        .bind(remove)
+        .execute(&mut *conn)
+        .await
+        .context("merge labels")?;
+
+    Ok(label)
+}
+
 #[cfg(test)]
 #[cfg(feature = "database-test")]
 mod test {
     use super::*;
-    use dotenv::dotenv;
-    use sqlx::PgPool;
-    use std::env;
+    use crate::repositories::todo::TodoId;
+    use crate::repositories::{reset_database, test_id};
 
     #[tokio::test]
     async fn label_crud_scenario() {
-        dotenv().ok();
-        let database_url = &env::var("DATABASE_URL").expect("undefined [DATABASE_URL]");
-        let pool = PgPool::connect(database_url)
-            .await
-            .expect(&format!("fail connect database. url is [{}]", database_url));
+        let (pool, _db) = reset_database().await;
         let repo = LabelRepositoryForDb::new(pool.clone());
         let label_text = "test_label";
 
@@ -106,94 +614,881 @@ mod test {
             .await
             .expect("[create] returned Err");
         assert_eq!(label.name, label_text);
+        let res = repo.create(CreateLabel::new(label_text.to_string())).await;
+        assert!(matches!(
+            res.unwrap_err().downcast_ref::<RepositoryError>(),
+            Some(RepositoryError::Duplicate(id)) if *id == label.id.into()
+        ));
+
+        // find
+        let found = repo.find(label.id).await.expect("[find] returned Err");
+        assert_eq!(label, found);
+        let res = repo.scoped(OwnerId(119)).find(label.id).await;
+        assert!(matches!(
+            res.unwrap_err().downcast_ref::<RepositoryError>(),
+            Some(RepositoryError::NotFound(_))
+        ));
+
+        // find_by_name
+        let found = repo
+            .find_by_name(label_text)
+            .await
+            .expect("[find_by_name] returned Err");
+        assert_eq!(Some(label.clone()), found);
 
         // all
         let labels = repo.all().await.expect("[all] returned Err");
         let label = labels.last().unwrap();
         assert_eq!(label.name, label_text);
 
+        // missing
+        let missing = repo
+            .missing(&[LabelId(test_id(-1)), label.id])
+            .await
+            .expect("[missing] returned Err");
+        assert_eq!(vec![LabelId(test_id(-1))], missing);
+
         // delete
-        repo.delete(label.id).await.expect("[delete] returned Err");
+        repo.delete(label.id, false)
+            .await
+            .expect("[delete] returned Err");
+    }
+
+    #[tokio::test]
+    async fn label_create_many_scenario() {
+        let (pool, _db) = reset_database().await;
+        let repo = LabelRepositoryForDb::new(pool.clone()).scoped(OwnerId(104));
+        let existing = repo
+            .create(CreateLabel::new("[create_many] existing".to_string()))
+            .await
+            .expect("[create] returned Err");
+
+        let labels = repo
+            .create_many(
+                ["existing", "new", "existing", "new"]
+                    .iter()
+                    .map(|name| CreateLabel::new(format!("[create_many] {}", name)))
+                    .collect(),
+            )
+            .await
+            .expect("[create_many] returned Err");
+        assert_eq!(2, labels.len());
+        assert_eq!(existing, labels[0]);
+        assert_eq!("[create_many] new", labels[1].name);
+        let mut by_id = labels.clone();
+        by_id.sort_by_key(|label| label.id);
+        assert_eq!(by_id, repo.all().await.expect("[all] returned Err"));
+
+        for label in labels {
+            repo.delete(label.id, true)
+                .await
+                .expect("[delete] returned Err");
+        }
+    }
+
+    #[tokio::test]
+    async fn label_delete_scenario() {
+        let (pool, _db) = reset_database().await;
+        let repo = LabelRepositoryForDb::new(pool.clone());
+        let label = repo
+            .create(CreateLabel::new("[delete_scenario] label".to_string()))
+            .await
+            .expect("[create] returned Err");
+        let (todo_id,) = sqlx::query_as::<_, (TodoId,)>(
+            r#"INSERT INTO todos (text, owner_id) VALUES ('[delete_scenario] text', 0) RETURNING id"#,
+        )
+        .fetch_one(&pool)
+        .await
+        .expect("Failed to insert todo data");
+        sqlx::query(r#"INSERT INTO todo_labels (todo_id, label_id) VALUES ($1, $2)"#)
+            .bind(todo_id)
+            .bind(label.id)
+            .execute(&pool)
+            .await
+            .expect("Failed to insert todo_labels data");
+
+        // guarded delete
+        let err = repo
+            .delete(label.id, false)
+            .await
+            .expect_err("[delete] returned Ok for a label in use");
+        assert!(matches!(
+            err.downcast_ref::<RepositoryError>(),
+            Some(RepositoryError::InUse(_, 1))
+        ));
+        let found = repo
+            .find_by_name("[delete_scenario] label")
+            .await
+            .expect("[find_by_name] returned Err");
+        assert_eq!(Some(label.clone()), found);
+
+        // forced delete
+        repo.delete(label.id, true)
+            .await
+            .expect("[delete] returned Err");
+        let (count,) =
+            sqlx::query_as::<_, (i64,)>(r#"SELECT COUNT(*) FROM todo_labels WHERE todo_id = $1"#)
+                .bind(todo_id)
+                .fetch_one(&pool)
+                .await
+                .expect("[delete] todo_labels fetch error");
+        assert_eq!(0, count);
+
+        // delete missing label
+        let err = repo
+            .delete(label.id, true)
+            .await
+            .expect_err("[delete] returned Ok for a missing label");
+        assert!(matches!(
+            err.downcast_ref::<RepositoryError>(),
+            Some(RepositoryError::NotFound(_))
+        ));
+
+        sqlx::query(r#"DELETE FROM todos WHERE id = $1"#)
+            .bind(todo_id)
+            .execute(&pool)
+            .await
+            .expect("Failed to clean up todo data");
+    }
+
+    #[tokio::test]
+    async fn label_usage_count_scenario() {
+        let (pool, _db) = reset_database().await;
+        let repo = LabelRepositoryForDb::new(pool.clone()).scoped(OwnerId(111));
+        let used = repo
+            .create(CreateLabel::new("[usage_count] used".to_string()))
+            .await
+            .expect("[create] returned Err");
+        let unused = repo
+            .create(CreateLabel::new("[usage_count] unused".to_string()))
+            .await
+            .expect("[create] returned Err");
+        let mut todo_ids = vec![];
+        for _ in 0..2 {
+            let (todo_id,) = sqlx::query_as::<_, (TodoId,)>(
+                r#"INSERT INTO todos (text, owner_id) VALUES ('[usage_count] text', 111) RETURNING id"#,
+            )
+            .fetch_one(&pool)
+            .await
+            .expect("Failed to insert todo data");
+            sqlx::query(r#"INSERT INTO todo_labels (todo_id, label_id) VALUES ($1, $2)"#)
+                .bind(todo_id)
+                .bind(used.id)
+                .execute(&pool)
+                .await
+                .expect("Failed to insert todo_labels data");
+            todo_ids.push(todo_id);
+        }
+        // labeled before the inserts checked the owner of the labels
+        let (foreign_id,) = sqlx::query_as::<_, (TodoId,)>(
+            r#"INSERT INTO todos (text, owner_id) VALUES ('[usage_count] foreign', 112) RETURNING id"#,
+        )
+        .fetch_one(&pool)
+        .await
+        .expect("Failed to insert todo data");
+        sqlx::query(r#"INSERT INTO todo_labels (todo_id, label_id) VALUES ($1, $2)"#)
+            .bind(foreign_id)
+            .bind(used.id)
+            .execute(&pool)
+            .await
+            .expect("Failed to insert todo_labels data");
+        todo_ids.push(foreign_id);
+
+        let labels = repo
+            .all_with_counts()
+            .await
+            .expect("[all_with_counts] returned Err");
+        let mut expected = vec![
+            LabelWithCount {
+                id: used.id,
+                name: used.name,
+                usage_count: 2,
+            },
+            LabelWithCount {
+                id: unused.id,
+                name: unused.name,
+                usage_count: 0,
+            },
+        ];
+        expected.sort_by_key(|label| label.id);
+        assert_eq!(expected, labels);
+
+        repo.delete(used.id, true).await.unwrap();
+        repo.delete(unused.id, true).await.unwrap();
+        sqlx::query(r#"DELETE FROM todos WHERE id = ANY($1)"#)
+            .bind(todo_ids)
+            .execute(&pool)
+            .await
+            .expect("Failed to clean up todo data");
+    }
+
+    #[tokio::test]
+    async fn all_by_name_scenario() {
+        let (pool, _db) = reset_database().await;
+        assert_eq!(
+            None,
+            resolve_collation(&pool, Some("no-such-collation".to_string())).await
+        );
+        let Some(collation) = resolve_collation(&pool, Some("ja-x-icu".to_string())).await else {
+            // the server was built without ICU
+            return;
+        };
+        let repo = LabelRepositoryForDb::new(pool.clone())
+            .with_collation(Some(collation))
+            .scoped(OwnerId(115));
+        for name in ["りんご", "Cherry", "あめ", "banana"] {
+            repo.create(CreateLabel::new(name.to_string()))
+                .await
+                .expect("[create] returned Err");
+        }
+
+        let labels = repo
+            .all_by_name()
+            .await
+            .expect("[all_by_name] returned Err");
+        let names: Vec<&str> = labels.iter().map(|label| label.name.as_str()).collect();
+        assert_eq!(vec!["banana", "Cherry", "あめ", "りんご"], names);
+
+        for label in labels {
+            repo.delete(label.id, true).await.unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn page_scenario() {
+        let (pool, _db) = reset_database().await;
+        let repo = LabelRepositoryForDb::new(pool.clone()).scoped(OwnerId(125));
+        for name in ["banana", "apple", "cherry"] {
+            repo.create(CreateLabel::new(name.to_string()))
+                .await
+                .expect("[create] returned Err");
+        }
+
+        let page = repo
+            .page(LabelSort::Name, 2, 1)
+            .await
+            .expect("[page] returned Err");
+        let names: Vec<&str> = page.items.iter().map(|label| label.name.as_str()).collect();
+        assert_eq!(vec!["banana", "cherry"], names);
+        assert_eq!(3, page.total);
+
+        let all = repo.all().await.expect("[all] returned Err");
+        let page = repo
+            .page(LabelSort::Id, 2, 0)
+            .await
+            .expect("[page] returned Err");
+        assert_eq!(
+            LabelPage {
+                items: all[..2].to_vec(),
+                total: 3
+            },
+            page
+        );
+
+        let counts = repo
+            .all_with_counts()
+            .await
+            .expect("[all_with_counts] returned Err");
+        let page = repo
+            .page_with_counts(20, 2)
+            .await
+            .expect("[page_with_counts] returned Err");
+        assert_eq!(
+            LabelPage {
+                items: counts[2..].to_vec(),
+                total: 3
+            },
+            page
+        );
+
+        for label in all {
+            repo.delete(label.id, true).await.unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn update_many_scenario() {
+        let (pool, _db) = reset_database().await;
+        let repo = LabelRepositoryForDb::new(pool.clone()).scoped(OwnerId(112));
+        let first = repo
+            .create(CreateLabel::new("[update_many] first".to_string()))
+            .await
+            .expect("[create] returned Err");
+        let second = repo
+            .create(CreateLabel::new("[update_many] second".to_string()))
+            .await
+            .expect("[create] returned Err");
+
+        // swapping names is not a conflict
+        let labels = repo
+            .update_many(vec![
+                UpdateLabel::new(first.id, second.name.clone()),
+                UpdateLabel::new(second.id, first.name.clone()),
+            ])
+            .await
+            .expect("[update_many] returned Err");
+        let swapped = vec![
+            Label::new(first.id, second.name.clone()),
+            Label::new(second.id, first.name.clone()),
+        ];
+        assert_eq!(swapped, labels);
+
+        // a conflict rolls back every rename
+        let renamed = "[update_many] renamed".to_string();
+        let res = repo
+            .update_many(vec![
+                UpdateLabel::new(first.id, renamed.clone()),
+                UpdateLabel::new(second.id, renamed.clone()),
+            ])
+            .await;
+        assert!(matches!(
+            res.unwrap_err().downcast_ref::<RepositoryError>(),
+            Some(RepositoryError::DuplicateNames(names)) if *names == vec![renamed.clone()]
+        ));
+        let res = repo
+            .update_many(vec![UpdateLabel::new(
+                LabelId(test_id(-1)),
+                "missing".to_string(),
+            )])
+            .await;
+        assert!(matches!(
+            res.unwrap_err().downcast_ref::<RepositoryError>(),
+            Some(RepositoryError::NotFound(_))
+        ));
+        let mut by_id = swapped.clone();
+        by_id.sort_by_key(|label| label.id);
+        assert_eq!(by_id, repo.all().await.expect("[all] returned Err"));
+
+        repo.delete(first.id, true).await.unwrap();
+        repo.delete(second.id, true).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn unique_name_scenario() {
+        let (pool, _db) = reset_database().await;
+        let repo = LabelRepositoryForDb::new(pool.clone()).scoped(OwnerId(113));
+        let label = repo
+            .create(CreateLabel::new("[unique_name] label".to_string()))
+            .await
+            .expect("[create] returned Err");
+
+        // as if a concurrent create had passed the check first
+        let res = sqlx::query(r#"INSERT INTO labels (name, owner_id) VALUES ($1, $2)"#)
+            .bind(&label.name)
+            .bind(OwnerId(113))
+            .execute(&pool)
+            .await;
+        assert!(matches!(
+            RepositoryError::from(res.unwrap_err()),
+            RepositoryError::Conflict(_)
+        ));
+        repo.scoped(OwnerId(114))
+            .create(CreateLabel::new(label.name.clone()))
+            .await
+            .expect("[create] returned Err");
+    }
+
+    #[tokio::test]
+    async fn label_merge_scenario() {
+        let (pool, _db) = reset_database().await;
+        let repo = LabelRepositoryForDb::new(pool.clone());
+        let keep = repo
+            .create(CreateLabel::new("[merge_scenario] keep".to_string()))
+            .await
+            .expect("[create] returned Err");
+        let remove = repo
+            .create(CreateLabel::new("[merge_scenario] remove".to_string()))
+            .await
+            .expect("[create] returned Err");
+        let (todo_id,) = sqlx::query_as::<_, (TodoId,)>(
+            r#"INSERT INTO todos (text, owner_id) VALUES ('[merge_scenario] text', 0) RETURNING id"#,
+        )
+        .fetch_one(&pool)
+        .await
+        .expect("Failed to insert todo data");
+        sqlx::query(r#"INSERT INTO todo_labels (todo_id, label_id) VALUES ($1, $2), ($1, $3)"#)
+            .bind(todo_id)
+            .bind(keep.id)
+            .bind(remove.id)
+            .execute(&pool)
+            .await
+            .expect("Failed to insert todo_labels data");
+
+        // merge
+        let label = repo
+            .merge(keep.id, remove.id)
+            .await
+            .expect("[merge] returned Err");
+        assert_eq!(keep, label);
+        let label_ids = sqlx::query_as::<_, (LabelId,)>(
+            r#"SELECT label_id FROM todo_labels WHERE todo_id = $1"#,
+        )
+        .bind(todo_id)
+        .fetch_all(&pool)
+        .await
+        .expect("[merge] todo_labels fetch error");
+        assert_eq!(vec![(keep.id,)], label_ids);
+        let removed = repo
+            .find_by_name("[merge_scenario] remove")
+            .await
+            .expect("[find_by_name] returned Err");
+        assert_eq!(None, removed);
+
+        // merge missing label
+        let res = repo.merge(keep.id, remove.id).await;
+        assert!(res.is_err());
+
+        sqlx::query(r#"DELETE FROM todo_labels WHERE todo_id = $1"#)
+            .bind(todo_id)
+            .execute(&pool)
+            .await
+            .expect("Failed to clean up todo_labels data");
+        sqlx::query(r#"DELETE FROM todos WHERE id = $1"#)
+            .bind(todo_id)
+            .execute(&pool)
+            .await
+            .expect("Failed to clean up todo data");
+        repo.delete(keep.id, false)
+            .await
+            .expect("[delete] returned Err");
     }
 }
 
 #[cfg(test)]
+#[cfg(feature = "database-test")]
+#[cfg(feature = "uuid")]
+mod uuid_test {
+    use super::*;
+    use crate::repositories::reset_database;
+
+    /// Needs a database migrated with `migrations_uuid` on top of `migrations`.
+    #[tokio::test]
+    async fn missing_with_uuid() {
+        let (pool, _db) = reset_database().await;
+        let repo = LabelRepositoryForDb::new(pool.clone()).scoped(OwnerId(120));
+        let label = repo
+            .create(CreateLabel::new("[uuid] label".to_string()))
+            .await
+            .expect("[create] returned Err");
+        let unknown = LabelId(uuid::Uuid::new_v4());
+
+        let missing = repo
+            .missing(&[unknown, label.id])
+            .await
+            .expect("[missing] returned Err");
+        assert_eq!(vec![unknown], missing);
+    }
+}
+
+#[cfg(any(test, feature = "testing"))]
 pub mod test_utils {
     use super::*;
-    use std::collections::HashMap;
+    use crate::repositories::next_memory_id;
+    use std::collections::{BTreeSet, HashMap};
+    use std::fmt;
     use std::sync::{Arc, RwLock, RwLockReadGuard, RwLockWriteGuard};
 
-    impl Label {
-        pub fn new(id: i32, name: String) -> Self {
-            Self { id, name }
-        }
+    type LabelDatas = HashMap<LabelId, (OwnerId, Label)>;
+
+    fn page_of<T>(items: Vec<T>, limit: i64, offset: i64) -> LabelPage<T> {
+        let total = items.len() as i64;
+        let items = items
+            .into_iter()
+            .skip(offset.max(0) as usize)
+            .take(limit.max(0) as usize)
+            .collect();
+        LabelPage { items, total }
     }
 
-    impl CreateLabel {
-        pub fn new(name: String) -> Self {
-            Self { name }
+    type MergeListener = Box<dyn Fn(&Label, LabelId) + Send + Sync>;
+
+    /// Callbacks of the stores embedding the labels, told the kept label and the removed id
+    /// after a merge.
+    #[derive(Clone, Default)]
+    struct MergeListeners(Arc<RwLock<Vec<MergeListener>>>);
+
+    impl fmt::Debug for MergeListeners {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.debug_struct("MergeListeners").finish_non_exhaustive()
         }
     }
 
-    type LabelDatas = HashMap<i32, Label>;
-
     #[derive(Debug, Clone)]
     pub struct LabelRepositoryForMemory {
         store: Arc<RwLock<LabelDatas>>,
+        merge_listeners: MergeListeners,
+        owner: OwnerId,
+    }
+
+    impl Default for LabelRepositoryForMemory {
+        fn default() -> Self {
+            Self::new()
+        }
     }
 
     impl LabelRepositoryForMemory {
         pub fn new() -> Self {
             Self {
                 store: Arc::default(),
+                merge_listeners: MergeListeners::default(),
+                owner: OwnerId::default(),
             }
         }
 
-        fn write_store_ref(&self) -> RwLockWriteGuard<LabelDatas> {
+        pub fn with_labels(labels: Vec<Label>) -> Self {
+            let repo = Self::new();
+            let owner = repo.owner;
+            repo.write_store_ref()
+                .extend(labels.into_iter().map(|label| (label.id, (owner, label))));
+            repo
+        }
+
+        pub(crate) fn get(&self, id: LabelId) -> Option<Label> {
+            self.get_owned(&self.read_store_ref(), id).cloned()
+        }
+
+        pub(crate) fn find_or_create(&self, name: &str) -> Label {
+            let mut store = self.write_store_ref();
+            let existing = self
+                .owned(&store)
+                .filter(|label| label.name.to_lowercase() == name.to_lowercase())
+                .min_by_key(|label| label.id);
+            if let Some(label) = existing {
+                return label.clone();
+            }
+
+            let id = LabelId(next_memory_id(store.len()));
+            let label = Label::new(id, name.to_string());
+            store.insert(id, (self.owner, label.clone()));
+            label
+        }
+
+        /// Calls `listener` after every merge, so that a store copying the labels can follow.
+        pub(crate) fn on_merge(&self, listener: impl Fn(&Label, LabelId) + Send + Sync + 'static) {
+            self.merge_listeners
+                .0
+                .write()
+                .unwrap()
+                .push(Box::new(listener));
+        }
+
+        fn owned<'a>(&self, store: &'a LabelDatas) -> impl Iterator<Item = &'a Label> {
+            let owner = self.owner;
+            store
+                .values()
+                .filter(move |(label_owner, _)| *label_owner == owner)
+                .map(|(_, label)| label)
+        }
+
+        fn get_owned<'a>(&self, store: &'a LabelDatas, id: LabelId) -> Option<&'a Label> {
+            store
+                .get(&id)
+                .filter(|(owner, _)| *owner == self.owner)
+                .map(|(_, label)| label)
+        }
+
+        fn write_store_ref(&self) -> RwLockWriteGuard<'_, LabelDatas> {
             self.store.write().unwrap()
         }
 
-        fn read_store_ref(&self) -> RwLockReadGuard<LabelDatas> {
+        fn read_store_ref(&self) -> RwLockReadGuard<'_, LabelDatas> {
             self.store.read().unwrap()
         }
     }
 
+    impl OwnerScoped for LabelRepositoryForMemory {
+        fn scoped(&self, owner: OwnerId) -> Self {
+            Self {
+                store: self.store.clone(),
+                merge_listeners: self.merge_listeners.clone(),
+                owner,
+            }
+        }
+    }
+
     #[async_trait]
     impl LabelRepository for LabelRepositoryForMemory {
         async fn create(&self, payload: CreateLabel) -> anyhow::Result<Label> {
             let mut store = self.write_store_ref();
-            if let Some((_key, label)) = store
-                .iter()
-                .find(|(_key, label)| label.name == payload.name)
-            {
-                return Ok(label.clone());
+            if let Some(label) = self.owned(&store).find(|label| label.name == payload.name) {
+                return Err(RepositoryError::Duplicate(label.id.into()).into());
             };
 
-            let id = (store.len() + 1) as i32;
+            let id = LabelId(next_memory_id(store.len()));
             let label = Label::new(id, payload.name.clone());
-            store.insert(id, label.clone());
+            store.insert(id, (self.owner, label.clone()));
+            Ok(label)
+        }
+
+        async fn create_many(&self, payloads: Vec<CreateLabel>) -> anyhow::Result<Vec<Label>> {
+            let mut store = self.write_store_ref();
+            let mut labels = Vec::with_capacity(payloads.len());
+            for name in unique_names(payloads) {
+                let existing = self
+                    .owned(&store)
+                    .filter(|label| label.name == name)
+                    .min_by_key(|label| label.id)
+                    .cloned();
+                let label = existing.unwrap_or_else(|| {
+                    let id = LabelId(next_memory_id(store.len()));
+                    let label = Label::new(id, name);
+                    store.insert(id, (self.owner, label.clone()));
+                    label
+                });
+                labels.push(label);
+            }
+            Ok(labels)
+        }
+
+        async fn find(&self, id: LabelId) -> anyhow::Result<Label> {
+            self.get(id)
+                .ok_or_else(|| RepositoryError::NotFound(id.into()).into())
+        }
+
+        async fn find_by_name(&self, name: &str) -> anyhow::Result<Option<Label>> {
+            let store = self.read_store_ref();
+            let label = self.owned(&store).find(|label| label.name == name).cloned();
             Ok(label)
         }
 
         async fn all(&self) -> anyhow::Result<Vec<Label>> {
             let store = self.read_store_ref();
-            Ok(Vec::from_iter(store.values().cloned()))
+            Ok(self.owned(&store).cloned().collect())
+        }
+
+        /// Names are compared case-insensitively, like the `NOCASE` collation of SQLite.
+        async fn all_by_name(&self) -> anyhow::Result<Vec<Label>> {
+            let store = self.read_store_ref();
+            let mut labels: Vec<Label> = self.owned(&store).cloned().collect();
+            labels.sort_by(|a, b| {
+                a.name
+                    .to_lowercase()
+                    .cmp(&b.name.to_lowercase())
+                    .then_with(|| a.id.cmp(&b.id))
+            });
+            Ok(labels)
+        }
+
+        /// The memory store does not know which todos use a label, so every count is zero.
+        async fn all_with_counts(&self) -> anyhow::Result<Vec<LabelWithCount>> {
+            let store = self.read_store_ref();
+            Ok(self
+                .owned(&store)
+                .map(|label| LabelWithCount {
+                    id: label.id,
+                    name: label.name.clone(),
+                    usage_count: 0,
+                })
+                .collect())
+        }
+
+        async fn page(
+            &self,
+            sort: LabelSort,
+            limit: i64,
+            offset: i64,
+        ) -> anyhow::Result<LabelPage<Label>> {
+            let labels = match sort {
+                LabelSort::Id => self.all().await?,
+                LabelSort::Name => self.all_by_name().await?,
+            };
+            Ok(page_of(labels, limit, offset))
+        }
+
+        async fn page_with_counts(
+            &self,
+            limit: i64,
+            offset: i64,
+        ) -> anyhow::Result<LabelPage<LabelWithCount>> {
+            Ok(page_of(self.all_with_counts().await?, limit, offset))
+        }
+
+        async fn missing(&self, ids: &[LabelId]) -> anyhow::Result<Vec<LabelId>> {
+            let store = self.read_store_ref();
+            Ok(ids
+                .iter()
+                .filter(|id| self.get_owned(&store, **id).is_none())
+                .copied()
+                .collect())
         }
 
-        async fn delete(&self, id: i32) -> anyhow::Result<()> {
+        /// The memory store does not know which todos use a label, so `force` has no effect.
+        async fn delete(&self, id: LabelId, _force: bool) -> anyhow::Result<()> {
             let mut store = self.write_store_ref();
-            store.remove(&id).ok_or(RepositoryError::NotFound(id))?;
+            if self.get_owned(&store, id).is_none() {
+                return Err(RepositoryError::NotFound(id.into()).into());
+            }
+            store.remove(&id);
             Ok(())
         }
+
+        async fn merge(&self, keep: LabelId, remove: LabelId) -> anyhow::Result<Label> {
+            let mut store = self.write_store_ref();
+            let label = self
+                .get_owned(&store, keep)
+                .cloned()
+                .ok_or(RepositoryError::NotFound(keep.into()))?;
+            if self.get_owned(&store, remove).is_none() {
+                return Err(RepositoryError::NotFound(remove.into()).into());
+            }
+            store.remove(&remove);
+            drop(store);
+            for listener in self.merge_listeners.0.read().unwrap().iter() {
+                listener(&label, remove);
+            }
+            Ok(label)
+        }
+
+        async fn update_many(&self, payloads: Vec<UpdateLabel>) -> anyhow::Result<Vec<Label>> {
+            let mut store = self.write_store_ref();
+            let mut renamed = store.clone();
+            let mut labels = Vec::with_capacity(payloads.len());
+            for payload in payloads.iter() {
+                self.get_owned(&renamed, payload.id)
+                    .ok_or(RepositoryError::NotFound(payload.id.into()))?;
+                let label = Label::new(payload.id, payload.name.clone());
+                renamed.insert(payload.id, (self.owner, label.clone()));
+                labels.push(label);
+            }
+            let duplicates: BTreeSet<String> = payloads
+                .iter()
+                .filter(|payload| {
+                    self.owned(&renamed)
+                        .filter(|label| label.name == payload.name)
+                        .count()
+                        > 1
+                })
+                .map(|payload| payload.name.clone())
+                .collect();
+            if !duplicates.is_empty() {
+                return Err(
+                    RepositoryError::DuplicateNames(duplicates.into_iter().collect()).into(),
+                );
+            }
+            *store = renamed;
+            Ok(labels)
+        }
+    }
+
+    #[derive(Debug, Clone)]
+    pub struct FailingLabelRepository {
+        error: RepositoryError,
+    }
+
+    impl FailingLabelRepository {
+        pub fn new(error: RepositoryError) -> Self {
+            Self { error }
+        }
+
+        fn error(&self) -> anyhow::Error {
+            self.error.clone().into()
+        }
+    }
+
+    impl OwnerScoped for FailingLabelRepository {
+        fn scoped(&self, _owner: OwnerId) -> Self {
+            self.clone()
+        }
     }
 
+    #[async_trait]
+    impl LabelRepository for FailingLabelRepository {
+        async fn create(&self, _payload: CreateLabel) -> anyhow::Result<Label> {
+            Err(self.error())
+        }
+
+        async fn create_many(&self, _payloads: Vec<CreateLabel>) -> anyhow::Result<Vec<Label>> {
+            Err(self.error())
+        }
+
+        async fn find(&self, _id: LabelId) -> anyhow::Result<Label> {
+            Err(self.error())
+        }
+
+        async fn find_by_name(&self, _name: &str) -> anyhow::Result<Option<Label>> {
+            Err(self.error())
+        }
+
+        async fn all(&self) -> anyhow::Result<Vec<Label>> {
+            Err(self.error())
+        }
+
+        async fn all_by_name(&self) -> anyhow::Result<Vec<Label>> {
+            Err(self.error())
+        }
+
+        async fn all_with_counts(&self) -> anyhow::Result<Vec<LabelWithCount>> {
+            Err(self.error())
+        }
+
+        async fn page(
+            &self,
+            _sort: LabelSort,
+            _limit: i64,
+            _offset: i64,
+        ) -> anyhow::Result<LabelPage<Label>> {
+            Err(self.error())
+        }
+
+        async fn page_with_counts(
+            &self,
+            _limit: i64,
+            _offset: i64,
+        ) -> anyhow::Result<LabelPage<LabelWithCount>> {
+            Err(self.error())
+        }
+
+        async fn missing(&self, _ids: &[LabelId]) -> anyhow::Result<Vec<LabelId>> {
+            Err(self.error())
+        }
+
+        async fn delete(&self, _id: LabelId, _force: bool) -> anyhow::Result<()> {
+            Err(self.error())
+        }
+
+        async fn update_many(&self, _payloads: Vec<UpdateLabel>) -> anyhow::Result<Vec<Label>> {
+            Err(self.error())
+        }
+
+        async fn merge(&self, _keep: LabelId, _remove: LabelId) -> anyhow::Result<Label> {
+            Err(self.error())
+        }
+    }
+
+    #[cfg(test)]
+    #[cfg(not(feature = "uuid"))]
     mod test {
         use super::*;
 
+        #[tokio::test]
+        async fn label_create_many() {
+            let repo = LabelRepositoryForMemory::with_labels(vec![Label::new(
+                LabelId(1),
+                "existing".to_string(),
+            )]);
+            let labels = repo
+                .create_many(
+                    ["new", "existing", "new"]
+                        .iter()
+                        .map(|name| CreateLabel::new(name.to_string()))
+                        .collect(),
+                )
+                .await
+                .unwrap();
+            assert_eq!(
+                vec![
+                    Label::new(LabelId(2), "new".to_string()),
+                    Label::new(LabelId(1), "existing".to_string()),
+                ],
+                labels
+            );
+            assert_eq!(2, repo.all().await.unwrap().len());
+        }
+
         #[tokio::test]
         async fn label_crud_scenario() {
             let text = "label text".to_string();
-            let id = 1;
+            let id = LabelId(1);
             let expected = Label::new(id, text.clone());
 
             // create
@@ -203,14 +1498,622 @@ pub mod test_utils {
                 .await
                 .expect("failed label create");
             assert_eq!(expected, label);
+            let res = repo.create(CreateLabel::new(text.clone())).await;
+            assert!(matches!(
+                res.unwrap_err().downcast_ref::<RepositoryError>(),
+                Some(RepositoryError::Duplicate(duplicate)) if *duplicate == id.into()
+            ));
+
+            // find
+            let label = repo.find(id).await.unwrap();
+            assert_eq!(expected, label);
+            let res = repo.find(LabelId(2)).await;
+            assert!(matches!(
+                res.unwrap_err().downcast_ref::<RepositoryError>(),
+                Some(RepositoryError::NotFound(_))
+            ));
+
+            // find_by_name
+            let label = repo.find_by_name(&text).await.unwrap();
+            assert_eq!(Some(expected.clone()), label);
+            let label = repo.find_by_name("missing").await.unwrap();
+            assert_eq!(None, label);
 
             // all
             let label = repo.all().await.unwrap();
             assert_eq!(vec![expected], label);
 
+            // missing
+            let missing = repo.missing(&[LabelId(2), id]).await.unwrap();
+            assert_eq!(vec![LabelId(2)], missing);
+
             // delete
-            let res = repo.delete(id).await;
+            let res = repo.delete(id, false).await;
             assert!(res.is_ok());
         }
     }
 }
+
+#[cfg(feature = "sqlite")]
+pub mod sqlite {
+    use super::*;
+    use sqlx::SqlitePool;
+
+    #[derive(Debug, Clone)]
+    pub struct LabelRepositoryForSqlite {
+        pool: SqlitePool,
+        owner: OwnerId,
+    }
+
+    impl LabelRepositoryForSqlite {
+        pub fn new(pool: SqlitePool) -> Self {
+            Self {
+                pool,
+                owner: OwnerId::default(),
+            }
+        }
+
+        async fn count(&self) -> anyhow::Result<i64> {
+            let (total,) =
+                sqlx::query_as::<_, (i64,)>(r#"SELECT count(*) FROM labels WHERE owner_id = ?1"#)
+                    .bind(self.owner)
+                    .fetch_one(&self.pool)
+                    .await
+                    .context("count labels")?;
+            Ok(total)
+        }
+    }
+
+    impl OwnerScoped for LabelRepositoryForSqlite {
+        fn scoped(&self, owner: OwnerId) -> Self {
+            Self {
+                pool: self.pool.clone(),
+                owner,
+            }
+        }
+    }
+
+    #[async_trait]
+    impl LabelRepository for LabelRepositoryForSqlite {
+        async fn create(&self, payload: CreateLabel) -> anyhow::Result<Label> {
+            if let Some(label) = self
+                .find_by_name(&payload.name)
+                .await
+                .context("create label")?
+            {
+                return Err(RepositoryError::Duplicate(label.id.into()).into());
+            }
+
+            let label = sqlx::query_as::<_, Label>(
+                r#"INSERT INTO labels (name, owner_id) VALUES (?1, ?2) RETURNING *"#,
+            )
+            .bind(payload.name.clone())
+            .bind(self.owner)
+            .fetch_one(&self.pool)
+            .await
+            .context("create label")?;
+            Ok(label)
+        }
+
+        async fn create_many(&self, payloads: Vec<CreateLabel>) -> anyhow::Result<Vec<Label>> {
+            let mut tx = self.pool.begin().await.context("create labels")?;
+            let mut labels: Vec<Label> = Vec::with_capacity(payloads.len());
+            for name in unique_names(payloads) {
+                let existing = sqlx::query_as::<_, Label>(
+                    r#"SELECT * FROM labels WHERE name = ?1 AND owner_id = ?2 ORDER BY id LIMIT 1"#,
+                )
+                .bind(&name)
+                .bind(self.owner)
+                .fetch_optional(&mut tx)
+                .await
+                .context("create labels")?;
+                let label = match existing {
+                    Some(label) => label,
+                    None => sqlx::query_as::<_, Label>(
+                        r#"INSERT INTO labels (name, owner_id) VALUES (?1, ?2) RETURNING *"#,
+                    )
+                    .bind(name)
+                    .bind(self.owner)
+                    .fetch_one(&mut tx)
+                    .await
+                    .context("create labels")?,
+                };
+                labels.push(label);
+            }
+            tx.commit().await.context("create labels")?;
+            Ok(labels)
+        }
+
+        async fn find(&self, id: LabelId) -> anyhow::Result<Label> {
+            let label = sqlx::query_as::<_, Label>(
+                r#"SELECT * FROM labels WHERE id = ?1 AND owner_id = ?2"#,
+            )
+            .bind(id)
+            .bind(self.owner)
+            .fetch_optional(&self.pool)
+            .await
+            .context("find label")?
+            .ok_or(RepositoryError::NotFound(id.into()))?;
+            Ok(label)
+        }
+
+        async fn find_by_name(&self, name: &str) -> anyhow::Result<Option<Label>> {
+            let label = sqlx::query_as::<_, Label>(
+                r#"SELECT * FROM labels WHERE name = ?1 AND owner_id = ?2"#,
+            )
+            .bind(name)
+            .bind(self.owner)
+            .fetch_optional(&self.pool)
+            .await
+            .context("find label by name")?;
+            Ok(label)
+        }
+
+        async fn all(&self) -> anyhow::Result<Vec<Label>> {
+            let labels = sqlx::query_as::<_, Label>(
+                r#"SELECT * FROM labels WHERE owner_id = ?1 ORDER BY labels.id ASC"#,
+            )
+            .bind(self.owner)
+            .fetch_all(&self.pool)
+            .await
+            .context("list labels")?;
+            Ok(labels)
+        }
+
+        /// SQLite has no locale-aware collation, names are compared with `NOCASE`.
+        async fn all_by_name(&self) -> anyhow::Result<Vec<Label>> {
+            let labels = sqlx::query_as::<_, Label>(
+                r#"SELECT * FROM labels WHERE owner_id = ?1 ORDER BY labels.name COLLATE NOCASE ASC, labels.id ASC"#,
+            )
+            .bind(self.owner)
+            .fetch_all(&self.pool)
+            .await
+            .context("list labels by name")?;
+            Ok(labels)
+        }
+
+        async fn all_with_counts(&self) -> anyhow::Result<Vec<LabelWithCount>> {
+            let labels = sqlx::query_as::<_, LabelWithCount>(
+                r#"
+            SELECT labels.id, labels.name, COUNT(DISTINCT todos.id) AS usage_count FROM labels
+            LEFT OUTER JOIN todo_labels t1 on labels.id = t1.label_id
+            LEFT OUTER JOIN todos on todos.id = t1.todo_id AND todos.owner_id = labels.owner_id
+            WHERE labels.owner_id = ?1
+            GROUP BY labels.id
+            ORDER BY labels.id ASC;"#,
+            )
+            .bind(self.owner)
+            .fetch_all(&self.pool)
+            .await
+            .context("count label usage")?;
+            Ok(labels)
+        }
+
+        async fn page(
+            &self,
+            sort: LabelSort,
+            limit: i64,
+            offset: i64,
+        ) -> anyhow::Result<LabelPage<Label>> {
+            let order = match sort {
+                LabelSort::Id => "",
+                LabelSort::Name => "labels.name COLLATE NOCASE ASC, ",
+            };
+            let items = sqlx::query_as::<_, Label>(&format!(
+                r#"SELECT * FROM labels WHERE owner_id = ?1 ORDER BY {}labels.id ASC LIMIT ?2 OFFSET ?3"#,
+                order
+            ))
+            .bind(self.owner)
+            .bind(limit)
+            .bind(offset)
+            .fetch_all(&self.pool)
+            .await
+            .context("page labels")?;
+            Ok(LabelPage {
+                items,
+                total: self.count().await?,
+            })
+        }
+
+        async fn page_with_counts(
+            &self,
+            limit: i64,
+            offset: i64,
+        ) -> anyhow::Result<LabelPage<LabelWithCount>> {
+            let items = sqlx::query_as::<_, LabelWithCount>(
+                r#"
+            SELECT labels.id, labels.name, COUNT(DISTINCT todos.id) AS usage_count FROM labels
+            LEFT OUTER JOIN todo_labels t1 on labels.id = t1.label_id
+            LEFT OUTER JOIN todos on todos.id = t1.todo_id AND todos.owner_id = labels.owner_id
+            WHERE labels.owner_id = ?1
+            GROUP BY labels.id
+            ORDER BY labels.id ASC
+            LIMIT ?2 OFFSET ?3;"#,
+            )
+            .bind(self.owner)
+            .bind(limit)
+            .bind(offset)
+            .fetch_all(&self.pool)
+            .await
+            .context("page label usage")?;
+            Ok(LabelPage {
+                items,
+                total: self.count().await?,
+            })
+        }
+
+        async fn missing(&self, ids: &[LabelId]) -> anyhow::Result<Vec<LabelId>> {
+            let ids = serde_json::to_string(ids)?;
+            let missing = sqlx::query_as::<_, (LabelId,)>(
+                r#"
+            SELECT t.value FROM json_each(?1) AS t
+            WHERE NOT EXISTS (
+                SELECT 1 FROM labels WHERE labels.id = t.value AND labels.owner_id = ?2
+            )
+            ORDER BY t.key;"#,
+            )
+            .bind(ids)
+            .bind(self.owner)
+            .fetch_all(&self.pool)
+            .await
+            .context("find missing labels")?;
+            Ok(missing.into_iter().map(|(id,)| id).collect())
+        }
+
+        async fn delete(&self, id: LabelId, force: bool) -> anyhow::Result<()> {
+            let mut tx = self.pool.begin().await.context("delete label")?;
+            sqlx::query(r#"SELECT id FROM labels WHERE id = ?1 AND owner_id = ?2"#)
+                .bind(id)
+                .bind(self.owner)
+                .fetch_optional(&mut tx)
+                .await
+                .context("delete label")?
+                .ok_or(RepositoryError::NotFound(id.into()))?;
+            if !force {
+                let (count,) = sqlx::query_as::<_, (i64,)>(
+                    r#"SELECT COUNT(DISTINCT todo_id) FROM todo_labels WHERE label_id = ?1"#,
+                )
+                .bind(id)
+                .fetch_one(&mut tx)
+                .await
+                .context("delete label")?;
+                if count > 0 {
+                    return Err(RepositoryError::InUse(id.into(), count).into());
+                }
+            }
+
+            sqlx::query(r#"DELETE FROM todo_labels WHERE label_id = ?1"#)
+                .bind(id)
+                .execute(&mut tx)
+                .await
+                .context("delete label")?;
+            sqlx::query(r#"DELETE FROM labels WHERE id = ?1"#)
+                .bind(id)
+                .execute(&mut tx)
+                .await
+                .context("delete label")?;
+            tx.commit().await.context("delete label")?;
+
+            Ok(())
+        }
+
+        async fn merge(&self, keep: LabelId, remove: LabelId) -> anyhow::Result<Label> {
+            let mut tx = self.pool.begin().await.context("merge labels")?;
+            let label = sqlx::query_as::<_, Label>(
+                r#"SELECT * FROM labels WHERE id = ?1 AND owner_id = ?2"#,
+            )
+            .bind(keep)
+            .bind(self.owner)
+            .fetch_optional(&mut tx)
+            .await
+            .context("merge labels")?
+            .ok_or(RepositoryError::NotFound(keep.into()))?;
+            sqlx::query(r#"SELECT id FROM labels WHERE id = ?1 AND owner_id = ?2"#)
+                .bind(remove)
+                .bind(self.owner)
+                .fetch_optional(&mut tx)
+                .await
+                .context("merge labels")?
+                .ok_or(RepositoryError::NotFound(remove.into()))?;
+
+            sqlx::query(
+                r#"
+            DELETE FROM todo_labels WHERE label_id = ?2
+            AND todo_id IN (SELECT todo_id FROM todo_labels WHERE label_id = ?1);"#,
+            )
+            .bind(keep)
+            .bind(remove)
+            .execute(&mut tx)
+            .await
+            .context("merge labels")?;
+            sqlx::query(r#"UPDATE todo_labels SET label_id = ?1 WHERE label_id = ?2"#)
+                .bind(keep)
+                .bind(remove)
+                .execute(&mut tx)
+                .await
+                .context("merge labels")?;
+            sqlx::query(r#"DELETE FROM labels WHERE id = ?1"#)
+                .bind(remove)
+                .execute(&mut tx)
+                .await
+                .context("merge labels")?;
+            tx.commit().await.context("merge labels")?;
+
+            Ok(label)
+        }
+
+        async fn update_many(&self, payloads: Vec<UpdateLabel>) -> anyhow::Result<Vec<Label>> {
+            let mut tx = self.pool.begin().await.context("update labels")?;
+            // SQLite checks the unique names row by row, so the labels first give theirs up
+            // for one no label can have, as names never start with a space
+            let ids: Vec<LabelId> = payloads.iter().map(|payload| payload.id).collect();
+            sqlx::query(
+                r#"UPDATE labels SET name = ' ' || id WHERE owner_id = ?1 AND id IN (SELECT value FROM json_each(?2))"#,
+            )
+            .bind(self.owner)
+            .bind(serde_json::to_string(&ids)?)
+            .execute(&mut tx)
+            .await
+            .context("update labels")?;
+            let mut labels = Vec::with_capacity(payloads.len());
+            for payload in payloads.iter() {
+                let label = sqlx::query_as::<_, Label>(
+                    r#"UPDATE labels SET name = ?1 WHERE id = ?2 AND owner_id = ?3 RETURNING *"#,
+                )
+                .bind(&payload.name)
+                .bind(payload.id)
+                .bind(self.owner)
+                .fetch_optional(&mut tx)
+                .await
+                .map_err(|e| match RepositoryError::from(&e) {
+                    RepositoryError::Conflict(_) => {
+                        RepositoryError::DuplicateNames(vec![payload.name.clone()]).into()
+                    }
+                    _ => anyhow::Error::from(e).context("update labels"),
+                })?
+                .ok_or(RepositoryError::NotFound(payload.id.into()))?;
+                labels.push(label);
+            }
+            tx.commit().await.context("update labels")?;
+
+            Ok(labels)
+        }
+    }
+
+    #[cfg(test)]
+    mod test {
+        use super::*;
+        use crate::repositories::migrate_sqlite;
+        use sqlx::sqlite::SqlitePoolOptions;
+
+        #[tokio::test]
+        async fn label_crud_scenario() {
+            let pool = SqlitePoolOptions::new()
+                .max_connections(1)
+                .connect("sqlite::memory:")
+                .await
+                .expect("fail connect sqlite");
+            migrate_sqlite(&pool).await.expect("fail migrate sqlite");
+            let repo = LabelRepositoryForSqlite::new(pool);
+            let label_text = "test_label";
+
+            // create
+            let label = repo
+                .create(CreateLabel::new(label_text.to_string()))
+                .await
+                .expect("[create] returned Err");
+            assert_eq!(label.name, label_text);
+            let res = repo.create(CreateLabel::new(label_text.to_string())).await;
+            assert!(matches!(
+                res.unwrap_err().downcast_ref::<RepositoryError>(),
+                Some(RepositoryError::Duplicate(id)) if *id == label.id.into()
+            ));
+
+            // find
+            let found = repo.find(label.id).await.expect("[find] returned Err");
+            assert_eq!(label, found);
+            let res = repo.find(LabelId(1000)).await;
+            assert!(matches!(
+                res.unwrap_err().downcast_ref::<RepositoryError>(),
+                Some(RepositoryError::NotFound(_))
+            ));
+
+            // find_by_name
+            let found = repo
+                .find_by_name(label_text)
+                .await
+                .expect("[find_by_name] returned Err");
+            assert_eq!(Some(label.clone()), found);
+
+            // all
+            let labels = repo.all().await.expect("[all] returned Err");
+            assert_eq!(vec![label.clone()], labels);
+
+            // all_by_name
+            let other = repo
+                .create(CreateLabel::new("Other".to_string()))
+                .await
+                .expect("[create] returned Err");
+            let labels = repo
+                .all_by_name()
+                .await
+                .expect("[all_by_name] returned Err");
+            assert_eq!(vec![other.clone(), label.clone()], labels);
+            repo.delete(other.id, true)
+                .await
+                .expect("[delete] returned Err");
+
+            // missing
+            let missing = repo
+                .missing(&[LabelId(-1), label.id])
+                .await
+                .expect("[missing] returned Err");
+            assert_eq!(vec![LabelId(-1)], missing);
+
+            // all_with_counts
+            let labels = repo
+                .all_with_counts()
+                .await
+                .expect("[all_with_counts] returned Err");
+            assert_eq!(
+                vec![LabelWithCount {
+                    id: label.id,
+                    name: label.name.clone(),
+                    usage_count: 0,
+                }],
+                labels
+            );
+
+            // merge
+            let other = repo
+                .create(CreateLabel::new("other".to_string()))
+                .await
+                .expect("[create] returned Err");
+            let merged = repo
+                .merge(label.id, other.id)
+                .await
+                .expect("[merge] returned Err");
+            assert_eq!(label, merged);
+
+            // update_many
+            let other = repo
+                .create(CreateLabel::new("other".to_string()))
+                .await
+                .expect("[create] returned Err");
+            let res = repo
+                .update_many(vec![UpdateLabel::new(other.id, label_text.to_string())])
+                .await;
+            assert!(res.is_err());
+            let renamed = repo
+                .update_many(vec![
+                    UpdateLabel::new(label.id, "renamed".to_string()),
+                    UpdateLabel::new(other.id, label_text.to_string()),
+                ])
+                .await
+                .expect("[update_many] returned Err");
+            assert_eq!(
+                vec![
+                    Label::new(label.id, "renamed".to_string()),
+                    Label::new(other.id, label_text.to_string()),
+                ],
+                renamed
+            );
+            repo.delete(other.id, true)
+                .await
+                .expect("[delete] returned Err");
+
+            // delete
+            repo.delete(label.id, false)
+                .await
+                .expect("[delete] returned Err");
+            let labels = repo.all().await.expect("[all] returned Err");
+            assert!(labels.is_empty());
+        }
+
+        #[tokio::test]
+        async fn update_many_scenario() {
+            let pool = SqlitePoolOptions::new()
+                .max_connections(1)
+                .connect("sqlite::memory:")
+                .await
+                .expect("fail connect sqlite");
+            migrate_sqlite(&pool).await.expect("fail migrate sqlite");
+            let repo = LabelRepositoryForSqlite::new(pool.clone());
+            let first = repo
+                .create(CreateLabel::new("first".to_string()))
+                .await
+                .expect("[create] returned Err");
+            let second = repo
+                .create(CreateLabel::new("second".to_string()))
+                .await
+                .expect("[create] returned Err");
+
+            // swapping names is not a conflict
+            let labels = repo
+                .update_many(vec![
+                    UpdateLabel::new(first.id, second.name.clone()),
+                    UpdateLabel::new(second.id, first.name.clone()),
+                ])
+                .await
+                .expect("[update_many] returned Err");
+            let swapped = vec![
+                Label::new(first.id, second.name.clone()),
+                Label::new(second.id, first.name.clone()),
+            ];
+            assert_eq!(swapped, labels);
+
+            // a conflict rolls back every rename
+            let res = repo
+                .update_many(vec![
+                    UpdateLabel::new(first.id, "renamed".to_string()),
+                    UpdateLabel::new(second.id, "renamed".to_string()),
+                ])
+                .await;
+            assert!(matches!(
+                res.unwrap_err().downcast_ref::<RepositoryError>(),
+                Some(RepositoryError::DuplicateNames(names)) if *names == vec!["renamed".to_string()]
+            ));
+            assert_eq!(swapped, repo.all().await.expect("[all] returned Err"));
+
+            // the names are unique even when the checks are raced
+            let res = sqlx::query(r#"INSERT INTO labels (name) VALUES ('first')"#)
+                .execute(&pool)
+                .await;
+            assert!(matches!(
+                RepositoryError::from(res.unwrap_err()),
+                RepositoryError::Conflict(_)
+            ));
+            sqlx::query(r#"INSERT INTO labels (name, owner_id) VALUES ('first', 1)"#)
+                .execute(&pool)
+                .await
+                .expect("Failed to insert label of another owner");
+        }
+
+        #[tokio::test]
+        async fn page_scenario() {
+            let pool = SqlitePoolOptions::new()
+                .max_connections(1)
+                .connect("sqlite::memory:")
+                .await
+                .expect("fail connect sqlite");
+            migrate_sqlite(&pool).await.expect("fail migrate sqlite");
+            let repo = LabelRepositoryForSqlite::new(pool);
+            for name in ["banana", "Apple", "cherry"] {
+                repo.create(CreateLabel::new(name.to_string()))
+                    .await
+                    .expect("[create] returned Err");
+            }
+
+            let page = repo
+                .page(LabelSort::Name, 2, 1)
+                .await
+                .expect("[page] returned Err");
+            let names: Vec<&str> = page.items.iter().map(|label| label.name.as_str()).collect();
+            assert_eq!(vec!["banana", "cherry"], names);
+            assert_eq!(3, page.total);
+
+            let page = repo
+                .page(LabelSort::Id, 2, 0)
+                .await
+                .expect("[page] returned Err");
+            let names: Vec<&str> = page.items.iter().map(|label| label.name.as_str()).collect();
+            assert_eq!(vec!["banana", "Apple"], names);
+
+            let page = repo
+                .page_with_counts(20, 2)
+                .await
+                .expect("[page_with_counts] returned Err");
+            assert_eq!(
+                LabelPage {
+                    items: vec![LabelWithCount {
+                        id: LabelId(3),
+                        name: "cherry".to_string(),
+                        usage_count: 0,
+                    }],
+                    total: 3,
+                },
+                page
+            );
+        }
+    }
+}
@@ -1,522 +1,6265 @@
-use super::RepositoryError;
-use crate::repositories::label::Label;
+use super::{
+    deserialize_comma_separated, deserialize_trimmed, deserialize_trimmed_option, id_type, timed,
+    until_deadline, OwnerId, OwnerScoped, Patch, RawId, RepositoryError, DEFAULT_SLOW_QUERY_MS,
+};
+use crate::repositories::label::{Label, LabelId};
+use anyhow::Context;
+use async_stream::try_stream;
 use axum::async_trait;
-use serde::{Deserialize, Serialize};
-use sqlx::{FromRow, PgPool};
-use validator::Validate;
+use chrono::{DateTime, Utc};
+use futures_util::{pin_mut, Stream, StreamExt, TryStreamExt};
+use serde::{Deserialize, Deserializer, Serialize};
+use sqlx::postgres::PgArguments;
+use sqlx::query::QueryAs;
+use sqlx::{FromRow, PgConnection, PgPool, Postgres, QueryBuilder};
+use std::collections::HashMap;
+use std::time::Duration;
+use validator::{Validate, ValidationError};
 
 #[async_trait]
-pub trait TodoRepository: Clone + Send + Sync + 'static {
+pub trait TodoRepository: OwnerScoped {
     async fn create(&self, payload: CreateTodo) -> anyhow::Result<TodoEntity>;
-    async fn find(&self, id: i32) -> anyhow::Result<TodoEntity>;
-    async fn all(&self) -> anyhow::Result<Vec<TodoEntity>>;
-    async fn update(&self, id: i32, payload: UpdateTodo) -> anyhow::Result<TodoEntity>;
-    async fn delete(&self, id: i32) -> anyhow::Result<()>;
+    async fn find(&self, id: TodoId) -> anyhow::Result<TodoEntity>;
+    async fn exists(&self, id: TodoId) -> anyhow::Result<bool>;
+    /// Todos of `ids` in that order, each once, leaving out the ids of no todo of the owner.
+    async fn find_many(&self, ids: &[TodoId]) -> anyhow::Result<Vec<TodoEntity>>;
+    async fn all(&self, query: TodoQuery) -> anyhow::Result<Vec<TodoEntity>>;
+    async fn update(&self, id: TodoId, payload: UpdateTodo) -> anyhow::Result<TodoEntity>;
+    /// Sets every field of the todo from `payload`, through `update` so that the changes are
+    /// recorded the same way.
+    async fn replace(&self, id: TodoId, payload: ReplaceTodo) -> anyhow::Result<TodoEntity> {
+        self.update(id, payload.into()).await
+    }
+    async fn delete(&self, id: TodoId) -> anyhow::Result<()>;
+    /// Bumps `updated_at` and nothing else, so that incremental sync sends the todo again.
+    async fn touch(&self, id: TodoId) -> anyhow::Result<TodoEntity>;
+    /// Copies the text, due date, priority and labels of the todo into a new one, neither
+    /// completed nor archived and with fresh timestamps.
+    async fn duplicate(&self, id: TodoId) -> anyhow::Result<TodoEntity>;
+    /// Changes recorded by `update`, oldest first; empty for a todo never updated.
+    async fn history(&self, id: TodoId) -> anyhow::Result<Vec<TodoChange>>;
+    /// Numbers the todos of `ids` in that order and clears the position of the other todos,
+    /// which then come after them; fails without changes when an id is unknown.
+    async fn reorder(&self, ids: Vec<TodoId>) -> anyhow::Result<()>;
+    /// Todos updated after `since` and tombstones of those deleted after it, by `updated_at`.
+    async fn changed_since(&self, since: DateTime<Utc>) -> anyhow::Result<Vec<SyncedTodo>>;
+    async fn search(&self, criteria: TodoSearchCriteria) -> anyhow::Result<TodoSearchResult>;
+    /// Todos whose text is similar to `q`, most similar first.
+    async fn search_ranked(
+        &self,
+        q: &str,
+        include_archived: bool,
+    ) -> anyhow::Result<Vec<TodoEntity>>;
+    async fn set_completed_all(&self, filter: TodoFilter, completed: bool) -> anyhow::Result<u64>;
+    /// Attaches the label to the todos of `ids` in one transaction, counting only those not
+    /// tagged already; ids of no todo of the owner are reported back rather than failing.
+    async fn attach_label(&self, label: LabelId, ids: Vec<TodoId>)
+        -> anyhow::Result<AttachedLabel>;
+    /// Counts of the todos that are not archived, restricted to a label by `filter`.
+    async fn summary(&self, filter: TodoFilter) -> anyhow::Result<TodoSummary>;
+    /// Last change to the todos or labels of the owner, the Unix epoch when they have none.
+    async fn last_modified(&self) -> anyhow::Result<DateTime<Utc>>;
 }
 
+id_type!(TodoId, RawId);
+
 #[derive(Debug, Clone, Eq, PartialEq, FromRow)]
 pub struct TodoWithLabelFromRow {
-    id: i32,
+    id: TodoId,
     text: String,
     completed: bool,
-    label_id: Option<i32>,
+    completed_at: Option<DateTime<Utc>>,
+    archived: bool,
+    due_date: Option<DateTime<Utc>>,
+    priority: Option<i16>,
+    position: Option<i32>,
+    created_at: DateTime<Utc>,
+    updated_at: DateTime<Utc>,
+    label_id: Option<LabelId>,
     label_name: Option<String>,
 }
 
+/// Columns added after the first migration are `Option` unless they are `NOT NULL` with a
+/// default, so that rows written before them still decode.
 #[derive(Debug, Clone, PartialEq, Eq, FromRow)]
 struct TodoFromRow {
-    id: i32,
+    id: TodoId,
     text: String,
     completed: bool,
+    completed_at: Option<DateTime<Utc>>,
+    archived: bool,
+    due_date: Option<DateTime<Utc>>,
+    priority: Option<i16>,
+    position: Option<i32>,
+    created_at: DateTime<Utc>,
+    updated_at: DateTime<Utc>,
+}
+
+impl TodoFromRow {
+    fn into_entity(self, mut labels: Vec<Label>) -> TodoEntity {
+        sort_labels(&mut labels);
+        TodoEntity {
+            id: self.id,
+            text: self.text,
+            completed: self.completed,
+            completed_at: self.completed_at,
+            archived: self.archived,
+            due_date: self.due_date,
+            priority: self.priority,
+            position: self.position,
+            created_at: self.created_at,
+            updated_at: self.updated_at,
+            labels,
+            labels_truncated: false,
+        }
+    }
+}
+
+/// Todo with its labels aggregated by `json_agg` into one row.
+#[derive(Debug, FromRow)]
+struct TodoWithLabelsFromRow {
+    #[sqlx(flatten)]
+    todo: TodoFromRow,
+    labels: sqlx::types::Json<Vec<Label>>,
+}
+
+impl TodoWithLabelsFromRow {
+    fn into_entity(self) -> TodoEntity {
+        self.todo.into_entity(self.labels.0)
+    }
 }
 
+/// Aggregate of the rows of a `labels` relation into a JSON array, empty without rows.
+const LABELS_JSON_AGG: &str = r#"COALESCE(
+            json_agg(json_build_object('id', labels.id, 'name', labels.name) ORDER BY labels.id),
+            '[]'
+        )"#;
+
+/// Labels the todo `$1` with the labels of `$2` owned by `$3`, skipping the other ids.
+const INSERT_TODO_LABELS: &str = r#"
+        WITH inserted AS (
+            INSERT INTO todo_labels (todo_id, label_id)
+            SELECT $1, labels.id FROM unnest($2) WITH ORDINALITY as t(id, n)
+            JOIN labels on labels.id = t.id AND labels.owner_id = $3
+            ORDER BY t.n
+            RETURNING id, label_id
+        )
+        SELECT labels.* FROM inserted
+        JOIN labels on labels.id = inserted.label_id
+        ORDER BY inserted.id;"#;
+
+/// Copies the labels of the todo `$2` to the todo `$1` and returns them.
+const COPY_TODO_LABELS: &str = r#"
+        WITH inserted AS (
+            INSERT INTO todo_labels (todo_id, label_id) SELECT $1, label_id FROM todo_labels
+            WHERE todo_id = $2 ORDER BY id
+            RETURNING label_id
+        )
+        SELECT labels.* FROM inserted
+        JOIN labels on labels.id = inserted.label_id;"#;
+
 #[derive(Debug, Serialize, Deserialize, Clone, Eq, PartialEq)]
 pub struct TodoEntity {
-    pub id: i32,
+    pub id: TodoId,
     pub text: String,
     pub completed: bool,
+    pub completed_at: Option<DateTime<Utc>>,
+    pub archived: bool,
+    pub due_date: Option<DateTime<Utc>>,
+    pub priority: Option<i16>,
+    /// Place given by `reorder`; todos never reordered have none and come after the others.
+    pub position: Option<i32>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
     pub labels: Vec<Label>,
+    /// Set when `find` left out the labels over its join cap, only serialized when set.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub labels_truncated: bool,
 }
 
-fn fold_entities(rows: Vec<TodoWithLabelFromRow>) -> Vec<TodoEntity> {
-    let mut rows = rows.iter();
-    let mut accum: Vec<TodoEntity> = vec![];
-    'outer: while let Some(row) = rows.next() {
-        let mut todos = accum.iter_mut();
-        while let Some(todo) = todos.next() {
-            if todo.id == row.id {
-                todo.labels.push(Label {
-                    id: row.label_id.unwrap(),
-                    name: row.label_name.clone().unwrap(),
-                });
-
-                continue 'outer;
-            }
+impl TodoEntity {
+    pub fn new(id: TodoId, text: String, completed: bool, labels: Vec<Label>) -> Self {
+        let now = Utc::now();
+        Self {
+            id,
+            text,
+            completed,
+            completed_at: None,
+            archived: false,
+            due_date: None,
+            priority: None,
+            position: None,
+            created_at: now,
+            updated_at: now,
+            labels,
+            labels_truncated: false,
         }
+    }
 
-        let labels = if row.label_id.is_some() {
-            vec![Label {
-                id: row.label_id.unwrap(),
-                name: row.label_name.clone().unwrap(),
-            }]
-        } else {
-            vec![]
-        };
+    /// Keeps the first `max` labels, flagging the todo when some were left out.
+    fn cap_labels(mut self, max: usize) -> Self {
+        if self.labels.len() > max {
+            self.labels.truncate(max);
+            self.labels_truncated = true;
+        }
+        self
+    }
 
-        accum.push(TodoEntity {
-            id: row.id,
-            text: row.text.clone(),
-            completed: row.completed,
-            labels,
-        });
+    /// The todo as `all` lists it with `TodoQuery::skip_labels`.
+    pub fn without_labels(self) -> Self {
+        Self {
+            labels: vec![],
+            labels_truncated: false,
+            ..self
+        }
     }
 
-    accum
+    /// Copies the timestamps of `other`, to compare todos regardless of when they were written.
+    pub fn with_timestamps_of(self, other: &TodoEntity) -> Self {
+        Self {
+            created_at: other.created_at,
+            updated_at: other.updated_at,
+            ..self
+        }
+    }
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone, Eq, PartialEq, Validate)]
-pub struct CreateTodo {
-    #[validate(length(min = 1, message = "Can not be empty"))]
-    #[validate(length(max = 100, message = "Over text length"))]
-    text: String,
-    labels: Vec<i32>,
-}
+/// Fields of a todo whose changes `update` records in its history.
+pub const HISTORY_FIELDS: [&str; 6] = [
+    "text",
+    "completed",
+    "archived",
+    "due_date",
+    "priority",
+    "labels",
+];
 
-#[derive(Debug, Serialize, Deserialize, Clone, Eq, PartialEq, Validate)]
-pub struct UpdateTodo {
-    #[validate(length(min = 1, message = "Can not be empty"))]
-    #[validate(length(max = 100, message = "Over text length"))]
-    text: Option<String>,
-    completed: Option<bool>,
-    labels: Option<Vec<i32>>,
+/// A field changed by an update, with its JSON value before and after.
+#[derive(Debug, Serialize, Deserialize, Clone, Eq, PartialEq, FromRow)]
+pub struct TodoChange {
+    pub changed_at: DateTime<Utc>,
+    pub field: String,
+    pub old: serde_json::Value,
+    pub new: serde_json::Value,
 }
 
-#[derive(Clone)]
-pub struct TodoRepositoryForDb {
-    pool: PgPool,
+/// Entry of an incremental sync: a todo changed after the cursor, or the tombstone of one
+/// deleted since, which only keeps its id and has the time of deletion as `updated_at`.
+#[derive(Debug, Serialize, Deserialize, Clone, Eq, PartialEq)]
+#[serde(untagged)]
+pub enum SyncedTodo {
+    Live {
+        #[serde(flatten)]
+        todo: TodoEntity,
+        deleted: bool,
+    },
+    Deleted {
+        id: TodoId,
+        deleted: bool,
+        updated_at: DateTime<Utc>,
+    },
 }
 
-impl TodoRepositoryForDb {
-    pub fn new(pool: PgPool) -> Self {
-        Self { pool }
+impl SyncedTodo {
+    pub fn live(todo: TodoEntity) -> Self {
+        Self::Live {
+            todo,
+            deleted: false,
+        }
     }
-}
-
-#[async_trait]
-impl TodoRepository for TodoRepositoryForDb {
-    async fn create(&self, payload: CreateTodo) -> anyhow::Result<TodoEntity> {
-        let tx = self.pool.begin().await?;
-        let row = sqlx::query_as::<_, TodoFromRow>(
-            r#"INSERT INTO todos (text, completed) VALUES ($1, false) RETURNING *;"#,
-        )
-        .bind(payload.text.clone())
-        .fetch_one(&self.pool)
-        .await?;
-
-        sqlx::query(r#"INSERT INTO todo_labels (todo_id, label_id) SELECT $1, id FROM unnest($2) as t(id);"#)
-            .bind(row.id)
-            .bind(payload.labels)
-            .execute(&self.pool)
-            .await?;
-        tx.commit().await?;
 
-        let todo = self.find(row.id).await?;
-        Ok(todo)
+    pub fn deleted(id: TodoId, deleted_at: DateTime<Utc>) -> Self {
+        Self::Deleted {
+            id,
+            deleted: true,
+            updated_at: deleted_at,
+        }
     }
 
-    async fn find(&self, id: i32) -> anyhow::Result<TodoEntity> {
-        let items = sqlx::query_as::<_, TodoWithLabelFromRow>(
-            r#"
-        SELECT todos.*, labels.id as label_id, labels.name as label_name FROM todos 
-        LEFT OUTER JOIN todo_labels t1 on todos.id = t1.todo_id
-        LEFT OUTER JOIN labels on labels.id = t1.label_id
-        WHERE todos.id = $1;"#,
-        )
-        .bind(id)
-        .fetch_all(&self.pool)
-        .await
-        .map_err(|e| match e {
-            sqlx::Error::RowNotFound => RepositoryError::NotFound(id),
-            _ => RepositoryError::Unexpected(e.to_string()),
-        })?;
+    pub fn id(&self) -> TodoId {
+        match self {
+            Self::Live { todo, .. } => todo.id,
+            Self::Deleted { id, .. } => *id,
+        }
+    }
 
-        let todos = fold_entities(items);
-        let todo = todos.first().ok_or(RepositoryError::NotFound(id))?;
-        Ok(todo.clone())
+    pub fn updated_at(&self) -> DateTime<Utc> {
+        match self {
+            Self::Live { todo, .. } => todo.updated_at,
+            Self::Deleted { updated_at, .. } => *updated_at,
+        }
     }
+}
 
-    async fn all(&self) -> anyhow::Result<Vec<TodoEntity>> {
-        let items = sqlx::query_as::<_, TodoWithLabelFromRow>(
-            r#"
-        SELECT todos.*, labels.id as label_id, labels.name as label_name FROM todos 
-        LEFT OUTER JOIN todo_labels t1 on todos.id = t1.todo_id
-        LEFT OUTER JOIN labels on labels.id = t1.label_id
-        ORDER BY id desc;"#,
+/// Changed todos and tombstones merged in `updated_at` order, ties broken by id.
+fn synced_todos(
+    todos: Vec<TodoEntity>,
+    tombstones: Vec<(TodoId, DateTime<Utc>)>,
+) -> Vec<SyncedTodo> {
+    let mut synced: Vec<SyncedTodo> = todos
+        .into_iter()
+        .map(SyncedTodo::live)
+        .chain(
+            tombstones
+                .into_iter()
+                .map(|(id, deleted_at)| SyncedTodo::deleted(id, deleted_at)),
         )
-        .fetch_all(&self.pool)
-        .await?;
+        .collect();
+    synced.sort_by_key(|todo| (todo.updated_at(), todo.id()));
+    synced
+}
 
-        Ok(fold_entities(items))
-    }
+/// Changes of the `HISTORY_FIELDS` from `old` to `new`, stamped with the `updated_at` of `new`.
+fn todo_changes(old: &TodoEntity, new: &TodoEntity) -> Vec<TodoChange> {
+    let old_values = serde_json::to_value(old).expect("todo is always serializable");
+    let new_values = serde_json::to_value(new).expect("todo is always serializable");
+    HISTORY_FIELDS
+        .iter()
+        .filter(|field| old_values[**field] != new_values[**field])
+        .map(|field| TodoChange {
+            changed_at: new.updated_at,
+            field: field.to_string(),
+            old: old_values[*field].clone(),
+            new: new_values[*field].clone(),
+        })
+        .collect()
+}
 
-    async fn update(&self, id: i32, payload: UpdateTodo) -> anyhow::Result<TodoEntity> {
-        let tx = self.pool.begin().await?;
-        let old_todo = self.find(id).await?;
-        sqlx::query(r#"UPDATE todos SET text = $1, completed = $2 WHERE id = $3"#)
-            .bind(payload.text.unwrap_or(old_todo.text))
-            .bind(payload.completed.unwrap_or(old_todo.completed))
-            .bind(id)
-            .execute(&self.pool)
-            .await?;
+#[derive(Debug, Default, Deserialize, Clone, Eq, PartialEq, Validate)]
+#[validate(schema(function = "validate_query_label_filter"))]
+pub struct TodoQuery {
+    #[serde(default)]
+    pub include_archived: bool,
+    pub q: Option<String>,
+    #[serde(default)]
+    pub fuzzy: bool,
+    pub completed: Option<bool>,
+    /// Todos with any of these labels, each listed once however many of them it has.
+    #[serde(default, deserialize_with = "deserialize_comma_separated")]
+    pub label_ids: Vec<LabelId>,
+    /// Todos without any label, which excludes `label_ids`.
+    #[serde(default)]
+    pub unlabeled: bool,
+    /// Lists these todos in this order through `find_many`, in place of the other filters.
+    #[serde(default, deserialize_with = "deserialize_comma_separated")]
+    #[validate(length(max = "MAX_LIMIT", code = "ids_count", message = "At most 100 ids"))]
+    pub ids: Vec<TodoId>,
+    pub page: Option<i64>,
+    #[serde(alias = "limit")]
+    pub page_size: Option<i64>,
+    pub offset: Option<i64>,
+    #[serde(default)]
+    pub sort: TodoSort,
+    #[serde(default)]
+    pub order: SortOrder,
+    /// Lists the todos of `all` with empty `labels`, reading only the `todos` table, which
+    /// is much faster for long lists. Other methods fetch labels regardless.
+    ///
+    /// Requests skip them unless they ask for `?expand=labels`.
+    #[serde(
+        rename = "expand",
+        default = "skip_labels_unless_expanded",
+        deserialize_with = "deserialize_skip_labels"
+    )]
+    pub skip_labels: bool,
+}
 
-        if let Some(labels) = payload.labels {
-            sqlx::query(r#"DELETE FROM todo_labels WHERE todo_id = $1"#)
-                .bind(id)
-                .execute(&self.pool)
-                .await?;
-            sqlx::query(r#"INSERT INTO todo_labels (todo_id, label_id) SELECT $1, id FROM unnest($2) as t(id);"#)
-                .bind(id)
-                .bind(labels)
-                .execute(&self.pool)
-                .await?;
-        };
-        tx.commit().await?;
+fn skip_labels_unless_expanded() -> bool {
+    true
+}
 
-        let todo = self.find(id).await?;
-        Ok(todo)
-    }
+fn deserialize_skip_labels<'de, D>(deserializer: D) -> Result<bool, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let expand = String::deserialize(deserializer)?;
+    Ok(!expand.split(',').any(|expand| expand.trim() == "labels"))
+}
 
-    async fn delete(&self, id: i32) -> anyhow::Result<()> {
-        let tx = self.pool.begin().await?;
-        sqlx::query(r#"DELETE FROM todo_labels WHERE todo_id = $1"#)
-            .bind(id)
-            .execute(&self.pool)
-            .await
-            .map_err(|e| match e {
-                sqlx::Error::RowNotFound => RepositoryError::NotFound(id),
-                _ => RepositoryError::Unexpected(e.to_string()),
-            })?;
+/// Column `all` orders todos by, ties are broken by id in the same direction.
+///
+/// `Position` follows the manual order of `reorder`, which the sort order doesn't flip;
+/// todos without a position come after and are ordered by `created_at` instead.
+#[derive(Debug, Default, Serialize, Deserialize, Clone, Copy, Eq, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum TodoSort {
+    #[default]
+    Position,
+    Id,
+    CreatedAt,
+    UpdatedAt,
+}
 
-        sqlx::query(r#"DELETE FROM todos WHERE id = $1"#)
-            .bind(id)
-            .execute(&self.pool)
-            .await
-            .map_err(|e| match e {
-                sqlx::Error::RowNotFound => RepositoryError::NotFound(id),
-                _ => RepositoryError::Unexpected(e.to_string()),
-            })?;
-        tx.commit().await?;
+#[derive(Debug, Default, Serialize, Deserialize, Clone, Copy, Eq, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum SortOrder {
+    Asc,
+    #[default]
+    Desc,
+}
 
-        Ok(())
+impl TodoSort {
+    /// `ORDER BY` clause of the todos, the default one is served by
+    /// `todos_owner_id_position_idx`.
+    fn order_by(self, order: SortOrder) -> &'static str {
+        match (self, order) {
+            (TodoSort::Position, SortOrder::Asc) => {
+                "todos.position ASC NULLS LAST, todos.created_at ASC, todos.id ASC"
+            }
+            (TodoSort::Position, SortOrder::Desc) => {
+                "todos.position ASC NULLS LAST, todos.created_at DESC, todos.id DESC"
+            }
+            (TodoSort::Id, SortOrder::Asc) => "todos.id ASC",
+            (TodoSort::Id, SortOrder::Desc) => "todos.id DESC",
+            (TodoSort::CreatedAt, SortOrder::Asc) => "todos.created_at ASC, todos.id ASC",
+            (TodoSort::CreatedAt, SortOrder::Desc) => "todos.created_at DESC, todos.id DESC",
+            (TodoSort::UpdatedAt, SortOrder::Asc) => "todos.updated_at ASC, todos.id ASC",
+            (TodoSort::UpdatedAt, SortOrder::Desc) => "todos.updated_at DESC, todos.id DESC",
+        }
+    }
+
+    #[cfg(any(test, feature = "testing"))]
+    fn sort(self, order: SortOrder, todos: &mut [TodoEntity]) {
+        todos.sort_by(|a, b| {
+            if self == TodoSort::Position {
+                let by_position = match (a.position, b.position) {
+                    (Some(a), Some(b)) => a.cmp(&b),
+                    (a, b) => a.is_none().cmp(&b.is_none()),
+                };
+                if by_position.is_ne() {
+                    return by_position;
+                }
+            }
+            let ordering = match self {
+                TodoSort::Id => a.id.cmp(&b.id),
+                TodoSort::Position | TodoSort::CreatedAt => {
+                    (a.created_at, a.id).cmp(&(b.created_at, b.id))
+                }
+                TodoSort::UpdatedAt => (a.updated_at, a.id).cmp(&(b.updated_at, b.id)),
+            };
+            match order {
+                SortOrder::Asc => ordering,
+                SortOrder::Desc => ordering.reverse(),
+            }
+        });
     }
 }
 
-#[cfg(test)]
-#[cfg(feature = "database-test")]
-mod test {
-    use super::*;
-    use dotenv::dotenv;
-    use sqlx::PgPool;
-    use std::env;
+impl TodoQuery {
+    /// `ORDER BY` clause of `all`.
+    fn order_by(&self) -> &'static str {
+        self.sort.order_by(self.order)
+    }
 
-    #[test]
-    fn fold_entities_test() {
-        let label_1 = Label {
-            id: 1,
-            name: "Label 1".to_string(),
-        };
-        let label_2 = Label {
-            id: 2,
-            name: "Label 2".to_string(),
-        };
+    #[cfg(any(test, feature = "testing"))]
+    fn sort(&self, todos: &mut [TodoEntity]) {
+        self.sort.sort(self.order, todos)
+    }
 
-        let rows = vec![
-            TodoWithLabelFromRow {
-                id: 1,
-                text: "Todo 1".to_string(),
-                completed: false,
-                label_id: Some(label_1.id),
-                label_name: Some(label_1.name.clone()),
-            },
-            TodoWithLabelFromRow {
-                id: 1,
-                text: "Todo 1".to_string(),
-                completed: false,
-                label_id: Some(label_2.id),
-                label_name: Some(label_2.name.clone()),
-            },
-            TodoWithLabelFromRow {
-                id: 2,
-                text: "Todo 2".to_string(),
-                completed: false,
-                label_id: Some(label_1.id),
-                label_name: Some(label_1.name.clone()),
-            },
-        ];
-        let res = fold_entities(rows);
-        assert_eq!(
-            res,
-            vec![
-                TodoEntity {
-                    id: 1,
-                    text: "Todo 1".to_string(),
-                    completed: false,
-                    labels: vec![label_1.clone(), label_2.clone()]
-                },
-                TodoEntity {
-                    id: 2,
-                    text: "Todo 2".to_string(),
-                    completed: false,
-                    labels: vec![label_1.clone()]
-                },
-            ]
-        );
+    /// Criteria of the page asked for by `page`, `limit` or `offset`, searched in the same
+    /// order as `all` would list the todos.
+    pub fn search_criteria(&self) -> Option<TodoSearchCriteria> {
+        if self.page.is_none() && self.page_size.is_none() && self.offset.is_none() {
+            return None;
+        }
+        Some(TodoSearchCriteria {
+            q: self.q.clone(),
+            completed: self.completed,
+            label_ids: self.label_ids.clone(),
+            unlabeled: self.unlabeled,
+            include_archived: self.include_archived,
+            sort: TodoSearchSort::Listing(self.sort, self.order),
+            page: self.page.unwrap_or_else(default_page),
+            page_size: self.page_size.unwrap_or(DEFAULT_LIMIT),
+            offset: self.offset,
+        })
     }
+}
 
-    #[tokio::test]
-    async fn crud_scenario() {
-        dotenv().ok();
-        let database_url = &env::var("DATABASE_URL").expect("undefined [DATABASE_URL]");
-        let pool = PgPool::connect(database_url)
-            .await
-            .expect(&format!("fail connect database. url is [{}]", database_url));
+#[derive(Debug, Default, Deserialize, Clone, Copy, Eq, PartialEq)]
+pub struct TodoFilter {
+    pub label_id: Option<LabelId>,
+}
 
-        // label data prepare
-        let label_name = "test label".to_string();
-        let optional_label = sqlx::query_as::<_, Label>(r#"SELECT * FROM labels WHERE name = $1"#)
-            .bind(label_name.clone())
-            .fetch_optional(&pool)
-            .await
-            .expect("Failed to prepare label data.");
-        let label_1 = if let Some(label) = optional_label {
-            label
-        } else {
-            let label = sqlx::query_as::<_, Label>(
-                r#"INSERT INTO labels ( name ) VALUES ( $1 ) RETURNING *"#,
-            )
-            .bind(label_name)
-            .fetch_one(&pool)
-            .await
-            .expect("Failed to insert label data");
+/// Progress of the todos `summary` counted.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub struct TodoSummary {
+    pub total: i64,
+    pub completed: i64,
+    pub active: i64,
+    /// Share of the todos completed, from 0 to 1; 0 when there are none.
+    pub completion_rate: f64,
+}
 
-            label
+impl TodoSummary {
+    pub fn new(total: i64, completed: i64) -> Self {
+        let completion_rate = if total == 0 {
+            0.0
+        } else {
+            completed as f64 / total as f64
         };
+        Self {
+            total,
+            completed,
+            active: total - completed,
+            completion_rate,
+        }
+    }
+}
 
-        let repo = TodoRepositoryForDb::new(pool.clone());
-        let todo_text = "[crud_scenario] text";
-
-        // create
-        let created = repo
-            .create(CreateTodo::new(todo_text.to_string(), vec![label_1.id]))
-            .await
-            .expect("[create] returned Err");
-        assert_eq!(created.text, todo_text);
-        assert!(!created.completed);
-        assert_eq!(*created.labels.first().unwrap(), label_1);
-
-        // find
-        let todo = repo.find(created.id).await.expect("[find] returned Err");
-        assert_eq!(created, todo);
+/// Minimum `pg_trgm` similarity for a todo to be returned by `search_ranked`.
+const SIMILARITY_THRESHOLD: f32 = 0.3;
 
-        // all
-        let todos = repo.all().await.expect("[all] returned Err");
-        let todo = todos.first().unwrap();
-        assert_eq!(created, *todo);
+/// Todos of `search_ranked` similar to `$1`, archived ones too when `$2`, of the owner `$3`.
+const RANKED_TODOS_SQL: &str = r#"
+SELECT todos.*, labels.id as label_id, labels.name as label_name FROM todos
+LEFT OUTER JOIN todo_labels t1 on todos.id = t1.todo_id
+LEFT OUTER JOIN labels on labels.id = t1.label_id
+WHERE todos.owner_id = $3 AND ($2 OR NOT todos.archived) AND todos.text % $1
+ORDER BY similarity(todos.text, $1) DESC, todos.id DESC;"#;
 
-        // update
-        let updated_text = "[crud_scenario] updated text";
-        let todo = repo
-            .update(
-                todo.id,
-                UpdateTodo::new(Some(updated_text.to_string()), Some(true), Some(vec![])),
-            )
-            .await
-            .expect("[update] returned Err");
-        assert_eq!(created.id, todo.id);
-        assert_eq!(todo.text, updated_text);
+fn like_pattern(q: &str) -> String {
+    let escaped = q
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_");
+    format!("%{}%", escaped)
+}
 
-        // delete
-        repo.delete(todo.id).await.expect("[delete] returned Err");
-        let res = repo.find(created.id).await;
-        assert!(res.is_err());
+impl TodoWithLabelFromRow {
+    fn label(&self) -> Option<Label> {
+        self.label_id.map(|id| Label {
+            id,
+            name: self.label_name.clone().unwrap(),
+        })
+    }
 
-        let todo_rows = sqlx::query(r#"SELECT * FROM todos WHERE id = $1"#)
-            .bind(todo.id)
-            .fetch_all(&pool)
-            .await
-            .expect("[delete] todo_labels fetch error");
-        assert_eq!(todo_rows.len(), 0);
+    fn into_entity(self) -> TodoEntity {
+        TodoEntity {
+            labels: self.label().into_iter().collect(),
+            id: self.id,
+            text: self.text,
+            completed: self.completed,
+            completed_at: self.completed_at,
+            archived: self.archived,
+            due_date: self.due_date,
+            priority: self.priority,
+            position: self.position,
+            created_at: self.created_at,
+            updated_at: self.updated_at,
+            labels_truncated: false,
+        }
     }
 }
 
-#[cfg(test)]
-pub mod test_utils {
-    use super::*;
-    use anyhow::Context;
-    use axum::async_trait;
-    use std::{
-        collections::HashMap,
-        sync::{Arc, RwLock, RwLockReadGuard, RwLockWriteGuard},
-    };
+fn fold_entities(rows: Vec<TodoWithLabelFromRow>) -> Vec<TodoEntity> {
+    let mut accum: Vec<TodoEntity> = vec![];
+    'outer: for row in rows.into_iter() {
+        for todo in accum.iter_mut() {
+            if todo.id == row.id {
+                todo.labels.extend(row.label());
 
-    impl TodoEntity {
-        pub fn new(id: i32, text: String, completed: bool, labels: Vec<Label>) -> Self {
-            Self {
-                id,
-                text,
-                completed,
-                labels,
+                continue 'outer;
             }
         }
-    }
 
-    impl CreateTodo {
-        pub fn new(text: String, labels: Vec<i32>) -> Self {
-            Self { text, labels }
-        }
+        accum.push(row.into_entity());
     }
-
-    impl UpdateTodo {
-        pub fn new(
-            text: Option<String>,
-            completed: Option<bool>,
-            labels: Option<Vec<i32>>,
-        ) -> Self {
-            Self {
-                text,
-                completed,
-                labels,
-            }
-        }
+    for todo in accum.iter_mut() {
+        sort_labels(&mut todo.labels);
     }
 
-    type TodoDatas = HashMap<i32, TodoEntity>;
+    accum
+}
 
-    #[derive(Debug, Clone)]
-    pub struct TodoRepositoryForMemory {
-        store: Arc<RwLock<TodoDatas>>,
-        labels: Vec<Label>,
-    }
+/// Todos of `todos` in the order of `ids`, once each, dropping those it has no id of.
+fn in_order_of(ids: &[TodoId], todos: Vec<TodoEntity>) -> Vec<TodoEntity> {
+    let mut todos: HashMap<TodoId, TodoEntity> =
+        todos.into_iter().map(|todo| (todo.id, todo)).collect();
+    ids.iter().filter_map(|id| todos.remove(id)).collect()
+}
 
-    impl TodoRepositoryForMemory {
-        pub fn new(labels: Vec<Label>) -> Self {
-            TodoRepositoryForMemory {
-                store: Arc::default(),
-                labels,
+/// Streaming counterpart of `fold_entities` for rows ordered so that those of a todo are
+/// adjacent, as `all` orders them: each todo is emitted once its last row went by, so only
+/// one is held at a time.
+pub fn fold_entity_stream(
+    rows: impl Stream<Item = anyhow::Result<TodoWithLabelFromRow>>,
+) -> impl Stream<Item = anyhow::Result<TodoEntity>> {
+    try_stream! {
+        pin_mut!(rows);
+        let mut current: Option<TodoEntity> = None;
+        while let Some(row) = rows.next().await {
+            let row = row?;
+            match current.as_mut() {
+                Some(todo) if todo.id == row.id => todo.labels.extend(row.label()),
+                _ => {
+                    if let Some(mut todo) = current.replace(row.into_entity()) {
+                        sort_labels(&mut todo.labels);
+                        yield todo;
+                    }
+                }
             }
         }
-
-        fn write_store_ref(&self) -> RwLockWriteGuard<TodoDatas> {
-            self.store.write().unwrap()
+        if let Some(mut todo) = current {
+            sort_labels(&mut todo.labels);
+            yield todo;
         }
+    }
+}
 
-        fn read_store_ref(&self) -> RwLockReadGuard<TodoDatas> {
-            self.store.read().unwrap()
+/// Error of an update that matched no row: the todo changed when the update was conditional,
+/// it is gone otherwise.
+fn missing_update(id: TodoId, if_updated_at: Option<DateTime<Utc>>) -> RepositoryError {
+    match if_updated_at {
+        Some(_) => RepositoryError::Stale(id.into()),
+        None => RepositoryError::NotFound(id.into()),
+    }
+}
+
+/// Labels are always returned ordered by id, whatever order they were joined or attached in.
+fn sort_labels(labels: &mut [Label]) {
+    labels.sort_by_key(|label| label.id);
+}
+
+/// Fuzzy ranking for backends without `pg_trgm`: keeps todos whose text, or one of its
+/// words, is within a third of the query length in edit distance, closest first.
+#[cfg(any(test, feature = "testing", feature = "sqlite"))]
+fn rank_by_distance(todos: impl Iterator<Item = TodoEntity>, q: &str) -> Vec<TodoEntity> {
+    let q = q.to_lowercase();
+    let max_distance = q.chars().count() / 3;
+    let mut ranked: Vec<(usize, TodoEntity)> = todos
+        .filter_map(|todo| {
+            let text = todo.text.to_lowercase();
+            let distance = text
+                .split_whitespace()
+                .chain(std::iter::once(text.as_str()))
+                .map(|word| levenshtein(word, &q))
+                .min()?;
+            (distance <= max_distance).then_some((distance, todo))
+        })
+        .collect();
+    ranked.sort_by_key(|(distance, todo)| (*distance, std::cmp::Reverse(todo.id)));
+    ranked.into_iter().map(|(_, todo)| todo).collect()
+}
+
+#[cfg(any(test, feature = "testing", feature = "sqlite"))]
+fn levenshtein(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut prev: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = prev[j] + usize::from(ca != *cb);
+            current.push(substitution.min(prev[j + 1] + 1).min(current[j] + 1));
+        }
+        prev = current;
+    }
+    prev[b.len()]
+}
+
+#[derive(Debug, Default, Serialize, Deserialize, Clone, Copy, Eq, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum TodoSearchSort {
+    IdAsc,
+    #[default]
+    IdDesc,
+    TextAsc,
+    TextDesc,
+    /// Order of `GET /todos`, when it pages the todos through `search`.
+    #[serde(skip)]
+    Listing(TodoSort, SortOrder),
+}
+
+impl TodoSearchSort {
+    fn order_by(&self) -> &'static str {
+        match self {
+            TodoSearchSort::Listing(sort, order) => sort.order_by(*order),
+            TodoSearchSort::IdAsc => "todos.id ASC",
+            TodoSearchSort::IdDesc => "todos.id DESC",
+            TodoSearchSort::TextAsc => "todos.text ASC, todos.id ASC",
+            TodoSearchSort::TextDesc => "todos.text DESC, todos.id DESC",
+        }
+    }
+}
+
+fn default_page() -> i64 {
+    1
+}
+
+/// Page size of a request that names none.
+pub const DEFAULT_LIMIT: i64 = 20;
+/// Largest page size a request may ask for; larger ones are refused rather than clamped.
+pub const MAX_LIMIT: i64 = 100;
+
+fn default_page_size() -> i64 {
+    DEFAULT_LIMIT
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Eq, PartialEq, Validate)]
+#[validate(schema(function = "validate_criteria_label_filter"))]
+pub struct TodoSearchCriteria {
+    pub q: Option<String>,
+    pub completed: Option<bool>,
+    #[serde(default)]
+    pub label_ids: Vec<LabelId>,
+    /// Todos without any label, which excludes `label_ids`.
+    #[serde(default)]
+    pub unlabeled: bool,
+    #[serde(default)]
+    pub include_archived: bool,
+    #[serde(default)]
+    pub sort: TodoSearchSort,
+    #[serde(default = "default_page")]
+    #[validate(range(min = 1, code = "page_range", message = "Must be 1 or more"))]
+    pub page: i64,
+    #[serde(default = "default_page_size", alias = "limit")]
+    #[validate(range(
+        min = 1,
+        max = "MAX_LIMIT",
+        code = "page_size_range",
+        message = "Must be between 1 and 100"
+    ))]
+    pub page_size: i64,
+    /// Number of todos to skip, in place of the whole pages skipped by `page`.
+    #[serde(default)]
+    #[validate(range(min = 0, code = "negative", message = "Can not be negative"))]
+    pub offset: Option<i64>,
+}
+
+impl Default for TodoSearchCriteria {
+    fn default() -> Self {
+        Self {
+            q: None,
+            completed: None,
+            label_ids: vec![],
+            unlabeled: false,
+            include_archived: false,
+            sort: TodoSearchSort::default(),
+            page: default_page(),
+            page_size: default_page_size(),
+            offset: None,
+        }
+    }
+}
+
+/// No todo has both some of the labels and none at all, so asking for it is a client bug.
+fn validate_label_filter(unlabeled: bool, label_ids: &[LabelId]) -> Result<(), ValidationError> {
+    if unlabeled && !label_ids.is_empty() {
+        let mut error = ValidationError::new("unlabeled_with_label_ids");
+        error.message = Some("Can not combine unlabeled with label_ids".into());
+        return Err(error);
+    }
+    Ok(())
+}
+
+fn validate_query_label_filter(query: &TodoQuery) -> Result<(), ValidationError> {
+    validate_label_filter(query.unlabeled, &query.label_ids)
+}
+
+fn validate_criteria_label_filter(criteria: &TodoSearchCriteria) -> Result<(), ValidationError> {
+    validate_label_filter(criteria.unlabeled, &criteria.label_ids)
+}
+
+impl TodoSearchCriteria {
+    pub fn offset(&self) -> i64 {
+        self.offset.unwrap_or((self.page - 1) * self.page_size)
+    }
+}
+
+impl TodoSearchResult {
+    pub fn total_pages(&self) -> i64 {
+        ((self.total + self.page_size - 1) / self.page_size).max(1)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Eq, PartialEq)]
+pub struct TodoSearchResult {
+    pub items: Vec<TodoEntity>,
+    pub total: i64,
+    pub page: i64,
+    pub page_size: i64,
+}
+
+fn push_search_conditions(
+    query: &mut QueryBuilder<Postgres>,
+    owner: OwnerId,
+    criteria: &TodoSearchCriteria,
+) {
+    query.push(" WHERE todos.owner_id = ").push_bind(owner);
+    if !criteria.include_archived {
+        query.push(" AND NOT todos.archived");
+    }
+    if let Some(q) = &criteria.q {
+        query
+            .push(" AND todos.text ILIKE ")
+            .push_bind(like_pattern(q));
+    }
+    if let Some(completed) = criteria.completed {
+        query.push(" AND todos.completed = ").push_bind(completed);
+    }
+    if !criteria.label_ids.is_empty() {
+        query
+            .push(" AND todos.id IN (SELECT todo_id FROM todo_labels WHERE label_id = ANY(")
+            .push_bind(criteria.label_ids.clone())
+            .push("))");
+    }
+    if criteria.unlabeled {
+        query.push(" AND NOT EXISTS (SELECT 1 FROM todo_labels WHERE todo_id = todos.id)");
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Eq, PartialEq)]
+pub struct LabelGroup {
+    pub label: Label,
+    pub todos: Vec<TodoEntity>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Eq, PartialEq)]
+pub struct TodosByLabel {
+    pub labels: Vec<LabelGroup>,
+    pub unlabeled: Vec<TodoEntity>,
+}
+
+pub fn group_by_label(todos: Vec<TodoEntity>) -> TodosByLabel {
+    let mut groups: Vec<LabelGroup> = vec![];
+    let mut unlabeled: Vec<TodoEntity> = vec![];
+    for todo in todos {
+        if todo.labels.is_empty() {
+            unlabeled.push(todo);
+            continue;
+        }
+
+        for label in todo.labels.iter() {
+            match groups.iter_mut().find(|group| group.label.id == label.id) {
+                Some(group) => group.todos.push(todo.clone()),
+                None => groups.push(LabelGroup {
+                    label: label.clone(),
+                    todos: vec![todo.clone()],
+                }),
+            }
+        }
+    }
+    groups.sort_by_key(|group| group.label.id);
+
+    TodosByLabel {
+        labels: groups,
+        unlabeled,
+    }
+}
+
+fn next_completed_at(
+    old_todo: &TodoEntity,
+    completed: bool,
+    now: DateTime<Utc>,
+) -> Option<DateTime<Utc>> {
+    match (old_todo.completed, completed) {
+        (false, true) => Some(now),
+        (true, false) => None,
+        _ => old_todo.completed_at,
+    }
+}
+
+fn labels_or_default(labels: Vec<LabelId>, default_label: Option<LabelId>) -> Vec<LabelId> {
+    if labels.is_empty() {
+        default_label.into_iter().collect()
+    } else {
+        labels
+    }
+}
+
+/// NotFound for the first of `ids` missing from the `labels` inserted for a todo, as the
+/// inserts skip the ids of unknown labels and of labels of other owners. The default label
+/// is not part of `ids`, owners it does not belong to just go without it.
+fn check_labels_found(ids: &[LabelId], labels: &[Label]) -> Result<(), RepositoryError> {
+    match ids
+        .iter()
+        .find(|id| labels.iter().all(|label| label.id != **id))
+    {
+        Some(id) => Err(RepositoryError::NotFound((*id).into())),
+        None => Ok(()),
+    }
+}
+
+pub const DEFAULT_MAX_LABELS_PER_TODO: usize = 20;
+
+/// Labels `find` joins at most per todo, so that todos labeled before `max_labels` was
+/// lowered, or by hand, cannot blow up the join.
+pub const DEFAULT_MAX_JOINED_LABELS: usize = 100;
+
+fn check_label_count(label_ids: &[LabelId], max_labels: usize) -> Result<(), RepositoryError> {
+    if label_ids.len() > max_labels {
+        return Err(RepositoryError::TooManyLabels(max_labels));
+    }
+    Ok(())
+}
+
+fn unique_label_ids(label_ids: Vec<LabelId>) -> Vec<LabelId> {
+    let mut unique: Vec<LabelId> = Vec::with_capacity(label_ids.len());
+    for id in label_ids {
+        if !unique.contains(&id) {
+            unique.push(id);
+        }
+    }
+    unique
+}
+
+fn collapse_label_name(name: &str) -> String {
+    name.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+fn unique_label_names(names: Vec<String>) -> Vec<String> {
+    let mut unique: Vec<String> = Vec::with_capacity(names.len());
+    for name in names.iter().map(|name| collapse_label_name(name)) {
+        if !unique
+            .iter()
+            .any(|n| n.to_lowercase() == name.to_lowercase())
+        {
+            unique.push(name);
+        }
+    }
+    unique
+}
+
+/// Rejects control characters such as `\0` that break rendering downstream. Line breaks
+/// and tabs count as ordinary whitespace, so multi-line text is accepted.
+fn validate_text(text: &str) -> Result<(), ValidationError> {
+    if text
+        .chars()
+        .any(|c| c.is_control() && !matches!(c, '\n' | '\r' | '\t'))
+    {
+        let mut error = ValidationError::new("control_character");
+        error.message = Some("Contains control characters".into());
+        return Err(error);
+    }
+    Ok(())
+}
+
+fn validate_label_names(names: &[String]) -> Result<(), ValidationError> {
+    for name in names.iter().map(|name| collapse_label_name(name)) {
+        if name.is_empty() {
+            let mut error = ValidationError::new("empty");
+            error.message = Some("Can not be empty".into());
+            return Err(error);
+        }
+        if name.chars().count() > 100 {
+            let mut error = ValidationError::new("too_long");
+            error.message = Some("Over text length".into());
+            return Err(error);
+        }
+    }
+    Ok(())
+}
+
+pub const MIN_PRIORITY: i16 = 1;
+pub const MAX_PRIORITY: i16 = 5;
+
+fn validate_priority(priority: i16) -> Result<(), ValidationError> {
+    if !(MIN_PRIORITY..=MAX_PRIORITY).contains(&priority) {
+        let mut error = ValidationError::new("priority_range");
+        error.message = Some("Out of priority range".into());
+        error.add_param("min".into(), &MIN_PRIORITY);
+        error.add_param("max".into(), &MAX_PRIORITY);
+        return Err(error);
+    }
+    Ok(())
+}
+
+fn validate_priority_patch(priority: &Patch<i16>) -> Result<(), ValidationError> {
+    match priority {
+        Patch::Value(priority) => validate_priority(*priority),
+        Patch::Undefined | Patch::Null => Ok(()),
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Eq, PartialEq, Validate)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct CreateTodo {
+    #[serde(deserialize_with = "deserialize_trimmed")]
+    #[validate(length(min = 1, code = "empty", message = "Can not be empty"))]
+    #[validate(length(max = 100, code = "too_long", message = "Over text length"))]
+    #[validate(custom = "validate_text")]
+    text: String,
+    #[serde(default)]
+    labels: Vec<LabelId>,
+    #[serde(default)]
+    #[validate(custom = "validate_label_names")]
+    label_names: Vec<String>,
+    #[serde(default)]
+    due_date: Option<DateTime<Utc>>,
+    #[serde(default)]
+    #[validate(custom = "validate_priority")]
+    priority: Option<i16>,
+}
+
+impl CreateTodo {
+    pub fn new(text: String, labels: Vec<LabelId>) -> Self {
+        Self {
+            text,
+            labels,
+            label_names: vec![],
+            due_date: None,
+            priority: None,
+        }
+    }
+
+    /// Payload that would create the copy `duplicate` makes of `todo`.
+    pub fn copy_of(todo: &TodoEntity) -> Self {
+        Self::new(
+            todo.text.clone(),
+            todo.labels.iter().map(|label| label.id).collect(),
+        )
+        .with_due_date(todo.due_date)
+        .with_priority(todo.priority)
+    }
+
+    pub fn with_label_names(mut self, label_names: Vec<String>) -> Self {
+        self.label_names = label_names;
+        self
+    }
+
+    pub fn with_due_date(mut self, due_date: Option<DateTime<Utc>>) -> Self {
+        self.due_date = due_date;
+        self
+    }
+
+    pub fn with_priority(mut self, priority: Option<i16>) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    pub fn with_labels(mut self, labels: Vec<LabelId>) -> Self {
+        self.labels = labels;
+        self
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    pub fn labels(&self) -> &[LabelId] {
+        &self.labels
+    }
+}
+
+impl From<CreateTodo> for UpdateTodo {
+    fn from(payload: CreateTodo) -> Self {
+        Self {
+            due_date: payload.due_date.into(),
+            priority: payload.priority.into(),
+            ..Self::new(Some(payload.text), None, Some(payload.labels))
+        }
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize, Clone, Eq, PartialEq, Validate)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[validate(schema(function = "validate_update_not_empty", skip_on_field_errors = false))]
+pub struct UpdateTodo {
+    #[serde(default, deserialize_with = "deserialize_trimmed_option")]
+    #[validate(length(min = 1, code = "empty", message = "Can not be empty"))]
+    #[validate(length(max = 100, code = "too_long", message = "Over text length"))]
+    #[validate(custom = "validate_text")]
+    text: Option<String>,
+    completed: Option<bool>,
+    archived: Option<bool>,
+    labels: Option<Vec<LabelId>>,
+    #[serde(default, skip_serializing_if = "Patch::is_undefined")]
+    due_date: Patch<DateTime<Utc>>,
+    #[serde(default, skip_serializing_if = "Patch::is_undefined")]
+    #[validate(custom = "validate_priority_patch")]
+    priority: Patch<i16>,
+    #[serde(skip)]
+    if_updated_at: Option<DateTime<Utc>>,
+}
+
+impl UpdateTodo {
+    pub fn new(
+        text: Option<String>,
+        completed: Option<bool>,
+        labels: Option<Vec<LabelId>>,
+    ) -> Self {
+        Self {
+            text,
+            completed,
+            archived: None,
+            labels,
+            due_date: Patch::Undefined,
+            priority: Patch::Undefined,
+            if_updated_at: None,
+        }
+    }
+
+    pub fn archive(archived: bool) -> Self {
+        Self {
+            archived: Some(archived),
+            ..Default::default()
+        }
+    }
+
+    pub fn with_due_date(mut self, due_date: Patch<DateTime<Utc>>) -> Self {
+        self.due_date = due_date;
+        self
+    }
+
+    pub fn with_priority(mut self, priority: Patch<i16>) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    /// Applies the update only if the todo is still the version last updated at
+    /// `updated_at`, failing with `Stale` otherwise.
+    pub fn if_updated_at(mut self, updated_at: DateTime<Utc>) -> Self {
+        self.if_updated_at = Some(updated_at);
+        self
+    }
+
+    pub fn with_labels(mut self, labels: Option<Vec<LabelId>>) -> Self {
+        self.labels = labels;
+        self
+    }
+
+    pub fn text(&self) -> Option<&str> {
+        self.text.as_deref()
+    }
+
+    pub fn labels(&self) -> Option<&[LabelId]> {
+        self.labels.as_deref()
+    }
+}
+
+/// Body of `PUT /todos/:id`: `text`, `completed` and `labels` are required, the other
+/// fields are reset when omitted. They are options so that a missing one is reported by
+/// validation with the other errors.
+#[derive(Debug, Default, Serialize, Deserialize, Clone, Eq, PartialEq, Validate)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct ReplaceTodo {
+    #[serde(default, deserialize_with = "deserialize_trimmed_option")]
+    #[validate(required(code = "required", message = "Is required"))]
+    #[validate(length(min = 1, code = "empty", message = "Can not be empty"))]
+    #[validate(length(max = 100, code = "too_long", message = "Over text length"))]
+    #[validate(custom = "validate_text")]
+    text: Option<String>,
+    #[validate(required(code = "required", message = "Is required"))]
+    completed: Option<bool>,
+    #[validate(required(code = "required", message = "Is required"))]
+    labels: Option<Vec<LabelId>>,
+    #[serde(default)]
+    archived: bool,
+    #[serde(default)]
+    due_date: Option<DateTime<Utc>>,
+    #[serde(default)]
+    #[validate(custom = "validate_priority")]
+    priority: Option<i16>,
+}
+
+impl ReplaceTodo {
+    pub fn new(text: String, completed: bool, labels: Vec<LabelId>) -> Self {
+        Self {
+            text: Some(text),
+            completed: Some(completed),
+            labels: Some(labels),
+            ..Default::default()
+        }
+    }
+}
+
+impl From<ReplaceTodo> for UpdateTodo {
+    fn from(payload: ReplaceTodo) -> Self {
+        Self {
+            archived: Some(payload.archived),
+            due_date: payload.due_date.into(),
+            priority: payload.priority.into(),
+            ..Self::new(payload.text, payload.completed, payload.labels)
+        }
+    }
+}
+
+/// An update changing nothing is almost always a client bug, so it's rejected rather than
+/// applied as a no-op.
+fn validate_update_not_empty(payload: &UpdateTodo) -> Result<(), ValidationError> {
+    if *payload == UpdateTodo::default() {
+        let mut error = ValidationError::new("no_fields");
+        error.message = Some("At least one field required".into());
+        return Err(error);
+    }
+    Ok(())
+}
+
+/// Body of `POST /todos/reorder`, ids in their new order.
+#[derive(Debug, Serialize, Deserialize, Clone, Eq, PartialEq, Validate)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct ReorderTodos {
+    #[validate(custom = "validate_unique_ids")]
+    pub ordered_ids: Vec<TodoId>,
+}
+
+fn validate_unique_ids(ids: &[TodoId]) -> Result<(), ValidationError> {
+    let mut seen = std::collections::HashSet::new();
+    if let Some(id) = ids.iter().find(|id| !seen.insert(**id)) {
+        let mut error = ValidationError::new("duplicate");
+        error.message = Some("Contains duplicate ids".into());
+        error.add_param("id".into(), id);
+        return Err(error);
+    }
+    Ok(())
+}
+
+/// Body of `POST /labels/:id/attach`.
+#[derive(Debug, Serialize, Deserialize, Clone, Eq, PartialEq, Validate)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct AttachLabel {
+    #[validate(length(
+        min = 1,
+        max = 100,
+        code = "todo_count",
+        message = "Between 1 and 100 todos"
+    ))]
+    #[validate(custom = "validate_unique_ids")]
+    pub todo_ids: Vec<TodoId>,
+}
+
+/// Outcome of `attach_label`, with the ids of `not_found` in the order they were given.
+#[derive(Debug, Serialize, Deserialize, Clone, Eq, PartialEq)]
+pub struct AttachedLabel {
+    pub attached: u64,
+    pub not_found: Vec<TodoId>,
+}
+
+#[derive(Clone)]
+pub struct TodoRepositoryForDb {
+    pool: PgPool,
+    owner: OwnerId,
+    default_label: Option<LabelId>,
+    max_labels: usize,
+    max_joined_labels: usize,
+    slow_query: Duration,
+    /// Instant `find` and `all` give up at, set per request by `with_deadline`.
+    deadline: Option<tokio::time::Instant>,
+}
+
+/// Query of `TodoRepositoryForDb::all`, its parameters are bound by `bind_all_todos`.
+fn all_todos_sql(query: &TodoQuery) -> String {
+    let labels = if query.skip_labels {
+        "'[]'::json".to_string()
+    } else {
+        format!(
+            r#"(
+            SELECT {} FROM todo_labels t1
+            JOIN labels on labels.id = t1.label_id
+            WHERE t1.todo_id = todos.id
+        )"#,
+            LABELS_JSON_AGG
+        )
+    };
+    format!(
+        r#"
+        SELECT todos.*, {} as labels FROM todos
+        WHERE todos.owner_id = $3 AND ($1 OR NOT todos.archived)
+        AND ($2::text IS NULL OR todos.text ILIKE $2)
+        AND ($4::boolean IS NULL OR todos.completed = $4)
+        AND (cardinality($5) = 0
+            OR todos.id IN (SELECT todo_id FROM todo_labels WHERE label_id = ANY($5)))
+        AND (NOT $6 OR NOT EXISTS (SELECT 1 FROM todo_labels WHERE todo_id = todos.id))
+        ORDER BY {};"#,
+        labels,
+        query.order_by()
+    )
+}
+
+/// Join rows of the todos of `all_todos_sql`, one per label, for `stream_all`.
+fn all_todo_rows_sql(query: &TodoQuery) -> String {
+    format!(
+        r#"
+        SELECT todos.*, labels.id as label_id, labels.name as label_name FROM todos
+        LEFT OUTER JOIN todo_labels t1 on todos.id = t1.todo_id
+        LEFT OUTER JOIN labels on labels.id = t1.label_id
+        WHERE todos.owner_id = $3 AND ($1 OR NOT todos.archived)
+        AND ($2::text IS NULL OR todos.text ILIKE $2)
+        AND ($4::boolean IS NULL OR todos.completed = $4)
+        AND (cardinality($5) = 0
+            OR todos.id IN (SELECT todo_id FROM todo_labels WHERE label_id = ANY($5)))
+        AND (NOT $6 OR NOT EXISTS (SELECT 1 FROM todo_labels WHERE todo_id = todos.id))
+        ORDER BY {};"#,
+        query.order_by()
+    )
+}
+
+/// Binds `include_archived`, the `ILIKE` pattern, the owner, `completed`, the label ids and
+/// `unlabeled` of `query` in the order `all_todos_sql` and `all_todo_rows_sql` number them.
+fn bind_all_todos<'q, O>(
+    sql: QueryAs<'q, Postgres, O, PgArguments>,
+    query: &TodoQuery,
+    owner: OwnerId,
+) -> QueryAs<'q, Postgres, O, PgArguments> {
+    sql.bind(query.include_archived)
+        .bind(query.q.as_deref().map(like_pattern))
+        .bind(owner)
+        .bind(query.completed)
+        .bind(query.label_ids.clone())
+        .bind(query.unlabeled)
+}
+
+impl TodoRepositoryForDb {
+    pub fn new(pool: PgPool) -> Self {
+        Self {
+            pool,
+            owner: OwnerId::default(),
+            default_label: None,
+            max_labels: DEFAULT_MAX_LABELS_PER_TODO,
+            max_joined_labels: DEFAULT_MAX_JOINED_LABELS,
+            slow_query: Duration::from_millis(DEFAULT_SLOW_QUERY_MS),
+            deadline: None,
+        }
+    }
+
+    pub fn with_default_label(mut self, default_label: Option<LabelId>) -> Self {
+        self.default_label = default_label;
+        self
+    }
+
+    pub fn with_max_labels(mut self, max_labels: usize) -> Self {
+        self.max_labels = max_labels;
+        self
+    }
+
+    pub fn with_max_joined_labels(mut self, max_joined_labels: usize) -> Self {
+        self.max_joined_labels = max_joined_labels;
+        self
+    }
+
+    /// Duration over which `create`, `find`, `all`, `update` and `delete` are logged as slow.
+    pub fn with_slow_query(mut self, slow_query: Duration) -> Self {
+        self.slow_query = slow_query;
+        self
+    }
+
+    /// `find` within the transaction on `conn`, with the row of the todo locked until it ends.
+    async fn find_for_update(
+        &self,
+        conn: &mut PgConnection,
+        id: TodoId,
+    ) -> anyhow::Result<TodoEntity> {
+        let row = sqlx::query_as::<_, TodoWithLabelsFromRow>(&find_todo_sql("FOR UPDATE OF todos"))
+            .bind(id)
+            .bind(self.owner)
+            .bind(self.max_joined_labels as i64 + 1)
+            .fetch_optional(conn)
+            .await
+            .context("find todo")?
+            .ok_or(RepositoryError::NotFound(id.into()))?;
+        Ok(row.into_entity().cap_labels(self.max_joined_labels))
+    }
+
+    /// Join rows of the todos `all` returns, fetched as they come instead of all at once;
+    /// [`fold_entity_stream`] turns them into todos. Meant for exports of the whole table.
+    pub fn stream_all(
+        &self,
+        query: TodoQuery,
+    ) -> impl Stream<Item = anyhow::Result<TodoWithLabelFromRow>> + Send + 'static {
+        let pool = self.pool.clone();
+        let owner = self.owner;
+        try_stream! {
+            let sql = all_todo_rows_sql(&query);
+            let rows = sqlx::query_as::<_, TodoWithLabelFromRow>(&sql);
+            let mut rows = bind_all_todos(rows, &query, owner).fetch(&pool);
+            while let Some(row) = rows.try_next().await.context("stream todos")? {
+                yield row;
+            }
+        }
+    }
+}
+
+/// The todo `$1` of the owner `$2` with `$3` of its labels at most, `lock` ending the query.
+fn find_todo_sql(lock: &str) -> String {
+    format!(
+        r#"
+    SELECT todos.*, (
+        SELECT {} FROM (
+            SELECT labels.* FROM todo_labels t1
+            JOIN labels on labels.id = t1.label_id
+            WHERE t1.todo_id = todos.id
+            ORDER BY labels.id LIMIT $3
+        ) labels
+    ) as labels FROM todos
+    WHERE todos.id = $1 AND todos.owner_id = $2 {};"#,
+        LABELS_JSON_AGG, lock
+    )
+}
+
+impl OwnerScoped for TodoRepositoryForDb {
+    fn scoped(&self, owner: OwnerId) -> Self {
+        Self {
+            owner,
+            ..self.clone()
+        }
+    }
+
+    fn with_deadline(&self, deadline: tokio::time::Instant) -> Self {
+        Self {
+            deadline: Some(deadline),
+            ..self.clone()
+        }
+    }
+}
+
+#[async_trait]
+impl TodoRepository for TodoRepositoryForDb {
+    async fn create(&self, payload: CreateTodo) -> anyhow::Result<TodoEntity> {
+        timed("todo.create", self.slow_query, async move {
+            let mut tx = self.pool.begin().await.context("create todo")?;
+            let row = sqlx::query_as::<_, TodoFromRow>(
+                r#"INSERT INTO todos (text, completed, owner_id, due_date, priority) VALUES ($1, false, $2, $3, $4) RETURNING *;"#,
+            )
+            .bind(payload.text.clone())
+            .bind(self.owner)
+            .bind(payload.due_date)
+            .bind(payload.priority)
+            .fetch_one(&mut tx)
+            .await
+            .context("create todo")?;
+
+            let mut label_ids = payload.labels;
+            for name in unique_label_names(payload.label_names) {
+                let existing = sqlx::query_as::<_, Label>(
+                    r#"SELECT * FROM labels WHERE lower(name) = lower($1) AND owner_id = $2 ORDER BY id LIMIT 1"#,
+                )
+                .bind(name.clone())
+                .bind(self.owner)
+                .fetch_optional(&mut tx)
+                .await
+                .context("create todo")?;
+                let label = match existing {
+                    Some(label) => label,
+                    None => {
+                        sqlx::query_as::<_, Label>(
+                            r#"INSERT INTO labels (name, owner_id) VALUES ($1, $2) RETURNING *"#,
+                        )
+                        .bind(name)
+                        .bind(self.owner)
+                        .fetch_one(&mut tx)
+                        .await
+                        .context("create todo")?
+                    }
+                };
+                label_ids.push(label.id);
+            }
+
+            let requested = unique_label_ids(label_ids);
+            let label_ids = labels_or_default(requested.clone(), self.default_label);
+            check_label_count(&label_ids, self.max_labels)?;
+            let labels = sqlx::query_as::<_, Label>(INSERT_TODO_LABELS)
+                .bind(row.id)
+                .bind(label_ids)
+                .bind(self.owner)
+                .fetch_all(&mut tx)
+                .await
+                .context("create todo")?;
+            check_labels_found(&requested, &labels)?;
+            tx.commit().await.context("create todo")?;
+
+            Ok(row.into_entity(labels))
+        })
+        .await
+    }
+
+    async fn find(&self, id: TodoId) -> anyhow::Result<TodoEntity> {
+        let find = timed("todo.find", self.slow_query, async move {
+            // one label over the cap tells whether some were left out
+            let row = sqlx::query_as::<_, TodoWithLabelsFromRow>(&find_todo_sql(""))
+                .bind(id)
+                .bind(self.owner)
+                .bind(self.max_joined_labels as i64 + 1)
+                .fetch_optional(&self.pool)
+                .await
+                .context("find todo")?
+                .ok_or(RepositoryError::NotFound(id.into()))?;
+
+            Ok(row.into_entity().cap_labels(self.max_joined_labels))
+        });
+        until_deadline(self.deadline, find).await
+    }
+
+    async fn exists(&self, id: TodoId) -> anyhow::Result<bool> {
+        let (exists,) = sqlx::query_as::<_, (bool,)>(
+            r#"SELECT EXISTS(SELECT 1 FROM todos WHERE id = $1 AND owner_id = $2)"#,
+        )
+        .bind(id)
+        .bind(self.owner)
+        .fetch_one(&self.pool)
+        .await
+        .context("check todo exists")?;
+        Ok(exists)
+    }
+
+    async fn find_many(&self, ids: &[TodoId]) -> anyhow::Result<Vec<TodoEntity>> {
+        let find_many = timed("todo.find_many", self.slow_query, async move {
+            let items = sqlx::query_as::<_, TodoWithLabelFromRow>(
+                r#"
+            SELECT todos.*, labels.id as label_id, labels.name as label_name FROM todos
+            LEFT OUTER JOIN todo_labels t1 on todos.id = t1.todo_id
+            LEFT OUTER JOIN labels on labels.id = t1.label_id
+            WHERE todos.id = ANY($1) AND todos.owner_id = $2;"#,
+            )
+            .bind(ids)
+            .bind(self.owner)
+            .fetch_all(&self.pool)
+            .await
+            .context("find todos")?;
+
+            Ok(in_order_of(ids, fold_entities(items)))
+        });
+        until_deadline(self.deadline, find_many).await
+    }
+
+    async fn all(&self, query: TodoQuery) -> anyhow::Result<Vec<TodoEntity>> {
+        let all = timed("todo.all", self.slow_query, async move {
+            let sql = all_todos_sql(&query);
+            let rows = bind_all_todos(
+                sqlx::query_as::<_, TodoWithLabelsFromRow>(&sql),
+                &query,
+                self.owner,
+            )
+            .fetch_all(&self.pool)
+            .await
+            .context("list todos")?;
+
+            Ok(rows
+                .into_iter()
+                .map(TodoWithLabelsFromRow::into_entity)
+                .collect())
+        });
+        until_deadline(self.deadline, all).await
+    }
+
+    async fn update(&self, id: TodoId, payload: UpdateTodo) -> anyhow::Result<TodoEntity> {
+        timed("todo.update", self.slow_query, async move {
+            let labels = payload.labels.map(unique_label_ids);
+            if let Some(labels) = &labels {
+                check_label_count(labels, self.max_labels)?;
+            }
+            let mut tx = self.pool.begin().await.context("update todo")?;
+            let old_todo = self
+                .find_for_update(&mut tx, id)
+                .await
+                .context("update todo")?;
+            let completed = payload.completed.unwrap_or(old_todo.completed);
+            let precondition = payload.if_updated_at;
+            let row = sqlx::query_as::<_, TodoFromRow>(
+                r#"UPDATE todos SET text = $1, completed = $2, completed_at = $3, archived = $4, due_date = $5, priority = $6, updated_at = now() WHERE id = $7 AND owner_id = $8 AND ($9::timestamptz IS NULL OR updated_at = $9) RETURNING *"#,
+            )
+            .bind(payload.text.unwrap_or(old_todo.text.clone()))
+            .bind(completed)
+            .bind(next_completed_at(&old_todo, completed, Utc::now()))
+            .bind(payload.archived.unwrap_or(old_todo.archived))
+            .bind(payload.due_date.apply(old_todo.due_date))
+            .bind(payload.priority.apply(old_todo.priority))
+            .bind(id)
+            .bind(self.owner)
+            .bind(precondition)
+            .fetch_optional(&mut tx)
+            .await
+            .context("update todo")?
+            .ok_or_else(|| missing_update(id, precondition))?;
+
+            let labels_truncated = labels.is_none() && old_todo.labels_truncated;
+            let labels = match labels {
+                Some(labels) => {
+                    sqlx::query(r#"DELETE FROM todo_labels WHERE todo_id = $1"#)
+                        .bind(id)
+                        .execute(&mut tx)
+                        .await
+                        .context("update todo")?;
+                    let inserted = sqlx::query_as::<_, Label>(INSERT_TODO_LABELS)
+                        .bind(id)
+                        .bind(&labels)
+                        .bind(self.owner)
+                        .fetch_all(&mut tx)
+                        .await
+                        .context("update todo")?;
+                    check_labels_found(&labels, &inserted)?;
+                    inserted
+                }
+                None => old_todo.labels.clone(),
+            };
+            let todo = TodoEntity {
+                labels_truncated,
+                ..row.into_entity(labels)
+            };
+            for change in todo_changes(&old_todo, &todo) {
+                sqlx::query(
+                    r#"INSERT INTO todo_history (todo_id, changed_at, field, old, new) VALUES ($1, $2, $3, $4, $5)"#,
+                )
+                .bind(id)
+                .bind(change.changed_at)
+                .bind(change.field)
+                .bind(change.old)
+                .bind(change.new)
+                .execute(&mut tx)
+                .await
+                .context("update todo")?;
+            }
+            tx.commit().await.context("update todo")?;
+
+            Ok(todo)
+        })
+        .await
+    }
+
+    async fn delete(&self, id: TodoId) -> anyhow::Result<()> {
+        timed("todo.delete", self.slow_query, async move {
+            let mut tx = self.pool.begin().await.context("delete todo")?;
+            sqlx::query(r#"DELETE FROM todo_history WHERE todo_id = $1"#)
+                .bind(id)
+                .execute(&mut tx)
+                .await
+                .context("delete todo")?;
+            sqlx::query(r#"DELETE FROM todo_labels WHERE todo_id = $1"#)
+                .bind(id)
+                .execute(&mut tx)
+                .await
+                .context("delete todo")?;
+
+            // dropping the transaction rolls back the deletes above when the todo is not ours
+            let deleted = sqlx::query(r#"DELETE FROM todos WHERE id = $1 AND owner_id = $2"#)
+                .bind(id)
+                .bind(self.owner)
+                .execute(&mut tx)
+                .await
+                .context("delete todo")?;
+            if deleted.rows_affected() == 0 {
+                return Err(RepositoryError::NotFound(id.into()).into());
+            }
+            sqlx::query(
+                r#"INSERT INTO todo_tombstones (todo_id, owner_id) VALUES ($1, $2) ON CONFLICT (todo_id) DO UPDATE SET owner_id = $2, deleted_at = now()"#,
+            )
+            .bind(id)
+            .bind(self.owner)
+            .execute(&mut tx)
+            .await
+            .context("delete todo")?;
+            tx.commit().await.context("delete todo")?;
+
+            Ok(())
+        })
+        .await
+    }
+
+    async fn touch(&self, id: TodoId) -> anyhow::Result<TodoEntity> {
+        let row = sqlx::query_as::<_, TodoWithLabelsFromRow>(&format!(
+            r#"
+        WITH touched AS (
+            UPDATE todos SET updated_at = now() WHERE id = $1 AND owner_id = $2 RETURNING *
+        )
+        SELECT touched.*, (
+            SELECT {} FROM todo_labels t1
+            JOIN labels on labels.id = t1.label_id
+            WHERE t1.todo_id = touched.id
+        ) as labels FROM touched;"#,
+            LABELS_JSON_AGG
+        ))
+        .bind(id)
+        .bind(self.owner)
+        .fetch_optional(&self.pool)
+        .await
+        .context("touch todo")?
+        .ok_or(RepositoryError::NotFound(id.into()))?;
+
+        Ok(row.into_entity())
+    }
+
+    async fn duplicate(&self, id: TodoId) -> anyhow::Result<TodoEntity> {
+        let mut tx = self.pool.begin().await.context("duplicate todo")?;
+        let row = sqlx::query_as::<_, TodoFromRow>(
+            r#"INSERT INTO todos (text, completed, owner_id, due_date, priority) SELECT text, false, owner_id, due_date, priority FROM todos WHERE id = $1 AND owner_id = $2 RETURNING *"#,
+        )
+        .bind(id)
+        .bind(self.owner)
+        .fetch_optional(&mut tx)
+        .await
+        .context("duplicate todo")?
+        .ok_or(RepositoryError::NotFound(id.into()))?;
+        let labels = sqlx::query_as::<_, Label>(COPY_TODO_LABELS)
+            .bind(row.id)
+            .bind(id)
+            .fetch_all(&mut tx)
+            .await
+            .context("duplicate todo")?;
+        tx.commit().await.context("duplicate todo")?;
+
+        Ok(row.into_entity(labels))
+    }
+
+    async fn history(&self, id: TodoId) -> anyhow::Result<Vec<TodoChange>> {
+        if !self.exists(id).await.context("read todo history")? {
+            return Err(RepositoryError::NotFound(id.into()).into());
+        }
+        let changes = sqlx::query_as::<_, TodoChange>(
+            r#"SELECT changed_at, field, old, new FROM todo_history WHERE todo_id = $1 ORDER BY changed_at, id"#,
+        )
+        .bind(id)
+        .fetch_all(&self.pool)
+        .await
+        .context("read todo history")?;
+        Ok(changes)
+    }
+
+    async fn reorder(&self, ids: Vec<TodoId>) -> anyhow::Result<()> {
+        let mut tx = self.pool.begin().await.context("reorder todos")?;
+        let found = sqlx::query_as::<_, (TodoId,)>(
+            r#"SELECT id FROM todos WHERE owner_id = $1 AND id = ANY($2)"#,
+        )
+        .bind(self.owner)
+        .bind(&ids)
+        .fetch_all(&mut tx)
+        .await
+        .context("reorder todos")?;
+        if let Some(id) = ids.iter().find(|id| !found.contains(&(**id,))) {
+            return Err(RepositoryError::NotFound((*id).into()).into());
+        }
+        sqlx::query(
+            r#"UPDATE todos SET position = NULL WHERE owner_id = $1 AND position IS NOT NULL AND NOT (id = ANY($2))"#,
+        )
+        .bind(self.owner)
+        .bind(&ids)
+        .execute(&mut tx)
+        .await
+        .context("reorder todos")?;
+        sqlx::query(
+            r#"
+        UPDATE todos SET position = ordered.position
+        FROM unnest($2) WITH ORDINALITY AS ordered(id, position)
+        WHERE todos.id = ordered.id AND todos.owner_id = $1"#,
+        )
+        .bind(self.owner)
+        .bind(&ids)
+        .execute(&mut tx)
+        .await
+        .context("reorder todos")?;
+        tx.commit().await.context("reorder todos")?;
+
+        Ok(())
+    }
+
+    async fn changed_since(&self, since: DateTime<Utc>) -> anyhow::Result<Vec<SyncedTodo>> {
+        let items = sqlx::query_as::<_, TodoWithLabelFromRow>(
+            r#"
+        SELECT todos.*, labels.id as label_id, labels.name as label_name FROM todos
+        LEFT OUTER JOIN todo_labels t1 on todos.id = t1.todo_id
+        LEFT OUTER JOIN labels on labels.id = t1.label_id
+        WHERE todos.owner_id = $1 AND todos.updated_at > $2;"#,
+        )
+        .bind(self.owner)
+        .bind(since)
+        .fetch_all(&self.pool)
+        .await
+        .context("list changed todos")?;
+        let tombstones = sqlx::query_as::<_, (TodoId, DateTime<Utc>)>(
+            r#"SELECT todo_id, deleted_at FROM todo_tombstones WHERE owner_id = $1 AND deleted_at > $2"#,
+        )
+        .bind(self.owner)
+        .bind(since)
+        .fetch_all(&self.pool)
+        .await
+        .context("list changed todos")?;
+
+        Ok(synced_todos(fold_entities(items), tombstones))
+    }
+
+    async fn search(&self, criteria: TodoSearchCriteria) -> anyhow::Result<TodoSearchResult> {
+        let mut count_query = QueryBuilder::new(r#"SELECT count(*) FROM todos"#);
+        push_search_conditions(&mut count_query, self.owner, &criteria);
+        let (total,) = count_query
+            .build_query_as::<(i64,)>()
+            .fetch_one(&self.pool)
+            .await
+            .context("search todos")?;
+
+        let mut query = QueryBuilder::new(
+            r#"
+        SELECT todos.*, labels.id as label_id, labels.name as label_name FROM todos
+        LEFT OUTER JOIN todo_labels t1 on todos.id = t1.todo_id
+        LEFT OUTER JOIN labels on labels.id = t1.label_id
+        WHERE todos.id IN (SELECT todos.id FROM todos"#,
+        );
+        push_search_conditions(&mut query, self.owner, &criteria);
+        query
+            .push(" ORDER BY ")
+            .push(criteria.sort.order_by())
+            .push(" LIMIT ")
+            .push_bind(criteria.page_size)
+            .push(" OFFSET ")
+            .push_bind(criteria.offset())
+            .push(") ORDER BY ")
+            .push(criteria.sort.order_by());
+        let items = query
+            .build_query_as::<TodoWithLabelFromRow>()
+            .fetch_all(&self.pool)
+            .await
+            .context("search todos")?;
+
+        Ok(TodoSearchResult {
+            items: fold_entities(items),
+            total,
+            page: criteria.page,
+            page_size: criteria.page_size,
+        })
+    }
+
+    async fn search_ranked(
+        &self,
+        q: &str,
+        include_archived: bool,
+    ) -> anyhow::Result<Vec<TodoEntity>> {
+        // `%` is what `todos_text_trgm_idx` serves, it compares with the threshold set here
+        let mut tx = self.pool.begin().await.context("rank todos")?;
+        sqlx::query(r#"SELECT set_config('pg_trgm.similarity_threshold', $1, true)"#)
+            .bind(SIMILARITY_THRESHOLD.to_string())
+            .execute(&mut tx)
+            .await
+            .context("rank todos")?;
+        let items = sqlx::query_as::<_, TodoWithLabelFromRow>(RANKED_TODOS_SQL)
+            .bind(q)
+            .bind(include_archived)
+            .bind(self.owner)
+            .fetch_all(&mut tx)
+            .await
+            .context("rank todos")?;
+        tx.commit().await.context("rank todos")?;
+
+        Ok(fold_entities(items))
+    }
+
+    async fn set_completed_all(&self, filter: TodoFilter, completed: bool) -> anyhow::Result<u64> {
+        let result = sqlx::query(
+            r#"
+        UPDATE todos SET completed = $1, completed_at = CASE WHEN $1 THEN now() END, updated_at = now()
+        WHERE owner_id = $3 AND completed <> $1 AND NOT archived
+        AND ($2 IS NULL OR id IN (SELECT todo_id FROM todo_labels WHERE label_id = $2));"#,
+        )
+        .bind(completed)
+        .bind(filter.label_id)
+        .bind(self.owner)
+        .execute(&self.pool)
+        .await
+        .context("complete todos")?;
+
+        Ok(result.rows_affected())
+    }
+
+    async fn attach_label(
+        &self,
+        label: LabelId,
+        ids: Vec<TodoId>,
+    ) -> anyhow::Result<AttachedLabel> {
+        timed("todo.attach_label", self.slow_query, async move {
+            let mut tx = self.pool.begin().await.context("attach label")?;
+            sqlx::query(r#"SELECT id FROM labels WHERE id = $1 AND owner_id = $2"#)
+                .bind(label)
+                .bind(self.owner)
+                .fetch_optional(&mut tx)
+                .await
+                .context("attach label")?
+                .ok_or(RepositoryError::NotFound(label.into()))?;
+            let found = sqlx::query_as::<_, (TodoId,)>(
+                r#"SELECT id FROM todos WHERE owner_id = $1 AND id = ANY($2)"#,
+            )
+            .bind(self.owner)
+            .bind(&ids)
+            .fetch_all(&mut tx)
+            .await
+            .context("attach label")?;
+            let found: Vec<TodoId> = found.into_iter().map(|(id,)| id).collect();
+            let not_found = ids.into_iter().filter(|id| !found.contains(id)).collect();
+
+            let attached = sqlx::query_as::<_, (TodoId,)>(
+                r#"
+            INSERT INTO todo_labels (todo_id, label_id) SELECT id, $2 FROM unnest($1) AS t(id)
+            ON CONFLICT DO NOTHING RETURNING todo_id"#,
+            )
+            .bind(&found)
+            .bind(label)
+            .fetch_all(&mut tx)
+            .await
+            .context("attach label")?;
+            let attached: Vec<TodoId> = attached.into_iter().map(|(id,)| id).collect();
+            let over = sqlx::query(
+                r#"
+            SELECT todo_id FROM todo_labels WHERE todo_id = ANY($1)
+            GROUP BY todo_id HAVING COUNT(*) > $2 LIMIT 1"#,
+            )
+            .bind(&attached)
+            .bind(self.max_labels as i64)
+            .fetch_optional(&mut tx)
+            .await
+            .context("attach label")?;
+            if over.is_some() {
+                return Err(RepositoryError::TooManyLabels(self.max_labels).into());
+            }
+            sqlx::query(r#"UPDATE todos SET updated_at = now() WHERE id = ANY($1)"#)
+                .bind(&attached)
+                .execute(&mut tx)
+                .await
+                .context("attach label")?;
+            tx.commit().await.context("attach label")?;
+
+            Ok(AttachedLabel {
+                attached: attached.len() as u64,
+                not_found,
+            })
+        })
+        .await
+    }
+
+    async fn summary(&self, filter: TodoFilter) -> anyhow::Result<TodoSummary> {
+        let (total, completed) = sqlx::query_as::<_, (i64, i64)>(
+            r#"
+        SELECT count(*), count(*) FILTER (WHERE completed) FROM todos
+        WHERE owner_id = $1 AND NOT archived
+        AND ($2 IS NULL OR id IN (SELECT todo_id FROM todo_labels WHERE label_id = $2));"#,
+        )
+        .bind(self.owner)
+        .bind(filter.label_id)
+        .fetch_one(&self.pool)
+        .await
+        .context("summarize todos")?;
+
+        Ok(TodoSummary::new(total, completed))
+    }
+
+    async fn last_modified(&self) -> anyhow::Result<DateTime<Utc>> {
+        let (modified_at,) = sqlx::query_as::<_, (DateTime<Utc>,)>(
+            r#"SELECT coalesce((SELECT modified_at FROM todos_modified WHERE owner_id = $1), 'epoch')"#,
+        )
+        .bind(self.owner)
+        .fetch_one(&self.pool)
+        .await
+        .context("read todos last modified")?;
+        Ok(modified_at)
+    }
+}
+
+#[cfg(test)]
+#[cfg(feature = "database-test")]
+mod test {
+    use super::*;
+    use crate::repositories::label::{
+        CreateLabel, LabelRepository, LabelRepositoryForDb, UpdateLabel,
+    };
+    use crate::repositories::{reset_database, test_id};
+    use sqlx::postgres::PgPoolOptions;
+
+    #[test]
+    fn fold_entities_test() {
+        let now = Utc::now();
+        let label_1 = Label {
+            id: LabelId(test_id(1)),
+            name: "Label 1".to_string(),
+        };
+        let label_2 = Label {
+            id: LabelId(test_id(2)),
+            name: "Label 2".to_string(),
+        };
+
+        let rows = vec![
+            TodoWithLabelFromRow {
+                id: TodoId(test_id(1)),
+                text: "Todo 1".to_string(),
+                completed: false,
+                completed_at: None,
+                archived: false,
+                due_date: None,
+                priority: None,
+                position: None,
+                created_at: now,
+                updated_at: now,
+                label_id: Some(label_2.id),
+                label_name: Some(label_2.name.clone()),
+            },
+            TodoWithLabelFromRow {
+                id: TodoId(test_id(1)),
+                text: "Todo 1".to_string(),
+                completed: false,
+                completed_at: None,
+                archived: false,
+                due_date: None,
+                priority: None,
+                position: None,
+                created_at: now,
+                updated_at: now,
+                label_id: Some(label_1.id),
+                label_name: Some(label_1.name.clone()),
+            },
+            TodoWithLabelFromRow {
+                id: TodoId(test_id(2)),
+                text: "Todo 2".to_string(),
+                completed: false,
+                completed_at: None,
+                archived: false,
+                due_date: None,
+                priority: None,
+                position: None,
+                created_at: now,
+                updated_at: now,
+                label_id: Some(label_1.id),
+                label_name: Some(label_1.name.clone()),
+            },
+        ];
+        let res = fold_entities(rows);
+        assert_eq!(
+            res,
+            vec![
+                TodoEntity {
+                    id: TodoId(test_id(1)),
+                    text: "Todo 1".to_string(),
+                    completed: false,
+                    completed_at: None,
+                    archived: false,
+                    due_date: None,
+                    priority: None,
+                    position: None,
+                    created_at: now,
+                    updated_at: now,
+                    labels: vec![label_1.clone(), label_2.clone()],
+                    labels_truncated: false,
+                },
+                TodoEntity {
+                    id: TodoId(test_id(2)),
+                    text: "Todo 2".to_string(),
+                    completed: false,
+                    completed_at: None,
+                    archived: false,
+                    due_date: None,
+                    priority: None,
+                    position: None,
+                    created_at: now,
+                    updated_at: now,
+                    labels: vec![label_1.clone()],
+                    labels_truncated: false,
+                },
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn crud_scenario() {
+        let (pool, _db) = reset_database().await;
+
+        // label data prepare
+        let label_1 = sqlx::query_as::<_, Label>(
+            r#"INSERT INTO labels ( name, owner_id ) VALUES ( 'test label', 0 ) RETURNING *"#,
+        )
+        .fetch_one(&pool)
+        .await
+        .expect("Failed to insert label data");
+        #[cfg(not(feature = "uuid"))]
+        assert_eq!(LabelId(1), label_1.id);
+
+        let repo = TodoRepositoryForDb::new(pool.clone());
+        let todo_text = "[crud_scenario] text";
+        let modified_at = repo
+            .last_modified()
+            .await
+            .expect("[last_modified] returned Err");
+
+        // create
+        let created = repo
+            .create(CreateTodo::new(
+                todo_text.to_string(),
+                vec![label_1.id, label_1.id],
+            ))
+            .await
+            .expect("[create] returned Err");
+        #[cfg(not(feature = "uuid"))]
+        assert_eq!(TodoId(1), created.id);
+        assert_eq!(created.text, todo_text);
+        assert!(!created.completed);
+        assert_eq!(created.labels, vec![label_1.clone()]);
+        let created_at = repo
+            .last_modified()
+            .await
+            .expect("[last_modified] returned Err");
+        assert!(created_at > modified_at);
+
+        // find
+        let todo = repo.find(created.id).await.expect("[find] returned Err");
+        assert_eq!(created, todo);
+
+        // exists
+        let exists = repo
+            .exists(created.id)
+            .await
+            .expect("[exists] returned Err");
+        assert!(exists);
+
+        // search
+        let result = repo
+            .search(TodoSearchCriteria {
+                q: Some("[CRUD_SCENARIO]".to_string()),
+                completed: Some(false),
+                label_ids: vec![label_1.id],
+                ..Default::default()
+            })
+            .await
+            .expect("[search] returned Err");
+        assert!(result.items.contains(&created));
+        let result = repo
+            .search(TodoSearchCriteria {
+                q: Some("[crud_scenario]".to_string()),
+                completed: Some(true),
+                ..Default::default()
+            })
+            .await
+            .expect("[search] returned Err");
+        assert!(!result.items.contains(&created));
+        let todos = repo
+            .search_ranked("crud_secnario", false)
+            .await
+            .expect("[search_ranked] returned Err");
+        assert!(todos.contains(&created));
+
+        // all
+        let todos = repo
+            .all(TodoQuery::default())
+            .await
+            .expect("[all] returned Err");
+        let todo = todos.iter().find(|todo| todo.id == created.id).unwrap();
+        assert_eq!(created, *todo);
+
+        // update
+        let updated_text = "[crud_scenario] updated text";
+        let todo = repo
+            .update(
+                todo.id,
+                UpdateTodo::new(
+                    Some(updated_text.to_string()),
+                    Some(true),
+                    Some(vec![label_1.id, label_1.id]),
+                ),
+            )
+            .await
+            .expect("[update] returned Err");
+        assert_eq!(created.id, todo.id);
+        assert_eq!(todo, repo.find(todo.id).await.expect("[find] returned Err"));
+        assert_eq!(todo.text, updated_text);
+        assert_eq!(todo.labels, vec![label_1.clone()]);
+        assert!(todo.completed);
+        assert!(todo.completed_at.is_some());
+
+        // uncomplete
+        let todo = repo
+            .update(todo.id, UpdateTodo::new(None, Some(false), None))
+            .await
+            .expect("[update] returned Err");
+        assert!(!todo.completed);
+        assert!(todo.completed_at.is_none());
+
+        // archive
+        let todo = repo
+            .update(todo.id, UpdateTodo::archive(true))
+            .await
+            .expect("[archive] returned Err");
+        assert!(todo.archived);
+        let todos = repo
+            .all(TodoQuery::default())
+            .await
+            .expect("[all] returned Err");
+        assert!(todos.iter().all(|t| t.id != todo.id));
+        let todos = repo
+            .all(TodoQuery {
+                include_archived: true,
+                ..Default::default()
+            })
+            .await
+            .expect("[all] returned Err");
+        assert!(todos.iter().any(|t| t.id == todo.id));
+        let todos = repo
+            .all(TodoQuery {
+                include_archived: true,
+                q: Some("UPDATED TEXT".to_string()),
+                ..Default::default()
+            })
+            .await
+            .expect("[all] returned Err");
+        assert!(todos.iter().any(|t| t.id == todo.id));
+
+        // delete
+        repo.delete(todo.id).await.expect("[delete] returned Err");
+        let err = repo
+            .find(created.id)
+            .await
+            .expect_err("[find] returned Ok for a deleted todo");
+        assert!(matches!(
+            err.downcast_ref::<RepositoryError>(),
+            Some(RepositoryError::NotFound(_))
+        ));
+        let exists = repo
+            .exists(created.id)
+            .await
+            .expect("[exists] returned Err");
+        assert!(!exists);
+        let res = repo.delete(created.id).await;
+        assert!(res.is_err());
+
+        let todo_rows = sqlx::query(r#"SELECT * FROM todos WHERE id = $1"#)
+            .bind(todo.id)
+            .fetch_all(&pool)
+            .await
+            .expect("[delete] todo_labels fetch error");
+        assert_eq!(todo_rows.len(), 0);
+    }
+
+    #[tokio::test]
+    async fn nullable_fields_scenario() {
+        let (pool, _db) = reset_database().await;
+        let repo = TodoRepositoryForDb::new(pool.clone());
+        let due_date: DateTime<Utc> = "2026-11-01T09:00:00Z".parse().unwrap();
+
+        let todo = repo
+            .create(
+                CreateTodo::new("[nullable_fields_scenario] text".to_string(), vec![])
+                    .with_due_date(Some(due_date))
+                    .with_priority(Some(3)),
+            )
+            .await
+            .expect("[create] returned Err");
+        assert_eq!((Some(due_date), Some(3)), (todo.due_date, todo.priority));
+
+        let todo = repo
+            .update(todo.id, UpdateTodo::archive(false))
+            .await
+            .expect("[keep] returned Err");
+        assert_eq!((Some(due_date), Some(3)), (todo.due_date, todo.priority));
+
+        let todo = repo
+            .update(
+                todo.id,
+                UpdateTodo::default()
+                    .with_due_date(Patch::Null)
+                    .with_priority(Patch::Value(5)),
+            )
+            .await
+            .expect("[clear] returned Err");
+        assert_eq!((None, Some(5)), (todo.due_date, todo.priority));
+        assert_eq!(todo, repo.find(todo.id).await.expect("[find] returned Err"));
+
+        repo.delete(todo.id).await.expect("[delete] returned Err");
+    }
+
+    #[tokio::test]
+    async fn recent_first_scenario() {
+        let (pool, _db) = reset_database().await;
+        let repo = TodoRepositoryForDb::new(pool.clone()).scoped(OwnerId(103));
+        let first = repo
+            .create(CreateTodo::new("[recent_first] first".to_string(), vec![]))
+            .await
+            .expect("[create] returned Err");
+        let second = repo
+            .create(CreateTodo::new("[recent_first] second".to_string(), vec![]))
+            .await
+            .expect("[create] returned Err");
+        assert!(first.created_at <= second.created_at);
+
+        let todos = repo
+            .all(TodoQuery::default())
+            .await
+            .expect("[all] returned Err");
+        assert_eq!(vec![second.id, first.id], ids(&todos));
+        let todos = repo
+            .all(TodoQuery {
+                order: SortOrder::Asc,
+                ..Default::default()
+            })
+            .await
+            .expect("[all] returned Err");
+        assert_eq!(vec![first.id, second.id], ids(&todos));
+        let criteria = TodoQuery {
+            order: SortOrder::Asc,
+            page: Some(1),
+            ..Default::default()
+        }
+        .search_criteria()
+        .expect("paged query has criteria");
+        let result = repo.search(criteria).await.expect("[search] returned Err");
+        assert_eq!(vec![first.id, second.id], ids(&result.items));
+
+        // The plan is only meaningful with sequential scans ruled out on a table this small.
+        // Whether the planner still sorts on top depends on its statistics, so only the index
+        // is checked.
+        let mut tx = pool.begin().await.unwrap();
+        sqlx::query("SET LOCAL enable_seqscan = off")
+            .execute(&mut tx)
+            .await
+            .unwrap();
+        let sql = format!("EXPLAIN {}", all_todos_sql(&TodoQuery::default()));
+        let plan: Vec<(String,)> =
+            bind_all_todos(sqlx::query_as(&sql), &TodoQuery::default(), OwnerId(103))
+                .fetch_all(&mut tx)
+                .await
+                .expect("[explain] returned Err");
+        let plan: Vec<String> = plan.into_iter().map(|(line,)| line).collect();
+        assert!(
+            plan.iter()
+                .any(|line| line.contains("todos_owner_id_position_idx")),
+            "{:#?}",
+            plan
+        );
+        tx.rollback().await.unwrap();
+
+        for todo in [first, second] {
+            repo.delete(todo.id).await.expect("[delete] returned Err");
+        }
+    }
+
+    fn ids(todos: &[TodoEntity]) -> Vec<TodoId> {
+        todos.iter().map(|todo| todo.id).collect()
+    }
+
+    #[tokio::test]
+    async fn owner_scenario() {
+        let (pool, _db) = reset_database().await;
+        let repo = TodoRepositoryForDb::new(pool.clone());
+        let alice = repo.scoped(OwnerId(101));
+        let bob = repo.scoped(OwnerId(102));
+
+        let todo = alice
+            .create(
+                CreateTodo::new("[owner_scenario] text".to_string(), vec![])
+                    .with_label_names(vec!["[owner_scenario] label".to_string()]),
+            )
+            .await
+            .expect("[create] returned Err");
+        let (owner_id,) =
+            sqlx::query_as::<_, (OwnerId,)>(r#"SELECT owner_id FROM todos WHERE id = $1"#)
+                .bind(todo.id)
+                .fetch_one(&pool)
+                .await
+                .expect("[create] owner_id fetch error");
+        assert_eq!(OwnerId(101), owner_id);
+
+        assert_eq!(
+            todo,
+            alice.find(todo.id).await.expect("[find] returned Err")
+        );
+        let res = bob.find(todo.id).await;
+        assert!(matches!(
+            res.unwrap_err().downcast_ref::<RepositoryError>(),
+            Some(RepositoryError::NotFound(_))
+        ));
+        let res = bob.update(todo.id, UpdateTodo::archive(true)).await;
+        assert!(matches!(
+            res.unwrap_err().downcast_ref::<RepositoryError>(),
+            Some(RepositoryError::NotFound(_))
+        ));
+        let archived = alice
+            .update(todo.id, UpdateTodo::archive(true))
+            .await
+            .expect("[update] returned Err");
+        let res = bob.delete(todo.id).await;
+        assert!(matches!(
+            res.unwrap_err().downcast_ref::<RepositoryError>(),
+            Some(RepositoryError::NotFound(_))
+        ));
+        assert_eq!(
+            archived,
+            alice.find(todo.id).await.expect("[find] returned Err")
+        );
+        let history = alice
+            .history(todo.id)
+            .await
+            .expect("[history] returned Err");
+        assert_eq!(1, history.len());
+        let todos = bob
+            .all(TodoQuery::default())
+            .await
+            .expect("[all] returned Err");
+        assert!(todos.iter().all(|t| t.id != todo.id));
+
+        alice.delete(todo.id).await.expect("[delete] returned Err");
+    }
+
+    #[tokio::test]
+    async fn foreign_label_scenario() {
+        let (pool, _db) = reset_database().await;
+        let label = LabelRepositoryForDb::new(pool.clone())
+            .scoped(OwnerId(105))
+            .create(CreateLabel::new("[foreign_label] secret".to_string()))
+            .await
+            .expect("[create label] returned Err");
+        let repo = TodoRepositoryForDb::new(pool.clone()).with_default_label(Some(label.id));
+        let owner = repo.scoped(OwnerId(105));
+        let stranger = repo.scoped(OwnerId(104));
+
+        let res = stranger
+            .create(CreateTodo::new(
+                "[foreign_label] text".to_string(),
+                vec![label.id],
+            ))
+            .await;
+        assert!(matches!(
+            res.unwrap_err().downcast_ref::<RepositoryError>(),
+            Some(RepositoryError::NotFound(_))
+        ));
+        let todo = stranger
+            .create(CreateTodo::new("[foreign_label] text".to_string(), vec![]))
+            .await
+            .expect("[create] returned Err");
+        assert!(todo.labels.is_empty());
+        let res = stranger
+            .update(todo.id, UpdateTodo::new(None, None, Some(vec![label.id])))
+            .await;
+        assert!(matches!(
+            res.unwrap_err().downcast_ref::<RepositoryError>(),
+            Some(RepositoryError::NotFound(_))
+        ));
+        let res = stranger
+            .create(CreateTodo::new(
+                "[foreign_label] text".to_string(),
+                vec![LabelId(test_id(-1))],
+            ))
+            .await;
+        assert!(matches!(
+            res.unwrap_err().downcast_ref::<RepositoryError>(),
+            Some(RepositoryError::NotFound(_))
+        ));
+
+        let todo = owner
+            .create(CreateTodo::new("[foreign_label] text".to_string(), vec![]))
+            .await
+            .expect("[create] returned Err");
+        assert_eq!(vec![label], todo.labels);
+    }
+
+    #[tokio::test]
+    async fn label_filter_scenario() {
+        let (pool, _db) = reset_database().await;
+        let repo = TodoRepositoryForDb::new(pool.clone()).scoped(OwnerId(120));
+        let mut todos = vec![];
+        for (text, labels) in [
+            ("[label_filter] Buy milk", vec!["[label_filter] a"]),
+            (
+                "[label_filter] Write report",
+                vec!["[label_filter] a", "[label_filter] b"],
+            ),
+            ("[label_filter] Read report", vec![]),
+        ] {
+            let labels = labels.into_iter().map(str::to_string).collect();
+            let todo = repo
+                .create(CreateTodo::new(text.to_string(), vec![]).with_label_names(labels))
+                .await
+                .expect("[create] returned Err");
+            todos.push(todo);
+        }
+        let label_ids: Vec<LabelId> = todos[1].labels.iter().map(|l| l.id).collect();
+        let mut labeled = vec![todos[0].id, todos[1].id];
+        labeled.sort();
+        let query = |q: Option<&str>, completed: Option<bool>| TodoQuery {
+            q: q.map(str::to_string),
+            completed,
+            label_ids: label_ids.clone(),
+            sort: TodoSort::Id,
+            order: SortOrder::Asc,
+            ..Default::default()
+        };
+
+        // todos with both labels are listed once
+        let found = repo.all(query(None, None)).await.unwrap();
+        assert_eq!(labeled, ids(&found));
+        let found = repo.all(query(Some("report"), Some(false))).await.unwrap();
+        assert_eq!(vec![todos[1].id], ids(&found));
+        let found = repo.all(query(Some("report"), Some(true))).await.unwrap();
+        assert!(found.is_empty());
+
+        let skipped = TodoQuery {
+            skip_labels: true,
+            ..query(None, None)
+        };
+        let found = repo.all(skipped).await.unwrap();
+        assert_eq!(labeled, ids(&found));
+        assert!(found.iter().all(|todo| todo.labels.is_empty()));
+
+        let unlabeled = |q: Option<&str>| TodoQuery {
+            unlabeled: true,
+            label_ids: vec![],
+            ..query(q, None)
+        };
+        let found = repo.all(unlabeled(None)).await.unwrap();
+        assert_eq!(vec![todos[2].id], ids(&found));
+        let found = repo.all(unlabeled(Some("milk"))).await.unwrap();
+        assert!(found.is_empty());
+        let criteria = TodoSearchCriteria {
+            q: Some("report".to_string()),
+            unlabeled: true,
+            ..Default::default()
+        };
+        let result = repo.search(criteria).await.unwrap();
+        assert_eq!(vec![todos[2].id], ids(&result.items));
+
+        for todo in todos {
+            repo.delete(todo.id).await.expect("[delete] returned Err");
+        }
+        sqlx::query(r#"DELETE FROM labels WHERE owner_id = $1"#)
+            .bind(OwnerId(120))
+            .execute(&pool)
+            .await
+            .expect("Failed to clean up label data");
+    }
+
+    #[tokio::test]
+    async fn create_with_label_names_scenario() {
+        let (pool, _db) = reset_database().await;
+        let existing = sqlx::query_as::<_, Label>(
+            r#"INSERT INTO labels ( name, owner_id ) VALUES ( '[label_names] Existing', 0 ) RETURNING *"#,
+        )
+        .fetch_one(&pool)
+        .await
+        .expect("Failed to insert label data");
+
+        let repo = TodoRepositoryForDb::new(pool.clone());
+        let todo = repo
+            .create(
+                CreateTodo::new("[label_names] text".to_string(), vec![]).with_label_names(vec![
+                    "[label_names] existing".to_string(),
+                    "[label_names] New".to_string(),
+                    "[LABEL_NAMES] NEW".to_string(),
+                ]),
+            )
+            .await
+            .expect("[create] returned Err");
+        let mut names: Vec<&str> = todo.labels.iter().map(|l| l.name.as_str()).collect();
+        names.sort();
+        assert_eq!(vec!["[label_names] Existing", "[label_names] New"], names);
+        assert!(todo.labels.contains(&existing));
+        // entities built from the write results match a fresh `find`
+        assert_eq!(todo, repo.find(todo.id).await.expect("[find] returned Err"));
+        let todo = repo
+            .update(todo.id, UpdateTodo::new(None, Some(true), None))
+            .await
+            .expect("[update] returned Err");
+        assert_eq!(todo, repo.find(todo.id).await.expect("[find] returned Err"));
+        let reversed: Vec<LabelId> = todo.labels.iter().rev().map(|l| l.id).collect();
+        let todo = repo
+            .update(todo.id, UpdateTodo::new(None, None, Some(reversed)))
+            .await
+            .expect("[update] returned Err");
+        assert_eq!(todo, repo.find(todo.id).await.expect("[find] returned Err"));
+
+        repo.delete(todo.id).await.expect("[delete] returned Err");
+        for label in todo.labels {
+            sqlx::query(r#"DELETE FROM labels WHERE id = $1"#)
+                .bind(label.id)
+                .execute(&pool)
+                .await
+                .expect("Failed to clean up label data");
+        }
+    }
+
+    #[tokio::test]
+    async fn deadline_scenario() {
+        let (pool, _db) = reset_database().await;
+        // a single connection, to see it back in the pool once the calls are abandoned
+        let todos_pool = PgPoolOptions::new()
+            .max_connections(1)
+            .acquire_timeout(Duration::from_secs(5))
+            .connect_with(pool.connect_options().clone())
+            .await
+            .expect("fail connect database");
+        let repo = TodoRepositoryForDb::new(todos_pool).scoped(OwnerId(121));
+        let todo = repo
+            .create(CreateTodo::new("[deadline] text".to_string(), vec![]))
+            .await
+            .expect("[create] returned Err");
+
+        let mut lock = pool.begin().await.unwrap();
+        sqlx::query("LOCK TABLE todos IN ACCESS EXCLUSIVE MODE")
+            .execute(&mut lock)
+            .await
+            .expect("[lock] returned Err");
+        let started = std::time::Instant::now();
+        let hurried = repo.with_deadline(tokio::time::Instant::now() + Duration::from_millis(100));
+        let res = hurried.find(todo.id).await;
+        assert!(matches!(
+            res.unwrap_err().downcast_ref::<RepositoryError>(),
+            Some(RepositoryError::DeadlineExceeded)
+        ));
+        let res = hurried.all(TodoQuery::default()).await;
+        assert!(matches!(
+            res.unwrap_err().downcast_ref::<RepositoryError>(),
+            Some(RepositoryError::DeadlineExceeded)
+        ));
+        assert!(started.elapsed() < Duration::from_secs(1));
+        lock.rollback().await.unwrap();
+
+        assert_eq!(todo, repo.find(todo.id).await.expect("[find] returned Err"));
+    }
+
+    #[tokio::test]
+    async fn set_completed_all_scenario() {
+        let (pool, _db) = reset_database().await;
+        let label = sqlx::query_as::<_, Label>(
+            r#"INSERT INTO labels ( name, owner_id ) VALUES ( '[set_completed_all] label', 0 ) RETURNING *"#,
+        )
+        .fetch_one(&pool)
+        .await
+        .expect("Failed to insert label data");
+
+        let repo = TodoRepositoryForDb::new(pool.clone());
+        let labeled = repo
+            .create(CreateTodo::new(
+                "[set_completed_all] labeled".to_string(),
+                vec![label.id],
+            ))
+            .await
+            .expect("[create] returned Err");
+        let unlabeled = repo
+            .create(CreateTodo::new(
+                "[set_completed_all] unlabeled".to_string(),
+                vec![],
+            ))
+            .await
+            .expect("[create] returned Err");
+        let filter = TodoFilter {
+            label_id: Some(label.id),
+        };
+
+        let updated = repo
+            .set_completed_all(filter, true)
+            .await
+            .expect("[set_completed_all] returned Err");
+        assert_eq!(1, updated);
+        let todo = repo.find(labeled.id).await.expect("[find] returned Err");
+        assert!(todo.completed);
+        assert!(todo.completed_at.is_some());
+        let todo = repo.find(unlabeled.id).await.expect("[find] returned Err");
+        assert!(!todo.completed);
+
+        // already completed todos are not counted
+        let updated = repo
+            .set_completed_all(filter, true)
+            .await
+            .expect("[set_completed_all] returned Err");
+        assert_eq!(0, updated);
+
+        let updated = repo
+            .set_completed_all(filter, false)
+            .await
+            .expect("[set_completed_all] returned Err");
+        assert_eq!(1, updated);
+        let todo = repo.find(labeled.id).await.expect("[find] returned Err");
+        assert!(!todo.completed);
+        assert!(todo.completed_at.is_none());
+
+        repo.delete(labeled.id)
+            .await
+            .expect("[delete] returned Err");
+        repo.delete(unlabeled.id)
+            .await
+            .expect("[delete] returned Err");
+        sqlx::query(r#"DELETE FROM labels WHERE id = $1"#)
+            .bind(label.id)
+            .execute(&pool)
+            .await
+            .expect("Failed to clean up label data");
+    }
+
+    #[tokio::test]
+    async fn attach_label_scenario() {
+        let (pool, _db) = reset_database().await;
+        let labels = sqlx::query_as::<_, Label>(
+            r#"INSERT INTO labels ( name, owner_id ) VALUES ( '[attach_label] label', 0 ), ( '[attach_label] other', 0 ) RETURNING *"#,
+        )
+        .fetch_all(&pool)
+        .await
+        .expect("Failed to insert label data");
+        let (label, other) = (labels[0].clone(), labels[1].clone());
+
+        let repo = TodoRepositoryForDb::new(pool.clone());
+        let mut todos = vec![];
+        for labels in [vec![label.id], vec![], vec![other.id]] {
+            let todo = repo
+                .create(CreateTodo::new("[attach_label] text".to_string(), labels))
+                .await
+                .expect("[create] returned Err");
+            todos.push(todo.id);
+        }
+        let foreign = repo
+            .scoped(OwnerId(122))
+            .create(CreateTodo::new(
+                "[attach_label] foreign".to_string(),
+                vec![],
+            ))
+            .await
+            .expect("[create] returned Err");
+
+        let ids = vec![
+            todos[0],
+            todos[1],
+            foreign.id,
+            todos[2],
+            TodoId(test_id(-1)),
+        ];
+        let attached = repo
+            .attach_label(label.id, ids)
+            .await
+            .expect("[attach_label] returned Err");
+        assert_eq!(
+            AttachedLabel {
+                attached: 2,
+                not_found: vec![foreign.id, TodoId(test_id(-1))],
+            },
+            attached
+        );
+        let todo = repo.find(todos[1]).await.expect("[find] returned Err");
+        assert_eq!(vec![label.clone()], todo.labels);
+        let todo = repo.find(todos[2]).await.expect("[find] returned Err");
+        let mut both = vec![label.clone(), other.clone()];
+        sort_labels(&mut both);
+        assert_eq!(both, todo.labels);
+        let todo = repo.find(todos[0]).await.expect("[find] returned Err");
+        assert_eq!(vec![label.clone()], todo.labels);
+
+        // over max_labels, nothing is attached
+        let res = repo
+            .clone()
+            .with_max_labels(1)
+            .attach_label(other.id, vec![todos[1]])
+            .await;
+        assert!(matches!(
+            res.unwrap_err().downcast_ref::<RepositoryError>(),
+            Some(RepositoryError::TooManyLabels(1))
+        ));
+        let todo = repo.find(todos[1]).await.expect("[find] returned Err");
+        assert_eq!(vec![label.clone()], todo.labels);
+
+        let res = repo
+            .scoped(OwnerId(122))
+            .attach_label(label.id, vec![foreign.id])
+            .await;
+        assert!(matches!(
+            res.unwrap_err().downcast_ref::<RepositoryError>(),
+            Some(RepositoryError::NotFound(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn legacy_row_scenario() {
+        let (pool, _db) = reset_database().await;
+        // only the columns a row predating the later migrations would have set
+        let (id,) = sqlx::query_as::<_, (TodoId,)>(
+            r#"INSERT INTO todos (text, owner_id) VALUES ('[legacy_row] text', 0) RETURNING id"#,
+        )
+        .fetch_one(&pool)
+        .await
+        .expect("Failed to insert todo data");
+
+        let repo = TodoRepositoryForDb::new(pool.clone());
+        let todo = repo.find(id).await.expect("[find] returned Err");
+        assert_eq!("[legacy_row] text", todo.text);
+        assert!(!todo.completed && !todo.archived);
+        assert_eq!(None, todo.completed_at);
+        assert_eq!(None, todo.due_date);
+        assert_eq!(None, todo.priority);
+        assert_eq!(None, todo.position);
+        assert!(todo.labels.is_empty());
+
+        let all = repo
+            .all(TodoQuery::default())
+            .await
+            .expect("[all] returned Err");
+        assert_eq!(vec![todo], all);
+    }
+
+    #[tokio::test]
+    async fn touch_scenario() {
+        let (pool, _db) = reset_database().await;
+        let repo = TodoRepositoryForDb::new(pool.clone());
+        let created = repo
+            .create(CreateTodo::new("[touch] text".to_string(), vec![]))
+            .await
+            .expect("[create] returned Err");
+
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        let touched = repo.touch(created.id).await.expect("[touch] returned Err");
+        assert!(touched.updated_at > created.updated_at);
+        let updated_at = touched.updated_at;
+        assert_eq!(
+            TodoEntity {
+                updated_at,
+                ..created.clone()
+            },
+            touched
+        );
+        assert_eq!(touched, repo.find(created.id).await.unwrap());
+        assert!(repo
+            .history(created.id)
+            .await
+            .expect("[history] returned Err")
+            .is_empty());
+
+        let res = repo.scoped(OwnerId(124)).touch(created.id).await;
+        assert!(matches!(
+            res.unwrap_err().downcast_ref::<RepositoryError>(),
+            Some(RepositoryError::NotFound(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn conditional_update_scenario() {
+        let (pool, _db) = reset_database().await;
+        let repo = TodoRepositoryForDb::new(pool.clone());
+        let created = repo
+            .create(CreateTodo::new("[conditional] text".to_string(), vec![]))
+            .await
+            .expect("[create] returned Err");
+
+        let updated = repo
+            .update(
+                created.id,
+                UpdateTodo::new(Some("[conditional] matched".to_string()), None, None)
+                    .if_updated_at(created.updated_at),
+            )
+            .await
+            .expect("[update] returned Err");
+        let res = repo
+            .update(
+                created.id,
+                UpdateTodo::new(Some("[conditional] stale".to_string()), None, None)
+                    .if_updated_at(created.updated_at),
+            )
+            .await;
+        assert!(matches!(
+            res.unwrap_err().downcast_ref::<RepositoryError>(),
+            Some(RepositoryError::Stale(_))
+        ));
+        assert_eq!(updated, repo.find(created.id).await.unwrap());
+
+        // deleted while the update waits on its row, without a precondition to be stale for
+        let mut tx = pool.begin().await.unwrap();
+        sqlx::query(r#"DELETE FROM todos WHERE id = $1"#)
+            .bind(created.id)
+            .execute(&mut tx)
+            .await
+            .expect("Failed to delete todo data");
+        let update = tokio::spawn({
+            let repo = repo.clone();
+            async move {
+                repo.update(
+                    created.id,
+                    UpdateTodo::new(Some("[conditional] gone".to_string()), None, None),
+                )
+                .await
+            }
+        });
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        tx.commit().await.unwrap();
+        let res = update.await.unwrap();
+        assert!(matches!(
+            res.unwrap_err().downcast_ref::<RepositoryError>(),
+            Some(RepositoryError::NotFound(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn find_many_scenario() {
+        let (pool, _db) = reset_database().await;
+        let label = sqlx::query_as::<_, Label>(
+            r#"INSERT INTO labels ( name, owner_id ) VALUES ( '[find_many] label', 0 ) RETURNING *"#,
+        )
+        .fetch_one(&pool)
+        .await
+        .expect("Failed to insert label data");
+
+        let repo = TodoRepositoryForDb::new(pool.clone());
+        let mut todos = vec![];
+        for labels in [vec![label.id], vec![], vec![label.id]] {
+            let todo = repo
+                .create(CreateTodo::new("[find_many] text".to_string(), labels))
+                .await
+                .expect("[create] returned Err");
+            todos.push(todo);
+        }
+        let foreign = repo
+            .scoped(OwnerId(123))
+            .create(CreateTodo::new("[find_many] foreign".to_string(), vec![]))
+            .await
+            .expect("[create] returned Err");
+
+        let ids = [
+            todos[2].id,
+            TodoId(test_id(-1)),
+            foreign.id,
+            todos[0].id,
+            todos[2].id,
+        ];
+        let found = repo
+            .find_many(&ids)
+            .await
+            .expect("[find_many] returned Err");
+        assert_eq!(vec![todos[2].clone(), todos[0].clone()], found);
+        assert_eq!(vec![label], found[0].labels);
+
+        let found = repo.find_many(&[]).await.expect("[find_many] returned Err");
+        assert!(found.is_empty());
+    }
+
+    #[tokio::test]
+    async fn history_scenario() {
+        let (pool, _db) = reset_database().await;
+        let repo = TodoRepositoryForDb::new(pool.clone()).scoped(OwnerId(106));
+        let todo = repo
+            .create(CreateTodo::new("[history] text".to_string(), vec![]))
+            .await
+            .expect("[create] returned Err");
+        assert!(repo.history(todo.id).await.unwrap().is_empty());
+
+        repo.update(
+            todo.id,
+            UpdateTodo::new(Some("[history] renamed".to_string()), None, None),
+        )
+        .await
+        .expect("[update] returned Err");
+        repo.update(
+            todo.id,
+            UpdateTodo::new(None, Some(true), None).with_priority(Patch::Value(2)),
+        )
+        .await
+        .expect("[update] returned Err");
+
+        let history = repo.history(todo.id).await.expect("[history] returned Err");
+        let changes: Vec<(&str, serde_json::Value, serde_json::Value)> = history
+            .iter()
+            .map(|change| {
+                (
+                    change.field.as_str(),
+                    change.old.clone(),
+                    change.new.clone(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            vec![
+                ("text", "[history] text".into(), "[history] renamed".into()),
+                ("completed", false.into(), true.into()),
+                ("priority", serde_json::Value::Null, 2.into()),
+            ],
+            changes
+        );
+        assert!(history[0].changed_at <= history[1].changed_at);
+
+        repo.delete(todo.id).await.expect("[delete] returned Err");
+        let res = repo.history(todo.id).await;
+        assert!(res.is_err());
+    }
+
+    #[tokio::test]
+    async fn reorder_scenario() {
+        let (pool, _db) = reset_database().await;
+        let repo = TodoRepositoryForDb::new(pool.clone()).scoped(OwnerId(107));
+        let mut todos = vec![];
+        for text in ["first", "second", "third"] {
+            let todo = repo
+                .create(CreateTodo::new(format!("[reorder] {}", text), vec![]))
+                .await
+                .expect("[create] returned Err");
+            todos.push(todo.id);
+        }
+        let [first, second, third] = todos[..] else {
+            unreachable!()
+        };
+
+        repo.reorder(vec![first, third])
+            .await
+            .expect("[reorder] returned Err");
+        let all = repo.all(TodoQuery::default()).await.unwrap();
+        assert_eq!(vec![first, third, second], ids(&all));
+        assert_eq!(
+            vec![Some(1), Some(2), None],
+            all.iter().map(|todo| todo.position).collect::<Vec<_>>()
+        );
+
+        // Todos left out of a later reorder lose their position.
+        repo.reorder(vec![second]).await.unwrap();
+        let all = repo.all(TodoQuery::default()).await.unwrap();
+        assert_eq!(vec![second, third, first], ids(&all));
+
+        let other = TodoRepositoryForDb::new(pool.clone()).scoped(OwnerId(101));
+        let res = other.reorder(vec![first]).await;
+        assert!(res.is_err());
+        let res = repo.reorder(vec![third, TodoId(test_id(0))]).await;
+        assert!(res.is_err());
+        let all = repo.all(TodoQuery::default()).await.unwrap();
+        assert_eq!(vec![second, third, first], ids(&all));
+
+        for id in [first, second, third] {
+            repo.delete(id).await.expect("[delete] returned Err");
+        }
+    }
+
+    #[tokio::test]
+    async fn changed_since_scenario() {
+        let (pool, _db) = reset_database().await;
+        let repo = TodoRepositoryForDb::new(pool.clone()).scoped(OwnerId(108));
+        let mut todos = vec![];
+        for text in ["updated", "unchanged", "deleted"] {
+            let todo = repo
+                .create(CreateTodo::new(format!("[changed_since] {}", text), vec![]))
+                .await
+                .expect("[create] returned Err");
+            todos.push(todo);
+        }
+        let since = todos.iter().map(|todo| todo.updated_at).max().unwrap();
+
+        let updated = repo
+            .update(todos[0].id, UpdateTodo::new(None, Some(true), None))
+            .await
+            .expect("[update] returned Err");
+        repo.delete(todos[2].id)
+            .await
+            .expect("[delete] returned Err");
+
+        let changes = repo
+            .changed_since(since)
+            .await
+            .expect("[changed_since] returned Err");
+        assert_eq!(2, changes.len(), "{:#?}", changes);
+        assert_eq!(SyncedTodo::live(updated), changes[0]);
+        assert!(matches!(
+            changes[1],
+            SyncedTodo::Deleted { id, deleted: true, .. } if id == todos[2].id
+        ));
+        assert!(changes[0].updated_at() <= changes[1].updated_at());
+
+        let other = TodoRepositoryForDb::new(pool.clone()).scoped(OwnerId(101));
+        let changes = other.changed_since(since).await.unwrap();
+        assert!(changes.iter().all(|change| change.id() != todos[2].id));
+
+        for todo in &todos[..2] {
+            repo.delete(todo.id).await.expect("[delete] returned Err");
+        }
+    }
+
+    #[tokio::test]
+    async fn delete_rollback_scenario() {
+        let (pool, _db) = reset_database().await;
+        let repo = TodoRepositoryForDb::new(pool.clone());
+        let todo = repo
+            .create(CreateTodo::new(
+                "[delete_rollback] text".to_string(),
+                vec![],
+            ))
+            .await
+            .expect("[create] returned Err");
+
+        // the tombstone insert fails after the todo is deleted
+        sqlx::query(r#"ALTER TABLE todo_tombstones RENAME TO todo_tombstones_away"#)
+            .execute(&pool)
+            .await
+            .expect("Failed to rename todo_tombstones");
+        let res = repo.delete(todo.id).await;
+        sqlx::query(r#"ALTER TABLE todo_tombstones_away RENAME TO todo_tombstones"#)
+            .execute(&pool)
+            .await
+            .expect("Failed to rename todo_tombstones back");
+        assert!(res.is_err());
+        assert_eq!(todo, repo.find(todo.id).await.expect("[find] returned Err"));
+
+        repo.delete(todo.id).await.expect("[delete] returned Err");
+    }
+
+    #[tokio::test]
+    async fn search_ranked_scenario() {
+        let (pool, _db) = reset_database().await;
+        let repo = TodoRepositoryForDb::new(pool.clone()).scoped(OwnerId(108));
+        let todo = repo
+            .create(CreateTodo::new("[ranked] buy grocery".to_string(), vec![]))
+            .await
+            .expect("[create] returned Err");
+        repo.update(todo.id, UpdateTodo::archive(true))
+            .await
+            .expect("[update] returned Err");
+
+        let todos = repo
+            .search_ranked("[ranked] buy grocry", false)
+            .await
+            .expect("[search_ranked] returned Err");
+        assert!(todos.is_empty());
+        let todos = repo
+            .search_ranked("[ranked] buy grocry", true)
+            .await
+            .expect("[search_ranked] returned Err");
+        assert_eq!(vec![todo.id], ids(&todos));
+
+        // Which index serves it depends on the statistics, the operator is what the trigram
+        // index needs to be an option at all.
+        let plan: Vec<(String,)> = sqlx::query_as(&format!("EXPLAIN {}", RANKED_TODOS_SQL))
+            .bind("[ranked] buy grocry")
+            .bind(false)
+            .bind(OwnerId(108))
+            .fetch_all(&pool)
+            .await
+            .expect("[explain] returned Err");
+        let plan: Vec<String> = plan.into_iter().map(|(line,)| line).collect();
+        assert!(
+            plan.iter().any(|line| line.contains("(text % ")),
+            "{:#?}",
+            plan
+        );
+    }
+
+    #[tokio::test]
+    async fn last_modified_scenario() {
+        let (pool, _db) = reset_database().await;
+        let repo = TodoRepositoryForDb::new(pool.clone());
+        let alice = repo.scoped(OwnerId(106));
+        let bob = repo.scoped(OwnerId(107));
+        let labels = LabelRepositoryForDb::new(pool.clone()).scoped(OwnerId(106));
+        assert_eq!(
+            DateTime::<Utc>::UNIX_EPOCH,
+            bob.last_modified()
+                .await
+                .expect("[last_modified] returned Err")
+        );
+
+        let label = labels
+            .create(CreateLabel::new("[last_modified] label".to_string()))
+            .await
+            .expect("[create label] returned Err");
+        alice
+            .create(CreateTodo::new(
+                "[last_modified] text".to_string(),
+                vec![label.id],
+            ))
+            .await
+            .expect("[create] returned Err");
+        let created_at = alice
+            .last_modified()
+            .await
+            .expect("[last_modified] returned Err");
+        assert_eq!(
+            DateTime::<Utc>::UNIX_EPOCH,
+            bob.last_modified()
+                .await
+                .expect("[last_modified] returned Err")
+        );
+
+        labels
+            .update_many(vec![UpdateLabel::new(
+                label.id,
+                "[last_modified] renamed".to_string(),
+            )])
+            .await
+            .expect("[update labels] returned Err");
+        let renamed_at = alice
+            .last_modified()
+            .await
+            .expect("[last_modified] returned Err");
+        assert!(renamed_at > created_at);
+    }
+
+    #[tokio::test]
+    async fn find_max_joined_labels_scenario() {
+        let (pool, _db) = reset_database().await;
+        let owner = OwnerId(114);
+        let repo = TodoRepositoryForDb::new(pool.clone())
+            .with_max_joined_labels(2)
+            .scoped(owner);
+        let todo = repo
+            .create(
+                CreateTodo::new("[max_joined_labels] text".to_string(), vec![]).with_label_names(
+                    (1..=3)
+                        .map(|i| format!("[max_joined_labels] {}", i))
+                        .collect(),
+                ),
+            )
+            .await
+            .expect("[create] returned Err");
+        assert_eq!(3, todo.labels.len());
+        assert!(!todo.labels_truncated);
+
+        let found = repo.find(todo.id).await.expect("[find] returned Err");
+        assert_eq!(todo.labels[..2].to_vec(), found.labels);
+        assert!(found.labels_truncated);
+        let updated = repo
+            .update(todo.id, UpdateTodo::new(None, Some(true), None))
+            .await
+            .expect("[update] returned Err");
+        assert!(updated.labels_truncated);
+
+        repo.delete(todo.id).await.expect("[delete] returned Err");
+        sqlx::query(r#"DELETE FROM labels WHERE owner_id = $1"#)
+            .bind(owner)
+            .execute(&pool)
+            .await
+            .expect("Failed to clean up label data");
+    }
+
+    #[tokio::test]
+    async fn json_agg_matches_fold_scenario() {
+        let (pool, _db) = reset_database().await;
+        let owner = OwnerId(118);
+        let repo = TodoRepositoryForDb::new(pool.clone()).scoped(owner);
+        let mut ids = vec![];
+        for (text, label_names) in [
+            ("[json_agg] none", vec![]),
+            ("[json_agg] one", vec!["[json_agg] b"]),
+            ("[json_agg] two", vec!["[json_agg] b", "[json_agg] a"]),
+        ] {
+            let todo = repo
+                .create(
+                    CreateTodo::new(text.to_string(), vec![])
+                        .with_label_names(label_names.into_iter().map(str::to_string).collect()),
+                )
+                .await
+                .expect("[create] returned Err");
+            ids.push(todo.id);
+        }
+
+        for query in [
+            TodoQuery::default(),
+            TodoQuery {
+                sort: TodoSort::Id,
+                order: SortOrder::Asc,
+                ..Default::default()
+            },
+        ] {
+            let sql = all_todo_rows_sql(&query);
+            let rows = bind_all_todos(
+                sqlx::query_as::<_, TodoWithLabelFromRow>(&sql),
+                &query,
+                owner,
+            )
+            .fetch_all(&pool)
+            .await
+            .unwrap();
+            let folded = fold_entities(rows);
+            assert_eq!(3, folded.len());
+            assert_eq!(folded, repo.all(query).await.expect("[all] returned Err"));
+            for todo in folded {
+                assert_eq!(todo, repo.find(todo.id).await.expect("[find] returned Err"));
+            }
+        }
+
+        for id in ids {
+            repo.delete(id).await.expect("[delete] returned Err");
+        }
+        sqlx::query(r#"DELETE FROM labels WHERE owner_id = $1"#)
+            .bind(owner)
+            .execute(&pool)
+            .await
+            .expect("Failed to clean up label data");
+    }
+
+    #[tokio::test]
+    async fn summary_scenario() {
+        let (pool, _db) = reset_database().await;
+        let owner = OwnerId(117);
+        let repo = TodoRepositoryForDb::new(pool.clone()).scoped(owner);
+        let summary = repo
+            .summary(TodoFilter::default())
+            .await
+            .expect("[summary] returned Err");
+        assert_eq!(TodoSummary::new(0, 0), summary);
+
+        let labeled = repo
+            .create(
+                CreateTodo::new("[summary] labeled".to_string(), vec![])
+                    .with_label_names(vec!["[summary] label".to_string()]),
+            )
+            .await
+            .expect("[create] returned Err");
+        let unlabeled = repo
+            .create(CreateTodo::new("[summary] unlabeled".to_string(), vec![]))
+            .await
+            .expect("[create] returned Err");
+        repo.update(labeled.id, UpdateTodo::new(None, Some(true), None))
+            .await
+            .expect("[update] returned Err");
+
+        let summary = repo.summary(TodoFilter::default()).await.unwrap();
+        assert_eq!(TodoSummary::new(2, 1), summary);
+        assert_eq!(0.5, summary.completion_rate);
+        let filter = TodoFilter {
+            label_id: Some(labeled.labels[0].id),
+        };
+        assert_eq!(TodoSummary::new(1, 1), repo.summary(filter).await.unwrap());
+
+        for id in [labeled.id, unlabeled.id] {
+            repo.delete(id).await.expect("[delete] returned Err");
+        }
+        sqlx::query(r#"DELETE FROM labels WHERE owner_id = $1"#)
+            .bind(owner)
+            .execute(&pool)
+            .await
+            .expect("Failed to clean up label data");
+    }
+
+    #[tokio::test]
+    async fn duplicate_scenario() {
+        let (pool, _db) = reset_database().await;
+        let owner = OwnerId(116);
+        let repo = TodoRepositoryForDb::new(pool.clone()).scoped(owner);
+        let todo = repo
+            .create(
+                CreateTodo::new("[duplicate] text".to_string(), vec![]).with_label_names(vec![
+                    "[duplicate] first".to_string(),
+                    "[duplicate] second".to_string(),
+                ]),
+            )
+            .await
+            .expect("[create] returned Err");
+        let todo = repo
+            .update(todo.id, UpdateTodo::new(None, Some(true), None))
+            .await
+            .expect("[update] returned Err");
+
+        let copy = repo
+            .duplicate(todo.id)
+            .await
+            .expect("[duplicate] returned Err");
+        assert_ne!(todo.id, copy.id);
+        assert_eq!(todo.text, copy.text);
+        assert_eq!(todo.labels, copy.labels);
+        assert!(!copy.completed);
+        assert_eq!(None, copy.completed_at);
+        assert_eq!(copy, repo.find(copy.id).await.unwrap());
+
+        let other = TodoRepositoryForDb::new(pool.clone()).scoped(OwnerId(101));
+        let res = other.duplicate(todo.id).await;
+        assert!(matches!(
+            res.unwrap_err().downcast_ref(),
+            Some(RepositoryError::NotFound(_))
+        ));
+
+        for id in [todo.id, copy.id] {
+            repo.delete(id).await.expect("[delete] returned Err");
+        }
+        sqlx::query(r#"DELETE FROM labels WHERE owner_id = $1"#)
+            .bind(owner)
+            .execute(&pool)
+            .await
+            .expect("Failed to clean up label data");
+    }
+
+    #[tokio::test]
+    async fn stream_all_scenario() {
+        let (pool, _db) = reset_database().await;
+        let owner = OwnerId(113);
+        let repo = TodoRepositoryForDb::new(pool.clone()).scoped(owner);
+        let mut label_ids = vec![];
+        for name in ["first", "second"] {
+            let (label_id,) = sqlx::query_as::<_, (LabelId,)>(
+                r#"INSERT INTO labels (name, owner_id) VALUES ($1, $2) RETURNING id"#,
+            )
+            .bind(format!("[stream_all] {}", name))
+            .bind(owner)
+            .fetch_one(&pool)
+            .await
+            .expect("Failed to insert label data");
+            label_ids.push(label_id);
+        }
+        let mut todos = vec![];
+        for labels in [vec![], label_ids.clone(), label_ids[1..].to_vec()] {
+            let todo = repo
+                .create(CreateTodo::new("[stream_all] text".to_string(), labels))
+                .await
+                .expect("[create] returned Err");
+            todos.push(todo);
+        }
+
+        let query = TodoQuery {
+            sort: TodoSort::Id,
+            order: SortOrder::Asc,
+            ..Default::default()
+        };
+        let streamed: Vec<TodoEntity> = fold_entity_stream(repo.stream_all(query.clone()))
+            .try_collect()
+            .await
+            .expect("[stream_all] returned Err");
+        let buffered = repo.all(query).await.expect("[all] returned Err");
+        assert_eq!(buffered, streamed);
+        todos.sort_by_key(|todo| todo.id);
+        assert_eq!(todos, streamed);
+
+        for todo in todos {
+            repo.delete(todo.id).await.expect("[delete] returned Err");
+        }
+        sqlx::query(r#"DELETE FROM labels WHERE owner_id = $1"#)
+            .bind(owner)
+            .execute(&pool)
+            .await
+            .expect("Failed to clean up label data");
+    }
+}
+
+#[cfg(test)]
+#[cfg(feature = "database-test")]
+#[cfg(feature = "uuid")]
+mod uuid_test {
+    use super::*;
+    use crate::repositories::reset_database;
+
+    /// Needs a database migrated with `migrations_uuid` on top of `migrations`.
+    #[tokio::test]
+    async fn todo_round_trips_uuid() {
+        let (pool, _db) = reset_database().await;
+        let repo = TodoRepositoryForDb::new(pool.clone()).scoped(OwnerId(105));
+        let label = sqlx::query_as::<_, Label>(
+            r#"INSERT INTO labels (name, owner_id) VALUES ('[uuid] label', 105) RETURNING *"#,
+        )
+        .fetch_one(&pool)
+        .await
+        .expect("failed insert label");
+
+        let created = repo
+            .create(CreateTodo::new("[uuid] text".to_string(), vec![label.id]))
+            .await
+            .expect("[create] returned Err");
+        let id: TodoId = created.id.to_string().parse().unwrap();
+        let todo = repo.find(id).await.expect("[find] returned Err");
+        assert_eq!(created, todo);
+        assert_eq!(vec![label.clone()], todo.labels);
+
+        repo.delete(id).await.expect("[delete] returned Err");
+        sqlx::query(r#"DELETE FROM labels WHERE id = $1"#)
+            .bind(label.id)
+            .execute(&pool)
+            .await
+            .expect("failed delete label");
+    }
+
+    #[tokio::test]
+    async fn summary_with_uuid() {
+        let (pool, _db) = reset_database().await;
+        let repo = TodoRepositoryForDb::new(pool.clone()).scoped(OwnerId(118));
+        let labeled = repo
+            .create(
+                CreateTodo::new("[uuid] labeled".to_string(), vec![])
+                    .with_label_names(vec!["[uuid] label".to_string()]),
+            )
+            .await
+            .expect("[create] returned Err");
+        repo.create(CreateTodo::new("[uuid] unlabeled".to_string(), vec![]))
+            .await
+            .expect("[create] returned Err");
+
+        let summary = repo
+            .summary(TodoFilter::default())
+            .await
+            .expect("[summary] returned Err");
+        assert_eq!(TodoSummary::new(2, 0), summary);
+        let filter = TodoFilter {
+            label_id: Some(labeled.labels[0].id),
+        };
+        let summary = repo.summary(filter).await.expect("[summary] returned Err");
+        assert_eq!(TodoSummary::new(1, 0), summary);
+    }
+
+    #[tokio::test]
+    async fn set_completed_all_with_uuid() {
+        let (pool, _db) = reset_database().await;
+        let repo = TodoRepositoryForDb::new(pool.clone()).scoped(OwnerId(119));
+        let labeled = repo
+            .create(
+                CreateTodo::new("[uuid] labeled".to_string(), vec![])
+                    .with_label_names(vec!["[uuid] label".to_string()]),
+            )
+            .await
+            .expect("[create] returned Err");
+        let unlabeled = repo
+            .create(CreateTodo::new("[uuid] unlabeled".to_string(), vec![]))
+            .await
+            .expect("[create] returned Err");
+        let filter = TodoFilter {
+            label_id: Some(labeled.labels[0].id),
+        };
+
+        let completed = repo
+            .set_completed_all(filter, true)
+            .await
+            .expect("[set_completed_all] returned Err");
+        assert_eq!(1, completed);
+        assert!(repo.find(labeled.id).await.unwrap().completed);
+        assert!(!repo.find(unlabeled.id).await.unwrap().completed);
+
+        let uncompleted = repo
+            .set_completed_all(TodoFilter::default(), false)
+            .await
+            .expect("[set_completed_all] returned Err");
+        assert_eq!(1, uncompleted);
+        assert!(!repo.find(labeled.id).await.unwrap().completed);
+    }
+}
+
+#[cfg(any(test, feature = "testing"))]
+pub mod test_utils {
+    use super::*;
+    use crate::repositories::label::test_utils::LabelRepositoryForMemory;
+    use crate::repositories::next_memory_id;
+    use anyhow::Context;
+    use axum::async_trait;
+    use std::{
+        collections::HashMap,
+        sync::{Arc, RwLock, RwLockReadGuard, RwLockWriteGuard},
+    };
+
+    type TodoDatas = HashMap<TodoId, (OwnerId, TodoEntity)>;
+    type Tombstones = Vec<(OwnerId, TodoId, DateTime<Utc>)>;
+
+    #[derive(Debug, Clone)]
+    pub struct TodoRepositoryForMemory {
+        store: Arc<RwLock<TodoDatas>>,
+        labels: LabelRepositoryForMemory,
+        owner: OwnerId,
+        default_label: Option<LabelId>,
+        max_labels: usize,
+        max_joined_labels: usize,
+        modified_at: Arc<RwLock<HashMap<OwnerId, DateTime<Utc>>>>,
+        history: Arc<RwLock<HashMap<TodoId, Vec<TodoChange>>>>,
+        tombstones: Arc<RwLock<Tombstones>>,
+        clock: fn() -> DateTime<Utc>,
+    }
+
+    impl TodoRepositoryForMemory {
+        pub fn new(labels: Vec<Label>) -> Self {
+            Self::with_label_repository(LabelRepositoryForMemory::with_labels(labels))
+        }
+
+        pub fn with_label_repository(labels: LabelRepositoryForMemory) -> Self {
+            let store: Arc<RwLock<TodoDatas>> = Arc::default();
+            let merged = store.clone();
+            labels.on_merge(move |keep, remove| {
+                merge_todo_labels(&mut merged.write().unwrap(), keep, remove)
+            });
+            TodoRepositoryForMemory {
+                store,
+                labels,
+                owner: OwnerId::default(),
+                default_label: None,
+                max_labels: DEFAULT_MAX_LABELS_PER_TODO,
+                max_joined_labels: DEFAULT_MAX_JOINED_LABELS,
+                modified_at: Arc::default(),
+                history: Arc::default(),
+                tombstones: Arc::default(),
+                clock: Utc::now,
+            }
+        }
+
+        pub fn with_default_label(mut self, default_label: Option<LabelId>) -> Self {
+            self.default_label = default_label;
+            self
+        }
+
+        pub fn with_max_labels(mut self, max_labels: usize) -> Self {
+            self.max_labels = max_labels;
+            self
+        }
+
+        pub fn with_max_joined_labels(mut self, max_joined_labels: usize) -> Self {
+            self.max_joined_labels = max_joined_labels;
+            self
+        }
+
+        /// Stamps todos with the time of `clock` instead of the current one, for responses
+        /// that stay the same from run to run.
+        pub fn with_clock(mut self, clock: fn() -> DateTime<Utc>) -> Self {
+            self.clock = clock;
+            self
+        }
+
+        fn write_store_ref(&self) -> RwLockWriteGuard<'_, TodoDatas> {
+            self.store.write().unwrap()
+        }
+
+        fn read_store_ref(&self) -> RwLockReadGuard<'_, TodoDatas> {
+            self.store.read().unwrap()
+        }
+
+        fn owned<'a>(&self, store: &'a TodoDatas) -> impl Iterator<Item = &'a TodoEntity> {
+            let owner = self.owner;
+            store
+                .values()
+                .filter(move |(todo_owner, _)| *todo_owner == owner)
+                .map(|(_, todo)| todo)
+        }
+
+        fn get_owned<'a>(&self, store: &'a TodoDatas, id: TodoId) -> Option<&'a TodoEntity> {
+            store
+                .get(&id)
+                .filter(|(owner, _)| *owner == self.owner)
+                .map(|(_, todo)| todo)
+        }
+
+        fn mark_modified(&self) {
+            let now = (self.clock)();
+            self.modified_at.write().unwrap().insert(self.owner, now);
+        }
+
+        /// Labels of the owner behind `labels`, skipping the others like `INSERT_TODO_LABELS`.
+        /// Called before taking the store lock, which a failed check would otherwise poison.
+        fn resolve_labels(&self, labels: &[LabelId]) -> Vec<Label> {
+            let mut labels: Vec<Label> = labels
+                .iter()
+                .filter_map(|id| self.labels.get(*id))
+                .collect();
+            sort_labels(&mut labels);
+            labels
+        }
+    }
+
+    /// Moves the todos labelled `remove` to `keep`, like `merge_labels` does in `todo_labels`.
+    fn merge_todo_labels(store: &mut TodoDatas, keep: &Label, remove: LabelId) {
+        for (_, todo) in store.values_mut() {
+            if todo.labels.iter().all(|label| label.id != remove) {
+                continue;
+            }
+            todo.labels
+                .retain(|label| label.id != remove && label.id != keep.id);
+            todo.labels.push(keep.clone());
+            sort_labels(&mut todo.labels);
+        }
+    }
+
+    impl OwnerScoped for TodoRepositoryForMemory {
+        fn scoped(&self, owner: OwnerId) -> Self {
+            Self {
+                labels: self.labels.scoped(owner),
+                owner,
+                ..self.clone()
+            }
+        }
+    }
+
+    #[async_trait]
+    impl TodoRepository for TodoRepositoryForMemory {
+        async fn create(&self, payload: CreateTodo) -> anyhow::Result<TodoEntity> {
+            let mut label_ids = payload.labels;
+            for name in unique_label_names(payload.label_names) {
+                label_ids.push(self.labels.find_or_create(&name).id);
+            }
+            let requested = unique_label_ids(label_ids);
+            let label_ids = labels_or_default(requested.clone(), self.default_label);
+            check_label_count(&label_ids, self.max_labels)?;
+            let labels = self.resolve_labels(&label_ids);
+            check_labels_found(&requested, &labels)?;
+            let mut store = self.write_store_ref();
+            let id = TodoId(next_memory_id(store.len()));
+            let now = (self.clock)();
+            let todo = TodoEntity {
+                due_date: payload.due_date,
+                priority: payload.priority,
+                created_at: now,
+                updated_at: now,
+                ..TodoEntity::new(id, payload.text.clone(), false, labels)
+            };
+            store.insert(id, (self.owner, todo.clone()));
+            self.mark_modified();
+            Ok(todo)
+        }
+
+        async fn find(&self, id: TodoId) -> anyhow::Result<TodoEntity> {
+            let store = self.read_store_ref();
+            let todo = self
+                .get_owned(&store, id)
+                .cloned()
+                .ok_or(RepositoryError::NotFound(id.into()))?;
+            Ok(todo.cap_labels(self.max_joined_labels))
+        }
+
+        async fn exists(&self, id: TodoId) -> anyhow::Result<bool> {
+            let store = self.read_store_ref();
+            Ok(self.get_owned(&store, id).is_some())
+        }
+
+        async fn find_many(&self, ids: &[TodoId]) -> anyhow::Result<Vec<TodoEntity>> {
+            let store = self.read_store_ref();
+            let todos = ids
+                .iter()
+                .filter_map(|id| self.get_owned(&store, *id).cloned())
+                .collect();
+            Ok(in_order_of(ids, todos))
+        }
+
+        async fn all(&self, query: TodoQuery) -> anyhow::Result<Vec<TodoEntity>> {
+            let store = self.read_store_ref();
+            let q = query.q.as_ref().map(|q| q.to_lowercase());
+            let mut todos: Vec<TodoEntity> = self
+                .owned(&store)
+                .filter(|todo| query.include_archived || !todo.archived)
+                .filter(|todo| match &q {
+                    Some(q) => todo.text.to_lowercase().contains(q),
+                    None => true,
+                })
+                .filter(|todo| query.completed.is_none_or(|c| todo.completed == c))
+                .filter(|todo| {
+                    query.label_ids.is_empty()
+                        || todo
+                            .labels
+                            .iter()
+                            .any(|label| query.label_ids.contains(&label.id))
+                })
+                .filter(|todo| !query.unlabeled || todo.labels.is_empty())
+                .cloned()
+                .map(|todo| match query.skip_labels {
+                    true => todo.without_labels(),
+                    false => todo,
+                })
+                .collect();
+            query.sort(&mut todos);
+            Ok(todos)
+        }
+
+        async fn update(&self, id: TodoId, payload: UpdateTodo) -> anyhow::Result<TodoEntity> {
+            let labels = match payload.labels {
+                Some(label_ids) => {
+                    let label_ids = unique_label_ids(label_ids);
+                    check_label_count(&label_ids, self.max_labels)?;
+                    let labels = self.resolve_labels(&label_ids);
+                    check_labels_found(&label_ids, &labels)?;
+                    Some(labels)
+                }
+                None => None,
+            };
+            let mut store = self.write_store_ref();
+            let todo = self
+                .get_owned(&store, id)
+                .context(RepositoryError::NotFound(id.into()))?;
+            if payload
+                .if_updated_at
+                .is_some_and(|updated_at| updated_at != todo.updated_at)
+            {
+                return Err(RepositoryError::Stale(id.into()).into());
+            }
+            let text = payload.text.unwrap_or(todo.text.clone());
+            let completed = payload.completed.unwrap_or(todo.completed);
+            let completed_at = next_completed_at(todo, completed, (self.clock)());
+            let labels = labels.unwrap_or_else(|| todo.labels.clone());
+            let archived = payload.archived.unwrap_or(todo.archived);
+            let updated = TodoEntity {
+                completed_at,
+                archived,
+                due_date: payload.due_date.apply(todo.due_date),
+                priority: payload.priority.apply(todo.priority),
+                position: todo.position,
+                created_at: todo.created_at,
+                updated_at: (self.clock)(),
+                ..TodoEntity::new(id, text, completed, labels)
+            };
+            self.history
+                .write()
+                .unwrap()
+                .entry(id)
+                .or_default()
+                .extend(todo_changes(todo, &updated));
+            store.insert(id, (self.owner, updated.clone()));
+            self.mark_modified();
+
+            Ok(updated)
+        }
+
+        async fn delete(&self, id: TodoId) -> anyhow::Result<()> {
+            let mut store = self.write_store_ref();
+            if self.get_owned(&store, id).is_none() {
+                return Err(RepositoryError::NotFound(id.into()).into());
+            }
+            store.remove(&id);
+            self.history.write().unwrap().remove(&id);
+            self.tombstones
+                .write()
+                .unwrap()
+                .push((self.owner, id, (self.clock)()));
+            self.mark_modified();
+            Ok(())
+        }
+
+        async fn touch(&self, id: TodoId) -> anyhow::Result<TodoEntity> {
+            let mut store = self.write_store_ref();
+            if self.get_owned(&store, id).is_none() {
+                return Err(RepositoryError::NotFound(id.into()).into());
+            }
+            let (_, todo) = store.get_mut(&id).unwrap();
+            todo.updated_at = (self.clock)();
+            let todo = todo.clone();
+            self.mark_modified();
+            Ok(todo)
+        }
+
+        async fn duplicate(&self, id: TodoId) -> anyhow::Result<TodoEntity> {
+            let mut store = self.write_store_ref();
+            let source = self
+                .get_owned(&store, id)
+                .cloned()
+                .ok_or(RepositoryError::NotFound(id.into()))?;
+            let id = TodoId(next_memory_id(store.len()));
+            let now = (self.clock)();
+            let todo = TodoEntity {
+                due_date: source.due_date,
+                priority: source.priority,
+                created_at: now,
+                updated_at: now,
+                ..TodoEntity::new(id, source.text, false, source.labels)
+            };
+            store.insert(id, (self.owner, todo.clone()));
+            self.mark_modified();
+            Ok(todo)
+        }
+
+        async fn history(&self, id: TodoId) -> anyhow::Result<Vec<TodoChange>> {
+            let store = self.read_store_ref();
+            self.get_owned(&store, id)
+                .context(RepositoryError::NotFound(id.into()))?;
+            let history = self.history.read().unwrap();
+            Ok(history.get(&id).cloned().unwrap_or_default())
+        }
+
+        async fn reorder(&self, ids: Vec<TodoId>) -> anyhow::Result<()> {
+            let mut store = self.write_store_ref();
+            if let Some(id) = ids.iter().find(|id| self.get_owned(&store, **id).is_none()) {
+                return Err(RepositoryError::NotFound((*id).into()).into());
+            }
+            for (owner, todo) in store.values_mut() {
+                if *owner == self.owner {
+                    todo.position = (1..)
+                        .zip(ids.iter())
+                        .find(|(_, id)| **id == todo.id)
+                        .map(|(position, _)| position);
+                }
+            }
+            self.mark_modified();
+            Ok(())
+        }
+
+        async fn changed_since(&self, since: DateTime<Utc>) -> anyhow::Result<Vec<SyncedTodo>> {
+            let store = self.read_store_ref();
+            let todos = self
+                .owned(&store)
+                .filter(|todo| todo.updated_at > since)
+                .cloned()
+                .collect();
+            let tombstones = self
+                .tombstones
+                .read()
+                .unwrap()
+                .iter()
+                .filter(|(owner, _, deleted_at)| *owner == self.owner && *deleted_at > since)
+                .map(|(_, id, deleted_at)| (*id, *deleted_at))
+                .collect();
+            Ok(synced_todos(todos, tombstones))
+        }
+
+        async fn search(&self, criteria: TodoSearchCriteria) -> anyhow::Result<TodoSearchResult> {
+            let store = self.read_store_ref();
+            let q = criteria.q.as_ref().map(|q| q.to_lowercase());
+            let mut todos: Vec<TodoEntity> = self
+                .owned(&store)
+                .filter(|todo| criteria.include_archived || !todo.archived)
+                .filter(|todo| match &q {
+                    Some(q) => todo.text.to_lowercase().contains(q),
+                    None => true,
+                })
+                .filter(|todo| match criteria.completed {
+                    Some(completed) => todo.completed == completed,
+                    None => true,
+                })
+                .filter(|todo| {
+                    criteria.label_ids.is_empty()
+                        || todo
+                            .labels
+                            .iter()
+                            .any(|label| criteria.label_ids.contains(&label.id))
+                })
+                .filter(|todo| !criteria.unlabeled || todo.labels.is_empty())
+                .cloned()
+                .collect();
+            match criteria.sort {
+                TodoSearchSort::IdAsc => todos.sort_by_key(|todo| todo.id),
+                TodoSearchSort::IdDesc => todos.sort_by_key(|todo| std::cmp::Reverse(todo.id)),
+                TodoSearchSort::TextAsc => {
+                    todos.sort_by(|a, b| a.text.cmp(&b.text).then(a.id.cmp(&b.id)))
+                }
+                TodoSearchSort::TextDesc => {
+                    todos.sort_by(|a, b| b.text.cmp(&a.text).then(b.id.cmp(&a.id)))
+                }
+                TodoSearchSort::Listing(sort, order) => sort.sort(order, &mut todos),
+            }
+
+            let total = todos.len() as i64;
+            let items = todos
+                .into_iter()
+                .skip(criteria.offset() as usize)
+                .take(criteria.page_size as usize)
+                .collect();
+            Ok(TodoSearchResult {
+                items,
+                total,
+                page: criteria.page,
+                page_size: criteria.page_size,
+            })
+        }
+
+        async fn search_ranked(
+            &self,
+            q: &str,
+            include_archived: bool,
+        ) -> anyhow::Result<Vec<TodoEntity>> {
+            let store = self.read_store_ref();
+            let todos = self
+                .owned(&store)
+                .filter(|todo| include_archived || !todo.archived)
+                .cloned();
+            Ok(rank_by_distance(todos, q))
+        }
+
+        async fn set_completed_all(
+            &self,
+            filter: TodoFilter,
+            completed: bool,
+        ) -> anyhow::Result<u64> {
+            let mut store = self.write_store_ref();
+            let mut updated = 0;
+            for (owner, todo) in store.values_mut() {
+                if *owner != self.owner || todo.completed == completed || todo.archived {
+                    continue;
+                }
+                if let Some(label_id) = filter.label_id {
+                    if todo.labels.iter().all(|label| label.id != label_id) {
+                        continue;
+                    }
+                }
+                todo.completed_at = next_completed_at(todo, completed, (self.clock)());
+                todo.completed = completed;
+                todo.updated_at = (self.clock)();
+                updated += 1;
+            }
+            if updated > 0 {
+                self.mark_modified();
+            }
+            Ok(updated)
+        }
+
+        async fn attach_label(
+            &self,
+            label: LabelId,
+            ids: Vec<TodoId>,
+        ) -> anyhow::Result<AttachedLabel> {
+            let label = self
+                .labels
+                .get(label)
+                .context(RepositoryError::NotFound(label.into()))?;
+            let mut store = self.write_store_ref();
+            let mut not_found = vec![];
+            let mut attached = vec![];
+            for id in ids {
+                match self.get_owned(&store, id) {
+                    None => not_found.push(id),
+                    Some(todo) if todo.labels.contains(&label) => {}
+                    Some(todo) => {
+                        if todo.labels.len() >= self.max_labels {
+                            return Err(RepositoryError::TooManyLabels(self.max_labels).into());
+                        }
+                        attached.push(id);
+                    }
+                }
+            }
+            for id in attached.iter() {
+                let (_, todo) = store.get_mut(id).unwrap();
+                todo.labels.push(label.clone());
+                sort_labels(&mut todo.labels);
+                todo.updated_at = (self.clock)();
+            }
+            if !attached.is_empty() {
+                self.mark_modified();
+            }
+            Ok(AttachedLabel {
+                attached: attached.len() as u64,
+                not_found,
+            })
+        }
+
+        async fn summary(&self, filter: TodoFilter) -> anyhow::Result<TodoSummary> {
+            let store = self.read_store_ref();
+            let (mut total, mut completed) = (0, 0);
+            for todo in self.owned(&store).filter(|todo| !todo.archived) {
+                if let Some(label_id) = filter.label_id {
+                    if todo.labels.iter().all(|label| label.id != label_id) {
+                        continue;
+                    }
+                }
+                total += 1;
+                if todo.completed {
+                    completed += 1;
+                }
+            }
+            Ok(TodoSummary::new(total, completed))
+        }
+
+        async fn last_modified(&self) -> anyhow::Result<DateTime<Utc>> {
+            let modified_at = self.modified_at.read().unwrap();
+            Ok(modified_at
+                .get(&self.owner)
+                .copied()
+                .unwrap_or(DateTime::<Utc>::UNIX_EPOCH))
+        }
+    }
+
+    #[derive(Debug, Clone)]
+    pub struct FailingTodoRepository {
+        error: RepositoryError,
+        operation: Option<&'static str>,
+    }
+
+    impl FailingTodoRepository {
+        pub fn new(error: RepositoryError) -> Self {
+            Self {
+                error,
+                operation: None,
+            }
+        }
+
+        /// Names the failing operation like the database repositories do.
+        pub fn with_context(mut self, operation: &'static str) -> Self {
+            self.operation = Some(operation);
+            self
+        }
+
+        fn error(&self) -> anyhow::Error {
+            let error = anyhow::Error::from(self.error.clone());
+            match self.operation {
+                Some(operation) => error.context(operation),
+                None => error,
+            }
+        }
+    }
+
+    impl OwnerScoped for FailingTodoRepository {
+        fn scoped(&self, _owner: OwnerId) -> Self {
+            self.clone()
+        }
+    }
+
+    #[async_trait]
+    impl TodoRepository for FailingTodoRepository {
+        async fn create(&self, _payload: CreateTodo) -> anyhow::Result<TodoEntity> {
+            Err(self.error())
+        }
+
+        async fn find(&self, _id: TodoId) -> anyhow::Result<TodoEntity> {
+            Err(self.error())
+        }
+
+        async fn exists(&self, _id: TodoId) -> anyhow::Result<bool> {
+            Err(self.error())
+        }
+
+        async fn find_many(&self, _ids: &[TodoId]) -> anyhow::Result<Vec<TodoEntity>> {
+            Err(self.error())
+        }
+
+        async fn all(&self, _query: TodoQuery) -> anyhow::Result<Vec<TodoEntity>> {
+            Err(self.error())
+        }
+
+        async fn update(&self, _id: TodoId, _payload: UpdateTodo) -> anyhow::Result<TodoEntity> {
+            Err(self.error())
+        }
+
+        async fn delete(&self, _id: TodoId) -> anyhow::Result<()> {
+            Err(self.error())
+        }
+
+        async fn touch(&self, _id: TodoId) -> anyhow::Result<TodoEntity> {
+            Err(self.error())
+        }
+
+        async fn duplicate(&self, _id: TodoId) -> anyhow::Result<TodoEntity> {
+            Err(self.error())
+        }
+
+        async fn history(&self, _id: TodoId) -> anyhow::Result<Vec<TodoChange>> {
+            Err(self.error())
+        }
+
+        async fn reorder(&self, _ids: Vec<TodoId>) -> anyhow::Result<()> {
+            Err(self.error())
+        }
+
+        async fn changed_since(&self, _since: DateTime<Utc>) -> anyhow::Result<Vec<SyncedTodo>> {
+            Err(self.error())
+        }
+
+        async fn search(&self, _criteria: TodoSearchCriteria) -> anyhow::Result<TodoSearchResult> {
+            Err(self.error())
+        }
+
+        async fn search_ranked(
+            &self,
+            _q: &str,
+            _include_archived: bool,
+        ) -> anyhow::Result<Vec<TodoEntity>> {
+            Err(self.error())
+        }
+
+        async fn set_completed_all(
+            &self,
+            _filter: TodoFilter,
+            _completed: bool,
+        ) -> anyhow::Result<u64> {
+            Err(self.error())
+        }
+
+        async fn attach_label(
+            &self,
+            _label: LabelId,
+            _ids: Vec<TodoId>,
+        ) -> anyhow::Result<AttachedLabel> {
+            Err(self.error())
+        }
+
+        async fn summary(&self, _filter: TodoFilter) -> anyhow::Result<TodoSummary> {
+            Err(self.error())
+        }
+
+        async fn last_modified(&self) -> anyhow::Result<DateTime<Utc>> {
+            Err(self.error())
+        }
+    }
+
+    #[cfg(test)]
+    #[cfg(not(feature = "uuid"))]
+    mod test {
+        use super::*;
+
+        #[tokio::test]
+        async fn todo_crud_scenario() {
+            let label_data = Label::new(LabelId(1), "test label".to_string());
+            let labels = vec![label_data.clone()];
+            let id = TodoId(1);
+            let text = "todo text".to_string();
+            let repo = TodoRepositoryForMemory::new(labels.clone());
+
+            // create
+            let expected = TodoEntity::new(id, text.clone(), false, labels.clone());
+            let todo = repo
+                .create(CreateTodo::new(text, vec![label_data.id]))
+                .await
+                .expect("failed create todo");
+            let expected = expected.with_timestamps_of(&todo);
+            assert_eq!(expected, todo);
+
+            // find
+            let todo = repo.find(todo.id).await.unwrap();
+            assert_eq!(expected, todo);
+
+            // all
+            let todos = repo
+                .all(TodoQuery::default())
+                .await
+                .expect("failed get all todos");
+            assert_eq!(vec![expected], todos);
+
+            // update
+            let text = "update todo text".to_string();
+            let todo = repo
+                .update(id, UpdateTodo::new(Some(text.clone()), Some(true), None))
+                .await
+                .expect("failed update todo.");
+            assert_eq!(todo.id, id);
+            assert_eq!(todo.text, text);
+            assert!(todo.completed);
+            assert!(todo.completed_at.is_some());
+            assert_eq!(todo.labels, labels);
+
+            // delete
+            let res = repo.delete(id).await;
+            assert!(res.is_ok());
+        }
+
+        #[tokio::test]
+        async fn todo_update_from_create_payload() {
+            let label = Label::new(LabelId(1), "test label".to_string());
+            let repo = TodoRepositoryForMemory::new(vec![label.clone()]);
+            let todo = repo
+                .create(CreateTodo::new("todo text".to_string(), vec![]))
+                .await
+                .expect("failed create todo");
+
+            let payload = CreateTodo::new("replaced text".to_string(), vec![label.id]);
+            let todo = repo
+                .update(todo.id, payload.into())
+                .await
+                .expect("failed update todo");
+            assert_eq!(
+                TodoEntity::new(todo.id, "replaced text".to_string(), false, vec![label])
+                    .with_timestamps_of(&todo),
+                todo
+            );
+        }
+
+        #[tokio::test]
+        async fn todo_default_label() {
+            let inbox = Label::new(LabelId(1), "inbox".to_string());
+            let other = Label::new(LabelId(2), "other".to_string());
+            let inbox_id = inbox.id;
+            let repo = TodoRepositoryForMemory::new(vec![inbox.clone(), other.clone()])
+                .with_default_label(Some(inbox.id));
+
+            // empty labels
+            let todo = repo
+                .create(CreateTodo::new("todo text".to_string(), vec![]))
+                .await
+                .expect("failed create todo");
+            assert_eq!(vec![inbox], todo.labels);
+
+            // non-empty labels
+            let todo = repo
+                .create(CreateTodo::new("todo text".to_string(), vec![other.id]))
+                .await
+                .expect("failed create todo");
+            assert_eq!(vec![other], todo.labels);
+
+            // the labels belong to the default owner only
+            let stranger = repo.scoped(OwnerId(1));
+            let todo = stranger
+                .create(CreateTodo::new("todo text".to_string(), vec![]))
+                .await
+                .expect("failed create todo");
+            assert!(todo.labels.is_empty());
+            let res = stranger
+                .create(CreateTodo::new("todo text".to_string(), vec![inbox_id]))
+                .await;
+            assert!(matches!(
+                res.unwrap_err().downcast_ref::<RepositoryError>(),
+                Some(RepositoryError::NotFound(_))
+            ));
+        }
+
+        #[tokio::test]
+        async fn todo_archive_scenario() {
+            let repo = TodoRepositoryForMemory::new(vec![]);
+            let todo = repo
+                .create(CreateTodo::new("todo text".to_string(), vec![]))
+                .await
+                .expect("failed create todo");
+            assert!(!todo.archived);
+
+            let todo = repo
+                .update(todo.id, UpdateTodo::archive(true))
+                .await
+                .expect("failed archive todo.");
+            assert!(todo.archived);
+            let todos = repo.all(TodoQuery::default()).await.unwrap();
+            assert!(todos.is_empty());
+            let todos = repo
+                .all(TodoQuery {
+                    include_archived: true,
+                    ..Default::default()
+                })
+                .await
+                .unwrap();
+            assert_eq!(vec![todo.clone()], todos);
+
+            let todo = repo
+                .update(todo.id, UpdateTodo::archive(false))
+                .await
+                .expect("failed unarchive todo.");
+            let todos = repo.all(TodoQuery::default()).await.unwrap();
+            assert_eq!(vec![todo], todos);
+        }
+
+        #[tokio::test]
+        async fn todo_search_scenario() {
+            let label_1 = Label::new(LabelId(1), "label 1".to_string());
+            let label_2 = Label::new(LabelId(2), "label 2".to_string());
+            let repo = TodoRepositoryForMemory::new(vec![label_1.clone(), label_2.clone()]);
+            for (text, labels) in [
+                ("Buy milk", vec![LabelId(1)]),
+                ("Buy bread", vec![LabelId(2)]),
+                ("Write report", vec![LabelId(1), LabelId(2)]),
+                ("Read book", vec![]),
+            ] {
+                repo.create(CreateTodo::new(text.to_string(), labels))
+                    .await
+                    .expect("failed create todo");
+            }
+            repo.update(TodoId(2), UpdateTodo::new(None, Some(true), None))
+                .await
+                .expect("failed update todo");
+            let ids = |result: TodoSearchResult| -> Vec<i32> {
+                result.items.iter().map(|todo| todo.id.0).collect()
+            };
+
+            // no filter
+            let result = repo.search(TodoSearchCriteria::default()).await.unwrap();
+            assert_eq!(4, result.total);
+            assert_eq!(vec![4, 3, 2, 1], ids(result));
+
+            // q + completed
+            let result = repo
+                .search(TodoSearchCriteria {
+                    q: Some("buy".to_string()),
+                    completed: Some(false),
+                    ..Default::default()
+                })
+                .await
+                .unwrap();
+            assert_eq!(vec![1], ids(result));
+
+            // label_ids + sort
+            let result = repo
+                .search(TodoSearchCriteria {
+                    label_ids: vec![LabelId(2)],
+                    sort: TodoSearchSort::TextAsc,
+                    ..Default::default()
+                })
+                .await
+                .unwrap();
+            assert_eq!(vec![2, 3], ids(result));
+
+            // page
+            let result = repo
+                .search(TodoSearchCriteria {
+                    sort: TodoSearchSort::IdAsc,
+                    page: 2,
+                    page_size: 3,
+                    ..Default::default()
+                })
+                .await
+                .unwrap();
+            assert_eq!(4, result.total);
+            assert_eq!(vec![4], ids(result));
+
+            // empty
+            let result = repo
+                .search(TodoSearchCriteria {
+                    q: Some("report".to_string()),
+                    completed: Some(true),
+                    ..Default::default()
+                })
+                .await
+                .unwrap();
+            assert_eq!(0, result.total);
+            assert!(result.items.is_empty());
+        }
+
+        #[tokio::test]
+        async fn todo_all_label_filter_scenario() {
+            let label_1 = Label::new(LabelId(1), "label 1".to_string());
+            let label_2 = Label::new(LabelId(2), "label 2".to_string());
+            let repo = TodoRepositoryForMemory::new(vec![label_1, label_2]);
+            for (text, labels) in [
+                ("Buy milk", vec![LabelId(1)]),
+                ("Buy bread", vec![LabelId(2)]),
+                ("Write report", vec![LabelId(1), LabelId(2)]),
+                ("Read report", vec![]),
+            ] {
+                repo.create(CreateTodo::new(text.to_string(), labels))
+                    .await
+                    .expect("failed create todo");
+            }
+            repo.update(TodoId(2), UpdateTodo::new(None, Some(true), None))
+                .await
+                .expect("failed update todo");
+            let all = |q: Option<&str>, completed: Option<bool>, label_ids: Vec<LabelId>| {
+                let query = TodoQuery {
+                    q: q.map(str::to_string),
+                    completed,
+                    label_ids,
+                    sort: TodoSort::Id,
+                    order: SortOrder::Asc,
+                    ..Default::default()
+                };
+                let repo = repo.clone();
+                async move {
+                    let todos = repo.all(query).await.unwrap();
+                    todos.iter().map(|todo| todo.id.0).collect::<Vec<_>>()
+                }
+            };
+
+            // todos with both labels are listed once
+            assert_eq!(
+                vec![1, 2, 3],
+                all(None, None, vec![LabelId(1), LabelId(2)]).await
+            );
+            assert_eq!(vec![3], all(Some("report"), None, vec![LabelId(1)]).await);
+            assert_eq!(vec![3], all(None, Some(false), vec![LabelId(2)]).await);
+            assert_eq!(vec![2], all(None, Some(true), vec![LabelId(2)]).await);
+            assert_eq!(
+                vec![1],
+                all(Some("buy"), Some(false), vec![LabelId(1), LabelId(2)]).await
+            );
+            assert_eq!(vec![1, 3, 4], all(None, Some(false), vec![]).await);
+            assert!(all(Some("milk"), None, vec![LabelId(2)]).await.is_empty());
+
+            let query = TodoQuery {
+                skip_labels: true,
+                ..Default::default()
+            };
+            let todos = repo.all(query).await.unwrap();
+            assert_eq!(4, todos.len());
+            assert!(todos.iter().all(|todo| todo.labels.is_empty()));
+        }
+
+        #[tokio::test]
+        async fn todo_unlabeled_scenario() {
+            let label = Label::new(LabelId(1), "label 1".to_string());
+            let repo = TodoRepositoryForMemory::new(vec![label]);
+            for (text, labels) in [
+                ("Write report", vec![LabelId(1)]),
+                ("Read report", vec![]),
+                ("Buy milk", vec![]),
+            ] {
+                repo.create(CreateTodo::new(text.to_string(), labels))
+                    .await
+                    .expect("failed create todo");
+            }
+            repo.update(TodoId(3), UpdateTodo::new(None, Some(true), None))
+                .await
+                .expect("failed update todo");
+            let query = |q: Option<&str>, completed: Option<bool>| TodoQuery {
+                q: q.map(str::to_string),
+                completed,
+                unlabeled: true,
+                sort: TodoSort::Id,
+                order: SortOrder::Asc,
+                ..Default::default()
+            };
+            let ids = |todos: &[TodoEntity]| -> Vec<i32> { todos.iter().map(|t| t.id.0).collect() };
+
+            let todos = repo.all(query(None, None)).await.unwrap();
+            assert_eq!(vec![2, 3], ids(&todos));
+            let todos = repo.all(query(Some("report"), None)).await.unwrap();
+            assert_eq!(vec![2], ids(&todos));
+            let todos = repo.all(query(None, Some(false))).await.unwrap();
+            assert_eq!(vec![2], ids(&todos));
+            let todos = repo.all(query(Some("write"), None)).await.unwrap();
+            assert!(todos.is_empty());
+
+            let paginated = TodoQuery {
+                page: Some(1),
+                ..query(None, Some(true))
+            };
+            let criteria = paginated.search_criteria().unwrap();
+            assert!(criteria.unlabeled);
+            let result = repo.search(criteria).await.unwrap();
+            assert_eq!(vec![3], ids(&result.items));
+
+            let query = TodoQuery {
+                label_ids: vec![LabelId(1)],
+                ..query(None, None)
+            };
+            assert!(query.validate().is_err());
+        }
+
+        #[tokio::test]
+        async fn todo_create_with_label_names() {
+            let existing = Label::new(LabelId(1), "Work".to_string());
+            let repo = TodoRepositoryForMemory::new(vec![existing.clone()]);
+            let todo = repo
+                .create(
+                    CreateTodo::new("todo text".to_string(), vec![]).with_label_names(vec![
+                        "work".to_string(),
+                        " Side   project ".to_string(),
+                        "SIDE PROJECT".to_string(),
+                    ]),
+                )
+                .await
+                .expect("failed create todo");
+            let created = Label::new(LabelId(2), "Side project".to_string());
+            assert_eq!(vec![existing.clone(), created.clone()], todo.labels);
+
+            let todo = repo
+                .create(
+                    CreateTodo::new("todo text".to_string(), vec![existing.id])
+                        .with_label_names(vec!["side project".to_string()]),
+                )
+                .await
+                .expect("failed create todo");
+            assert_eq!(vec![existing, created], todo.labels);
+        }
+
+        #[tokio::test]
+        async fn todo_labels_sorted_by_id() {
+            let labels: Vec<Label> = (1..=3)
+                .map(|i| Label::new(LabelId(i), format!("label {}", i)))
+                .collect();
+            let repo = TodoRepositoryForMemory::new(labels.clone());
+            let todo = repo
+                .create(CreateTodo::new(
+                    "todo text".to_string(),
+                    vec![LabelId(3), LabelId(1), LabelId(2)],
+                ))
+                .await
+                .expect("failed create todo");
+            assert_eq!(labels, todo.labels);
+        }
+
+        #[tokio::test]
+        async fn todo_all_sorted() {
+            let repo = TodoRepositoryForMemory::new(vec![]);
+            for text in ["first", "second", "third"] {
+                repo.create(CreateTodo::new(text.to_string(), vec![]))
+                    .await
+                    .expect("failed create todo");
+            }
+            repo.update(TodoId(1), UpdateTodo::archive(false))
+                .await
+                .expect("failed update todo");
+
+            let texts = |todos: Vec<TodoEntity>| -> Vec<String> {
+                todos.into_iter().map(|todo| todo.text).collect()
+            };
+            let all = |sort, order| {
+                repo.all(TodoQuery {
+                    sort,
+                    order,
+                    ..Default::default()
+                })
+            };
+            assert_eq!(
+                vec!["third", "second", "first"],
+                texts(all(TodoSort::CreatedAt, SortOrder::Desc).await.unwrap())
+            );
+            assert_eq!(
+                vec!["first", "second", "third"],
+                texts(all(TodoSort::CreatedAt, SortOrder::Asc).await.unwrap())
+            );
+            assert_eq!(
+                vec!["first", "third", "second"],
+                texts(all(TodoSort::UpdatedAt, SortOrder::Desc).await.unwrap())
+            );
+        }
+
+        #[tokio::test]
+        async fn todo_scoped_to_owner() {
+            let repo = TodoRepositoryForMemory::new(vec![]);
+            let alice = repo.scoped(OwnerId(1));
+            let bob = repo.scoped(OwnerId(2));
+            let todo = alice
+                .create(CreateTodo::new("todo text".to_string(), vec![]))
+                .await
+                .expect("failed create todo");
+
+            assert_eq!(todo, alice.find(todo.id).await.unwrap());
+            assert!(matches!(
+                bob.find(todo.id).await.unwrap_err().downcast_ref(),
+                Some(RepositoryError::NotFound(_))
+            ));
+            assert!(bob.all(TodoQuery::default()).await.unwrap().is_empty());
+            assert!(repo.all(TodoQuery::default()).await.unwrap().is_empty());
+        }
+
+        #[tokio::test]
+        async fn todo_max_labels() {
+            let labels: Vec<Label> = (1..=3)
+                .map(|i| Label::new(LabelId(i), format!("label {}", i)))
+                .collect();
+            let repo = TodoRepositoryForMemory::new(labels).with_max_labels(2);
+            let todo = repo
+                .create(CreateTodo::new(
+                    "todo text".to_string(),
+                    vec![LabelId(1), LabelId(2), LabelId(2)],
+                ))
+                .await
+                .expect("failed create todo");
+            assert_eq!(2, todo.labels.len());
+
+            let res = repo
+                .create(CreateTodo::new(
+                    "todo text".to_string(),
+                    vec![LabelId(1), LabelId(2), LabelId(3)],
+                ))
+                .await;
+            assert!(matches!(
+                res.unwrap_err().downcast_ref::<RepositoryError>(),
+                Some(RepositoryError::TooManyLabels(2))
+            ));
+
+            let res = repo
+                .update(
+                    todo.id,
+                    UpdateTodo::new(None, None, Some(vec![LabelId(1), LabelId(2), LabelId(3)])),
+                )
+                .await;
+            assert!(matches!(
+                res.unwrap_err().downcast_ref::<RepositoryError>(),
+                Some(RepositoryError::TooManyLabels(2))
+            ));
+            assert_eq!(todo, repo.find(todo.id).await.unwrap());
+        }
+
+        #[tokio::test]
+        async fn todo_max_joined_labels() {
+            let labels: Vec<Label> = (1..=3)
+                .map(|i| Label::new(LabelId(i), format!("label {}", i)))
+                .collect();
+            let repo = TodoRepositoryForMemory::new(labels.clone()).with_max_joined_labels(2);
+            let todo = repo
+                .create(CreateTodo::new(
+                    "todo text".to_string(),
+                    vec![LabelId(3), LabelId(1), LabelId(2)],
+                ))
+                .await
+                .expect("failed create todo");
+            assert!(!todo.labels_truncated);
+
+            let found = repo.find(todo.id).await.expect("failed find todo");
+            assert_eq!(labels[..2].to_vec(), found.labels);
+            assert!(found.labels_truncated);
+            let json = serde_json::to_value(&found).unwrap();
+            assert_eq!(serde_json::Value::Bool(true), json["labels_truncated"]);
+            let json = serde_json::to_value(&todo).unwrap();
+            assert!(json.get("labels_truncated").is_none());
+        }
+
+        #[tokio::test]
+        async fn todo_exists() {
+            let repo = TodoRepositoryForMemory::new(vec![]);
+            let todo = repo
+                .create(CreateTodo::new("todo text".to_string(), vec![]))
+                .await
+                .expect("failed create todo");
+            assert!(repo.exists(todo.id).await.unwrap());
+            assert!(!repo.exists(TodoId(2)).await.unwrap());
+
+            repo.delete(todo.id).await.expect("failed delete todo");
+            assert!(!repo.exists(todo.id).await.unwrap());
+        }
+
+        #[tokio::test]
+        async fn todo_all_sorted_by_id_desc() {
+            let repo = TodoRepositoryForMemory::new(vec![]);
+            for i in 0..10 {
+                repo.create(CreateTodo::new(format!("todo {}", i), vec![]))
+                    .await
+                    .expect("failed create todo");
+            }
+            let todos = repo.all(TodoQuery::default()).await.unwrap();
+            let ids: Vec<i32> = todos.iter().map(|todo| todo.id.0).collect();
+            assert_eq!((1..=10).rev().collect::<Vec<i32>>(), ids);
+        }
+
+        #[tokio::test]
+        async fn todo_search_ranked_scenario() {
+            let repo = TodoRepositoryForMemory::new(vec![]);
+            for text in ["Buy grocery", "Groceries list", "Write report"] {
+                repo.create(CreateTodo::new(text.to_string(), vec![]))
+                    .await
+                    .expect("failed create todo");
+            }
+            let ids = |todos: Vec<TodoEntity>| -> Vec<i32> {
+                todos.iter().map(|todo| todo.id.0).collect()
+            };
+
+            let todos = repo.search_ranked("grocry", false).await.unwrap();
+            assert_eq!(vec![1], ids(todos));
+
+            let todos = repo.search_ranked("grocerie", false).await.unwrap();
+            assert_eq!(vec![2, 1], ids(todos));
+
+            repo.update(TodoId(1), UpdateTodo::archive(true))
+                .await
+                .expect("failed archive todo");
+            let todos = repo.search_ranked("grocry", false).await.unwrap();
+            assert!(todos.is_empty());
+            let todos = repo.search_ranked("grocry", true).await.unwrap();
+            assert_eq!(vec![1], ids(todos));
+        }
+
+        #[tokio::test]
+        async fn todo_set_completed_all_scenario() {
+            let label = Label::new(LabelId(1), "label".to_string());
+            let repo = TodoRepositoryForMemory::new(vec![label.clone()]);
+            for labels in [vec![label.id], vec![], vec![label.id]] {
+                repo.create(CreateTodo::new("todo text".to_string(), labels))
+                    .await
+                    .expect("failed create todo");
+            }
+            repo.update(TodoId(3), UpdateTodo::archive(true))
+                .await
+                .expect("failed archive todo");
+            let completed_ids = |todos: Vec<TodoEntity>| -> Vec<i32> {
+                todos
+                    .iter()
+                    .filter(|todo| todo.completed)
+                    .map(|todo| todo.id.0)
+                    .collect()
+            };
+            let query = TodoQuery {
+                include_archived: true,
+                ..Default::default()
+            };
+
+            // label scoped
+            let filter = TodoFilter {
+                label_id: Some(label.id),
+            };
+            let updated = repo.set_completed_all(filter, true).await.unwrap();
+            assert_eq!(1, updated);
+            let todos = repo.all(query.clone()).await.unwrap();
+            assert_eq!(vec![1], completed_ids(todos));
+            assert!(repo.find(TodoId(1)).await.unwrap().completed_at.is_some());
+
+            // global
+            let updated = repo
+                .set_completed_all(TodoFilter::default(), true)
+                .await
+                .unwrap();
+            assert_eq!(1, updated);
+            let todos = repo.all(query.clone()).await.unwrap();
+            assert_eq!(vec![2, 1], completed_ids(todos));
+
+            let updated = repo
+                .set_completed_all(TodoFilter::default(), false)
+                .await
+                .unwrap();
+            assert_eq!(2, updated);
+            let todos = repo.all(query).await.unwrap();
+            assert!(completed_ids(todos).is_empty());
+            assert!(repo.find(TodoId(1)).await.unwrap().completed_at.is_none());
+        }
+
+        #[tokio::test]
+        async fn todo_touch_scenario() {
+            let repo = TodoRepositoryForMemory::new(vec![]);
+            let created = repo
+                .create(CreateTodo::new("todo text".to_string(), vec![]))
+                .await
+                .expect("failed create todo");
+            let modified_at = repo.last_modified().await.unwrap();
+
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            let touched = repo.touch(created.id).await.unwrap();
+            assert!(touched.updated_at > created.updated_at);
+            let updated_at = touched.updated_at;
+            assert_eq!(
+                TodoEntity {
+                    updated_at,
+                    ..created
+                },
+                touched
+            );
+            assert_eq!(touched, repo.find(created.id).await.unwrap());
+            assert!(repo.last_modified().await.unwrap() > modified_at);
+            assert!(repo.scoped(OwnerId(1)).touch(created.id).await.is_err());
+        }
+
+        #[tokio::test]
+        async fn todo_merge_labels_scenario() {
+            use crate::repositories::label::LabelRepository;
+
+            let keep = Label::new(LabelId(1), "keep".to_string());
+            let remove = Label::new(LabelId(2), "remove".to_string());
+            let label_repo = LabelRepositoryForMemory::with_labels(vec![keep.clone(), remove]);
+            let repo = TodoRepositoryForMemory::with_label_repository(label_repo.clone());
+            let both = repo
+                .create(CreateTodo::new(
+                    "both".to_string(),
+                    vec![LabelId(1), LabelId(2)],
+                ))
+                .await
+                .expect("failed create todo");
+            let removed = repo
+                .create(CreateTodo::new("removed".to_string(), vec![LabelId(2)]))
+                .await
+                .expect("failed create todo");
+
+            label_repo
+                .merge(LabelId(1), LabelId(2))
+                .await
+                .expect("failed merge labels");
+            for todo in [both, removed] {
+                let merged = repo.find(todo.id).await.expect("failed find todo");
+                assert_eq!(vec![keep.clone()], merged.labels);
+            }
+        }
+
+        #[tokio::test]
+        async fn todo_conditional_update_scenario() {
+            let repo = TodoRepositoryForMemory::new(vec![]);
+            let created = repo
+                .create(CreateTodo::new("todo text".to_string(), vec![]))
+                .await
+                .expect("failed create todo");
+
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            let updated = repo
+                .update(
+                    created.id,
+                    UpdateTodo::new(Some("matched".to_string()), None, None)
+                        .if_updated_at(created.updated_at),
+                )
+                .await
+                .expect("failed update todo");
+            let res = repo
+                .update(
+                    created.id,
+                    UpdateTodo::new(Some("stale".to_string()), None, None)
+                        .if_updated_at(created.updated_at),
+                )
+                .await;
+            assert!(matches!(
+                res.unwrap_err().downcast_ref::<RepositoryError>(),
+                Some(RepositoryError::Stale(_))
+            ));
+            assert_eq!(updated, repo.find(created.id).await.unwrap());
+        }
+
+        #[tokio::test]
+        async fn todo_find_many_scenario() {
+            let label = Label::new(LabelId(1), "label".to_string());
+            let repo = TodoRepositoryForMemory::new(vec![label.clone()]);
+            let mut todos = vec![];
+            for labels in [vec![label.id], vec![], vec![]] {
+                let todo = repo
+                    .create(CreateTodo::new("todo text".to_string(), labels))
+                    .await
+                    .expect("failed create todo");
+                todos.push(todo);
+            }
+            let foreign = repo
+                .scoped(OwnerId(1))
+                .create(CreateTodo::new("foreign".to_string(), vec![]))
+                .await
+                .expect("failed create todo");
+
+            let ids = [TodoId(3), TodoId(10), foreign.id, TodoId(1), TodoId(3)];
+            let found = repo.find_many(&ids).await.unwrap();
+            assert_eq!(vec![todos[2].clone(), todos[0].clone()], found);
+            assert_eq!(vec![label], found[1].labels);
+            assert!(repo.find_many(&[]).await.unwrap().is_empty());
+        }
+
+        #[tokio::test]
+        async fn todo_attach_label_scenario() {
+            let label = Label::new(LabelId(1), "label".to_string());
+            let other = Label::new(LabelId(2), "other".to_string());
+            let repo = TodoRepositoryForMemory::new(vec![label.clone(), other.clone()]);
+            for labels in [vec![label.id], vec![], vec![other.id]] {
+                repo.create(CreateTodo::new("todo text".to_string(), labels))
+                    .await
+                    .expect("failed create todo");
+            }
+            let foreign = repo
+                .scoped(OwnerId(1))
+                .create(CreateTodo::new("foreign".to_string(), vec![]))
+                .await
+                .expect("failed create todo");
+
+            let ids = vec![TodoId(1), TodoId(2), foreign.id, TodoId(3), TodoId(10)];
+            let attached = repo.attach_label(label.id, ids).await.unwrap();
+            assert_eq!(
+                AttachedLabel {
+                    attached: 2,
+                    not_found: vec![foreign.id, TodoId(10)],
+                },
+                attached
+            );
+            let todo = repo.find(TodoId(2)).await.unwrap();
+            assert_eq!(vec![label.clone()], todo.labels);
+            let todo = repo.find(TodoId(3)).await.unwrap();
+            assert_eq!(vec![label.clone(), other.clone()], todo.labels);
+            assert!(repo.find(foreign.id).await.is_err());
+
+            // over max_labels
+            let res = repo
+                .clone()
+                .with_max_labels(1)
+                .attach_label(other.id, vec![TodoId(2)])
+                .await;
+            assert!(matches!(
+                res.unwrap_err().downcast_ref::<RepositoryError>(),
+                Some(RepositoryError::TooManyLabels(1))
+            ));
+            let todo = repo.find(TodoId(2)).await.unwrap();
+            assert_eq!(vec![label.clone()], todo.labels);
+
+            let res = repo.attach_label(LabelId(3), vec![TodoId(1)]).await;
+            assert!(matches!(
+                res.unwrap_err().downcast_ref::<RepositoryError>(),
+                Some(RepositoryError::NotFound(_))
+            ));
+        }
+
+        #[tokio::test]
+        async fn todo_completed_at_transitions() {
+            let repo = TodoRepositoryForMemory::new(vec![]);
+            let todo = repo
+                .create(CreateTodo::new("todo text".to_string(), vec![]))
+                .await
+                .expect("failed create todo");
+            assert!(todo.completed_at.is_none());
+
+            // false -> true
+            let completed = repo
+                .update(todo.id, UpdateTodo::new(None, Some(true), None))
+                .await
+                .expect("failed update todo.");
+            let completed_at = completed.completed_at.expect("completed_at is not set");
+
+            // no-op
+            let todo = repo
+                .update(
+                    todo.id,
+                    UpdateTodo::new(Some("renamed".to_string()), Some(true), None),
+                )
+                .await
+                .expect("failed update todo.");
+            assert_eq!(todo.completed_at, Some(completed_at));
+
+            // true -> false
+            let todo = repo
+                .update(todo.id, UpdateTodo::new(None, Some(false), None))
+                .await
+                .expect("failed update todo.");
+            assert!(todo.completed_at.is_none());
+        }
+    }
+
+    #[cfg(test)]
+    #[cfg(feature = "uuid")]
+    mod uuid_test {
+        use super::*;
+        use crate::repositories::label::{CreateLabel, LabelRepository};
+
+        #[tokio::test]
+        async fn todo_round_trips_uuid() {
+            let label_repo = LabelRepositoryForMemory::new();
+            let label = label_repo
+                .create(CreateLabel::new("uuid label".to_string()))
+                .await
+                .expect("failed create label");
+            let repo = TodoRepositoryForMemory::with_label_repository(label_repo);
+
+            let created = repo
+                .create(CreateTodo::new("uuid todo".to_string(), vec![label.id]))
+                .await
+                .expect("failed create todo");
+            let other = repo
+                .create(CreateTodo::new("other todo".to_string(), vec![]))
+                .await
+                .expect("failed create todo");
+            assert_ne!(created.id, other.id);
+
+            let id: TodoId = created.id.to_string().parse().unwrap();
+            let todo = repo.find(id).await.expect("failed find todo");
+            assert_eq!(created, todo);
+            assert_eq!(vec![label], todo.labels);
+        }
+    }
+}
+
+#[cfg(feature = "sqlite")]
+pub mod sqlite {
+    use super::*;
+    use sqlx::{Sqlite, SqlitePool, Transaction};
+
+    const SELECT_TODOS_WITH_LABELS: &str = r#"
+        SELECT todos.*, labels.id as label_id, labels.name as label_name FROM todos
+        LEFT OUTER JOIN todo_labels t1 on todos.id = t1.todo_id
+        LEFT OUTER JOIN labels on labels.id = t1.label_id"#;
+
+    /// Rows of `SELECT_TODOS_WITH_LABELS` without a label, for `TodoQuery::skip_labels`.
+    const SELECT_TODOS_WITHOUT_LABELS: &str = r#"
+        SELECT todos.*, NULL as label_id, NULL as label_name FROM todos"#;
+
+    #[derive(Clone)]
+    pub struct TodoRepositoryForSqlite {
+        pool: SqlitePool,
+        owner: OwnerId,
+        default_label: Option<LabelId>,
+        max_labels: usize,
+        max_joined_labels: usize,
+        /// Instant `find` and `all` give up at, set per request by `with_deadline`.
+        deadline: Option<tokio::time::Instant>,
+    }
+
+    impl TodoRepositoryForSqlite {
+        pub fn new(pool: SqlitePool) -> Self {
+            Self {
+                pool,
+                owner: OwnerId::default(),
+                default_label: None,
+                max_labels: DEFAULT_MAX_LABELS_PER_TODO,
+                max_joined_labels: DEFAULT_MAX_JOINED_LABELS,
+                deadline: None,
+            }
+        }
+
+        pub fn with_default_label(mut self, default_label: Option<LabelId>) -> Self {
+            self.default_label = default_label;
+            self
+        }
+
+        pub fn with_max_labels(mut self, max_labels: usize) -> Self {
+            self.max_labels = max_labels;
+            self
+        }
+
+        pub fn with_max_joined_labels(mut self, max_joined_labels: usize) -> Self {
+            self.max_joined_labels = max_joined_labels;
+            self
+        }
+    }
+
+    /// Folds the join rows of a todo already known to exist; no rows at that point means the
+    /// join itself misbehaved, which is reported as unexpected rather than not found.
+    fn existing_entity(
+        id: TodoId,
+        rows: Vec<TodoWithLabelFromRow>,
+    ) -> Result<TodoEntity, RepositoryError> {
+        fold_entities(rows).into_iter().next().ok_or_else(|| {
+            tracing::error!("todo {:?} exists but its join returned no rows", id);
+            RepositoryError::Unexpected(format!("empty join rows for todo {}", id.0))
+        })
+    }
+
+    /// SQLite has no `unnest`, so associations are inserted one by one in the given order,
+    /// skipping the labels that are not `owner`'s like `INSERT_TODO_LABELS`.
+    async fn insert_todo_labels(
+        tx: &mut Transaction<'_, Sqlite>,
+        todo_id: TodoId,
+        label_ids: &[LabelId],
+        owner: OwnerId,
+    ) -> anyhow::Result<Vec<Label>> {
+        let mut labels = Vec::with_capacity(label_ids.len());
+        for &label_id in label_ids {
+            let label = sqlx::query_as::<_, Label>(
+                r#"SELECT * FROM labels WHERE id = ?1 AND owner_id = ?2"#,
+            )
+            .bind(label_id)
+            .bind(owner)
+            .fetch_optional(&mut *tx)
+            .await
+            .context("insert todo labels")?;
+            let Some(label) = label else {
+                continue;
+            };
+            sqlx::query(r#"INSERT INTO todo_labels (todo_id, label_id) VALUES (?1, ?2)"#)
+                .bind(todo_id)
+                .bind(label_id)
+                .execute(&mut *tx)
+                .await
+                .context("insert todo labels")?;
+            labels.push(label);
+        }
+        Ok(labels)
+    }
+
+    fn push_search_conditions(
+        query: &mut QueryBuilder<Sqlite>,
+        owner: OwnerId,
+        criteria: &TodoSearchCriteria,
+    ) {
+        query.push(" WHERE todos.owner_id = ").push_bind(owner);
+        if !criteria.include_archived {
+            query.push(" AND NOT todos.archived");
+        }
+        if let Some(q) = &criteria.q {
+            query
+                .push(" AND todos.text LIKE ")
+                .push_bind(like_pattern(q))
+                .push(r#" ESCAPE '\'"#);
+        }
+        if let Some(completed) = criteria.completed {
+            query.push(" AND todos.completed = ").push_bind(completed);
+        }
+        if !criteria.label_ids.is_empty() {
+            query.push(" AND todos.id IN (SELECT todo_id FROM todo_labels WHERE label_id IN (");
+            let mut ids = query.separated(", ");
+            for id in criteria.label_ids.iter() {
+                ids.push_bind(*id);
+            }
+            query.push("))");
+        }
+        if criteria.unlabeled {
+            query.push(" AND NOT EXISTS (SELECT 1 FROM todo_labels WHERE todo_id = todos.id)");
+        }
+    }
+
+    impl OwnerScoped for TodoRepositoryForSqlite {
+        fn scoped(&self, owner: OwnerId) -> Self {
+            Self {
+                owner,
+                ..self.clone()
+            }
+        }
+
+        fn with_deadline(&self, deadline: tokio::time::Instant) -> Self {
+            Self {
+                deadline: Some(deadline),
+                ..self.clone()
+            }
+        }
+    }
+
+    #[async_trait]
+    impl TodoRepository for TodoRepositoryForSqlite {
+        async fn create(&self, payload: CreateTodo) -> anyhow::Result<TodoEntity> {
+            let mut tx = self.pool.begin().await.context("create todo")?;
+            let row = sqlx::query_as::<_, TodoFromRow>(
+                r#"INSERT INTO todos (text, completed, owner_id, due_date, priority, created_at, updated_at) VALUES (?1, false, ?2, ?3, ?4, ?5, ?5) RETURNING *;"#,
+            )
+            .bind(payload.text.clone())
+            .bind(self.owner)
+            .bind(payload.due_date)
+            .bind(payload.priority)
+            .bind(Utc::now())
+            .fetch_one(&mut tx)
+            .await
+            .context("create todo")?;
+
+            let mut label_ids = payload.labels;
+            for name in unique_label_names(payload.label_names) {
+                let existing = sqlx::query_as::<_, Label>(
+                    r#"SELECT * FROM labels WHERE lower(name) = lower(?1) AND owner_id = ?2 ORDER BY id LIMIT 1"#,
+                )
+                .bind(name.clone())
+                .bind(self.owner)
+                .fetch_optional(&mut tx)
+                .await
+                .context("create todo")?;
+                let label = match existing {
+                    Some(label) => label,
+                    None => sqlx::query_as::<_, Label>(
+                        r#"INSERT INTO labels (name, owner_id) VALUES (?1, ?2) RETURNING *"#,
+                    )
+                    .bind(name)
+                    .bind(self.owner)
+                    .fetch_one(&mut tx)
+                    .await
+                    .context("create todo")?,
+                };
+                label_ids.push(label.id);
+            }
+
+            let requested = unique_label_ids(label_ids);
+            let label_ids = labels_or_default(requested.clone(), self.default_label);
+            check_label_count(&label_ids, self.max_labels)?;
+            let labels = insert_todo_labels(&mut tx, row.id, &label_ids, self.owner)
+                .await
+                .context("create todo")?;
+            check_labels_found(&requested, &labels)?;
+            tx.commit().await.context("create todo")?;
+
+            Ok(row.into_entity(labels))
+        }
+
+        async fn find(&self, id: TodoId) -> anyhow::Result<TodoEntity> {
+            until_deadline(self.deadline, async move {
+                if !self.exists(id).await.context("find todo")? {
+                    return Err(RepositoryError::NotFound(id.into()).into());
+                }
+                // one label over the cap tells whether some were left out
+                let items = sqlx::query_as::<_, TodoWithLabelFromRow>(
+                    r#"
+                SELECT todos.*, labels.id as label_id, labels.name as label_name FROM todos
+                LEFT OUTER JOIN labels on labels.id IN (
+                    SELECT t1.label_id FROM todo_labels t1
+                    WHERE t1.todo_id = todos.id
+                    ORDER BY t1.label_id LIMIT ?3
+                )
+                WHERE todos.id = ?1 AND todos.owner_id = ?2;"#,
+                )
+                .bind(id)
+                .bind(self.owner)
+                .bind(self.max_joined_labels as i64 + 1)
+                .fetch_all(&self.pool)
+                .await
+                .context("find todo")?;
+
+                Ok(existing_entity(id, items)?.cap_labels(self.max_joined_labels))
+            })
+            .await
+        }
+
+        async fn exists(&self, id: TodoId) -> anyhow::Result<bool> {
+            let (exists,) = sqlx::query_as::<_, (bool,)>(
+                r#"SELECT EXISTS(SELECT 1 FROM todos WHERE id = ?1 AND owner_id = ?2)"#,
+            )
+            .bind(id)
+            .bind(self.owner)
+            .fetch_one(&self.pool)
+            .await
+            .context("check todo exists")?;
+            Ok(exists)
+        }
+
+        async fn find_many(&self, ids: &[TodoId]) -> anyhow::Result<Vec<TodoEntity>> {
+            until_deadline(self.deadline, async move {
+                let items = sqlx::query_as::<_, TodoWithLabelFromRow>(&format!(
+                    r#"{} WHERE todos.id IN (SELECT value FROM json_each(?1))
+                    AND todos.owner_id = ?2;"#,
+                    SELECT_TODOS_WITH_LABELS
+                ))
+                .bind(serde_json::to_string(ids)?)
+                .bind(self.owner)
+                .fetch_all(&self.pool)
+                .await
+                .context("find todos")?;
+
+                Ok(in_order_of(ids, fold_entities(items)))
+            })
+            .await
+        }
+
+        async fn all(&self, query: TodoQuery) -> anyhow::Result<Vec<TodoEntity>> {
+            until_deadline(self.deadline, async move {
+                let (select, label_order) = if query.skip_labels {
+                    (SELECT_TODOS_WITHOUT_LABELS, "")
+                } else {
+                    (SELECT_TODOS_WITH_LABELS, ", t1.id")
+                };
+                let items = sqlx::query_as::<_, TodoWithLabelFromRow>(&format!(
+                    r#"{} WHERE todos.owner_id = ?3 AND (?1 OR NOT todos.archived)
+                    AND (?2 IS NULL OR todos.text LIKE ?2 ESCAPE '\')
+                    AND (?4 IS NULL OR todos.completed = ?4)
+                    AND (json_array_length(?5) = 0 OR todos.id IN (
+                        SELECT todo_id FROM todo_labels
+                        WHERE label_id IN (SELECT value FROM json_each(?5))
+                    ))
+                    AND (NOT ?6 OR NOT EXISTS (SELECT 1 FROM todo_labels WHERE todo_id = todos.id))
+                    ORDER BY {}{};"#,
+                    select,
+                    query.order_by(),
+                    label_order
+                ))
+                .bind(query.include_archived)
+                .bind(query.q.as_deref().map(like_pattern))
+                .bind(self.owner)
+                .bind(query.completed)
+                .bind(serde_json::to_string(&query.label_ids)?)
+                .bind(query.unlabeled)
+                .fetch_all(&self.pool)
+                .await
+                .context("list todos")?;
+
+                Ok(fold_entities(items))
+            })
+            .await
+        }
+
+        async fn update(&self, id: TodoId, payload: UpdateTodo) -> anyhow::Result<TodoEntity> {
+            let labels = payload.labels.map(unique_label_ids);
+            if let Some(labels) = &labels {
+                check_label_count(labels, self.max_labels)?;
+            }
+            let old_todo = self.find(id).await.context("update todo")?;
+            let completed = payload.completed.unwrap_or(old_todo.completed);
+            let mut tx = self.pool.begin().await.context("update todo")?;
+            // sqlx writes the timestamps in a text form that round-trips, rows stamped by the
+            // migration end in `Z` and hold milliseconds, which julianday still tells apart
+            let row = sqlx::query_as::<_, TodoFromRow>(
+                r#"UPDATE todos SET text = ?1, completed = ?2, completed_at = ?3, archived = ?4, due_date = ?5, priority = ?6, updated_at = ?9 WHERE id = ?7 AND owner_id = ?8 AND (?10 IS NULL OR updated_at = ?10 OR (updated_at LIKE '%Z' AND julianday(updated_at) = julianday(?10))) RETURNING *"#,
+            )
+            .bind(payload.text.unwrap_or(old_todo.text.clone()))
+            .bind(completed)
+            .bind(next_completed_at(&old_todo, completed, Utc::now()))
+            .bind(payload.archived.unwrap_or(old_todo.archived))
+            .bind(payload.due_date.apply(old_todo.due_date))
+            .bind(payload.priority.apply(old_todo.priority))
+            .bind(id)
+            .bind(self.owner)
+            .bind(Utc::now())
+            .bind(payload.if_updated_at)
+            .fetch_optional(&mut tx)
+            .await
+            .context("update todo")?
+            .ok_or_else(|| missing_update(id, payload.if_updated_at))?;
+
+            let labels_truncated = labels.is_none() && old_todo.labels_truncated;
+            let labels = match labels {
+                Some(labels) => {
+                    sqlx::query(r#"DELETE FROM todo_labels WHERE todo_id = ?1"#)
+                        .bind(id)
+                        .execute(&mut tx)
+                        .await
+                        .context("update todo")?;
+                    let inserted = insert_todo_labels(&mut tx, id, &labels, self.owner)
+                        .await
+                        .context("update todo")?;
+                    check_labels_found(&labels, &inserted)?;
+                    inserted
+                }
+                None => old_todo.labels.clone(),
+            };
+            let todo = TodoEntity {
+                labels_truncated,
+                ..row.into_entity(labels)
+            };
+            for change in todo_changes(&old_todo, &todo) {
+                sqlx::query(
+                    r#"INSERT INTO todo_history (todo_id, changed_at, field, old, new) VALUES (?1, ?2, ?3, ?4, ?5)"#,
+                )
+                .bind(id)
+                .bind(change.changed_at)
+                .bind(change.field)
+                .bind(change.old)
+                .bind(change.new)
+                .execute(&mut tx)
+                .await
+                .context("update todo")?;
+            }
+            tx.commit().await.context("update todo")?;
+
+            Ok(todo)
+        }
+
+        async fn delete(&self, id: TodoId) -> anyhow::Result<()> {
+            let mut tx = self.pool.begin().await.context("delete todo")?;
+            sqlx::query(r#"DELETE FROM todo_history WHERE todo_id = ?1"#)
+                .bind(id)
+                .execute(&mut tx)
+                .await
+                .context("delete todo")?;
+            sqlx::query(r#"DELETE FROM todo_labels WHERE todo_id = ?1"#)
+                .bind(id)
+                .execute(&mut tx)
+                .await
+                .context("delete todo")?;
+            // dropping the transaction rolls back the deletes above when the todo is not ours
+            let deleted = sqlx::query(r#"DELETE FROM todos WHERE id = ?1 AND owner_id = ?2"#)
+                .bind(id)
+                .bind(self.owner)
+                .execute(&mut tx)
+                .await
+                .context("delete todo")?;
+            if deleted.rows_affected() == 0 {
+                return Err(RepositoryError::NotFound(id.into()).into());
+            }
+            sqlx::query(
+                r#"INSERT OR REPLACE INTO todo_tombstones (todo_id, owner_id, deleted_at) VALUES (?1, ?2, ?3)"#,
+            )
+            .bind(id)
+            .bind(self.owner)
+            .bind(Utc::now())
+            .execute(&mut tx)
+            .await
+            .context("delete todo")?;
+            tx.commit().await.context("delete todo")?;
+
+            Ok(())
+        }
+
+        async fn touch(&self, id: TodoId) -> anyhow::Result<TodoEntity> {
+            let result =
+                sqlx::query(r#"UPDATE todos SET updated_at = ?1 WHERE id = ?2 AND owner_id = ?3"#)
+                    .bind(Utc::now())
+                    .bind(id)
+                    .bind(self.owner)
+                    .execute(&self.pool)
+                    .await
+                    .context("touch todo")?;
+            if result.rows_affected() == 0 {
+                return Err(RepositoryError::NotFound(id.into()).into());
+            }
+            self.find(id).await
+        }
+
+        async fn duplicate(&self, id: TodoId) -> anyhow::Result<TodoEntity> {
+            let mut tx = self.pool.begin().await.context("duplicate todo")?;
+            let row = sqlx::query_as::<_, TodoFromRow>(
+                r#"INSERT INTO todos (text, completed, owner_id, due_date, priority, created_at, updated_at) SELECT text, false, owner_id, due_date, priority, ?3, ?3 FROM todos WHERE id = ?1 AND owner_id = ?2 RETURNING *"#,
+            )
+            .bind(id)
+            .bind(self.owner)
+            .bind(Utc::now())
+            .fetch_optional(&mut tx)
+            .await
+            .context("duplicate todo")?
+            .ok_or(RepositoryError::NotFound(id.into()))?;
+            sqlx::query(
+                r#"INSERT INTO todo_labels (todo_id, label_id) SELECT ?1, label_id FROM todo_labels WHERE todo_id = ?2 ORDER BY id"#,
+            )
+            .bind(row.id)
+            .bind(id)
+            .execute(&mut tx)
+            .await
+            .context("duplicate todo")?;
+            let labels = sqlx::query_as::<_, Label>(
+                r#"SELECT labels.* FROM todo_labels JOIN labels on labels.id = todo_labels.label_id WHERE todo_labels.todo_id = ?1"#,
+            )
+            .bind(row.id)
+            .fetch_all(&mut tx)
+            .await
+            .context("duplicate todo")?;
+            tx.commit().await.context("duplicate todo")?;
+
+            Ok(row.into_entity(labels))
+        }
+
+        async fn history(&self, id: TodoId) -> anyhow::Result<Vec<TodoChange>> {
+            if !self.exists(id).await.context("read todo history")? {
+                return Err(RepositoryError::NotFound(id.into()).into());
+            }
+            let changes = sqlx::query_as::<_, TodoChange>(
+                r#"SELECT changed_at, field, old, new FROM todo_history WHERE todo_id = ?1 ORDER BY changed_at, id"#,
+            )
+            .bind(id)
+            .fetch_all(&self.pool)
+            .await
+            .context("read todo history")?;
+            Ok(changes)
         }
 
-        fn resolve_labels(&self, labels: Vec<i32>) -> Vec<Label> {
-            let mut label_list = self.labels.iter().cloned();
-            let labels = labels
-                .iter()
-                .map(|id| label_list.find(|label| label.id == *id).unwrap())
-                .collect();
-            labels
+        async fn reorder(&self, ids: Vec<TodoId>) -> anyhow::Result<()> {
+            let mut tx = self.pool.begin().await.context("reorder todos")?;
+            for id in ids.iter() {
+                let (exists,) = sqlx::query_as::<_, (bool,)>(
+                    r#"SELECT EXISTS(SELECT 1 FROM todos WHERE id = ?1 AND owner_id = ?2)"#,
+                )
+                .bind(id)
+                .bind(self.owner)
+                .fetch_one(&mut tx)
+                .await
+                .context("reorder todos")?;
+                if !exists {
+                    return Err(RepositoryError::NotFound((*id).into()).into());
+                }
+            }
+            sqlx::query(r#"UPDATE todos SET position = NULL WHERE owner_id = ?1"#)
+                .bind(self.owner)
+                .execute(&mut tx)
+                .await
+                .context("reorder todos")?;
+            for (position, id) in (1..).zip(ids) {
+                sqlx::query(r#"UPDATE todos SET position = ?1 WHERE id = ?2 AND owner_id = ?3"#)
+                    .bind(position)
+                    .bind(id)
+                    .bind(self.owner)
+                    .execute(&mut tx)
+                    .await
+                    .context("reorder todos")?;
+            }
+            tx.commit().await.context("reorder todos")?;
+
+            Ok(())
         }
-    }
 
-    #[async_trait]
-    impl TodoRepository for TodoRepositoryForMemory {
-        async fn create(&self, payload: CreateTodo) -> anyhow::Result<TodoEntity> {
-            let mut store = self.write_store_ref();
-            let id = (store.len() + 1) as i32;
-            let labels = self.resolve_labels(payload.labels);
-            let todo = TodoEntity::new(id, payload.text.clone(), false, labels);
-            store.insert(id, todo.clone());
-            Ok(todo)
+        async fn changed_since(&self, since: DateTime<Utc>) -> anyhow::Result<Vec<SyncedTodo>> {
+            let items = sqlx::query_as::<_, TodoWithLabelFromRow>(&format!(
+                "{} WHERE todos.owner_id = ?1 AND todos.updated_at > ?2",
+                SELECT_TODOS_WITH_LABELS
+            ))
+            .bind(self.owner)
+            .bind(since)
+            .fetch_all(&self.pool)
+            .await
+            .context("list changed todos")?;
+            let tombstones = sqlx::query_as::<_, (TodoId, DateTime<Utc>)>(
+                r#"SELECT todo_id, deleted_at FROM todo_tombstones WHERE owner_id = ?1 AND deleted_at > ?2"#,
+            )
+            .bind(self.owner)
+            .bind(since)
+            .fetch_all(&self.pool)
+            .await
+            .context("list changed todos")?;
+
+            Ok(synced_todos(fold_entities(items), tombstones))
         }
 
-        async fn find(&self, id: i32) -> anyhow::Result<TodoEntity> {
-            let store = self.read_store_ref();
-            let todo = store
-                .get(&id)
-                .cloned()
-                .ok_or(RepositoryError::NotFound(id))?;
-            Ok(todo)
+        async fn search(&self, criteria: TodoSearchCriteria) -> anyhow::Result<TodoSearchResult> {
+            let mut count_query = QueryBuilder::new(r#"SELECT count(*) FROM todos"#);
+            push_search_conditions(&mut count_query, self.owner, &criteria);
+            let (total,) = count_query
+                .build_query_as::<(i64,)>()
+                .fetch_one(&self.pool)
+                .await
+                .context("search todos")?;
+
+            let mut query = QueryBuilder::new(SELECT_TODOS_WITH_LABELS);
+            query.push(" WHERE todos.id IN (SELECT todos.id FROM todos");
+            push_search_conditions(&mut query, self.owner, &criteria);
+            query
+                .push(" ORDER BY ")
+                .push(criteria.sort.order_by())
+                .push(" LIMIT ")
+                .push_bind(criteria.page_size)
+                .push(" OFFSET ")
+                .push_bind(criteria.offset())
+                .push(") ORDER BY ")
+                .push(criteria.sort.order_by())
+                .push(", t1.id");
+            let items = query
+                .build_query_as::<TodoWithLabelFromRow>()
+                .fetch_all(&self.pool)
+                .await
+                .context("search todos")?;
+
+            Ok(TodoSearchResult {
+                items: fold_entities(items),
+                total,
+                page: criteria.page,
+                page_size: criteria.page_size,
+            })
         }
 
-        async fn all(&self) -> anyhow::Result<Vec<TodoEntity>> {
-            let store = self.read_store_ref();
-            Ok(Vec::from_iter(store.values().map(|todo| todo.clone())))
+        async fn search_ranked(
+            &self,
+            q: &str,
+            include_archived: bool,
+        ) -> anyhow::Result<Vec<TodoEntity>> {
+            let items = sqlx::query_as::<_, TodoWithLabelFromRow>(&format!(
+                "{} WHERE todos.owner_id = ?1 AND (?2 OR NOT todos.archived) ORDER BY todos.id, t1.id;",
+                SELECT_TODOS_WITH_LABELS
+            ))
+            .bind(self.owner)
+            .bind(include_archived)
+            .fetch_all(&self.pool)
+            .await
+            .context("rank todos")?;
+
+            Ok(rank_by_distance(fold_entities(items).into_iter(), q))
         }
 
-        async fn update(&self, id: i32, payload: UpdateTodo) -> anyhow::Result<TodoEntity> {
-            let mut store = self.write_store_ref();
-            let todo = store.get(&id).context(RepositoryError::NotFound(id))?;
-            let text = payload.text.unwrap_or(todo.text.clone());
-            let completed = payload.completed.unwrap_or(todo.completed);
-            let labels = match payload.labels {
-                Some(label_ids) => self.resolve_labels(label_ids),
-                None => todo.labels.clone(),
-            };
-            let todo = TodoEntity::new(id, text, completed, labels);
-            store.insert(id, todo.clone());
+        async fn set_completed_all(
+            &self,
+            filter: TodoFilter,
+            completed: bool,
+        ) -> anyhow::Result<u64> {
+            let result = sqlx::query(
+                r#"
+            UPDATE todos SET completed = ?1, completed_at = CASE WHEN ?1 THEN ?2 END, updated_at = ?2
+            WHERE owner_id = ?4 AND completed <> ?1 AND NOT archived
+            AND (?3 IS NULL OR id IN (SELECT todo_id FROM todo_labels WHERE label_id = ?3));"#,
+            )
+            .bind(completed)
+            .bind(Utc::now())
+            .bind(filter.label_id)
+            .bind(self.owner)
+            .execute(&self.pool)
+            .await
+            .context("complete todos")?;
 
-            Ok(todo)
+            Ok(result.rows_affected())
         }
 
-        async fn delete(&self, id: i32) -> anyhow::Result<()> {
-            let mut store = self.write_store_ref();
-            store.remove(&id).ok_or(RepositoryError::NotFound(id))?;
-            Ok(())
+        async fn attach_label(
+            &self,
+            label: LabelId,
+            ids: Vec<TodoId>,
+        ) -> anyhow::Result<AttachedLabel> {
+            let mut tx = self.pool.begin().await.context("attach label")?;
+            sqlx::query(r#"SELECT id FROM labels WHERE id = ?1 AND owner_id = ?2"#)
+                .bind(label)
+                .bind(self.owner)
+                .fetch_optional(&mut tx)
+                .await
+                .context("attach label")?
+                .ok_or(RepositoryError::NotFound(label.into()))?;
+            let now = Utc::now();
+            let mut attached = 0;
+            let mut not_found = vec![];
+            for id in ids {
+                let (exists,) = sqlx::query_as::<_, (bool,)>(
+                    r#"SELECT EXISTS(SELECT 1 FROM todos WHERE id = ?1 AND owner_id = ?2)"#,
+                )
+                .bind(id)
+                .bind(self.owner)
+                .fetch_one(&mut tx)
+                .await
+                .context("attach label")?;
+                if !exists {
+                    not_found.push(id);
+                    continue;
+                }
+                let result = sqlx::query(
+                    r#"INSERT INTO todo_labels (todo_id, label_id) VALUES (?1, ?2) ON CONFLICT DO NOTHING"#,
+                )
+                .bind(id)
+                .bind(label)
+                .execute(&mut tx)
+                .await
+                .context("attach label")?;
+                if result.rows_affected() == 0 {
+                    continue;
+                }
+                let (count,) = sqlx::query_as::<_, (i64,)>(
+                    r#"SELECT COUNT(*) FROM todo_labels WHERE todo_id = ?1"#,
+                )
+                .bind(id)
+                .fetch_one(&mut tx)
+                .await
+                .context("attach label")?;
+                if count as usize > self.max_labels {
+                    return Err(RepositoryError::TooManyLabels(self.max_labels).into());
+                }
+                sqlx::query(r#"UPDATE todos SET updated_at = ?1 WHERE id = ?2"#)
+                    .bind(now)
+                    .bind(id)
+                    .execute(&mut tx)
+                    .await
+                    .context("attach label")?;
+                attached += 1;
+            }
+            tx.commit().await.context("attach label")?;
+
+            Ok(AttachedLabel {
+                attached,
+                not_found,
+            })
+        }
+
+        async fn summary(&self, filter: TodoFilter) -> anyhow::Result<TodoSummary> {
+            let (total, completed) = sqlx::query_as::<_, (i64, i64)>(
+                r#"
+            SELECT count(*), count(*) FILTER (WHERE completed) FROM todos
+            WHERE owner_id = ?1 AND NOT archived
+            AND (?2 IS NULL OR id IN (SELECT todo_id FROM todo_labels WHERE label_id = ?2));"#,
+            )
+            .bind(self.owner)
+            .bind(filter.label_id)
+            .fetch_one(&self.pool)
+            .await
+            .context("summarize todos")?;
+
+            Ok(TodoSummary::new(total, completed))
+        }
+
+        async fn last_modified(&self) -> anyhow::Result<DateTime<Utc>> {
+            let (modified_at,) = sqlx::query_as::<_, (DateTime<Utc>,)>(
+                r#"SELECT coalesce((SELECT modified_at FROM todos_modified WHERE owner_id = ?1), '1970-01-01T00:00:00.000Z')"#,
+            )
+            .bind(self.owner)
+            .fetch_one(&self.pool)
+            .await
+            .context("read todos last modified")?;
+            Ok(modified_at)
         }
     }
 
+    #[cfg(test)]
     mod test {
         use super::*;
+        use crate::repositories::migrate_sqlite;
+        use sqlx::sqlite::SqlitePoolOptions;
+
+        async fn connect() -> SqlitePool {
+            // every in-memory connection is its own database, so keep a single one
+            let pool = SqlitePoolOptions::new()
+                .max_connections(1)
+                .connect("sqlite::memory:")
+                .await
+                .expect("fail connect sqlite");
+            migrate_sqlite(&pool).await.expect("fail migrate sqlite");
+            pool
+        }
+
+        #[test]
+        fn existing_entity_test() {
+            let now = Utc::now();
+            let row = TodoWithLabelFromRow {
+                id: TodoId(1),
+                text: "Todo 1".to_string(),
+                completed: false,
+                completed_at: None,
+                archived: false,
+                due_date: None,
+                priority: None,
+                position: None,
+                created_at: now,
+                updated_at: now,
+                label_id: None,
+                label_name: None,
+            };
+            let todo = existing_entity(TodoId(1), vec![row]).unwrap();
+            assert_eq!(TodoId(1), todo.id);
+
+            let err = existing_entity(TodoId(1), vec![]).unwrap_err();
+            assert!(matches!(err, RepositoryError::Unexpected(_)));
+        }
 
         #[tokio::test]
-        async fn todo_crud_scenario() {
-            let label_data = Label::new(1, "test label".to_string());
-            let labels = vec![label_data.clone()];
-            let id = 1;
-            let text = "todo text".to_string();
-            let repo = TodoRepositoryForMemory::new(labels.clone());
+        async fn crud_scenario() {
+            let pool = connect().await;
+            let (label_id,) = sqlx::query_as::<_, (LabelId,)>(
+                r#"INSERT INTO labels (name) VALUES ('test label') RETURNING id"#,
+            )
+            .fetch_one(&pool)
+            .await
+            .expect("Failed to insert label data");
+            let label = Label::new(label_id, "test label".to_string());
+            let repo = TodoRepositoryForSqlite::new(pool.clone());
 
             // create
-            let expected = TodoEntity::new(id, text.clone(), false, labels.clone());
-            let todo = repo
-                .create(CreateTodo::new(text, vec![label_data.id]))
+            let created = repo
+                .create(CreateTodo::new(
+                    "[crud_scenario] text".to_string(),
+                    vec![label.id],
+                ))
                 .await
-                .expect("failed create todo");
-            assert_eq!(expected, todo);
+                .expect("[create] returned Err");
+            assert_eq!(vec![label.clone()], created.labels);
 
             // find
-            let todo = repo.find(todo.id).await.unwrap();
-            assert_eq!(expected, todo);
+            let todo = repo.find(created.id).await.expect("[find] returned Err");
+            assert_eq!(created, todo);
 
             // all
-            let todos = repo.all().await.expect("failed get all todos");
-            assert_eq!(vec![expected], todos);
+            let todos = repo
+                .all(TodoQuery::default())
+                .await
+                .expect("[all] returned Err");
+            assert_eq!(vec![created.clone()], todos);
+            let todos = repo
+                .all(TodoQuery {
+                    q: Some("CRUD_SCENARIO".to_string()),
+                    ..Default::default()
+                })
+                .await
+                .expect("[all] returned Err");
+            assert_eq!(vec![created.clone()], todos);
 
             // update
-            let text = "update todo text".to_string();
+            let updated = repo
+                .update(
+                    todo.id,
+                    UpdateTodo::new(
+                        Some("[crud_scenario] updated text".to_string()),
+                        Some(true),
+                        Some(vec![]),
+                    ),
+                )
+                .await
+                .expect("[update] returned Err");
+            assert_eq!("[crud_scenario] updated text", updated.text);
+            assert!(updated.completed);
+            assert!(updated.completed_at.is_some());
+            assert!(updated.labels.is_empty());
+            assert_eq!(updated, repo.find(todo.id).await.unwrap());
+
+            // search
+            let result = repo
+                .search(TodoSearchCriteria {
+                    completed: Some(true),
+                    ..Default::default()
+                })
+                .await
+                .expect("[search] returned Err");
+            assert_eq!(vec![updated.clone()], result.items);
+            assert_eq!(1, result.total);
+
+            // search_ranked
+            let todos = repo
+                .search_ranked("updatd", false)
+                .await
+                .expect("[search_ranked] returned Err");
+            assert_eq!(vec![updated.clone()], todos);
+
+            // set_completed_all
+            let count = repo
+                .set_completed_all(TodoFilter::default(), false)
+                .await
+                .expect("[set_completed_all] returned Err");
+            assert_eq!(1, count);
+
+            // last_modified
+            repo.last_modified()
+                .await
+                .expect("[last_modified] returned Err");
+
+            // delete
+            repo.delete(todo.id).await.expect("[delete] returned Err");
+            let err = repo
+                .find(todo.id)
+                .await
+                .expect_err("[find] returned Ok for a deleted todo");
+            assert!(matches!(
+                err.downcast_ref::<RepositoryError>(),
+                Some(RepositoryError::NotFound(_))
+            ));
+        }
+
+        #[tokio::test]
+        async fn create_with_label_names_scenario() {
+            let repo = TodoRepositoryForSqlite::new(connect().await);
             let todo = repo
-                .update(1, UpdateTodo::new(Some(text.clone()), Some(true), None))
+                .create(
+                    CreateTodo::new("text".to_string(), vec![])
+                        .with_label_names(vec!["Work".to_string(), "work".to_string()]),
+                )
                 .await
-                .expect("failed update todo.");
+                .expect("[create] returned Err");
+            assert_eq!(vec!["Work".to_string()], label_names(&todo));
+            let todo = repo
+                .create(
+                    CreateTodo::new("text".to_string(), vec![])
+                        .with_label_names(vec!["WORK".to_string()]),
+                )
+                .await
+                .expect("[create] returned Err");
+            assert_eq!(vec!["Work".to_string()], label_names(&todo));
+        }
+
+        #[tokio::test]
+        async fn label_filter_scenario() {
+            let repo = TodoRepositoryForSqlite::new(connect().await);
+            let mut todos = vec![];
+            for (text, labels) in [
+                ("Buy milk", vec!["a"]),
+                ("Write report", vec!["a", "b"]),
+                ("Read report", vec![]),
+            ] {
+                let labels = labels.into_iter().map(str::to_string).collect();
+                let todo = repo
+                    .create(CreateTodo::new(text.to_string(), vec![]).with_label_names(labels))
+                    .await
+                    .expect("[create] returned Err");
+                todos.push(todo);
+            }
+            let label_ids: Vec<LabelId> = todos[1].labels.iter().map(|l| l.id).collect();
+            let query = |q: Option<&str>, completed: Option<bool>| TodoQuery {
+                q: q.map(str::to_string),
+                completed,
+                label_ids: label_ids.clone(),
+                sort: TodoSort::Id,
+                order: SortOrder::Asc,
+                ..Default::default()
+            };
+            let ids =
+                |todos: Vec<TodoEntity>| -> Vec<TodoId> { todos.iter().map(|t| t.id).collect() };
+
+            // todos with both labels are listed once, with all their labels
+            let found = repo.all(query(None, None)).await.unwrap();
+            assert_eq!(vec![todos[0].id, todos[1].id], ids(found.clone()));
+            assert_eq!(todos[1].labels, found[1].labels);
+            let found = repo.all(query(Some("report"), Some(false))).await.unwrap();
+            assert_eq!(vec![todos[1].id], ids(found));
+            let found = repo.all(query(Some("report"), Some(true))).await.unwrap();
+            assert!(found.is_empty());
+
+            let skipped = TodoQuery {
+                skip_labels: true,
+                ..query(None, None)
+            };
+            let found = repo.all(skipped).await.unwrap();
+            assert_eq!(vec![todos[0].id, todos[1].id], ids(found.clone()));
+            assert!(found.iter().all(|todo| todo.labels.is_empty()));
+
+            let unlabeled = |q: Option<&str>| TodoQuery {
+                unlabeled: true,
+                label_ids: vec![],
+                ..query(q, None)
+            };
+            let found = repo.all(unlabeled(None)).await.unwrap();
+            assert_eq!(vec![todos[2].id], ids(found));
+            let found = repo.all(unlabeled(Some("milk"))).await.unwrap();
+            assert!(found.is_empty());
+            let criteria = TodoSearchCriteria {
+                q: Some("report".to_string()),
+                unlabeled: true,
+                ..Default::default()
+            };
+            let result = repo.search(criteria).await.unwrap();
+            assert_eq!(vec![todos[2].id], ids(result.items));
+        }
+
+        #[tokio::test]
+        async fn find_max_joined_labels_scenario() {
+            let repo = TodoRepositoryForSqlite::new(connect().await).with_max_joined_labels(1);
+            let todo = repo
+                .create(
+                    CreateTodo::new("text".to_string(), vec![])
+                        .with_label_names(vec!["first".to_string(), "second".to_string()]),
+                )
+                .await
+                .expect("[create] returned Err");
+            assert!(!todo.labels_truncated);
+
+            let found = repo.find(todo.id).await.expect("[find] returned Err");
+            assert_eq!(todo.labels[..1].to_vec(), found.labels);
+            assert!(found.labels_truncated);
+        }
+
+        fn label_names(todo: &TodoEntity) -> Vec<String> {
+            todo.labels.iter().map(|label| label.name.clone()).collect()
+        }
+
+        #[tokio::test]
+        async fn history_scenario() {
+            let repo = TodoRepositoryForSqlite::new(connect().await);
+            let todo = repo
+                .create(CreateTodo::new("[history] text".to_string(), vec![]))
+                .await
+                .expect("[create] returned Err");
+            assert!(repo.history(todo.id).await.unwrap().is_empty());
+
+            repo.update(
+                todo.id,
+                UpdateTodo::new(Some("[history] renamed".to_string()), None, None),
+            )
+            .await
+            .expect("[update] returned Err");
+            repo.update(todo.id, UpdateTodo::new(None, Some(true), None))
+                .await
+                .expect("[update] returned Err");
+
+            let history = repo.history(todo.id).await.expect("[history] returned Err");
+            let fields: Vec<&str> = history.iter().map(|change| change.field.as_str()).collect();
+            assert_eq!(vec!["text", "completed"], fields);
+            assert_eq!(serde_json::Value::from("[history] text"), history[0].old);
+            assert_eq!(serde_json::Value::from(true), history[1].new);
+        }
+
+        #[tokio::test]
+        async fn conditional_update_scenario() {
+            let repo = TodoRepositoryForSqlite::new(connect().await);
+            let created = repo
+                .create(CreateTodo::new("[conditional] text".to_string(), vec![]))
+                .await
+                .expect("[create] returned Err");
+
+            let updated = repo
+                .update(
+                    created.id,
+                    UpdateTodo::new(Some("[conditional] matched".to_string()), None, None)
+                        .if_updated_at(created.updated_at),
+                )
+                .await
+                .expect("[update] returned Err");
+            let res = repo
+                .update(
+                    created.id,
+                    UpdateTodo::new(Some("[conditional] stale".to_string()), None, None)
+                        .if_updated_at(created.updated_at),
+                )
+                .await;
+            assert!(matches!(
+                res.unwrap_err().downcast_ref::<RepositoryError>(),
+                Some(RepositoryError::Stale(_))
+            ));
+            assert_eq!(updated, repo.find(created.id).await.unwrap());
+
+            let (id,) = sqlx::query_as::<_, (TodoId,)>(
+                r#"INSERT INTO todos (text) VALUES ('[conditional] legacy') RETURNING id"#,
+            )
+            .fetch_one(&repo.pool)
+            .await
+            .expect("Failed to insert todo data");
+            let legacy = repo.find(id).await.expect("[find] returned Err");
+            repo.update(
+                id,
+                UpdateTodo::new(Some("[conditional] matched".to_string()), None, None)
+                    .if_updated_at(legacy.updated_at),
+            )
+            .await
+            .expect("[update] returned Err");
+        }
+
+        #[tokio::test]
+        async fn reorder_scenario() {
+            let repo = TodoRepositoryForSqlite::new(connect().await);
+            let mut todos = vec![];
+            for text in ["first", "second", "third"] {
+                let todo = repo
+                    .create(CreateTodo::new(format!("[reorder] {}", text), vec![]))
+                    .await
+                    .expect("[create] returned Err");
+                todos.push(todo.id);
+            }
+
+            repo.reorder(vec![todos[0], todos[2]])
+                .await
+                .expect("[reorder] returned Err");
+            let all = repo.all(TodoQuery::default()).await.unwrap();
+            let ids: Vec<TodoId> = all.iter().map(|todo| todo.id).collect();
+            assert_eq!(vec![todos[0], todos[2], todos[1]], ids);
+
+            let res = repo.reorder(vec![TodoId(1000)]).await;
+            assert!(res.is_err());
+        }
+
+        #[tokio::test]
+        async fn summary_scenario() {
+            let repo = TodoRepositoryForSqlite::new(connect().await);
+            assert_eq!(
+                TodoSummary::new(0, 0),
+                repo.summary(TodoFilter::default()).await.unwrap()
+            );
+            let labeled = repo
+                .create(
+                    CreateTodo::new("labeled".to_string(), vec![])
+                        .with_label_names(vec!["label".to_string()]),
+                )
+                .await
+                .expect("[create] returned Err");
+            repo.create(CreateTodo::new("unlabeled".to_string(), vec![]))
+                .await
+                .expect("[create] returned Err");
+            repo.update(labeled.id, UpdateTodo::new(None, Some(true), None))
+                .await
+                .expect("[update] returned Err");
+
+            assert_eq!(
+                TodoSummary::new(2, 1),
+                repo.summary(TodoFilter::default()).await.unwrap()
+            );
+            let filter = TodoFilter {
+                label_id: Some(labeled.labels[0].id),
+            };
+            assert_eq!(TodoSummary::new(1, 1), repo.summary(filter).await.unwrap());
+        }
+
+        #[tokio::test]
+        async fn legacy_row_scenario() {
+            let pool = connect().await;
+            let (id,) = sqlx::query_as::<_, (TodoId,)>(
+                r#"INSERT INTO todos (text) VALUES ('[legacy_row] text') RETURNING id"#,
+            )
+            .fetch_one(&pool)
+            .await
+            .expect("Failed to insert todo data");
+
+            let repo = TodoRepositoryForSqlite::new(pool);
+            let todo = repo.find(id).await.expect("[find] returned Err");
+            assert_eq!("[legacy_row] text", todo.text);
+            assert_eq!(None, todo.completed_at);
+            assert_eq!(None, todo.due_date);
+            assert_eq!(None, todo.priority);
+            assert_eq!(None, todo.position);
+            assert!(todo.labels.is_empty());
+        }
+
+        #[tokio::test]
+        async fn touch_scenario() {
+            let repo = TodoRepositoryForSqlite::new(connect().await);
+            let created = repo
+                .create(CreateTodo::new("[touch] text".to_string(), vec![]))
+                .await
+                .expect("[create] returned Err");
+
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            let touched = repo.touch(created.id).await.expect("[touch] returned Err");
+            assert!(touched.updated_at > created.updated_at);
+            let updated_at = touched.updated_at;
             assert_eq!(
                 TodoEntity {
-                    id,
-                    text,
-                    completed: true,
-                    labels: labels.clone()
+                    updated_at,
+                    ..created
                 },
-                todo
+                touched
             );
+            assert!(repo.touch(TodoId(1000)).await.is_err());
+        }
 
-            // delete
-            let res = repo.delete(id).await;
-            assert!(res.is_ok());
+        #[tokio::test]
+        async fn find_many_scenario() {
+            let pool = connect().await;
+            let label = sqlx::query_as::<_, Label>(
+                r#"INSERT INTO labels (name) VALUES ('label') RETURNING *"#,
+            )
+            .fetch_one(&pool)
+            .await
+            .expect("Failed to insert label data");
+            let repo = TodoRepositoryForSqlite::new(pool);
+            let mut todos = vec![];
+            for labels in [vec![label.id], vec![], vec![label.id]] {
+                let todo = repo
+                    .create(CreateTodo::new("[find_many] text".to_string(), labels))
+                    .await
+                    .expect("[create] returned Err");
+                todos.push(todo);
+            }
+            let foreign = repo
+                .scoped(OwnerId(1))
+                .create(CreateTodo::new("[find_many] foreign".to_string(), vec![]))
+                .await
+                .expect("[create] returned Err");
+
+            let ids = [
+                todos[2].id,
+                TodoId(1000),
+                foreign.id,
+                todos[0].id,
+                todos[2].id,
+            ];
+            let found = repo
+                .find_many(&ids)
+                .await
+                .expect("[find_many] returned Err");
+            assert_eq!(vec![todos[2].clone(), todos[0].clone()], found);
+            assert_eq!(vec![label], found[0].labels);
+        }
+
+        #[tokio::test]
+        async fn foreign_label_scenario() {
+            let pool = connect().await;
+            let label = sqlx::query_as::<_, Label>(
+                r#"INSERT INTO labels (name) VALUES ('secret') RETURNING *"#,
+            )
+            .fetch_one(&pool)
+            .await
+            .expect("Failed to insert label data");
+            let repo = TodoRepositoryForSqlite::new(pool).with_default_label(Some(label.id));
+            let stranger = repo.scoped(OwnerId(1));
+
+            let res = stranger
+                .create(CreateTodo::new(
+                    "[foreign_label] text".to_string(),
+                    vec![label.id],
+                ))
+                .await;
+            assert!(matches!(
+                res.unwrap_err().downcast_ref::<RepositoryError>(),
+                Some(RepositoryError::NotFound(_))
+            ));
+            let todo = stranger
+                .create(CreateTodo::new("[foreign_label] text".to_string(), vec![]))
+                .await
+                .expect("[create] returned Err");
+            assert!(todo.labels.is_empty());
+            let res = stranger
+                .update(todo.id, UpdateTodo::new(None, None, Some(vec![label.id])))
+                .await;
+            assert!(matches!(
+                res.unwrap_err().downcast_ref::<RepositoryError>(),
+                Some(RepositoryError::NotFound(_))
+            ));
+
+            let todo = repo
+                .create(CreateTodo::new("[foreign_label] text".to_string(), vec![]))
+                .await
+                .expect("[create] returned Err");
+            assert_eq!(vec![label], todo.labels);
+        }
+
+        #[tokio::test]
+        async fn last_modified_scenario() {
+            let pool = connect().await;
+            let repo = TodoRepositoryForSqlite::new(pool.clone());
+            let stranger = repo.scoped(OwnerId(1));
+            let label = sqlx::query_as::<_, Label>(
+                r#"INSERT INTO labels (name) VALUES ('label') RETURNING *"#,
+            )
+            .fetch_one(&pool)
+            .await
+            .expect("Failed to insert label data");
+            repo.create(CreateTodo::new(
+                "[last_modified] text".to_string(),
+                vec![label.id],
+            ))
+            .await
+            .expect("[create] returned Err");
+            let created_at = repo
+                .last_modified()
+                .await
+                .expect("[last_modified] returned Err");
+            assert_eq!(
+                DateTime::<Utc>::UNIX_EPOCH,
+                stranger
+                    .last_modified()
+                    .await
+                    .expect("[last_modified] returned Err")
+            );
+
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+            sqlx::query(r#"UPDATE labels SET name = 'renamed' WHERE id = ?1"#)
+                .bind(label.id)
+                .execute(&pool)
+                .await
+                .expect("Failed to rename label");
+            let renamed_at = repo
+                .last_modified()
+                .await
+                .expect("[last_modified] returned Err");
+            assert!(renamed_at > created_at);
+        }
+
+        #[tokio::test]
+        async fn attach_label_scenario() {
+            let pool = connect().await;
+            let labels = sqlx::query_as::<_, Label>(
+                r#"INSERT INTO labels (name) VALUES ('label'), ('other') RETURNING *"#,
+            )
+            .fetch_all(&pool)
+            .await
+            .expect("Failed to insert label data");
+            let (label, other) = (labels[0].clone(), labels[1].clone());
+            let repo = TodoRepositoryForSqlite::new(pool);
+            let mut todos = vec![];
+            for labels in [vec![label.id], vec![], vec![other.id]] {
+                let todo = repo
+                    .create(CreateTodo::new("[attach_label] text".to_string(), labels))
+                    .await
+                    .expect("[create] returned Err");
+                todos.push(todo.id);
+            }
+            let foreign = repo
+                .scoped(OwnerId(1))
+                .create(CreateTodo::new(
+                    "[attach_label] foreign".to_string(),
+                    vec![],
+                ))
+                .await
+                .expect("[create] returned Err");
+
+            let ids = vec![todos[0], todos[1], foreign.id, todos[2], TodoId(1000)];
+            let attached = repo
+                .attach_label(label.id, ids)
+                .await
+                .expect("[attach_label] returned Err");
+            assert_eq!(
+                AttachedLabel {
+                    attached: 2,
+                    not_found: vec![foreign.id, TodoId(1000)],
+                },
+                attached
+            );
+            let todo = repo.find(todos[1]).await.expect("[find] returned Err");
+            assert_eq!(vec![label.clone()], todo.labels);
+            let todo = repo.find(todos[2]).await.expect("[find] returned Err");
+            assert_eq!(vec![label.clone(), other.clone()], todo.labels);
+
+            // over max_labels, nothing is attached
+            let res = repo
+                .clone()
+                .with_max_labels(1)
+                .attach_label(other.id, vec![todos[1]])
+                .await;
+            assert!(matches!(
+                res.unwrap_err().downcast_ref::<RepositoryError>(),
+                Some(RepositoryError::TooManyLabels(1))
+            ));
+            let todo = repo.find(todos[1]).await.expect("[find] returned Err");
+            assert_eq!(vec![label.clone()], todo.labels);
+        }
+
+        #[tokio::test]
+        async fn duplicate_scenario() {
+            let repo = TodoRepositoryForSqlite::new(connect().await);
+            let todo = repo
+                .create(
+                    CreateTodo::new("[duplicate] text".to_string(), vec![])
+                        .with_label_names(vec!["first".to_string(), "second".to_string()]),
+                )
+                .await
+                .expect("[create] returned Err");
+            repo.update(todo.id, UpdateTodo::new(None, Some(true), None))
+                .await
+                .expect("[update] returned Err");
+
+            let copy = repo
+                .duplicate(todo.id)
+                .await
+                .expect("[duplicate] returned Err");
+            assert_ne!(todo.id, copy.id);
+            assert_eq!(todo.labels, copy.labels);
+            assert!(!copy.completed);
+            assert_eq!(copy.labels, repo.find(copy.id).await.unwrap().labels);
+
+            let res = repo.duplicate(TodoId(1000)).await;
+            assert!(res.is_err());
+        }
+
+        #[tokio::test]
+        async fn context_scenario() {
+            let pool = connect().await;
+            let repo = TodoRepositoryForSqlite::new(pool.clone());
+            pool.close().await;
+
+            let e = repo.find(TodoId(1)).await.unwrap_err();
+            assert_eq!("find todo", e.to_string());
+            assert!(matches!(
+                e.downcast_ref::<sqlx::Error>(),
+                Some(sqlx::Error::PoolClosed)
+            ));
         }
     }
 }
@@ -0,0 +1,120 @@
+use crate::repositories::label::{CreateLabel, LabelRepository};
+use crate::repositories::todo::{CreateTodo, TodoQuery, TodoRepository};
+use crate::repositories::OwnerId;
+use validator::Validate;
+
+/// Owner the demo data belongs to, send `x-owner-id: 1` to see it.
+pub const DEMO_OWNER: OwnerId = OwnerId(1);
+
+const DEMO_LABELS: [&str; 3] = ["work", "home", "errands"];
+
+/// Texts of the demo todos with the indexes of their labels in [`DEMO_LABELS`].
+const DEMO_TODOS: [(&str, &[usize]); 5] = [
+    ("Read the onboarding guide", &[0]),
+    ("Review open pull requests", &[0]),
+    ("Water the plants", &[1]),
+    ("Buy groceries", &[1, 2]),
+    ("Pick up the dry cleaning", &[2]),
+];
+
+/// What [`seed`] did.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Seeded {
+    /// The owner already had todos, nothing was written.
+    Skipped,
+    Inserted {
+        labels: usize,
+        todos: usize,
+    },
+}
+
+/// Inserts the demo labels and todos through the given repositories unless they already
+/// hold a todo, archived ones included, so that it can run on every start.
+pub async fn seed<T: TodoRepository, L: LabelRepository>(
+    todo_repo: &T,
+    label_repo: &L,
+) -> anyhow::Result<Seeded> {
+    let existing = todo_repo
+        .all(TodoQuery {
+            include_archived: true,
+            page_size: Some(1),
+            ..Default::default()
+        })
+        .await?;
+    if !existing.is_empty() {
+        return Ok(Seeded::Skipped);
+    }
+
+    let payloads: Vec<CreateLabel> = DEMO_LABELS
+        .iter()
+        .map(|name| CreateLabel::new(name.to_string()))
+        .collect();
+    for payload in payloads.iter() {
+        payload.validate()?;
+    }
+    let labels = label_repo.create_many(payloads).await?;
+    for (text, label_indexes) in DEMO_TODOS {
+        let payload = CreateTodo::new(
+            text.to_string(),
+            label_indexes.iter().map(|i| labels[*i].id).collect(),
+        );
+        payload.validate()?;
+        todo_repo.create(payload).await?;
+    }
+
+    Ok(Seeded::Inserted {
+        labels: labels.len(),
+        todos: DEMO_TODOS.len(),
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::repositories::label::test_utils::LabelRepositoryForMemory;
+    use crate::repositories::todo::test_utils::TodoRepositoryForMemory;
+    use crate::repositories::OwnerScoped;
+
+    #[tokio::test]
+    async fn should_seed_once() {
+        let label_repo = LabelRepositoryForMemory::new().scoped(DEMO_OWNER);
+        let todo_repo =
+            TodoRepositoryForMemory::with_label_repository(label_repo.clone()).scoped(DEMO_OWNER);
+
+        let seeded = seed(&todo_repo, &label_repo).await.unwrap();
+        assert_eq!(
+            Seeded::Inserted {
+                labels: 3,
+                todos: 5
+            },
+            seeded
+        );
+        let mut names: Vec<String> = label_repo
+            .all()
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|label| label.name)
+            .collect();
+        names.sort();
+        assert_eq!(vec!["errands", "home", "work"], names);
+        let todos = todo_repo.all(TodoQuery::default()).await.unwrap();
+        assert_eq!(5, todos.len());
+        let groceries = todos
+            .iter()
+            .find(|todo| todo.text == "Buy groceries")
+            .unwrap();
+        let mut label_names: Vec<&str> = groceries
+            .labels
+            .iter()
+            .map(|label| label.name.as_str())
+            .collect();
+        label_names.sort();
+        assert_eq!(vec!["errands", "home"], label_names);
+
+        let seeded = seed(&todo_repo, &label_repo).await.unwrap();
+        assert_eq!(Seeded::Skipped, seeded);
+        assert_eq!(5, todo_repo.all(TodoQuery::default()).await.unwrap().len());
+        assert_eq!(3, label_repo.all().await.unwrap().len());
+    }
+}
@@ -0,0 +1,165 @@
+use axum::body::Body;
+use axum::http::Request;
+use axum::middleware::Next;
+use axum::response::Response;
+use std::future::Future;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::oneshot;
+
+pub const DEFAULT_SHUTDOWN_GRACE_SECS: u64 = 30;
+
+/// Number of requests being served, kept up to date by [`track_in_flight`].
+#[derive(Debug, Clone, Default)]
+pub struct InFlight(Arc<AtomicUsize>);
+
+impl InFlight {
+    pub fn count(&self) -> usize {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// Decrements the count when the request is done, including when its future is dropped.
+struct InFlightGuard(InFlight);
+
+impl Drop for InFlightGuard {
+    fn drop(&mut self) {
+        (self.0).0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// Counts the request in the `InFlight` extension while it is served.
+pub async fn track_in_flight(req: Request<Body>, next: Next<Body>) -> Response {
+    let _guard = req
+        .extensions()
+        .get::<InFlight>()
+        .cloned()
+        .map(|in_flight| {
+            in_flight.0.fetch_add(1, Ordering::Relaxed);
+            InFlightGuard(in_flight)
+        });
+    next.run(req).await
+}
+
+/// How [`drain`] ended.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Drained<T> {
+    /// The server stopped by itself, before or within the grace period.
+    Finished(T),
+    /// The grace period ran out with these requests still being served.
+    Forced { in_flight: usize },
+}
+
+/// Runs `serve` until `signal` fires, then asks it to stop through `shutdown` and waits up
+/// to `grace` for the requests in flight before giving up on them.
+pub async fn drain<F: Future>(
+    serve: F,
+    signal: impl Future<Output = ()>,
+    shutdown: oneshot::Sender<()>,
+    grace: Duration,
+    in_flight: &InFlight,
+) -> Drained<F::Output> {
+    tokio::pin!(serve);
+    tokio::select! {
+        output = &mut serve => return Drained::Finished(output),
+        () = signal => {}
+    }
+    tracing::info!(
+        "shutting down, draining {} requests for up to {:?}",
+        in_flight.count(),
+        grace
+    );
+    // the server may already be gone, in which case it finishes right below
+    let _ = shutdown.send(());
+    match tokio::time::timeout(grace, serve).await {
+        Ok(output) => Drained::Finished(output),
+        Err(_) => {
+            let in_flight = in_flight.count();
+            tracing::warn!(
+                "grace period over, force closing with {} requests in flight",
+                in_flight
+            );
+            Drained::Forced { in_flight }
+        }
+    }
+}
+
+/// Resolves on SIGTERM or Ctrl-C.
+pub async fn shutdown_signal() {
+    let ctrl_c = async {
+        tokio::signal::ctrl_c()
+            .await
+            .expect("fail install Ctrl-C handler");
+    };
+    #[cfg(unix)]
+    let terminate = async {
+        tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+            .expect("fail install SIGTERM handler")
+            .recv()
+            .await;
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        () = ctrl_c => {}
+        () = terminate => {}
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Server stand-in that needs `work` to finish once asked to stop.
+    async fn serve(stop: oneshot::Receiver<()>, work: Duration) -> &'static str {
+        stop.await.unwrap();
+        tokio::time::sleep(work).await;
+        "stopped"
+    }
+
+    #[tokio::test]
+    async fn should_finish_within_grace_period() {
+        let (shutdown, stop) = oneshot::channel();
+        let drained = drain(
+            serve(stop, Duration::from_millis(10)),
+            async {},
+            shutdown,
+            Duration::from_secs(5),
+            &InFlight::default(),
+        )
+        .await;
+        assert_eq!(Drained::Finished("stopped"), drained);
+    }
+
+    #[tokio::test]
+    async fn should_force_close_after_grace_period() {
+        let in_flight = InFlight::default();
+        in_flight.0.fetch_add(2, Ordering::Relaxed);
+        let (shutdown, stop) = oneshot::channel();
+        let drained = drain(
+            serve(stop, Duration::from_secs(10)),
+            async {},
+            shutdown,
+            Duration::from_millis(10),
+            &in_flight,
+        )
+        .await;
+        assert_eq!(Drained::Forced { in_flight: 2 }, drained);
+    }
+
+    #[tokio::test]
+    async fn should_finish_without_signal() {
+        let (shutdown, _stop) = oneshot::channel();
+        let drained = drain(
+            async { "finished" },
+            std::future::pending(),
+            shutdown,
+            Duration::ZERO,
+            &InFlight::default(),
+        )
+        .await;
+        assert_eq!(Drained::Finished("finished"), drained);
+    }
+}
@@ -0,0 +1,72 @@
+use axum::Router;
+use axum_server::tls_rustls::RustlsConfig;
+use axum_server::Handle;
+use std::io;
+use std::net::SocketAddr;
+use std::path::Path;
+use tokio::sync::oneshot;
+
+/// Reads the PEM certificate chain and private key the server presents.
+pub async fn load_tls_config(cert: &Path, key: &Path) -> io::Result<RustlsConfig> {
+    RustlsConfig::from_pem_file(cert, key).await
+}
+
+/// Serves `app` over TLS until `stop` fires, then stops accepting connections and lets the
+/// ones open finish. HTTP/2 is offered along HTTP/1.1 through ALPN.
+pub async fn serve_tls(
+    app: Router,
+    addr: SocketAddr,
+    config: RustlsConfig,
+    handle: Handle,
+    stop: oneshot::Receiver<()>,
+) -> io::Result<()> {
+    let shutdown = handle.clone();
+    tokio::spawn(async move {
+        if stop.await.is_ok() {
+            shutdown.graceful_shutdown(None);
+        }
+    });
+    axum_server::bind_rustls(addr, config)
+        .handle(handle)
+        .serve(app.into_make_service())
+        .await
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn fixture(name: &str) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/fixtures")
+            .join(name)
+    }
+
+    #[tokio::test]
+    async fn should_serve_with_self_signed_cert() {
+        let config = load_tls_config(&fixture("localhost.crt"), &fixture("localhost.key"))
+            .await
+            .expect("fail load self-signed certificate");
+        let handle = Handle::new();
+        let (shutdown, stop) = oneshot::channel();
+        let server = tokio::spawn(serve_tls(
+            Router::new(),
+            SocketAddr::from(([127, 0, 0, 1], 0)),
+            config,
+            handle.clone(),
+            stop,
+        ));
+
+        let addr = handle.listening().await.expect("server did not start");
+        assert!(addr.port() > 0);
+        shutdown.send(()).unwrap();
+        server.await.unwrap().expect("server returned Err");
+    }
+
+    #[tokio::test]
+    async fn should_reject_missing_cert() {
+        let res = load_tls_config(&fixture("missing.crt"), &fixture("localhost.key")).await;
+        assert!(res.is_err());
+    }
+}
@@ -0,0 +1,118 @@
+//! End-to-end tests driving the public router with the in-memory repositories.
+//!
+//! The memory repositories live behind the `testing` feature, which the
+//! self dev-dependency in `Cargo.toml` turns on for these tests.
+use axum::{
+    body::Body,
+    http::{header, Method, Request, StatusCode},
+    response::Response,
+    Router,
+};
+use axum_tutorial::create_app;
+use axum_tutorial::handlers::{Paginated, OWNER_ID_HEADER};
+use axum_tutorial::repositories::health::test_utils::HealthRepositoryForMemory;
+use axum_tutorial::repositories::label::test_utils::LabelRepositoryForMemory;
+use axum_tutorial::repositories::label::Label;
+use axum_tutorial::repositories::todo::test_utils::TodoRepositoryForMemory;
+use axum_tutorial::repositories::todo::TodoEntity;
+use serde::de::DeserializeOwned;
+use tower::ServiceExt;
+
+fn app() -> Router {
+    let label_repo = LabelRepositoryForMemory::new();
+    create_app(
+        TodoRepositoryForMemory::with_label_repository(label_repo.clone()),
+        label_repo,
+        HealthRepositoryForMemory::new(),
+    )
+}
+
+fn json_req(method: Method, path: &str, json: serde_json::Value) -> Request<Body> {
+    Request::builder()
+        .uri(path)
+        .method(method)
+        .header(&OWNER_ID_HEADER, "0")
+        .header(header::CONTENT_TYPE, mime::APPLICATION_JSON.as_ref())
+        .body(Body::from(json.to_string()))
+        .unwrap()
+}
+
+fn empty_req(method: Method, path: &str) -> Request<Body> {
+    Request::builder()
+        .uri(path)
+        .method(method)
+        .header(&OWNER_ID_HEADER, "0")
+        .body(Body::empty())
+        .unwrap()
+}
+
+async fn send(app: &Router, req: Request<Body>) -> Response {
+    app.clone().oneshot(req).await.unwrap()
+}
+
+async fn body_json<T: DeserializeOwned>(res: Response) -> T {
+    let bytes = hyper::body::to_bytes(res.into_body()).await.unwrap();
+    serde_json::from_slice(&bytes).expect("cannot deserialize response body")
+}
+
+#[tokio::test]
+async fn todo_lifecycle_through_router() {
+    let app = app();
+
+    let res = send(
+        &app,
+        json_req(
+            Method::POST,
+            "/labels",
+            serde_json::json!({ "name": "work" }),
+        ),
+    )
+    .await;
+    assert_eq!(StatusCode::CREATED, res.status());
+    let label: Label = body_json(res).await;
+    assert_eq!("work", label.name);
+
+    let res = send(
+        &app,
+        json_req(
+            Method::POST,
+            "/todos",
+            serde_json::json!({ "text": "write report", "labels": [label.id] }),
+        ),
+    )
+    .await;
+    assert_eq!(StatusCode::CREATED, res.status());
+    let todo: TodoEntity = body_json(res).await;
+    assert_eq!("write report", todo.text);
+    assert_eq!(vec![label.clone()], todo.labels);
+
+    let res = send(
+        &app,
+        json_req(
+            Method::PATCH,
+            &format!("/todos/{}", todo.id),
+            serde_json::json!({ "text": "write final report", "completed": true }),
+        ),
+    )
+    .await;
+    assert_eq!(StatusCode::CREATED, res.status());
+    let updated: TodoEntity = body_json(res).await;
+    assert_eq!("write final report", updated.text);
+    assert!(updated.completed);
+    assert_eq!(vec![label], updated.labels);
+
+    let res = send(&app, empty_req(Method::GET, "/todos?expand=labels")).await;
+    assert_eq!(StatusCode::OK, res.status());
+    let todos: Paginated<TodoEntity> = body_json(res).await;
+    assert_eq!(vec![updated], todos.items);
+
+    let res = send(
+        &app,
+        empty_req(Method::DELETE, &format!("/todos/{}", todo.id)),
+    )
+    .await;
+    assert_eq!(StatusCode::NO_CONTENT, res.status());
+
+    let res = send(&app, empty_req(Method::GET, &format!("/todos/{}", todo.id))).await;
+    assert_eq!(StatusCode::NOT_FOUND, res.status());
+}